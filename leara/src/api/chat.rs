@@ -31,16 +31,28 @@ use axum::{
 // Import Serde for JSON serialization/deserialization
 use serde::{Deserialize, Serialize};
 // Import our local chat models
-use crate::models::chat::ChatResponse;
+use crate::models::chat::{ChatResponse, ToolCallTrace};
 // Import tracing for structured logging
-use tracing::{info, error};
+use tracing::{info, warn};
 // Import our Ollama client for AI model integration
-use crate::utils::ollama::{OllamaClient, OllamaOptions};
+use crate::utils::ollama::{OllamaChatMessage, OllamaClient};
+use crate::system::tools;
 // Import uuid for generating conversation IDs
 use uuid;
 // Import our AppState
 use crate::models::AppState;
-use chrono::Utc;
+
+/// Model used when neither the request nor `LEARA_MODEL` names one; it must support tool calling
+const DEFAULT_MODEL: &str = "qwen2.5-coder:7b";
+
+/// Rounds of tool calls before the model has to answer without tools
+const MAX_TOOL_ROUNDS: usize = 8;
+
+/// Instructions given to the model at the start of every chat
+const SYSTEM_PROMPT: &str = "You are Leara, an assistant running on the user's own computer. \
+Use the tools to look things up or act instead of guessing. Commands are checked by a policy: \
+when a tool result says a command is pending, it has not run yet and waits for the user's \
+approval, and blocked commands must not be retried in another form. Answer concisely.";
 
 /// Request structure for incoming chat messages
 /// Contains the user's message and optional context information
@@ -52,6 +64,8 @@ pub struct ChatRequest {
     pub context: Option<String>,
     /// Optional session ID for conversation continuity
     pub session_id: Option<String>,
+    /// Ollama model to answer with
+    pub model: Option<String>,
 }
 
 /// Error response structure for chat API failures
@@ -64,10 +78,15 @@ pub struct ChatError {
 
 /// Handle incoming chat messages from clients
 /// 
-/// This function processes user messages and generates appropriate responses
-/// using the hexbenjamin/memgpt-dpo-uncensored:f16 model via Ollama.
-/// It also integrates with the memory system to remember conversations,
-/// create tasks, and provide context-aware responses.
+/// This function answers user messages with a model served by Ollama
+/// (`LEARA_MODEL`, at `OLLAMA_URL`). The model can call Leara's tools —
+/// running commands, reading files, searching memory, creating tasks and
+/// looking at the system — and sees their results until it gives a final
+/// answer. Commands go through the command policy like `/system/execute`;
+/// those needing confirmation are left pending for the user. Every call is
+/// returned in `tool_calls`. When the model cannot be reached, tasks and
+/// memories matching the message are listed instead; when it fails after
+/// tools already ran, the error names those calls.
 /// 
 /// # Arguments
 /// * `payload` - The deserialized chat request containing user message and context
@@ -85,7 +104,122 @@ pub async fn handle_chat(
     State(state): State<AppState>,
     Json(payload): Json<ChatRequest>,
 ) -> Result<JsonResponse<ChatResponse>, (StatusCode, Json<ChatError>)> {
-    // Example: Search for tasks and memories containing keywords from the message
+    let client = std::env::var("OLLAMA_URL").map(OllamaClient::with_url).unwrap_or_default();
+    answer_chat(&state, &client, payload).await.map(JsonResponse)
+}
+
+/// Answer a chat message with the given Ollama client
+/// 
+/// # Returns
+/// * `Ok(ChatResponse)` - The model's answer, or matching tasks and memories
+///   when the model could not be reached
+/// * `Err((StatusCode, Json<ChatError>))` - The model failed after running tools
+async fn answer_chat(
+    state: &AppState,
+    client: &OllamaClient,
+    payload: ChatRequest,
+) -> Result<ChatResponse, (StatusCode, Json<ChatError>)> {
+    let conversation_id = payload
+        .session_id
+        .as_deref()
+        .and_then(|id| uuid::Uuid::parse_str(id).ok())
+        .unwrap_or_else(uuid::Uuid::new_v4);
+    let model = payload
+        .model
+        .clone()
+        .or_else(|| std::env::var("LEARA_MODEL").ok())
+        .unwrap_or_else(|| DEFAULT_MODEL.to_string());
+
+    let mut trace = Vec::new();
+    let message = match run_agent(state, client, &model, &payload, &conversation_id.to_string(), &mut trace).await {
+        Ok(answer) => answer,
+        Err(e) if trace.is_empty() => {
+            warn!("Chat model {} failed, answering from tasks and memories: {}", model, e);
+            keyword_response(state, &payload.message)
+        }
+        Err(e) => {
+            warn!("Chat model {} failed after {} tool calls: {}", model, trace.len(), e);
+            // Commands may have run and files changed, so say so instead of
+            // answering as if nothing happened
+            let calls: Vec<String> = trace
+                .iter()
+                .map(|call| format!("{} {} ({:?})", call.tool, call.arguments, call.status))
+                .collect();
+            return Err((StatusCode::BAD_GATEWAY, Json(ChatError {
+                error: format!(
+                    "The model failed after these tool calls had already run: {}. Check their effects before asking again. Error: {}",
+                    calls.join("; "),
+                    e
+                ),
+            })));
+        }
+    };
+
+    Ok(ChatResponse {
+        message,
+        conversation_id,
+        timestamp: chrono::Utc::now(),
+        context: payload.context,
+        tool_calls: trace,
+    })
+}
+
+/// Let the model answer a message, running the tools it asks for
+/// 
+/// # Arguments
+/// * `state` - Application state the tools work on
+/// * `client` - Ollama client
+/// * `model` - Model to use
+/// * `payload` - The user's message and context
+/// * `conversation_id` - Recorded with commands the model runs
+/// * `trace` - Receives every tool call, also when the model fails later
+/// 
+/// # Returns
+/// * `Ok(String)` - The model's final answer
+/// * `Err(anyhow::Error)` - The model could not be reached
+async fn run_agent(
+    state: &AppState,
+    client: &OllamaClient,
+    model: &str,
+    payload: &ChatRequest,
+    conversation_id: &str,
+    trace: &mut Vec<ToolCallTrace>,
+) -> anyhow::Result<String> {
+    let mut system = SYSTEM_PROMPT.to_string();
+    if let Some(context) = &payload.context {
+        system.push_str(&format!("\n\nContext: {}", context));
+    }
+    let mut messages = vec![
+        OllamaChatMessage::new("system", system),
+        OllamaChatMessage::new("user", payload.message.clone()),
+    ];
+    let tools = tools::definitions();
+
+    for _ in 0..MAX_TOOL_ROUNDS {
+        let reply = client.chat(model, &messages, &tools).await?;
+        if reply.tool_calls.is_empty() {
+            return Ok(reply.content);
+        }
+
+        let calls = reply.tool_calls.clone();
+        messages.push(reply);
+        for call in calls {
+            let traced = tools::call(state, &call.function.name, call.function.arguments, conversation_id).await;
+            let mut result = OllamaChatMessage::new("tool", traced.result.to_string());
+            result.tool_name = Some(traced.tool.clone());
+            messages.push(result);
+            trace.push(traced);
+        }
+    }
+
+    // Out of rounds: the model has to work with what it has
+    info!("Chat used {} tool rounds, asking for a final answer", MAX_TOOL_ROUNDS);
+    Ok(client.chat(model, &messages, &[]).await?.content)
+}
+
+/// List tasks and memories whose names appear in a message
+fn keyword_response(state: &AppState, message: &str) -> String {
+    // Search for tasks and memories containing keywords from the message
     let db = state.db.get().unwrap();
     let mut found_tasks = Vec::new();
     let mut found_memories = Vec::new();
@@ -97,9 +231,10 @@ pub async fn handle_chat(
         limit: Some(5),
         offset: Some(0),
         include_completed: Some(false),
+        ..Default::default()
     }) {
         for task in task_response.tasks.iter() {
-            if payload.message.to_lowercase().contains(&task.title.to_lowercase()) {
+            if message.to_lowercase().contains(&task.title.to_lowercase()) {
                 found_tasks.push(task.title.clone());
             }
         }
@@ -113,9 +248,10 @@ pub async fn handle_chat(
         limit: Some(5),
         offset: Some(0),
         include_expired: Some(false),
+        ..Default::default()
     }) {
         for memory in memory_response.memories.iter() {
-            if payload.message.to_lowercase().contains(&memory.key.to_lowercase()) {
+            if message.to_lowercase().contains(&memory.key.to_lowercase()) {
                 found_memories.push(memory.key.clone());
             }
         }
@@ -131,15 +267,7 @@ pub async fn handle_chat(
     if response_text.is_empty() {
         response_text = "I searched your tasks and memories but didn't find anything directly related. Please provide more details or create a new task or memory if needed.".to_string();
     }
-
-    let response = ChatResponse {
-        message: response_text,
-        conversation_id: uuid::Uuid::new_v4(),
-        timestamp: chrono::Utc::now(),
-        context: payload.context,
-    };
-
-    Ok(JsonResponse(response))
+    response_text
 }
 
 /// Handle memory-related chat queries
@@ -184,6 +312,7 @@ pub async fn handle_memory_query(
         limit: Some(10),
         offset: Some(0),
         include_expired: Some(false),
+        ..Default::default()
     }) {
         for memory in memory_response.memories.iter() {
             if payload.message.to_lowercase().contains(&memory.key.to_lowercase()) ||
@@ -200,6 +329,7 @@ pub async fn handle_memory_query(
         limit: Some(10),
         offset: Some(0),
         include_completed: Some(false),
+        ..Default::default()
     }) {
         for task in task_response.tasks.iter() {
             if payload.message.to_lowercase().contains(&task.title.to_lowercase()) ||
@@ -231,6 +361,7 @@ pub async fn handle_memory_query(
         conversation_id: uuid::Uuid::new_v4(),
         timestamp: chrono::Utc::now(),
         context: payload.context,
+        tool_calls: Vec::new(),
     };
 
     Ok(JsonResponse(response))
@@ -262,6 +393,7 @@ pub async fn get_conversation_summary(
         limit: Some(3),
         offset: Some(0),
         include_completed: Some(false),
+        ..Default::default()
     }) {
         for task in task_response.tasks.iter() {
             summary_lines.push(format!("• Task: {} (priority {})", task.title, task.priority));
@@ -275,6 +407,7 @@ pub async fn get_conversation_summary(
         limit: Some(3),
         offset: Some(0),
         include_expired: Some(false),
+        ..Default::default()
     }) {
         for memory in memory_response.memories.iter() {
             summary_lines.push(format!("• Memory: {} (category {})", memory.key, memory.category));
@@ -291,6 +424,7 @@ pub async fn get_conversation_summary(
         conversation_id: uuid::Uuid::new_v4(),
         timestamp: chrono::Utc::now(),
         context: Some("conversation_summary".to_string()),
+        tool_calls: Vec::new(),
     };
 
     Ok(JsonResponse(response))
@@ -302,4 +436,124 @@ pub fn create_router() -> Router<AppState> {
         .route("/", post(handle_chat))
         .route("/memory", post(handle_memory_query))
         .route("/summary", post(get_conversation_summary))
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::chat::ToolCallStatus;
+    use crate::testing::{app_state, TestDatabase};
+    use serde_json::{json, Value};
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    /// Reply of the stub for the n-th request, `None` for a server error
+    type Script = fn(usize, &Value) -> Option<Value>;
+
+    /// Ollama stand-in answering `/api/chat` from a script
+    ///
+    /// # Returns
+    /// * `(OllamaClient, Arc<Mutex<Vec<Value>>>)` - Client for the stub and the requests it received
+    async fn stub_ollama(script: Script) -> (OllamaClient, Arc<Mutex<Vec<Value>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        let app = Router::new().route(
+            "/api/chat",
+            post(move |Json(request): Json<Value>| async move {
+                let n = {
+                    let mut requests = received.lock().unwrap();
+                    requests.push(request.clone());
+                    requests.len() - 1
+                };
+                match script(n, &request) {
+                    Some(message) => Ok(Json(json!({ "message": message, "done": true }))),
+                    None => Err((StatusCode::INTERNAL_SERVER_ERROR, "model crashed")),
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (OllamaClient::with_url(url), requests)
+    }
+
+    fn request(message: &str) -> ChatRequest {
+        ChatRequest { message: message.to_string(), context: None, session_id: None, model: Some("stub".to_string()) }
+    }
+
+    fn tool_call(name: &str, arguments: Value) -> Value {
+        json!({ "role": "assistant", "content": "", "tool_calls": [{ "function": { "name": name, "arguments": arguments } }] })
+    }
+
+    fn answer(content: &str) -> Value {
+        json!({ "role": "assistant", "content": content })
+    }
+
+    #[tokio::test]
+    async fn tool_calls_are_run_and_their_results_returned_to_the_model() {
+        let db = TestDatabase::new();
+        let state = app_state(&db, PathBuf::from("/nonexistent/policy.toml")).await;
+        let (client, requests) = stub_ollama(|n, _| match n {
+            0 => Some(tool_call("run_command", json!({ "command": "echo", "args": ["from the tool"] }))),
+            _ => Some(answer("It printed the text")),
+        })
+        .await;
+
+        let response = answer_chat(&state, &client, request("print something")).await.unwrap();
+        assert_eq!(response.message, "It printed the text");
+        assert_eq!(response.tool_calls.len(), 1);
+        assert_eq!(response.tool_calls[0].tool, "run_command");
+        assert_eq!(response.tool_calls[0].status, ToolCallStatus::Ok);
+        assert_eq!(response.tool_calls[0].result["stdout"], "from the tool\n");
+
+        // The second request carries the assistant's call and the tool's result
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0]["tools"].as_array().unwrap().is_empty());
+        let messages = requests[1]["messages"].as_array().unwrap();
+        let result = messages.last().unwrap();
+        assert_eq!(result["role"], "tool");
+        assert_eq!(result["tool_name"], "run_command");
+        assert!(result["content"].as_str().unwrap().contains("from the tool"));
+    }
+
+    #[tokio::test]
+    async fn the_model_must_answer_after_the_last_tool_round() {
+        let db = TestDatabase::new();
+        let state = app_state(&db, PathBuf::from("/nonexistent/policy.toml")).await;
+        // Keeps calling tools for as long as it is offered any
+        let (client, requests) = stub_ollama(|_, request| match request.get("tools") {
+            Some(_) => Some(tool_call("search_memory", json!({ "query": "editor" }))),
+            None => Some(answer("Giving up on tools")),
+        })
+        .await;
+
+        let response = answer_chat(&state, &client, request("loop forever")).await.unwrap();
+        assert_eq!(response.message, "Giving up on tools");
+        assert_eq!(response.tool_calls.len(), MAX_TOOL_ROUNDS);
+        assert!(response.tool_calls.iter().all(|call| call.tool == "search_memory" && call.status == ToolCallStatus::Ok));
+        assert_eq!(requests.lock().unwrap().len(), MAX_TOOL_ROUNDS + 1);
+    }
+
+    #[tokio::test]
+    async fn failures_after_tool_calls_name_the_calls() {
+        let db = TestDatabase::new();
+        let state = app_state(&db, PathBuf::from("/nonexistent/policy.toml")).await;
+        let (client, _) = stub_ollama(|n, _| match n {
+            0 => Some(tool_call("run_command", json!({ "command": "echo", "args": ["half done"] }))),
+            _ => None,
+        })
+        .await;
+
+        let (status, Json(error)) = answer_chat(&state, &client, request("start something")).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert!(error.error.contains("run_command"), "{}", error.error);
+        assert!(error.error.contains("half done"), "{}", error.error);
+
+        // Without any tool calls the tasks and memories are searched instead
+        let (client, _) = stub_ollama(|_, _| None).await;
+        let response = answer_chat(&state, &client, request("anything")).await.unwrap();
+        assert!(response.tool_calls.is_empty());
+        assert!(response.message.contains("tasks and memories"));
+    }
+}
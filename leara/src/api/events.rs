@@ -0,0 +1,62 @@
+/*
+ * Leara AI Assistant - Events API Handler
+ *
+ * This module streams server-wide notifications, such as alerts changing
+ * state, to the frontend as server-sent events.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/api/events.rs
+ * Purpose: Server-sent event stream of notifications
+ */
+
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Router,
+};
+use futures::StreamExt;
+use tokio::sync::broadcast::error::RecvError;
+use crate::models::AppState;
+
+/// Stream notifications as server-sent events
+/// 
+/// Only events published after the client connects are sent. Each event is
+/// named after its `type` and carries the JSON `ServerEvent` as data.
+/// 
+/// # Returns
+/// * `Sse<...>` - Event stream that stays open until the client disconnects
+pub async fn stream_events(
+    State(state): State<AppState>,
+) -> Sse<impl futures::Stream<Item = Result<Event, std::convert::Infallible>>> {
+    let receiver = state.events.subscribe();
+    let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((event, receiver)),
+                // Slow clients skip what they missed
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+    .map(|event| Ok(Event::default().event(event.name()).json_data(&event).unwrap_or_default()));
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Create router for the event stream
+pub fn create_router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(stream_events))
+}
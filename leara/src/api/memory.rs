@@ -23,7 +23,8 @@
 // Import Axum web framework components for HTTP handling
 use axum::{
     extract::{Json, Query, Path, State},
-    http::StatusCode,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post, put},
     Router,
 };
@@ -34,6 +35,8 @@ use crate::models::memory::*;
 use crate::system::MemoryService;
 use crate::db::queries::*;
 use crate::models::AppState;
+use crate::utils::ical::{parse_ics, tasks_to_ics, validate_rrule, CalendarComponent};
+use crate::utils::todotxt::TaskListFormat;
 // Import tracing for structured logging
 use tracing::{info, error};
 
@@ -55,6 +58,8 @@ pub struct MemoryRequest {
     pub category: Option<String>,
     /// Optional expiration date for the memory entry
     pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Optional comma-separated tags for the memory entry
+    pub tags: Option<String>,
 }
 
 /// Request structure for searching memories
@@ -89,11 +94,12 @@ pub struct MemoryError {
 /// 
 /// # Returns
 /// * `Ok(Json<MemoryResponse>)` - Successfully retrieved memory entries
-/// * `Err((StatusCode, Json<MemoryError>))` - Error response with appropriate HTTP status
+/// * `Err((StatusCode, Json<MemoryError>))` - Error response with appropriate HTTP status (400 for an invalid `tag_match`)
 pub async fn get_memory(
     State(state): State<AppState>,
     Query(query): Query<MemoryQuery>,
 ) -> Result<Json<MemoryResponse>, (StatusCode, Json<MemoryError>)> {
+    crate::db::queries::match_all_tags(query.tag_match.as_deref()).map_err(|error| (StatusCode::BAD_REQUEST, Json(MemoryError { error })))?;
     let db = state.db.get().unwrap();
     match crate::db::queries::get_enhanced_memories(&db, &query) {
         Ok(response) => Ok(Json(response)),
@@ -128,6 +134,7 @@ pub async fn store_memory(
         updated_at: chrono::Utc::now(),
         expires_at: payload.expires_at,
         is_active: true,
+        tags: payload.tags.clone(),
     };
     match crate::db::queries::insert_enhanced_memory(&db, &memory) {
         Ok(_) => Ok(Json(MemoryOperationResponse {
@@ -159,9 +166,12 @@ pub async fn create_task(
             error: "Task title cannot be empty".to_string() 
         })));
     }
+    if let Some(ref rule) = payload.recurrence {
+        validate_rrule(rule).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, Json(MemoryError { error: e.to_string() })))?;
+    }
 
     let db = state.db.get().unwrap();
-    let task = Task {
+    let mut task = Task {
         id: 0,
         title: payload.title.clone(),
         description: payload.description.clone(),
@@ -173,25 +183,32 @@ pub async fn create_task(
         completed_at: None,
         context: payload.context.clone(),
         tags: payload.tags.clone(),
+        recurrence: payload.recurrence.clone(),
     };
     match crate::db::queries::insert_task(&db, &task) {
-        Ok(_) => Ok(Json(task)),
+        Ok(id) => {
+            task.id = id;
+            task.tags = payload.tags.as_deref().map(parse_tag_list).map(|names| names.join(","));
+            Ok(Json(task))
+        }
         Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(MemoryError { error: e.to_string() }))),
     }
 }
 
-/// Get all tasks with optional filtering
+/// Get all tasks with optional filtering, sorting and pagination
 /// 
 /// # Arguments
-/// * `query` - Query parameters for filtering tasks
+/// * `query` - Query parameters for filtering tasks (see `TaskQuery`)
 /// 
 /// # Returns
-/// * `Ok(Json<TaskResponse>)` - Tasks and total count
-/// * `Err((StatusCode, Json<MemoryError>))` - Error response
+/// * `Ok(Json<TaskResponse>)` - Tasks, total count and next page cursor
+/// * `Err((StatusCode, Json<MemoryError>))` - Error response (400 for an invalid sort, cursor or `tag_match`)
 pub async fn get_tasks(
     State(state): State<AppState>,
     Query(query): Query<TaskQuery>,
 ) -> Result<Json<TaskResponse>, (StatusCode, Json<MemoryError>)> {
+    crate::db::queries::task_order(&query).map_err(|error| (StatusCode::BAD_REQUEST, Json(MemoryError { error })))?;
+    crate::db::queries::match_all_tags(query.tag_match.as_deref()).map_err(|error| (StatusCode::BAD_REQUEST, Json(MemoryError { error })))?;
     let db = state.db.get().unwrap();
     match crate::db::queries::get_tasks(&db, &query) {
         Ok(response) => Ok(Json(response)),
@@ -311,6 +328,357 @@ pub async fn get_session_context(
     }
 }
 
+/// Export tasks with due dates as an iCalendar feed
+/// 
+/// Calendar applications can subscribe to this URL directly.
+/// 
+/// # Arguments
+/// * `query` - Component type and whether finished tasks are included
+/// 
+/// # Returns
+/// * `Ok(Response)` - `text/calendar` document
+/// * `Err((StatusCode, Json<MemoryError>))` - Error response
+/// 
+/// # Usage Examples
+/// ```bash
+/// curl http://localhost:3000/api/memory/tasks/calendar.ics
+/// curl "http://localhost:3000/api/memory/tasks/calendar.ics?component=vevent&include_completed=false"
+/// ```
+pub async fn export_tasks_ics(
+    State(state): State<AppState>,
+    Query(query): Query<TaskCalendarQuery>,
+) -> Result<Response, (StatusCode, Json<MemoryError>)> {
+    let component = match query.component.as_deref() {
+        Some(name) => CalendarComponent::from_str(name).ok_or_else(|| (StatusCode::BAD_REQUEST, Json(MemoryError {
+            error: format!("Unknown calendar component: {}", name),
+        })))?,
+        None => CalendarComponent::Todo,
+    };
+
+    let db = state.db.get().unwrap();
+    match crate::db::queries::get_tasks_with_due_dates(&db, query.include_completed.unwrap_or(true)) {
+        Ok(tasks) => Ok((
+            [
+                (header::CONTENT_TYPE, "text/calendar; charset=utf-8"),
+                (header::CONTENT_DISPOSITION, "inline; filename=\"leara-tasks.ics\""),
+            ],
+            tasks_to_ics(&tasks, component),
+        ).into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(MemoryError { error: e.to_string() }))),
+    }
+}
+
+/// Import tasks from an iCalendar document
+/// 
+/// Each VTODO and VEVENT in the request body becomes a new task, with
+/// priority, status, completion time, categories and recurrence carried over.
+/// Items exported by Leara (UID `task-<id>@leara`) update their task instead.
+/// 
+/// # Arguments
+/// * `body` - Raw iCalendar document
+/// 
+/// # Returns
+/// * `Ok(Json<TaskImportResponse>)` - Created and updated tasks
+/// * `Err((StatusCode, Json<MemoryError>))` - Error response (400 if the body is not iCalendar or has an invalid RRULE)
+pub async fn import_tasks_ics(
+    State(state): State<AppState>,
+    body: String,
+) -> Result<Json<TaskImportResponse>, (StatusCode, Json<MemoryError>)> {
+    let tasks = parse_ics(&body).map_err(|e| (StatusCode::BAD_REQUEST, Json(MemoryError { error: e.to_string() })))?;
+    let db = state.db.get().unwrap();
+    match crate::db::queries::insert_tasks(&db, tasks) {
+        Ok((tasks, updated)) => Ok(Json(TaskImportResponse { imported: tasks.len(), updated, tasks })),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(MemoryError { error: e.to_string() }))),
+    }
+}
+
+/// Resolve a plain-text task list format from a path segment
+fn task_list_format(name: &str) -> Result<TaskListFormat, (StatusCode, Json<MemoryError>)> {
+    TaskListFormat::from_str(name).ok_or_else(|| (StatusCode::NOT_FOUND, Json(MemoryError {
+        error: format!("Unknown task list format: {} (expected todotxt or markdown)", name),
+    })))
+}
+
+/// Export tasks as a todo.txt file or Markdown checklist
+/// 
+/// # Arguments
+/// * `format` - "todotxt" or "markdown"
+/// * `query` - Whether finished tasks are included
+/// 
+/// # Returns
+/// * `Ok(Response)` - Plain-text task list
+/// * `Err((StatusCode, Json<MemoryError>))` - Error response (404 for an unknown format)
+/// 
+/// # Usage Examples
+/// ```bash
+/// curl http://localhost:3000/api/memory/tasks/export/todotxt > todo.txt
+/// curl "http://localhost:3000/api/memory/tasks/export/markdown?include_completed=false"
+/// ```
+pub async fn export_task_list(
+    State(state): State<AppState>,
+    Path(format): Path<String>,
+    Query(query): Query<TaskListExportQuery>,
+) -> Result<Response, (StatusCode, Json<MemoryError>)> {
+    let format = task_list_format(&format)?;
+    let db = state.db.get().unwrap();
+    match crate::db::queries::get_all_tasks(&db, query.include_completed.unwrap_or(true)) {
+        Ok(tasks) => Ok(([(header::CONTENT_TYPE, format.content_type())], format.render(&tasks)).into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(MemoryError { error: e.to_string() }))),
+    }
+}
+
+/// Import tasks from a todo.txt file or Markdown checklist
+/// 
+/// # Arguments
+/// * `format` - "todotxt" or "markdown"
+/// * `body` - Raw task list
+/// 
+/// # Returns
+/// * `Ok(Json<TaskImportResponse>)` - Created tasks
+/// * `Err((StatusCode, Json<MemoryError>))` - Error response
+pub async fn import_task_list(
+    State(state): State<AppState>,
+    Path(format): Path<String>,
+    body: String,
+) -> Result<Json<TaskImportResponse>, (StatusCode, Json<MemoryError>)> {
+    let format = task_list_format(&format)?;
+    let db = state.db.get().unwrap();
+    match crate::db::queries::insert_tasks(&db, format.parse(&body)) {
+        Ok((tasks, updated)) => Ok(Json(TaskImportResponse { imported: tasks.len(), updated, tasks })),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(MemoryError { error: e.to_string() }))),
+    }
+}
+
+/// List all tags with usage counts
+/// 
+/// # Returns
+/// * `Ok(Json<TagResponse>)` - Tags with task and memory counts
+/// * `Err((StatusCode, Json<MemoryError>))` - Error response
+pub async fn get_tags(
+    State(state): State<AppState>,
+) -> Result<Json<TagResponse>, (StatusCode, Json<MemoryError>)> {
+    let db = state.db.get().unwrap();
+    match crate::db::queries::get_tags(&db) {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(MemoryError { error: e.to_string() }))),
+    }
+}
+
+/// Rename a tag, merging it into an existing tag of the same name
+/// 
+/// # Arguments
+/// * `name` - Current tag name
+/// * `payload` - Rename request with the new name
+/// 
+/// # Returns
+/// * `Ok(Json<MemoryOperationResponse>)` - Success response
+/// * `Err((StatusCode, Json<MemoryError>))` - Error response (404 if the tag does not exist)
+pub async fn rename_tag(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(payload): Json<TagRenameRequest>,
+) -> Result<Json<MemoryOperationResponse>, (StatusCode, Json<MemoryError>)> {
+    let (Some(old_name), Some(new_name)) = (normalize_tag(&name), normalize_tag(&payload.name)) else {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(MemoryError {
+            error: "Tag names cannot be empty".to_string(),
+        })));
+    };
+
+    let db = state.db.get().unwrap();
+    match crate::db::queries::rename_tag(&db, &old_name, &new_name) {
+        Ok(_) => Ok(Json(MemoryOperationResponse {
+            success: true,
+            message: format!("Renamed tag {} to {}", old_name, new_name),
+        })),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err((StatusCode::NOT_FOUND, Json(MemoryError {
+            error: format!("Tag not found: {}", old_name),
+        }))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(MemoryError { error: e.to_string() }))),
+    }
+}
+
+/// Merge several tags into one
+/// 
+/// # Arguments
+/// * `payload` - Merge request with source tags and the target tag
+/// 
+/// # Returns
+/// * `Ok(Json<MemoryOperationResponse>)` - Success response
+/// * `Err((StatusCode, Json<MemoryError>))` - Error response
+pub async fn merge_tags(
+    State(state): State<AppState>,
+    Json(payload): Json<TagMergeRequest>,
+) -> Result<Json<MemoryOperationResponse>, (StatusCode, Json<MemoryError>)> {
+    let Some(target) = normalize_tag(&payload.target) else {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(MemoryError {
+            error: "Target tag cannot be empty".to_string(),
+        })));
+    };
+    let sources: Vec<String> = payload.sources.iter().filter_map(|s| normalize_tag(s)).collect();
+
+    let db = state.db.get().unwrap();
+    match crate::db::queries::merge_tags(&db, &sources, &target) {
+        Ok(merged) => Ok(Json(MemoryOperationResponse {
+            success: true,
+            message: format!("Merged {} tags into {}", merged, target),
+        })),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(MemoryError { error: e.to_string() }))),
+    }
+}
+
+/// Parse the `period` query parameter, defaulting to daily
+fn stats_period(period: Option<&str>) -> Result<StatsPeriod, (StatusCode, Json<MemoryError>)> {
+    match period {
+        None => Ok(StatsPeriod::Day),
+        Some(p) => StatsPeriod::from_str(p).ok_or_else(|| (StatusCode::BAD_REQUEST, Json(MemoryError {
+            error: format!("Unsupported period: {} (expected day or week)", p),
+        }))),
+    }
+}
+
+/// Start a timer on a task
+/// 
+/// # Arguments
+/// * `id` - Task ID
+/// * `payload` - Optional note for the session
+/// 
+/// # Returns
+/// * `Ok(Json<TimeEntry>)` - The running entry
+/// * `Err((StatusCode, Json<MemoryError>))` - 404 for unknown tasks, 409 if a timer is already
+///   running or the task is completed or cancelled
+pub async fn start_task_timer(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    payload: Option<Json<TimerRequest>>,
+) -> Result<Json<TimeEntry>, (StatusCode, Json<MemoryError>)> {
+    let note = payload.and_then(|Json(p)| p.note);
+    let db = state.db.get().unwrap();
+    let internal = |e: rusqlite::Error| (StatusCode::INTERNAL_SERVER_ERROR, Json(MemoryError { error: e.to_string() }));
+
+    let Some(task) = get_task_by_id(&db, id).map_err(internal)? else {
+        return Err((StatusCode::NOT_FOUND, Json(MemoryError { error: format!("Task not found: {}", id) })));
+    };
+    if task.status == "completed" || task.status == "cancelled" {
+        return Err((StatusCode::CONFLICT, Json(MemoryError { error: format!("Task {} is {}", id, task.status) })));
+    }
+    if let Some(running) = get_running_time_entry(&db, id).map_err(internal)? {
+        return Err((StatusCode::CONFLICT, Json(MemoryError {
+            error: format!("Timer already running on task {} since {}", id, running.started_at.to_rfc3339()),
+        })));
+    }
+
+    // Another request may have started one or finished the task since the checks
+    let Some(entry) = start_time_entry(&db, id, note.as_deref()).map_err(internal)? else {
+        return Err((StatusCode::CONFLICT, Json(MemoryError {
+            error: format!("Timer already running on task {} or the task was finished", id),
+        })));
+    };
+    info!("Started timer on task {}", id);
+    Ok(Json(entry))
+}
+
+/// Stop the running timer of a task
+/// 
+/// # Arguments
+/// * `id` - Task ID
+/// * `payload` - Optional note replacing the session's note
+/// 
+/// # Returns
+/// * `Ok(Json<TimeEntry>)` - The finished entry
+/// * `Err((StatusCode, Json<MemoryError>))` - 404 if no timer is running
+pub async fn stop_task_timer(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    payload: Option<Json<TimerRequest>>,
+) -> Result<Json<TimeEntry>, (StatusCode, Json<MemoryError>)> {
+    let note = payload.and_then(|Json(p)| p.note);
+    let db = state.db.get().unwrap();
+    let internal = |e: rusqlite::Error| (StatusCode::INTERNAL_SERVER_ERROR, Json(MemoryError { error: e.to_string() }));
+
+    let Some(running) = get_running_time_entry(&db, id).map_err(internal)? else {
+        return Err((StatusCode::NOT_FOUND, Json(MemoryError { error: format!("No timer running on task {}", id) })));
+    };
+
+    let entry = stop_time_entry(&db, running.id, note.as_deref()).map_err(internal)?;
+    info!("Stopped timer on task {} after {}s", id, entry.duration_seconds);
+    Ok(Json(entry))
+}
+
+/// List the time entries of a task
+/// 
+/// # Arguments
+/// * `id` - Task ID
+/// 
+/// # Returns
+/// * `Ok(Json<TimeEntryResponse>)` - Entries, newest first, with the total tracked time
+/// * `Err((StatusCode, Json<MemoryError>))` - Error response
+pub async fn get_task_time(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<TimeEntryResponse>, (StatusCode, Json<MemoryError>)> {
+    let db = state.db.get().unwrap();
+    match get_time_entries(&db, id) {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(MemoryError { error: e.to_string() }))),
+    }
+}
+
+/// Get productivity statistics
+/// 
+/// # Arguments
+/// * `query` - Bucket period and reporting window
+/// 
+/// # Returns
+/// * `Ok(Json<TaskStats>)` - Completion series, lead time, overdue count and tracked time
+/// * `Err((StatusCode, Json<MemoryError>))` - Error response (400 for invalid parameters)
+pub async fn get_task_stats(
+    State(state): State<AppState>,
+    Query(query): Query<TaskStatsQuery>,
+) -> Result<Json<TaskStats>, (StatusCode, Json<MemoryError>)> {
+    let period = stats_period(query.period.as_deref())?;
+    let until = query.until.unwrap_or_else(chrono::Utc::now);
+    let since = query.since.unwrap_or_else(|| match period {
+        StatsPeriod::Day => until - chrono::Duration::days(30),
+        StatsPeriod::Week => until - chrono::Duration::weeks(12),
+    });
+    if since >= until {
+        return Err((StatusCode::BAD_REQUEST, Json(MemoryError {
+            error: "since must be before until".to_string(),
+        })));
+    }
+    if until - since > period.duration() * MAX_STATS_BUCKETS {
+        return Err((StatusCode::BAD_REQUEST, Json(MemoryError {
+            error: format!("The window may span at most {} {}s", MAX_STATS_BUCKETS, period.as_str()),
+        })));
+    }
+
+    let db = state.db.get().unwrap();
+    match crate::db::queries::get_task_stats(&db, period, since, until) {
+        Ok(stats) => Ok(Json(stats)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(MemoryError { error: e.to_string() }))),
+    }
+}
+
+/// Get a daily or weekly digest of task activity
+/// 
+/// # Arguments
+/// * `query` - Digest period
+/// 
+/// # Returns
+/// * `Ok(Json<TaskDigest>)` - Completed, overdue and upcoming tasks with a text summary
+/// * `Err((StatusCode, Json<MemoryError>))` - Error response (400 for an invalid period)
+pub async fn get_task_digest(
+    State(state): State<AppState>,
+    Query(query): Query<TaskDigestQuery>,
+) -> Result<Json<TaskDigest>, (StatusCode, Json<MemoryError>)> {
+    let period = stats_period(query.period.as_deref())?;
+    let db = state.db.get().unwrap();
+    match crate::db::queries::get_task_digest(&db, period) {
+        Ok(digest) => Ok(Json(digest)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(MemoryError { error: e.to_string() }))),
+    }
+}
+
 /// Create router for memory-related endpoints
 pub fn create_router() -> Router<AppState> {
     Router::new()
@@ -321,6 +689,18 @@ pub fn create_router() -> Router<AppState> {
         .route("/tasks", get(get_tasks))
         .route("/tasks", post(create_task))
         .route("/tasks/:id/status", put(update_task_status))
+        .route("/tasks/:id/timer/start", post(start_task_timer))
+        .route("/tasks/:id/timer/stop", post(stop_task_timer))
+        .route("/tasks/:id/time", get(get_task_time))
+        .route("/tasks/stats", get(get_task_stats))
+        .route("/tasks/digest", get(get_task_digest))
+        .route("/tasks/calendar.ics", get(export_tasks_ics))
+        .route("/tasks/import/ics", post(import_tasks_ics))
+        .route("/tasks/export/:format", get(export_task_list))
+        .route("/tasks/import/:format", post(import_task_list))
+        .route("/tags", get(get_tags))
+        .route("/tags/merge", post(merge_tags))
+        .route("/tags/:name", put(rename_tag))
         .route("/context", post(store_session_context))
         .route("/context/:session_id", get(get_session_context))
 } 
\ No newline at end of file
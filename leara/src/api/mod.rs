@@ -21,6 +21,7 @@
  */
 
 use axum::{
+    middleware,
     routing::{get, post, put},
     Router,
 };
@@ -29,8 +30,10 @@ use crate::models::AppState;
 
 pub mod health;
 pub mod chat;
+pub mod events;
 pub mod system;
 pub mod memory;
+pub mod origin;
 
 use health::health_check;
 use chat::{handle_chat, handle_memory_query, get_conversation_summary};
@@ -45,12 +48,18 @@ use memory::{
 /// This function sets up all the API routes for the Leara AI Assistant,
 /// including chat, memory, system, and health endpoints.
 /// 
+/// # Arguments
+/// * `origin` - Origin of the frontend; requests that change state or open
+///   WebSockets from any other origin are refused
+/// 
 /// # Returns
 /// * `Router<AppState>` - Configured Axum router with all API endpoints
-pub fn create_router() -> Router<AppState> {
+pub fn create_router(origin: origin::FrontendOrigin) -> Router<AppState> {
     Router::new()
         .nest("/health", health::create_router())
         .nest("/chat", chat::create_router())
         .nest("/memory", memory::create_router())
         .nest("/system", system::create_router())
+        .nest("/events", events::create_router())
+        .layer(middleware::from_fn_with_state(origin, origin::require_frontend_origin))
 } 
\ No newline at end of file
@@ -0,0 +1,166 @@
+/*
+ * Leara AI Assistant - Request Origin Checks
+ *
+ * This module keeps other web sites from driving the API. The server only
+ * listens on 127.0.0.1, but any page open in the user's browser can still
+ * send requests there; CORS does not stop a request from being sent, and
+ * WebSocket upgrades are not covered by it at all.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/api/origin.rs
+ * Purpose: Frontend origin checks and CORS configuration
+ */
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tower_http::cors::{AllowHeaders, CorsLayer};
+use tracing::warn;
+use crate::api::system::CommandError;
+
+/// Environment variable naming the origin the frontend is served from
+pub const FRONTEND_ORIGIN_ENV: &str = "LEARA_FRONTEND_ORIGIN";
+
+/// Origin of the Vite development server
+pub const DEFAULT_FRONTEND_ORIGIN: &str = "http://localhost:5173";
+
+/// The only origin browsers may call the API from
+#[derive(Debug, Clone)]
+pub struct FrontendOrigin(HeaderValue);
+
+impl FrontendOrigin {
+    /// Read `LEARA_FRONTEND_ORIGIN`, falling back to the Vite development server
+    pub fn from_env() -> Self {
+        let origin = std::env::var(FRONTEND_ORIGIN_ENV).unwrap_or_else(|_| DEFAULT_FRONTEND_ORIGIN.to_string());
+        Self::new(&origin).unwrap_or_else(|| {
+            warn!("Invalid {} {:?}, using {}", FRONTEND_ORIGIN_ENV, origin, DEFAULT_FRONTEND_ORIGIN);
+            Self::new(DEFAULT_FRONTEND_ORIGIN).expect("default frontend origin is valid")
+        })
+    }
+
+    /// Origin such as `http://localhost:5173`, without a trailing slash
+    ///
+    /// # Returns
+    /// * `Some(FrontendOrigin)` - The origin
+    /// * `None` - Not usable as a header value
+    pub fn new(origin: &str) -> Option<Self> {
+        HeaderValue::from_str(origin.trim().trim_end_matches('/')).ok().map(FrontendOrigin)
+    }
+
+    /// CORS layer letting only the frontend read responses
+    pub fn cors(&self) -> CorsLayer {
+        CorsLayer::new()
+            .allow_origin(self.0.clone())
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            .allow_headers(AllowHeaders::mirror_request())
+    }
+}
+
+/// Refuse state-changing requests and WebSocket upgrades from other origins
+///
+/// Browsers send `Origin` with every such request, so a mismatch means a
+/// page other than the frontend made it. Requests without the header come
+/// from programs such as curl and are let through.
+///
+/// # Returns
+/// * `Response` - The route's response, or `403 Forbidden`
+pub async fn require_frontend_origin(State(allowed): State<FrontendOrigin>, request: Request, next: Next) -> Response {
+    let safe_method = matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let upgrade = request.headers().contains_key(header::UPGRADE);
+    if safe_method && !upgrade {
+        return next.run(request).await;
+    }
+    match request.headers().get(header::ORIGIN) {
+        Some(origin) if *origin != allowed.0 => {
+            warn!("Refused {} {} from origin {:?}", request.method(), request.uri().path(), origin);
+            (
+                StatusCode::FORBIDDEN,
+                Json(CommandError {
+                    error: format!("Requests from {} are not allowed", origin.to_str().unwrap_or("this origin")),
+                    blocked: true,
+                }),
+            )
+                .into_response()
+        }
+        _ => next.run(request).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app_state, TempTree, TestDatabase};
+
+    const EVIL: &str = "https://evil.example";
+
+    /// Serve the API on an ephemeral port and return its base URL
+    async fn serve(origin: &FrontendOrigin) -> String {
+        let db = TestDatabase::new();
+        let dir = TempTree::new();
+        let state = app_state(&db, dir.join("policy.toml")).await;
+        let app = axum::Router::new()
+            .nest("/api", crate::api::create_router(origin.clone()).with_state(state))
+            .layer(origin.cors());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _keep = (db, dir);
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}/api", addr)
+    }
+
+    #[tokio::test]
+    async fn other_origins_cannot_open_terminals_or_approve_commands() {
+        let origin = FrontendOrigin::new(DEFAULT_FRONTEND_ORIGIN).unwrap();
+        let base = serve(&origin).await;
+        let client = reqwest::Client::new();
+
+        let upgrade = |from: &str| {
+            client
+                .get(format!("{}/system/terminal", base))
+                .header("Origin", from)
+                .header("Connection", "Upgrade")
+                .header("Upgrade", "websocket")
+                .header("Sec-WebSocket-Version", "13")
+                .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+                .send()
+        };
+        assert_eq!(upgrade(EVIL).await.unwrap().status(), reqwest::StatusCode::FORBIDDEN);
+
+        let approve = |from: Option<&str>| {
+            let request = client.post(format!("{}/system/pending/1/approve", base));
+            match from {
+                Some(from) => request.header("Origin", from).send(),
+                None => request.send(),
+            }
+        };
+        let refused = approve(Some(EVIL)).await.unwrap();
+        assert_eq!(refused.status(), reqwest::StatusCode::FORBIDDEN);
+        assert!(refused.json::<CommandError>().await.unwrap().blocked);
+        // The frontend and non-browser clients reach the handler
+        assert_eq!(approve(Some(DEFAULT_FRONTEND_ORIGIN)).await.unwrap().status(), reqwest::StatusCode::NOT_FOUND);
+        assert_eq!(approve(None).await.unwrap().status(), reqwest::StatusCode::NOT_FOUND);
+
+        // Reading is allowed, but CORS only exposes responses to the frontend
+        for from in [EVIL, DEFAULT_FRONTEND_ORIGIN] {
+            let read = client.get(format!("{}/system/pending", base)).header("Origin", from).send().await.unwrap();
+            assert_eq!(read.headers().get("access-control-allow-origin").unwrap(), DEFAULT_FRONTEND_ORIGIN);
+        }
+    }
+}
@@ -22,27 +22,56 @@
 
 // Import Axum web framework components for HTTP handling
 use axum::{
-    extract::{Query, State},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
     http::StatusCode,
-    response::Json,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
     routing::{get, post},
     Router,
 };
 // Import Serde for JSON serialization/deserialization
 use serde::{Deserialize, Serialize};
 // Import our local system models
-use crate::models::system::SystemInfo;
+use crate::models::system::{
+    Alert, AlertRule, AlertRuleRequest, AlertStatus, ContentMatch, DiskInfo, DnsConfig, FileChecksum, FileContent, FileEntry, FileKind,
+    HardwareSensors, IndexRoot, IndexStatus, IndexedFile, JournalEntry, LatencyProbe,
+    NetworkInterfaceDetails, NetworkRoute, SocketInfo, SystemdUnit, UnitStatus, MemoryUsage, MetricResolution, MetricsResponse, NetworkInterfaceInfo, ProcessDetails, ProcessInfo, ProcessSortField, ProcessTreeNode,
+    ResourceUsage, SystemInfo,
+};
 use crate::models::AppState;
+use crate::system::{files, indexer, network, sensors};
+use crate::system::files::{FileAccess, FilesConfig};
+use crate::system::processes::{parse_signal, process_details, process_start_ticks, process_tree, sort_processes, ProcessFilter};
+use crate::system::systemd::{
+    list_units_args, parse_journal, parse_priority, parse_unit_action, parse_unit_list, parse_unit_status,
+    show_unit_args, validate_unit_name, JournalFilter,
+};
+use crate::system::policy::{normalize_lexically, PolicyAction, PolicyConfig, PolicyDecision, PolicySource};
 // Import tracing for structured logging
-use tracing::info;
+use tracing::{info, warn};
 use std::process::Command;
-use std::collections::HashSet;
-use tokio::process::Command as TokioCommand;
-use std::fs;
-use std::path::Path;
+use crate::system::alerts::validate_rule;
+use crate::system::apps::{query_terms, search, App, AppMatch, CategoryFacet};
+use crate::system::environment::{CommandEnvironment, REDACTED};
+use crate::system::executor::{run_limited, shell_command, spawn_detached, truncate_output, CappedBuffer, CommandSpec};
+use crate::system::jobs::{Job, JobEvent, JobEventKind, JobInfo, JobStatus};
+use crate::system::terminal::{plain_text, TerminalProcess, TerminalSize, TERM};
+use futures::StreamExt;
+use std::path::PathBuf;
 use std::env;
-use serde_json::json;
-use std::os::unix::fs::PermissionsExt;
+use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
+
+/// Applications returned per page of a search by default
+const DEFAULT_APP_SEARCH_LIMIT: usize = 20;
+
+/// Largest page of application search results
+const MAX_APP_SEARCH_LIMIT: usize = 200;
 
 /// Retrieve comprehensive system information and hardware statistics
 /// 
@@ -86,333 +115,3562 @@ use std::os::unix::fs::PermissionsExt;
 /// ```
 /// 
 /// # Performance Considerations
-/// - The sysinfo library performs system calls to gather information
-/// - Response time may vary depending on system load and hardware
-/// - Consider caching for high-frequency monitoring scenarios
-pub async fn get_system_info() -> (StatusCode, Json<SystemInfo>) {
+/// - Served from the background sampler, so no system calls are made per request
+pub async fn get_system_info(State(state): State<AppState>) -> (StatusCode, Json<SystemInfo>) {
     // Log the system information request for monitoring and debugging
     info!("Fetching system information");
     
-    // Initialize sysinfo with all system components
-    // This creates a comprehensive system information gatherer
-    let mut sys = sysinfo::System::new_all();
-    // Refresh all system information to get current values
-    // This ensures we have the most up-to-date system statistics
-    sys.refresh_all();
-
-    // Build the system information response
-    // Each field is gathered from the appropriate sysinfo method
-    let system_info = SystemInfo {
-        // Get the system hostname (e.g., "nyxaria", "server-01")
-        hostname: sysinfo::System::host_name().unwrap_or_else(|| "unknown".to_string()),
-        // Get the operating system name (e.g., "Linux", "Windows", "macOS")
-        os_name: sysinfo::System::name().unwrap_or_else(|| "unknown".to_string()),
-        // Get the OS version string (e.g., "6.15.3-zen1-1-zen", "10.0.19045")
-        os_version: sysinfo::System::os_version().unwrap_or_else(|| "unknown".to_string()),
-        // Get the kernel version (e.g., "6.15.3-zen1-1-zen", "NT 10.0")
-        kernel_version: sysinfo::System::kernel_version().unwrap_or_else(|| "unknown".to_string()),
-        // Get the number of physical CPU cores (not logical cores/hyperthreading)
-        cpu_count: sys.physical_core_count().unwrap_or(0),
-        // Get total system memory in bytes (RAM)
-        total_memory: sys.total_memory(),
-        // Get system uptime in seconds since last boot
-        uptime: sysinfo::System::uptime(),
-    };
-
-    // Return HTTP 200 OK with comprehensive system information
-    // This provides a complete snapshot of the current system state
-    (StatusCode::OK, Json(system_info))
+    // Return HTTP 200 OK with host, operating system and hardware details
+    (StatusCode::OK, Json(state.sampler.info().as_ref().clone()))
 }
 
-/// Request structure for executing system commands
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ExecuteCommandRequest {
-    /// The command to execute
-    pub command: String,
-    /// Optional arguments for the command
-    pub args: Option<Vec<String>>,
-    /// Working directory for the command (optional)
-    pub working_dir: Option<String>,
-    /// Whether to require user confirmation for this command
-    pub require_confirmation: Option<bool>,
+/// Live resource usage: CPU per core, memory and swap, load average, disks and network
+/// 
+/// Returns the latest background sample; CPU usage and network rates are
+/// measured over the sampling interval.
+/// 
+/// # Returns
+/// * `Json<ResourceUsage>` - Current resource usage
+pub async fn get_resource_usage(State(state): State<AppState>) -> Json<ResourceUsage> {
+    Json(state.sampler.latest().usage.clone())
 }
 
-/// Response structure for command execution
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ExecuteCommandResponse {
-    /// Whether the command executed successfully
-    pub success: bool,
-    /// The command that was executed
-    pub command: String,
-    /// Standard output from the command
-    pub stdout: String,
-    /// Standard error from the command
-    pub stderr: String,
-    /// Exit code from the command
-    pub exit_code: i32,
-    /// Execution time in milliseconds
-    pub execution_time_ms: u64,
-    /// Timestamp when the command was executed
-    pub timestamp: String,
+/// Recent resource usage samples, oldest first
+/// 
+/// # Arguments
+/// * `query` - How many samples to return (default: all that are kept)
+/// 
+/// # Returns
+/// * `Json<ResourceHistoryResponse>` - Samples and the interval between them
+pub async fn get_resource_history(
+    State(state): State<AppState>,
+    Query(query): Query<ResourceHistoryQuery>,
+) -> Json<ResourceHistoryResponse> {
+    let samples = state.sampler.history(query.limit.unwrap_or(usize::MAX));
+    Json(ResourceHistoryResponse {
+        interval_secs: state.sampler.interval().as_secs(),
+        samples: samples.iter().map(|sample| sample.usage.clone()).collect(),
+    })
 }
 
-/// Error response for command execution
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CommandError {
-    /// Human-readable error message
-    pub error: String,
-    /// Whether the command was blocked for safety reasons
-    pub blocked: bool,
+/// Memory and swap usage
+/// 
+/// # Returns
+/// * `Json<MemoryUsage>` - Memory and swap, in bytes
+pub async fn get_memory_info(State(state): State<AppState>) -> Json<MemoryUsage> {
+    let sample = state.sampler.latest();
+    Json(MemoryUsage { memory: sample.usage.memory.clone(), swap: sample.usage.swap.clone() })
 }
 
-/// Command history entry
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CommandHistory {
-    pub id: i64,
-    pub command: String,
-    pub args: Option<String>,
-    pub working_dir: Option<String>,
-    pub success: bool,
-    pub exit_code: i32,
-    pub execution_time_ms: u64,
-    pub timestamp: String,
-    pub user_confirmed: bool,
+/// Usage of every mounted disk
+/// 
+/// # Returns
+/// * `Json<Vec<DiskInfo>>` - One entry per mount point
+pub async fn get_disk_info(State(state): State<AppState>) -> Json<Vec<DiskInfo>> {
+    Json(state.sampler.latest().disks.clone())
 }
 
-/// Query structure for command history
+/// Traffic counters and rates of every network interface
+/// 
+/// # Returns
+/// * `Json<Vec<NetworkInterfaceInfo>>` - Bytes, packets and errors since boot per interface
+pub async fn get_network_info(State(state): State<AppState>) -> Json<Vec<NetworkInterfaceInfo>> {
+    Json(state.sampler.latest().networks.clone())
+}
+
+/// Query parameters for the socket list
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SocketListQuery {
+    /// "tcp" or "udp", over IPv4 and IPv6
+    pub protocol: Option<String>,
+    /// Socket state, e.g. "listen" or "established"
+    pub state: Option<String>,
+    /// Local or remote port
+    pub port: Option<u16>,
+    /// Case-insensitive match against the owning process
+    pub process: Option<String>,
+}
+
+/// Response structure for the socket list
 #[derive(Debug, Serialize, Deserialize)]
-pub struct CommandHistoryQuery {
-    pub limit: Option<i32>,
-    pub offset: Option<i32>,
-    pub success_only: Option<bool>,
+pub struct SocketListResponse {
+    pub sockets: Vec<SocketInfo>,
+    pub total: usize,
+    /// Matching sockets by state
+    pub states: BTreeMap<String, usize>,
 }
 
-/// Response structure for command history
+/// Request structure for a latency probe
 #[derive(Debug, Serialize, Deserialize)]
-pub struct CommandHistoryResponse {
-    pub commands: Vec<CommandHistory>,
-    pub total: i64,
+pub struct LatencyProbeRequest {
+    /// Host name or address
+    pub host: String,
+    /// TCP port to connect to (default 443)
+    pub port: Option<u16>,
+    /// Connections to time (default 4, at most 10)
+    pub count: Option<u32>,
+    /// Limit for each connection in milliseconds (default 2000, at most 10000)
+    pub timeout_ms: Option<u64>,
 }
 
-// Whitelist of safe commands that don't require confirmation
-const SAFE_COMMANDS: &[&str] = &[
-    "ls", "pwd", "whoami", "date", "uptime", "free", "df", "ps", "top", "htop",
-    "cat", "head", "tail", "grep", "find", "locate", "which", "whereis",
-    "echo", "printf", "wc", "sort", "uniq", "cut", "paste", "join",
-    "mkdir", "rmdir", "touch", "cp", "mv", "rm", "chmod", "chown",
-    "git", "npm", "cargo", "python", "node", "bash", "sh", "zsh", "fish",
-    "code", "vim", "nano", "emacs", "firefox", "chrome", "chromium",
-    "xdg-open", "open", "start", "xdg-mime", "file", "type", "stat",
-    "du", "tree", "ncdu", "rsync", "scp", "ssh", "curl", "wget",
-    "ping", "traceroute", "netstat", "ss", "ip", "ifconfig", "route",
-    "systemctl", "service", "journalctl", "log", "dmesg", "lspci", "lsusb",
-];
+/// Network interfaces with their addresses, link state and current rates
+/// 
+/// # Returns
+/// * `Json<Vec<NetworkInterfaceDetails>>` - Interfaces sorted by name
+pub async fn get_network_interfaces(State(state): State<AppState>) -> Json<Vec<NetworkInterfaceDetails>> {
+    let mut interfaces = tokio::task::spawn_blocking(|| {
+        network::interfaces(std::path::Path::new(sensors::SYSFS_CLASS), network::interface_addresses())
+    })
+    .await
+    .unwrap_or_default();
+    let sample = state.sampler.latest();
+    for interface in &mut interfaces {
+        if let Some(sampled) = sample.networks.iter().find(|sampled| sampled.name == interface.name) {
+            interface.receive_rate = sampled.receive_rate;
+            interface.transmit_rate = sampled.transmit_rate;
+        }
+    }
+    Json(interfaces)
+}
 
-// Dangerous commands that should be blocked
-const DANGEROUS_COMMANDS: &[&str] = &[
-    "rm -rf /", "rm -rf /*", "dd if=/dev/zero", "mkfs", "fdisk", "parted",
-    "chmod 777", "chown root", "sudo rm", "sudo dd", "sudo mkfs",
-    "format", "del", "erase", "wipe", "shred", "srm",
-];
+/// IPv4 and IPv6 routing table
+/// 
+/// # Returns
+/// * `Json<Vec<NetworkRoute>>` - Routes as the kernel lists them
+pub async fn get_network_routes() -> Json<Vec<NetworkRoute>> {
+    Json(tokio::task::spawn_blocking(|| network::routes(std::path::Path::new(network::PROC))).await.unwrap_or_default())
+}
 
-/// Execute a system command with safety measures
+/// Open TCP and UDP sockets with the processes that own them
 /// 
-/// This endpoint allows Leara to execute system commands with proper
-/// safety measures including command whitelisting and user confirmation.
+/// Owners are only known for processes the server may inspect.
 /// 
 /// # Arguments
-/// * `payload` - Command execution request with safety parameters
+/// * `query` - Protocol, state, port and process filters
 /// 
 /// # Returns
-/// * `Ok(Json<ExecuteCommandResponse>)` - Command execution result
-/// * `Err((StatusCode, Json<CommandError>))` - Error response
-pub async fn execute_command(
-    State(state): State<AppState>,
-    Json(payload): Json<ExecuteCommandRequest>,
-) -> Result<Json<ExecuteCommandResponse>, (StatusCode, Json<CommandError>)> {
-    let start_time = std::time::Instant::now();
-    
-    // Check if command is in dangerous commands list
-    for dangerous in DANGEROUS_COMMANDS {
-        if payload.command.contains(dangerous) {
-            return Err((StatusCode::FORBIDDEN, Json(CommandError {
-                error: format!("Command blocked for safety: {}", payload.command),
-                blocked: true,
-            })));
-        }
-    }
-    
-    // Check if command requires confirmation (not in safe list)
-    let is_safe = SAFE_COMMANDS.contains(&payload.command.as_str());
-    let require_confirmation = payload.require_confirmation.unwrap_or(!is_safe);
-    
-    if require_confirmation {
-        // For now, we'll allow safe commands but require confirmation for others
-        // In a real implementation, you'd want to implement a confirmation system
-        if !is_safe {
+/// * `Ok(Json<SocketListResponse>)` - Matching sockets, by protocol and local port
+/// * `Err((StatusCode, Json<CommandError>))` - 400 for an unknown protocol
+pub async fn get_network_sockets(
+    Query(query): Query<SocketListQuery>,
+) -> Result<Json<SocketListResponse>, (StatusCode, Json<CommandError>)> {
+    if let Some(ref protocol) = query.protocol {
+        if protocol != "tcp" && protocol != "udp" {
             return Err((StatusCode::BAD_REQUEST, Json(CommandError {
-                error: format!("Command requires user confirmation: {}", payload.command),
+                error: format!("Unknown protocol {:?}; use tcp or udp", protocol),
                 blocked: false,
             })));
         }
     }
-    
-    // Execute the command
-    let mut cmd = TokioCommand::new(&payload.command);
-    
-    if let Some(ref args) = payload.args {
-        cmd.args(args);
+    let all = tokio::task::spawn_blocking(|| network::sockets(std::path::Path::new(network::PROC))).await.unwrap_or_default();
+    let process = query.process.as_ref().map(|name| name.to_lowercase());
+    let mut sockets: Vec<SocketInfo> = all
+        .into_iter()
+        .filter(|socket| query.protocol.as_ref().is_none_or(|protocol| socket.protocol.starts_with(protocol.as_str())))
+        .filter(|socket| query.state.as_ref().is_none_or(|state| socket.state == *state))
+        .filter(|socket| query.port.is_none_or(|port| socket.local_port == port || socket.remote_port == Some(port)))
+        .filter(|socket| {
+            process.as_ref().is_none_or(|name| {
+                socket.process.as_ref().is_some_and(|owner| owner.to_lowercase().contains(name))
+            })
+        })
+        .collect();
+    sockets.sort_by(|a, b| a.protocol.cmp(&b.protocol).then(a.local_port.cmp(&b.local_port)));
+    Ok(Json(SocketListResponse { total: sockets.len(), states: network::count_states(&sockets), sockets }))
+}
+
+/// DNS resolver configuration
+/// 
+/// # Returns
+/// * `Json<DnsConfig>` - Name servers, search domains and options; empty without a resolv.conf
+pub async fn get_dns_config() -> Json<DnsConfig> {
+    let content = tokio::fs::read_to_string(network::RESOLV_CONF).await.unwrap_or_default();
+    Json(network::parse_resolv_conf(&content))
+}
+
+/// Measure latency to a host by timing TCP connections
+/// 
+/// Connections are closed as soon as they are established; no data is sent.
+/// 
+/// # Arguments
+/// * `request` - Host, port, number of connections and timeout
+/// 
+/// # Returns
+/// * `Ok(Json<LatencyProbe>)` - Connect times; failures are reported in the probe
+/// * `Err((StatusCode, Json<CommandError>))` - 400 without a host
+pub async fn probe_network_latency(
+    Json(request): Json<LatencyProbeRequest>,
+) -> Result<Json<LatencyProbe>, (StatusCode, Json<CommandError>)> {
+    let host = request.host.trim();
+    if host.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(CommandError { error: "Host cannot be empty".to_string(), blocked: false })));
     }
-    
-    if let Some(ref working_dir) = payload.working_dir {
-        cmd.current_dir(working_dir);
+    let port = request.port.unwrap_or(443);
+    info!("Probing latency to {}:{}", host, port);
+    let probe = network::probe_latency(
+        host,
+        port,
+        request.count.unwrap_or(4),
+        std::time::Duration::from_millis(request.timeout_ms.unwrap_or(2000)),
+    )
+    .await;
+    Ok(Json(probe))
+}
+
+/// Temperatures, fan speeds, power meters and batteries
+/// 
+/// Sensors the machine does not have are left out, so containers and
+/// virtual machines usually get empty lists.
+/// 
+/// # Returns
+/// * `Json<HardwareSensors>` - Readings as of the latest sample
+pub async fn get_sensors(State(state): State<AppState>) -> Json<HardwareSensors> {
+    Json(state.sampler.latest().sensors.clone())
+}
+
+/// Query parameters for the resource usage history
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResourceHistoryQuery {
+    pub limit: Option<usize>,
+}
+
+/// Response structure for the resource usage history
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResourceHistoryResponse {
+    pub interval_secs: u64,
+    pub samples: Vec<ResourceUsage>,
+}
+
+/// Query parameters for the metrics history
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MetricsQuery {
+    /// Start of the range (default: an hour before `until`)
+    pub since: Option<DateTime<Utc>>,
+    /// End of the range (default: now)
+    pub until: Option<DateTime<Utc>>,
+    /// "raw", "minute" or "hour"; picked from the range if absent
+    pub resolution: Option<String>,
+    /// Merge the points into periods of this many seconds
+    pub step_secs: Option<i64>,
+}
+
+/// Resource usage history over a time range
+/// 
+/// Recorded samples are kept raw for a day, then as one-minute points for
+/// a week and as one-hour points for 90 days by default. Without a
+/// `resolution` the finest one still kept for `since` that fits the range
+/// in at most 1000 points, or in `step_secs` periods, is used. Each point carries the heaviest programs
+/// by CPU and memory; the `summary` merges the whole range.
+/// 
+/// # Arguments
+/// * `query` - Time range, resolution and step
+/// 
+/// # Returns
+/// * `Ok(Json<MetricsResponse>)` - Points, oldest first, and their summary
+/// * `Err((StatusCode, Json<CommandError>))` - 400 for an invalid range or resolution
+pub async fn get_metrics(
+    State(state): State<AppState>,
+    Query(query): Query<MetricsQuery>,
+) -> Result<Json<MetricsResponse>, (StatusCode, Json<CommandError>)> {
+    let invalid = |error: String| (StatusCode::BAD_REQUEST, Json(CommandError { error, blocked: false }));
+    let until = query.until.unwrap_or_else(Utc::now);
+    let since = query.since.unwrap_or(until - chrono::Duration::hours(1));
+    if since >= until {
+        return Err(invalid("`since` must be before `until`".to_string()));
     }
-    
-    let start_execution = std::time::Instant::now();
-    let output = cmd.output().await;
-    let execution_time = start_execution.elapsed().as_millis() as u64;
-    
-    match output {
-        Ok(output) => {
-            let success = output.status.success();
-            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-            let exit_code = output.status.code().unwrap_or(-1);
-            
-            // Store command in history
-            let db = state.db.get().unwrap();
-            let _ = crate::db::queries::store_command_history(&db, &payload.command, &payload.args, &payload.working_dir, success, exit_code, execution_time, true);
-            
-            Ok(Json(ExecuteCommandResponse {
-                success,
-                command: payload.command,
-                stdout,
-                stderr,
-                exit_code,
-                execution_time_ms: execution_time,
-                timestamp: chrono::Utc::now().to_rfc3339(),
-            }))
-        }
-        Err(e) => {
-            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
-                error: format!("Failed to execute command: {}", e),
-                blocked: false,
-            })))
-        }
+    let resolution = match query.resolution.as_deref() {
+        Some(name) => Some(MetricResolution::from_str(name).ok_or_else(|| invalid(format!("Unknown resolution: {}", name)))?),
+        None => None,
+    };
+    if query.step_secs.is_some_and(|step| step <= 0) {
+        return Err(invalid("`step_secs` must be positive".to_string()));
     }
+
+    let metrics = state.metrics.clone();
+    tokio::task::spawn_blocking(move || metrics.query(since, until, resolution, query.step_secs))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result.map_err(|e| e.to_string()))
+        .map(Json)
+        .map_err(|error| (StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError { error, blocked: false })))
 }
 
-/// Get command execution history
+/// Query parameters for the alert history
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlertHistoryQuery {
+    pub rule_id: Option<i64>,
+    /// "firing" or "resolved"
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Response structure for alert lists
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlertListResponse {
+    pub alerts: Vec<Alert>,
+}
+
+/// Response structure for the alert rule list
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlertRuleListResponse {
+    pub rules: Vec<AlertRule>,
+}
+
+fn alert_error(status: StatusCode, error: impl ToString) -> (StatusCode, Json<CommandError>) {
+    (status, Json(CommandError { error: error.to_string(), blocked: false }))
+}
+
+/// Pending and firing alerts
 /// 
-/// This endpoint retrieves the history of commands executed by Leara,
-/// useful for auditing and debugging purposes.
+/// # Returns
+/// * `Json<AlertListResponse>` - Active alerts, oldest first
+pub async fn get_active_alerts(State(state): State<AppState>) -> Json<AlertListResponse> {
+    Json(AlertListResponse { alerts: state.alerts.active() })
+}
+
+/// Alerts that fired, newest first
 /// 
 /// # Arguments
-/// * `query` - Query parameters for filtering history
+/// * `query` - Rule, status and limit (default 50)
 /// 
 /// # Returns
-/// * `Ok(Json<CommandHistoryResponse>)` - Command history
-/// * `Err((StatusCode, Json<CommandError>))` - Error response
-pub async fn get_command_history(
+/// * `Ok(Json<AlertListResponse>)` - Recorded alerts
+/// * `Err((StatusCode, Json<CommandError>))` - 400 for an unknown status
+pub async fn get_alert_history(
     State(state): State<AppState>,
-    Query(query): Query<CommandHistoryQuery>,
-) -> Result<Json<CommandHistoryResponse>, (StatusCode, Json<CommandError>)> {
+    Query(query): Query<AlertHistoryQuery>,
+) -> Result<Json<AlertListResponse>, (StatusCode, Json<CommandError>)> {
+    let status = match query.status.as_deref() {
+        Some(name) => Some(
+            AlertStatus::from_str(name)
+                .ok_or_else(|| alert_error(StatusCode::BAD_REQUEST, format!("Unknown alert status: {}", name)))?,
+        ),
+        None => None,
+    };
     let db = state.db.get().unwrap();
-    match crate::db::queries::get_command_history(&db, &query) {
-        Ok(response) => Ok(Json(response)),
-        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
-            error: e.to_string(),
-            blocked: false,
-        }))),
-    }
+    crate::db::queries::get_alerts(&db, query.rule_id, status, query.limit.unwrap_or(50).max(1))
+        .map(|alerts| Json(AlertListResponse { alerts }))
+        .map_err(|e| alert_error(StatusCode::INTERNAL_SERVER_ERROR, e))
 }
 
-/// Get list of available applications dynamically
+/// Every alert rule
 /// 
-/// This endpoint scans .desktop files and $PATH for available applications.
+/// # Returns
+/// * `Ok(Json<AlertRuleListResponse>)` - Rules, oldest first
+/// * `Err((StatusCode, Json<CommandError>))` - Database error
+pub async fn get_alert_rules(
+    State(state): State<AppState>,
+) -> Result<Json<AlertRuleListResponse>, (StatusCode, Json<CommandError>)> {
+    let db = state.db.get().unwrap();
+    crate::db::queries::get_alert_rules(&db)
+        .map(|rules| Json(AlertRuleListResponse { rules }))
+        .map_err(|e| alert_error(StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+/// Look up one alert rule
 /// 
 /// # Returns
-/// * `Ok(Json<serde_json::Value>)` - List of available applications
-/// * `Err((StatusCode, Json<CommandError>))` - Error response
-pub async fn get_available_apps() -> Result<Json<serde_json::Value>, (StatusCode, Json<CommandError>)> {
-    let mut apps = Vec::new();
-    let mut seen = HashSet::new();
-
-    // Fix the temporary value dropped error by binding the .local path to a variable first
-    let home_dir = env::var("HOME").unwrap_or_default();
-    let local_applications = format!("{}/.local/share/applications", home_dir);
-    let desktop_dirs = vec![
-        "/usr/share/applications",
-        "/usr/local/share/applications",
-        &local_applications,
-    ];
-    for dir in desktop_dirs {
-        if let Ok(entries) = fs::read_dir(dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.extension().map(|e| e == "desktop").unwrap_or(false) {
-                    if let Ok(content) = fs::read_to_string(&path) {
-                        let mut name = None;
-                        let mut exec = None;
-                        let mut comment = None;
-                        let mut category = None;
-                        for line in content.lines() {
-                            if line.starts_with("Name=") {
-                                name = Some(line[5..].trim().to_string());
-                            } else if line.starts_with("Exec=") {
-                                exec = Some(line[5..].split_whitespace().next().unwrap_or("").to_string());
-                            } else if line.starts_with("Comment=") {
-                                comment = Some(line[8..].trim().to_string());
-                            } else if line.starts_with("Categories=") {
-                                category = Some(line[11..].split(';').next().unwrap_or("").to_string());
-                            }
-                        }
-                        if let (Some(name), Some(exec)) = (name, exec) {
-                            // Avoid duplicates
-                            if seen.insert(exec.clone()) {
-                                apps.push(json!({
-                                    "name": name,
-                                    "command": exec,
-                                    "description": comment.unwrap_or_default(),
-                                    "category": category.unwrap_or_default(),
-                                }));
-                            }
-                        }
-                    }
-                }
-            }
-        }
+/// * `Ok(Json<AlertRule>)` - The rule
+/// * `Err((StatusCode, Json<CommandError>))` - 404 if unknown
+pub async fn get_alert_rule(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<AlertRule>, (StatusCode, Json<CommandError>)> {
+    let db = state.db.get().unwrap();
+    match crate::db::queries::get_alert_rule(&db, id) {
+        Ok(Some(rule)) => Ok(Json(rule)),
+        Ok(None) => Err(alert_error(StatusCode::NOT_FOUND, format!("Unknown alert rule: {}", id))),
+        Err(e) => Err(alert_error(StatusCode::INTERNAL_SERVER_ERROR, e)),
     }
+}
 
-    // Optionally, scan $PATH for executables not already listed
-    if let Ok(path_var) = env::var("PATH") {
-        for dir in path_var.split(':') {
-            if let Ok(entries) = fs::read_dir(dir) {
-                for entry in entries.flatten() {
-                    let path = entry.path();
-                    if path.is_file() && path.metadata().map(|m| m.permissions().mode() & 0o111 != 0).unwrap_or(false) {
-                        let cmd = path.file_name().unwrap_or_default().to_string_lossy().to_string();
-                        if seen.insert(cmd.clone()) {
-                            apps.push(json!({
-                                "name": cmd.clone(),
-                                "command": cmd,
-                                "description": "",
-                                "category": "cli"
-                            }));
-                        }
-                    }
-                }
-            }
-        }
+/// Create an alert rule
+/// 
+/// The rule is evaluated from the next sample on. Conditions are either a
+/// `threshold` on a metric, held for `for_secs` before the alert fires,
+/// or `process_not_running`.
+/// 
+/// # Arguments
+/// * `request` - Name, condition, duration and whether to create a task when it fires
+/// 
+/// # Returns
+/// * `Ok((StatusCode::CREATED, Json<AlertRule>))` - The stored rule
+/// * `Err((StatusCode, Json<CommandError>))` - 422 for an invalid rule
+pub async fn create_alert_rule(
+    State(state): State<AppState>,
+    Json(request): Json<AlertRuleRequest>,
+) -> Result<(StatusCode, Json<AlertRule>), (StatusCode, Json<CommandError>)> {
+    validate_rule(&request).map_err(|error| alert_error(StatusCode::UNPROCESSABLE_ENTITY, error))?;
+    let now = Utc::now();
+    let mut rule = AlertRule {
+        id: 0,
+        name: request.name.trim().to_string(),
+        condition: request.condition,
+        for_secs: request.for_secs.unwrap_or(0),
+        enabled: request.enabled.unwrap_or(true),
+        create_task: request.create_task.unwrap_or(false),
+        created_at: now,
+        updated_at: now,
+    };
+    let db = state.db.get().unwrap();
+    rule.id = crate::db::queries::insert_alert_rule(&db, &rule)
+        .map_err(|e| alert_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    info!("Created alert rule {} ({})", rule.name, rule.id);
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+/// Replace an alert rule
+/// 
+/// Changing a rule does not reset its active alert; it resolves or fires
+/// under the new condition at the next evaluation.
+/// 
+/// # Returns
+/// * `Ok(Json<AlertRule>)` - The updated rule
+/// * `Err((StatusCode, Json<CommandError>))` - 404 if unknown, 422 for an invalid rule
+pub async fn update_alert_rule(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(request): Json<AlertRuleRequest>,
+) -> Result<Json<AlertRule>, (StatusCode, Json<CommandError>)> {
+    validate_rule(&request).map_err(|error| alert_error(StatusCode::UNPROCESSABLE_ENTITY, error))?;
+    let Json(existing) = get_alert_rule(State(state.clone()), Path(id)).await?;
+    let rule = AlertRule {
+        name: request.name.trim().to_string(),
+        condition: request.condition,
+        for_secs: request.for_secs.unwrap_or(existing.for_secs),
+        enabled: request.enabled.unwrap_or(existing.enabled),
+        create_task: request.create_task.unwrap_or(existing.create_task),
+        updated_at: Utc::now(),
+        ..existing
+    };
+    let db = state.db.get().unwrap();
+    crate::db::queries::update_alert_rule(&db, &rule).map_err(|e| alert_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Json(rule))
+}
+
+/// Delete an alert rule
+/// 
+/// Its active alert resolves at the next evaluation; its history is kept.
+/// 
+/// # Returns
+/// * `Ok(StatusCode::NO_CONTENT)` - Deleted
+/// * `Err((StatusCode, Json<CommandError>))` - 404 if unknown
+pub async fn delete_alert_rule(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, (StatusCode, Json<CommandError>)> {
+    let db = state.db.get().unwrap();
+    match crate::db::queries::delete_alert_rule(&db, id) {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(alert_error(StatusCode::NOT_FOUND, format!("Unknown alert rule: {}", id))),
+        Err(e) => Err(alert_error(StatusCode::INTERNAL_SERVER_ERROR, e)),
     }
+}
 
-    Ok(Json(json!({ "applications": apps })))
+/// Query parameters for the process list
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProcessListQuery {
+    /// Case-insensitive match against the name and command line
+    pub name: Option<String>,
+    /// Owner's user name or user ID
+    pub user: Option<String>,
+    /// Lowest CPU usage in percent
+    pub min_cpu: Option<f32>,
+    /// Lowest resident memory in bytes
+    pub min_memory: Option<u64>,
+    /// Sort field: "cpu" (default), "memory", "name", "pid", "user" or "start_time"
+    pub sort_by: Option<String>,
+    /// Sort direction: "asc" or "desc" (default depends on `sort_by`)
+    pub order: Option<String>,
+    pub limit: Option<usize>,
 }
 
-/// Create router for system-related endpoints
-pub fn create_router() -> Router<AppState> {
-    Router::new()
-        .route("/execute", post(execute_command))
-        .route("/history", get(get_command_history))
-        .route("/apps", get(get_available_apps))
-} 
\ No newline at end of file
+/// Response structure for the process list
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProcessListResponse {
+    pub processes: Vec<ProcessInfo>,
+    /// Processes matching the filters, before `limit`
+    pub total: usize,
+}
+
+/// Query parameters for the process tree
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProcessTreeQuery {
+    /// Only return the subtree of this process
+    pub root: Option<u32>,
+}
+
+/// Request structure for signalling a process
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProcessSignalRequest {
+    /// TERM, KILL, STOP or CONT, with or without the SIG prefix
+    pub signal: String,
+    /// Ask for confirmation even if the policy would allow the signal
+    pub require_confirmation: Option<bool>,
+    /// Conversation that asked for the signal, recorded in the history
+    pub conversation_id: Option<String>,
+}
+
+/// Running processes, filtered and sorted
+/// 
+/// Served from the latest background sample, so CPU usage is measured over
+/// the sampling interval.
+/// 
+/// # Arguments
+/// * `query` - Filters, sorting and limit
+/// 
+/// # Returns
+/// * `Ok(Json<ProcessListResponse>)` - Matching processes
+/// * `Err((StatusCode, Json<CommandError>))` - 400 for an unknown sort field or order
+pub async fn get_processes(
+    State(state): State<AppState>,
+    Query(query): Query<ProcessListQuery>,
+) -> Result<Json<ProcessListResponse>, (StatusCode, Json<CommandError>)> {
+    let invalid = |error: String| (StatusCode::BAD_REQUEST, Json(CommandError { error, blocked: false }));
+    let sort_field = match query.sort_by.as_deref() {
+        Some(name) => ProcessSortField::from_str(name).ok_or_else(|| invalid(format!("Unknown sort field: {}", name)))?,
+        None => ProcessSortField::Cpu,
+    };
+    let descending = match query.order.as_deref().map(|o| o.to_lowercase()) {
+        Some(ref o) if o == "asc" => false,
+        Some(ref o) if o == "desc" => true,
+        Some(o) => return Err(invalid(format!("Unknown sort order: {}", o))),
+        None => sort_field.default_descending(),
+    };
+
+    let filter = ProcessFilter {
+        name: query.name,
+        user: query.user,
+        min_cpu: query.min_cpu,
+        min_memory: query.min_memory,
+    };
+    let mut processes: Vec<ProcessInfo> = state
+        .sampler
+        .processes()
+        .iter()
+        .filter(|process| filter.matches(process))
+        .cloned()
+        .collect();
+    sort_processes(&mut processes, sort_field, descending);
+    let total = processes.len();
+    processes.truncate(query.limit.unwrap_or(usize::MAX));
+    Ok(Json(ProcessListResponse { processes, total }))
+}
+
+/// Running processes arranged by parent
+/// 
+/// # Arguments
+/// * `query` - Optional process whose subtree is returned
+/// 
+/// # Returns
+/// * `Ok(Json<Vec<ProcessTreeNode>>)` - Root processes with their descendants
+/// * `Err((StatusCode, Json<CommandError>))` - 404 if `root` is not running
+pub async fn get_process_tree(
+    State(state): State<AppState>,
+    Query(query): Query<ProcessTreeQuery>,
+) -> Result<Json<Vec<ProcessTreeNode>>, (StatusCode, Json<CommandError>)> {
+    let tree = process_tree(state.sampler.processes().as_ref().clone(), query.root);
+    match query.root {
+        Some(pid) if tree.is_empty() => Err(process_not_found(pid)),
+        _ => Ok(Json(tree)),
+    }
+}
+
+/// One process with its command line, directories, open files and environment
+/// 
+/// The environment is only returned for processes the server may inspect,
+/// with secrets redacted like in the command history.
+/// 
+/// # Arguments
+/// * `pid` - Process ID
+/// 
+/// # Returns
+/// * `Ok(Json<ProcessDetails>)` - The process
+/// * `Err((StatusCode, Json<CommandError>))` - 404 if the process is not running
+pub async fn get_process(
+    State(state): State<AppState>,
+    Path(pid): Path<u32>,
+) -> Result<Json<ProcessDetails>, (StatusCode, Json<CommandError>)> {
+    let environment = state.policy.current().config().environment.clone();
+    let mut details = tokio::task::spawn_blocking(move || process_details(pid, &environment))
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(|| process_not_found(pid))?;
+    if let Some(sampled) = state.sampler.processes().iter().find(|process| process.pid == pid) {
+        details.process.cpu_usage = sampled.cpu_usage;
+    }
+    Ok(Json(details))
+}
+
+/// Send a signal to a process
+/// 
+/// The signal is sent with `kill -s`, checked against the execution policy
+/// and recorded in the history like any other command, so it may need
+/// confirmation first. The process's start time is stored with a pending
+/// signal, which is refused on approval if the PID was reused meanwhile.
+/// 
+/// # Arguments
+/// * `pid` - Process ID
+/// * `request` - Signal and confirmation options
+/// 
+/// # Returns
+/// * `Ok(Response)` - `ExecuteCommandResponse` (200) or `PendingCommand` (202)
+/// * `Err((StatusCode, Json<CommandError>))` - 400 unsupported signal, 404 no such process, 403 blocked
+pub async fn signal_process(
+    State(state): State<AppState>,
+    Path(pid): Path<u32>,
+    Json(request): Json<ProcessSignalRequest>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    let signal = parse_signal(&request.signal).map_err(|error| {
+        (StatusCode::BAD_REQUEST, Json(CommandError { error, blocked: false }))
+    })?;
+    let process_start = process_start_ticks(pid).ok_or_else(|| process_not_found(pid))?;
+
+    info!("Sending SIG{} to process {}", signal, pid);
+    let payload = ExecuteCommandRequest {
+        command: "kill".to_string(),
+        args: Some(vec!["-s".to_string(), signal.to_string(), pid.to_string()]),
+        require_confirmation: request.require_confirmation,
+        conversation_id: request.conversation_id,
+        ..Default::default()
+    };
+    let spec = command_spec(&state, &payload)?;
+    let origin = CommandOrigin {
+        conversation_id: payload.conversation_id.clone(),
+        process_start: Some(process_start),
+        ..Default::default()
+    };
+    match authorize_command(&state, &payload, &spec, CommandMode::Foreground, &origin)? {
+        Some(pending) => Ok((StatusCode::ACCEPTED, Json(pending)).into_response()),
+        None => Ok(Json(run_command(&state, &spec, payload.timeout_secs, origin).await?).into_response()),
+    }
+}
+
+/// Whether the process a pending signal is for is still the one that was running
+/// 
+/// # Returns
+/// * `Err((StatusCode, Json<CommandError>))` - 409 if the process exited, even if its PID was reused
+fn check_signal_target(pending: &PendingCommand) -> Result<(), (StatusCode, Json<CommandError>)> {
+    let Some(started) = pending.process_start else {
+        return Ok(());
+    };
+    let pid = pending.args.last().and_then(|pid| pid.parse::<u32>().ok());
+    if pid.and_then(process_start_ticks) == Some(started) {
+        return Ok(());
+    }
+    Err((StatusCode::CONFLICT, Json(CommandError {
+        error: format!(
+            "Process {} has exited since the signal was requested; its PID may belong to another process now",
+            pid.map(|pid| pid.to_string()).unwrap_or_default()
+        ),
+        blocked: false,
+    })))
+}
+
+fn process_not_found(pid: u32) -> (StatusCode, Json<CommandError>) {
+    (StatusCode::NOT_FOUND, Json(CommandError {
+        error: format!("No running process with PID {}", pid),
+        blocked: false,
+    }))
+}
+
+/// Query parameters for the systemd unit list
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ServiceListQuery {
+    /// Unit type such as "service" (default), "timer" or "socket"
+    #[serde(rename = "type")]
+    pub unit_type: Option<String>,
+    /// Only units in this active state, e.g. "failed"
+    pub state: Option<String>,
+    /// Case-insensitive match against the unit name and description
+    pub name: Option<String>,
+}
+
+/// Response structure for the systemd unit list
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServiceListResponse {
+    pub units: Vec<SystemdUnit>,
+    pub total: usize,
+}
+
+/// Request structure for starting, stopping or restarting a unit
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ServiceActionRequest {
+    /// Ask for confirmation even if the policy would allow the action
+    pub require_confirmation: Option<bool>,
+    /// Conversation that asked for the action, recorded in the history
+    pub conversation_id: Option<String>,
+}
+
+/// Query parameters for the journal
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct JournalQuery {
+    pub unit: Option<String>,
+    /// Highest priority to include, by name ("err", "warning", ...) or 0-7
+    pub priority: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Pattern the message must match, as understood by `journalctl --grep`
+    pub grep: Option<String>,
+    /// Most recent entries to return (default 100, at most 1000)
+    pub limit: Option<usize>,
+}
+
+/// Response structure for journal queries
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalResponse {
+    /// Matching entries, oldest first
+    pub entries: Vec<JournalEntry>,
+}
+
+/// Unit types `systemctl list-units --type` accepts
+const UNIT_TYPES: &[&str] = &[
+    "service", "socket", "target", "device", "mount", "automount", "swap", "timer", "path", "slice", "scope",
+];
+
+/// Journal entries returned unless the caller asks for fewer or more
+const DEFAULT_JOURNAL_LINES: usize = 100;
+const MAX_JOURNAL_LINES: usize = 1000;
+
+/// Journal entries included with a unit's status
+const STATUS_JOURNAL_LINES: usize = 20;
+
+fn systemd_error(status: StatusCode, error: impl ToString) -> (StatusCode, Json<CommandError>) {
+    (status, Json(CommandError { error: error.to_string(), blocked: false }))
+}
+
+/// Run `systemctl` or `journalctl` through the policy and return its output
+/// 
+/// The command is recorded in the history like any other. A command that
+/// fails without output, like `journalctl --grep` finding nothing, counts
+/// as empty output.
+/// 
+/// # Returns
+/// * `Ok(CommandSubmission<String>)` - Standard output, or the pending command
+/// * `Err((StatusCode, Json<CommandError>))` - Denied (403) or failed (502)
+async fn run_systemd(
+    state: &AppState,
+    program: &str,
+    args: Vec<String>,
+    options: &ServiceActionRequest,
+) -> Result<CommandSubmission<String>, (StatusCode, Json<CommandError>)> {
+    let payload = ExecuteCommandRequest {
+        command: program.to_string(),
+        args: Some(args),
+        require_confirmation: options.require_confirmation,
+        conversation_id: options.conversation_id.clone(),
+        ..Default::default()
+    };
+    match submit_command(state, &payload).await? {
+        CommandSubmission::Completed(response) if !response.success && !response.stderr.trim().is_empty() => {
+            Err(systemd_error(StatusCode::BAD_GATEWAY, format!("{} failed: {}", program, response.stderr.trim())))
+        }
+        submission => Ok(submission.map(|response| response.stdout)),
+    }
+}
+
+/// systemd units with their state
+/// 
+/// # Arguments
+/// * `query` - Unit type and filters
+/// * `options` - Conversation recorded with the command
+/// 
+/// # Returns
+/// * `Ok(CommandSubmission<ServiceListResponse>)` - Units sorted by name
+/// * `Err((StatusCode, Json<CommandError>))` - 400 unknown unit type, 403 denied, 502 systemctl failed
+pub async fn list_units(
+    state: &AppState,
+    query: &ServiceListQuery,
+    options: &ServiceActionRequest,
+) -> Result<CommandSubmission<ServiceListResponse>, (StatusCode, Json<CommandError>)> {
+    let unit_type = query.unit_type.as_deref().unwrap_or("service");
+    if !UNIT_TYPES.contains(&unit_type) {
+        return Err(systemd_error(
+            StatusCode::BAD_REQUEST,
+            format!("Unknown unit type {:?}; use one of {}", unit_type, UNIT_TYPES.join(", ")),
+        ));
+    }
+    let output = run_systemd(state, "systemctl", list_units_args(unit_type), options).await?;
+    Ok(output.map(|stdout| {
+        let needle = query.name.as_ref().map(|name| name.to_lowercase());
+        let mut units: Vec<SystemdUnit> = parse_unit_list(&stdout)
+            .into_iter()
+            .filter(|unit| query.state.as_ref().is_none_or(|state| unit.active_state == *state))
+            .filter(|unit| {
+                needle.as_ref().is_none_or(|needle| {
+                    unit.name.to_lowercase().contains(needle) || unit.description.to_lowercase().contains(needle)
+                })
+            })
+            .collect();
+        units.sort_by(|a, b| a.name.cmp(&b.name));
+        ServiceListResponse { total: units.len(), units }
+    }))
+}
+
+/// Status of one unit with its latest journal entries
+/// 
+/// The journal is left out if reading it needs confirmation or fails.
+/// 
+/// # Returns
+/// * `Ok(CommandSubmission<UnitStatus>)` - The unit
+/// * `Err((StatusCode, Json<CommandError>))` - 400 invalid name, 404 unknown unit, 403 denied, 502 systemctl failed
+pub async fn unit_status(
+    state: &AppState,
+    unit: &str,
+    options: &ServiceActionRequest,
+) -> Result<CommandSubmission<UnitStatus>, (StatusCode, Json<CommandError>)> {
+    validate_unit_name(unit).map_err(|error| systemd_error(StatusCode::BAD_REQUEST, error))?;
+    let stdout = match run_systemd(state, "systemctl", show_unit_args(unit), options).await? {
+        CommandSubmission::Completed(stdout) => stdout,
+        CommandSubmission::Pending(pending) => return Ok(CommandSubmission::Pending(pending)),
+    };
+    let mut status = parse_unit_status(&stdout)
+        .ok_or_else(|| systemd_error(StatusCode::NOT_FOUND, format!("Unknown unit: {}", unit)))?;
+
+    let filter = JournalFilter { unit: Some(status.name.clone()), limit: STATUS_JOURNAL_LINES, ..Default::default() };
+    if let Ok(CommandSubmission::Completed(entries)) = read_journal(state, &filter, options).await {
+        status.recent_logs = entries;
+    }
+    Ok(CommandSubmission::Completed(status))
+}
+
+/// Journal entries matching a filter, oldest first
+/// 
+/// # Returns
+/// * `Ok(CommandSubmission<Vec<JournalEntry>>)` - The entries
+/// * `Err((StatusCode, Json<CommandError>))` - 403 denied, 502 journalctl failed
+pub async fn read_journal(
+    state: &AppState,
+    filter: &JournalFilter,
+    options: &ServiceActionRequest,
+) -> Result<CommandSubmission<Vec<JournalEntry>>, (StatusCode, Json<CommandError>)> {
+    let output = run_systemd(state, "journalctl", filter.args(), options).await?;
+    Ok(output.map(|stdout| parse_journal(&stdout)))
+}
+
+/// List systemd units
+/// 
+/// Runs `systemctl list-units` through the execution policy.
+/// 
+/// # Arguments
+/// * `query` - Unit type (default "service"), active state and name filters
+/// 
+/// # Returns
+/// * `Ok(Response)` - `ServiceListResponse` (200) or `PendingCommand` (202)
+/// * `Err((StatusCode, Json<CommandError>))` - Error response
+pub async fn get_services(
+    State(state): State<AppState>,
+    Query(query): Query<ServiceListQuery>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    Ok(list_units(&state, &query, &ServiceActionRequest::default()).await?.into_response())
+}
+
+/// Status of a systemd unit
+/// 
+/// Names without a suffix are taken as services by systemd.
+/// 
+/// # Arguments
+/// * `unit` - Unit name, e.g. `nginx.service`
+/// 
+/// # Returns
+/// * `Ok(Response)` - `UnitStatus` (200) or `PendingCommand` (202)
+/// * `Err((StatusCode, Json<CommandError>))` - 404 if systemd does not know the unit
+pub async fn get_service(
+    State(state): State<AppState>,
+    Path(unit): Path<String>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    Ok(unit_status(&state, &unit, &ServiceActionRequest::default()).await?.into_response())
+}
+
+/// Start, stop or restart a systemd unit
+/// 
+/// Runs `systemctl <action> <unit>` through the execution policy, which
+/// asks for confirmation of service changes by default.
+/// 
+/// # Arguments
+/// * `unit` - Unit name
+/// * `action` - "start", "stop" or "restart"
+/// * `request` - Confirmation options
+/// 
+/// # Returns
+/// * `Ok(Response)` - `ExecuteCommandResponse` (200) or `PendingCommand` (202)
+/// * `Err((StatusCode, Json<CommandError>))` - 400 invalid unit or action, 403 blocked
+pub async fn control_service(
+    State(state): State<AppState>,
+    Path((unit, action)): Path<(String, String)>,
+    Json(request): Json<ServiceActionRequest>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    let action = parse_unit_action(&action).map_err(|error| systemd_error(StatusCode::BAD_REQUEST, error))?;
+    validate_unit_name(&unit).map_err(|error| systemd_error(StatusCode::BAD_REQUEST, error))?;
+
+    info!("Requesting systemctl {} {}", action, unit);
+    let payload = ExecuteCommandRequest {
+        command: "systemctl".to_string(),
+        args: Some(vec![action.to_string(), unit]),
+        require_confirmation: request.require_confirmation,
+        conversation_id: request.conversation_id,
+        ..Default::default()
+    };
+    Ok(submit_command(&state, &payload).await?.into_response())
+}
+
+/// Query the systemd journal
+/// 
+/// Runs `journalctl --output=json` through the execution policy and returns
+/// the most recent matching entries.
+/// 
+/// # Arguments
+/// * `query` - Unit, priority, time range, pattern and limit
+/// 
+/// # Returns
+/// * `Ok(Response)` - `JournalResponse` (200) or `PendingCommand` (202)
+/// * `Err((StatusCode, Json<CommandError>))` - 400 for an invalid unit or priority
+pub async fn get_journal(
+    State(state): State<AppState>,
+    Query(query): Query<JournalQuery>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    let filter = journal_filter(query)?;
+    let entries = read_journal(&state, &filter, &ServiceActionRequest::default()).await?;
+    Ok(entries.map(|entries| JournalResponse { entries }).into_response())
+}
+
+/// Check a journal query and turn it into a filter
+pub fn journal_filter(query: JournalQuery) -> Result<JournalFilter, (StatusCode, Json<CommandError>)> {
+    if let Some(ref unit) = query.unit {
+        validate_unit_name(unit).map_err(|error| systemd_error(StatusCode::BAD_REQUEST, error))?;
+    }
+    let priority = match query.priority.as_deref() {
+        Some(priority) => Some(parse_priority(priority).map_err(|error| systemd_error(StatusCode::BAD_REQUEST, error))?),
+        None => None,
+    };
+    Ok(JournalFilter {
+        unit: query.unit,
+        priority,
+        since: query.since,
+        until: query.until,
+        grep: query.grep.filter(|grep| !grep.is_empty()),
+        limit: query.limit.unwrap_or(DEFAULT_JOURNAL_LINES).clamp(1, MAX_JOURNAL_LINES),
+    })
+}
+
+/// Query parameters for a directory listing
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DirectoryQuery {
+    /// Directory to list; the first root when absent
+    pub path: Option<String>,
+    /// Include names starting with a dot (default false)
+    pub hidden: Option<bool>,
+}
+
+/// Query parameters naming one file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilePathQuery {
+    pub path: String,
+}
+
+/// Query parameters for reading a text file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileReadQuery {
+    pub path: String,
+    /// Byte to start at (default 0)
+    pub offset: Option<u64>,
+    /// Bytes to read (default and maximum `max_read_bytes` of the policy)
+    pub length: Option<u64>,
+}
+
+/// Query parameters for a checksum
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileChecksumQuery {
+    pub path: String,
+    /// "md5", "sha1", "sha256" (default) or "sha512"
+    pub algorithm: Option<String>,
+}
+
+/// Response structure for a directory listing
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DirectoryListing {
+    pub path: String,
+    /// Enclosing directory, absent at a root
+    pub parent: Option<String>,
+    pub entries: Vec<FileEntry>,
+    pub total: usize,
+}
+
+/// Response structure for the allowed roots
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileRootsResponse {
+    pub roots: Vec<FileEntry>,
+}
+
+/// Request structure for writing a text file
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FileWriteRequest {
+    pub path: String,
+    pub content: String,
+    /// Replace an existing file; this needs confirmation
+    pub overwrite: Option<bool>,
+    /// Create missing parent directories of a new file
+    pub create_dirs: Option<bool>,
+    /// Ask for confirmation even if the policy would allow the change
+    pub require_confirmation: Option<bool>,
+    /// Conversation that asked for the change, recorded in the history
+    pub conversation_id: Option<String>,
+}
+
+/// Request structure for creating a directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CreateDirectoryRequest {
+    pub path: String,
+    /// Create missing parent directories too
+    pub parents: Option<bool>,
+}
+
+/// Request structure for moving a file or directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FileMoveRequest {
+    pub from: String,
+    /// New path, including the name
+    pub to: String,
+    /// Replace what is at `to`
+    pub overwrite: Option<bool>,
+    pub require_confirmation: Option<bool>,
+    pub conversation_id: Option<String>,
+}
+
+/// Request structure for renaming within the same directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FileRenameRequest {
+    pub path: String,
+    /// New name, without a directory
+    pub name: String,
+    pub overwrite: Option<bool>,
+    pub require_confirmation: Option<bool>,
+    pub conversation_id: Option<String>,
+}
+
+/// Query parameters for deleting a file or directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FileDeleteQuery {
+    pub path: String,
+    /// Delete a directory with everything in it; otherwise only empty directories are removed
+    pub recursive: Option<bool>,
+    pub require_confirmation: Option<bool>,
+    pub conversation_id: Option<String>,
+}
+
+fn file_error(status: StatusCode, error: impl ToString) -> (StatusCode, Json<CommandError>) {
+    (status, Json(CommandError { error: error.to_string(), blocked: false }))
+}
+
+/// Map a filesystem error to a response
+fn file_io_error(path: &std::path::Path, error: std::io::Error) -> (StatusCode, Json<CommandError>) {
+    let status = match error.kind() {
+        std::io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+        std::io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+        std::io::ErrorKind::AlreadyExists => StatusCode::CONFLICT,
+        std::io::ErrorKind::InvalidData => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        std::io::ErrorKind::InvalidInput => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    file_error(status, format!("{}: {}", path.display(), error))
+}
+
+/// File settings and roots of the current policy
+fn file_access(state: &AppState) -> (FilesConfig, FileAccess) {
+    let config = state.policy.current().config().files.clone();
+    let access = FileAccess::new(&config);
+    (config, access)
+}
+
+/// Resolve a requested path inside the roots
+/// 
+/// # Arguments
+/// * `follow` - Also check where a final symlink points, for operations that open the file
+fn confined_path(access: &FileAccess, path: &str, follow: bool) -> Result<PathBuf, (StatusCode, Json<CommandError>)> {
+    if path.trim().is_empty() {
+        return Err(file_error(StatusCode::BAD_REQUEST, "Path cannot be empty"));
+    }
+    let resolved = if follow { access.resolve_target(path) } else { access.resolve(path) };
+    resolved.map_err(|error| (StatusCode::FORBIDDEN, Json(CommandError { error, blocked: true })))
+}
+
+/// Run blocking file work off the async runtime
+async fn blocking_file_op<T: Send + 'static>(
+    path: PathBuf,
+    op: impl FnOnce(&std::path::Path) -> std::io::Result<T> + Send + 'static,
+) -> Result<T, (StatusCode, Json<CommandError>)> {
+    tokio::task::spawn_blocking(move || op(&path).map_err(|e| file_io_error(&path, e)))
+        .await
+        .map_err(|e| file_error(StatusCode::INTERNAL_SERVER_ERROR, e))?
+}
+
+/// Run a file-changing command through the execution policy
+/// 
+/// Deletes, moves and overwrites are ordinary `rm`, `mv` and `cp` commands,
+/// so they are approved, recorded and replayed like any other command.
+async fn submit_file_change(
+    state: &AppState,
+    config: &FilesConfig,
+    program: &str,
+    args: Vec<String>,
+    require_confirmation: Option<bool>,
+    conversation_id: Option<String>,
+) -> Result<CommandSubmission, (StatusCode, Json<CommandError>)> {
+    let payload = ExecuteCommandRequest {
+        command: program.to_string(),
+        args: Some(args),
+        require_confirmation: Some(config.confirm_changes || require_confirmation.unwrap_or(false)),
+        conversation_id,
+        ..Default::default()
+    };
+    submit_command(state, &payload).await
+}
+
+/// The directories the file API may access
+/// 
+/// # Returns
+/// * `Json<FileRootsResponse>` - Roots that exist, as configured in `[files]` of the policy
+pub async fn get_file_roots(State(state): State<AppState>) -> Json<FileRootsResponse> {
+    let (_, access) = file_access(&state);
+    let roots = access.roots().iter().filter_map(|root| files::entry(root).ok()).collect();
+    Json(FileRootsResponse { roots })
+}
+
+/// List a directory
+/// 
+/// # Arguments
+/// * `query` - Directory, defaulting to the first root, and whether to show hidden entries
+/// 
+/// # Returns
+/// * `Ok(Json<DirectoryListing>)` - Entries with metadata, directories first
+/// * `Err((StatusCode, Json<CommandError>))` - 400 not a directory, 403 outside the roots, 404 missing
+pub async fn list_files(
+    State(state): State<AppState>,
+    Query(query): Query<DirectoryQuery>,
+) -> Result<Json<DirectoryListing>, (StatusCode, Json<CommandError>)> {
+    let (_, access) = file_access(&state);
+    let path = match query.path {
+        Some(ref path) => confined_path(&access, path, true)?,
+        None => access.roots().into_iter().next().ok_or_else(|| file_error(StatusCode::NOT_FOUND, "No file roots exist"))?,
+    };
+    let hidden = query.hidden.unwrap_or(false);
+    let access = std::sync::Arc::new(access);
+    let listed = access.clone();
+    let entries = blocking_file_op(path.clone(), move |path| {
+        if !std::fs::metadata(path)?.is_dir() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Not a directory"));
+        }
+        files::list_directory(path, hidden, &listed)
+    })
+    .await?;
+    let parent = path
+        .parent()
+        .filter(|_| !access.is_root(&path))
+        .filter(|parent| access.permits(parent))
+        .map(|parent| parent.display().to_string());
+    Ok(Json(DirectoryListing { path: path.display().to_string(), parent, total: entries.len(), entries }))
+}
+
+/// Metadata of a file or directory, with the MIME type detected from its content
+/// 
+/// # Arguments
+/// * `query` - The path
+/// 
+/// # Returns
+/// * `Ok(Json<FileEntry>)` - The entry
+/// * `Err((StatusCode, Json<CommandError>))` - 403 outside the roots, 404 missing
+pub async fn get_file_info(
+    State(state): State<AppState>,
+    Query(query): Query<FilePathQuery>,
+) -> Result<Json<FileEntry>, (StatusCode, Json<CommandError>)> {
+    let (_, access) = file_access(&state);
+    let path = confined_path(&access, &query.path, false)?;
+    let entry = blocking_file_op(path, |path| {
+        let mut entry = files::entry(path)?;
+        if entry.kind == FileKind::File {
+            entry.mime = files::sniff(path).ok().or(entry.mime);
+        }
+        Ok(entry)
+    })
+    .await?;
+    Ok(Json(entry))
+}
+
+/// Read a page of a text file
+/// 
+/// Follow `next_offset` to read the rest of a large file.
+/// 
+/// # Arguments
+/// * `query` - Path, byte offset and length
+/// 
+/// # Returns
+/// * `Ok(Json<FileContent>)` - The text and the range it covers
+/// * `Err((StatusCode, Json<CommandError>))` - 403 outside the roots, 404 missing, 415 not text
+pub async fn read_file(
+    State(state): State<AppState>,
+    Query(query): Query<FileReadQuery>,
+) -> Result<Json<FileContent>, (StatusCode, Json<CommandError>)> {
+    let (config, access) = file_access(&state);
+    let path = confined_path(&access, &query.path, true)?;
+    let offset = query.offset.unwrap_or(0);
+    let length = query.length.unwrap_or(config.max_read_bytes).clamp(1, config.max_read_bytes.max(1));
+    let content = blocking_file_op(path, move |path| files::read_text(path, offset, length)).await?;
+    Ok(Json(content))
+}
+
+/// Checksum of a file
+/// 
+/// # Arguments
+/// * `query` - Path and algorithm
+/// 
+/// # Returns
+/// * `Ok(Json<FileChecksum>)` - Hex digest and the bytes hashed
+/// * `Err((StatusCode, Json<CommandError>))` - 400 unknown algorithm, 403 outside the roots, 404 missing
+pub async fn get_file_checksum(
+    State(state): State<AppState>,
+    Query(query): Query<FileChecksumQuery>,
+) -> Result<Json<FileChecksum>, (StatusCode, Json<CommandError>)> {
+    let algorithm = query.algorithm.unwrap_or_else(|| "sha256".to_string());
+    files::parse_algorithm(&algorithm).map_err(|error| file_error(StatusCode::BAD_REQUEST, error))?;
+    let (_, access) = file_access(&state);
+    let path = confined_path(&access, &query.path, true)?;
+    let checksum = blocking_file_op(path, move |path| {
+        if std::fs::metadata(path)?.is_dir() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Is a directory"));
+        }
+        files::checksum(path, &algorithm)
+    })
+    .await?;
+    Ok(Json(checksum))
+}
+
+/// Write a text file
+/// 
+/// New files are created right away. Replacing an existing file needs
+/// `overwrite` and becomes a `cp` of the new content over it, checked by
+/// the execution policy; the content is held back until that is approved.
+/// 
+/// # Arguments
+/// * `request` - Path, content and options
+/// 
+/// # Returns
+/// * `Ok(Response)` - `FileEntry` (201) for a new file, `ExecuteCommandResponse` (200) or `PendingCommand` (202) for an overwrite
+/// * `Err((StatusCode, Json<CommandError>))` - 403 outside the roots, 409 exists without `overwrite`, 413 too large
+pub async fn write_file(
+    State(state): State<AppState>,
+    Json(request): Json<FileWriteRequest>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    let (config, access) = file_access(&state);
+    let path = confined_path(&access, &request.path, true)?;
+    if request.content.len() as u64 > config.max_write_bytes {
+        return Err(file_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Content is {} bytes; at most {} can be written", request.content.len(), config.max_write_bytes),
+        ));
+    }
+
+    match std::fs::metadata(&path) {
+        Ok(metadata) if metadata.is_dir() => {
+            Err(file_error(StatusCode::CONFLICT, format!("{} is a directory", path.display())))
+        }
+        Ok(_) if !request.overwrite.unwrap_or(false) => Err(file_error(
+            StatusCode::CONFLICT,
+            format!("{} already exists; set overwrite to replace it", path.display()),
+        )),
+        Ok(_) => {
+            let content = request.content.into_bytes();
+            let max_age = std::time::Duration::from_secs(state.policy.current().config().confirmation_ttl_secs * 2);
+            let staged = blocking_file_op(files::staging_dir(), move |dir| files::stage(dir, &content, max_age)).await?;
+            info!("Requesting overwrite of {}", path.display());
+            let args = vec!["--".to_string(), staged.display().to_string(), path.display().to_string()];
+            let submission =
+                submit_file_change(&state, &config, "cp", args, request.require_confirmation, request.conversation_id).await;
+            // Staged content is only needed while the overwrite awaits approval
+            if !matches!(submission, Ok(CommandSubmission::Pending(_))) {
+                let _ = std::fs::remove_file(&staged);
+            }
+            Ok(submission?.into_response())
+        }
+        Err(_) => {
+            let content = request.content.into_bytes();
+            let create_dirs = request.create_dirs.unwrap_or(false);
+            info!("Creating {}", path.display());
+            let entry = blocking_file_op(path, move |path| {
+                files::create_file(path, &content, create_dirs)?;
+                files::entry(path)
+            })
+            .await?;
+            Ok((StatusCode::CREATED, Json(entry)).into_response())
+        }
+    }
+}
+
+/// Create a directory
+/// 
+/// # Arguments
+/// * `request` - Path and whether to create parents
+/// 
+/// # Returns
+/// * `Ok((StatusCode, Json<FileEntry>))` - 201 with the new directory
+/// * `Err((StatusCode, Json<CommandError>))` - 403 outside the roots, 404 missing parent, 409 exists
+pub async fn create_directory(
+    State(state): State<AppState>,
+    Json(request): Json<CreateDirectoryRequest>,
+) -> Result<(StatusCode, Json<FileEntry>), (StatusCode, Json<CommandError>)> {
+    let (_, access) = file_access(&state);
+    let path = confined_path(&access, &request.path, true)?;
+    let parents = request.parents.unwrap_or(false);
+    info!("Creating directory {}", path.display());
+    let entry = blocking_file_op(path, move |path| {
+        if parents {
+            if path.exists() {
+                return Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists));
+            }
+            std::fs::create_dir_all(path)?;
+        } else {
+            std::fs::create_dir(path)?;
+        }
+        files::entry(path)
+    })
+    .await?;
+    Ok((StatusCode::CREATED, Json(entry)))
+}
+
+/// Move `from` to `to` with `mv`, both already resolved
+async fn move_path(
+    state: &AppState,
+    config: &FilesConfig,
+    access: &FileAccess,
+    from: PathBuf,
+    to: PathBuf,
+    request: FileMoveRequest,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    let overwrite = request.overwrite.unwrap_or(false);
+    if access.is_root(&from) {
+        return Err(file_error(StatusCode::FORBIDDEN, format!("{} is a root and cannot be moved", from.display())));
+    }
+    if let Err(e) = std::fs::symlink_metadata(&from) {
+        return Err(file_io_error(&from, e));
+    }
+    if from == to {
+        return Err(file_error(StatusCode::BAD_REQUEST, "Source and destination are the same"));
+    }
+    if !overwrite && std::fs::symlink_metadata(&to).is_ok() {
+        return Err(file_error(
+            StatusCode::CONFLICT,
+            format!("{} already exists; set overwrite to replace it", to.display()),
+        ));
+    }
+
+    info!("Requesting move of {} to {}", from.display(), to.display());
+    // -T treats `to` as the new name even if it is a directory
+    let mut args = vec!["-T".to_string(), if overwrite { "-f" } else { "-n" }.to_string(), "--".to_string()];
+    args.push(from.display().to_string());
+    args.push(to.display().to_string());
+    Ok(submit_file_change(state, config, "mv", args, request.require_confirmation, request.conversation_id)
+        .await?
+        .into_response())
+}
+
+/// Move or rename a file or directory
+/// 
+/// Runs `mv` through the execution policy, which asks for confirmation
+/// of file changes by default.
+/// 
+/// # Arguments
+/// * `request` - Source, destination and options
+/// 
+/// # Returns
+/// * `Ok(Response)` - `ExecuteCommandResponse` (200) or `PendingCommand` (202)
+/// * `Err((StatusCode, Json<CommandError>))` - 403 outside the roots or blocked, 404 missing, 409 destination exists
+pub async fn move_file(
+    State(state): State<AppState>,
+    Json(request): Json<FileMoveRequest>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    let (config, access) = file_access(&state);
+    let from = confined_path(&access, &request.from, false)?;
+    let to = confined_path(&access, &request.to, false)?;
+    move_path(&state, &config, &access, from, to, request).await
+}
+
+/// Rename a file or directory within its directory
+/// 
+/// # Arguments
+/// * `request` - Path and new name
+/// 
+/// # Returns
+/// * `Ok(Response)` - `ExecuteCommandResponse` (200) or `PendingCommand` (202)
+/// * `Err((StatusCode, Json<CommandError>))` - 400 invalid name, otherwise as for moves
+pub async fn rename_file(
+    State(state): State<AppState>,
+    Json(request): Json<FileRenameRequest>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    if !files::is_plain_name(&request.name) {
+        return Err(file_error(StatusCode::BAD_REQUEST, format!("Invalid name {:?}", request.name)));
+    }
+    let (config, access) = file_access(&state);
+    let from = confined_path(&access, &request.path, false)?;
+    let to = match from.parent() {
+        Some(parent) => confined_path(&access, &parent.join(&request.name).display().to_string(), false)?,
+        None => return Err(file_error(StatusCode::BAD_REQUEST, "Cannot rename the filesystem root")),
+    };
+    let options = FileMoveRequest {
+        from: request.path,
+        to: request.name,
+        overwrite: request.overwrite,
+        require_confirmation: request.require_confirmation,
+        conversation_id: request.conversation_id,
+    };
+    move_path(&state, &config, &access, from, to, options).await
+}
+
+/// Delete a file or directory
+/// 
+/// Runs `rm`, or `rmdir` for directories unless `recursive` is set,
+/// through the execution policy. Symlinks are removed, not their targets.
+/// 
+/// # Arguments
+/// * `query` - Path and options
+/// 
+/// # Returns
+/// * `Ok(Response)` - `ExecuteCommandResponse` (200) or `PendingCommand` (202)
+/// * `Err((StatusCode, Json<CommandError>))` - 403 outside the roots, a root, or blocked; 404 missing
+pub async fn delete_file(
+    State(state): State<AppState>,
+    Query(query): Query<FileDeleteQuery>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    let (config, access) = file_access(&state);
+    let path = confined_path(&access, &query.path, false)?;
+    if access.is_root(&path) {
+        return Err(file_error(StatusCode::FORBIDDEN, format!("{} is a root and cannot be deleted", path.display())));
+    }
+    let metadata = std::fs::symlink_metadata(&path).map_err(|e| file_io_error(&path, e))?;
+    let (program, mut args) = match (metadata.is_dir(), query.recursive.unwrap_or(false)) {
+        (true, true) => ("rm", vec!["-r".to_string()]),
+        (true, false) => ("rmdir", Vec::new()),
+        (false, _) => ("rm", Vec::new()),
+    };
+    args.push("--".to_string());
+    args.push(path.display().to_string());
+
+    info!("Requesting {} of {}", program, path.display());
+    Ok(submit_file_change(&state, &config, program, args, query.require_confirmation, query.conversation_id)
+        .await?
+        .into_response())
+}
+
+/// Request structure for adding a directory to the file index
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexRootRequest {
+    pub path: String,
+}
+
+/// Response structure for the indexed directories
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexRootListResponse {
+    pub roots: Vec<IndexRoot>,
+}
+
+/// Query parameters for a full-text search of indexed files
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IndexSearchQuery {
+    /// Words that must all appear; "quoted phrases" and prefix* are supported
+    pub q: String,
+    /// Only files below this indexed directory
+    pub root: Option<i64>,
+    /// Results per page (default 20, at most 200)
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Response structure for full-text searches
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexSearchResponse {
+    /// Best matches first
+    pub results: Vec<ContentMatch>,
+}
+
+/// Query parameters for finding indexed files by name
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IndexedFileQuery {
+    /// Glob matched against the file name, or against the path if it contains `/`
+    pub glob: Option<String>,
+    /// Regular expression searched for in the full path
+    pub regex: Option<String>,
+    /// Only files below this indexed directory
+    pub root: Option<i64>,
+    /// Files to return (default 20, at most 200)
+    pub limit: Option<usize>,
+}
+
+/// Response structure for file name searches
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexedFileListResponse {
+    /// Matching files by path
+    pub files: Vec<IndexedFile>,
+    /// Number of matching files, including those past the limit
+    pub total: usize,
+}
+
+/// Index search results returned unless the caller asks for fewer or more
+const DEFAULT_INDEX_RESULTS: usize = 20;
+const MAX_INDEX_RESULTS: usize = 200;
+
+/// What the file indexer is doing and what it covers
+/// 
+/// # Returns
+/// * `Ok(Json<IndexStatus>)` - Scan and watch state, file counts and indexed directories
+/// * `Err((StatusCode, Json<CommandError>))` - Database error
+pub async fn get_index_status(State(state): State<AppState>) -> Result<Json<IndexStatus>, (StatusCode, Json<CommandError>)> {
+    state.indexer.status().map(Json).map_err(|e| file_error(StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+/// Directories covered by the file index
+/// 
+/// # Returns
+/// * `Ok(Json<IndexRootListResponse>)` - Directories by path with their file counts
+/// * `Err((StatusCode, Json<CommandError>))` - Database error
+pub async fn get_index_roots(
+    State(state): State<AppState>,
+) -> Result<Json<IndexRootListResponse>, (StatusCode, Json<CommandError>)> {
+    let db = state.db.get().unwrap();
+    crate::db::queries::get_index_roots(&db)
+        .map(|roots| Json(IndexRootListResponse { roots }))
+        .map_err(|e| file_error(StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+/// Add a directory to the file index
+/// 
+/// The directory has to be inside the file API's roots. It is scanned in
+/// the background right away.
+/// 
+/// # Arguments
+/// * `request` - The directory
+/// 
+/// # Returns
+/// * `Ok((StatusCode::CREATED, Json<IndexRoot>))` - The indexed directory
+/// * `Err((StatusCode, Json<CommandError>))` - 400 not a directory, 403 outside the roots, 409 overlaps an indexed directory
+pub async fn add_index_root(
+    State(state): State<AppState>,
+    Json(request): Json<IndexRootRequest>,
+) -> Result<(StatusCode, Json<IndexRoot>), (StatusCode, Json<CommandError>)> {
+    let access = state.indexer.access();
+    let path = confined_path(&access, &request.path, true)?;
+    let path = std::fs::canonicalize(&path).map_err(|e| file_io_error(&path, e))?;
+    if !path.is_dir() {
+        return Err(file_error(StatusCode::BAD_REQUEST, format!("{} is not a directory", path.display())));
+    }
+
+    let db = state.db.get().unwrap();
+    let roots = crate::db::queries::get_index_roots(&db).map_err(|e| file_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    // Nested directories would claim the same files
+    if let Some(existing) = roots
+        .iter()
+        .find(|root| path.starts_with(&root.path) || std::path::Path::new(&root.path).starts_with(&path))
+    {
+        return Err(file_error(
+            StatusCode::CONFLICT,
+            format!("{} overlaps the indexed directory {}", path.display(), existing.path),
+        ));
+    }
+    let path = path.display().to_string();
+    let id = crate::db::queries::insert_index_root(&db, &path).map_err(|e| file_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let root = crate::db::queries::get_index_root(&db, id)
+        .map_err(|e| file_error(StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(|| file_error(StatusCode::INTERNAL_SERVER_ERROR, "Indexed directory vanished"))?;
+    info!("Indexing {}", path);
+    state.indexer.rescan();
+    Ok((StatusCode::CREATED, Json(root)))
+}
+
+/// Stop indexing a directory and forget its files
+/// 
+/// # Returns
+/// * `Ok(StatusCode::NO_CONTENT)` - Removed
+/// * `Err((StatusCode, Json<CommandError>))` - 404 if unknown
+pub async fn remove_index_root(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, (StatusCode, Json<CommandError>)> {
+    let db = state.db.get().unwrap();
+    match crate::db::queries::delete_index_root(&db, id) {
+        Ok(true) => {
+            state.indexer.rescan();
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err(file_error(StatusCode::NOT_FOUND, format!("Unknown indexed directory: {}", id))),
+        Err(e) => Err(file_error(StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+/// Rescan every indexed directory now
+/// 
+/// # Returns
+/// * `Ok((StatusCode::ACCEPTED, Json<IndexStatus>))` - The status before the rescan starts
+/// * `Err((StatusCode, Json<CommandError>))` - Database error
+pub async fn refresh_index(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<IndexStatus>), (StatusCode, Json<CommandError>)> {
+    state.indexer.rescan();
+    let status = state.indexer.status().map_err(|e| file_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok((StatusCode::ACCEPTED, Json(status)))
+}
+
+/// Full-text search over the paths and text of indexed files
+/// 
+/// # Returns
+/// * `Ok(IndexSearchResponse)` - Matches with snippets, best first
+/// * `Err((StatusCode, Json<CommandError>))` - 400 without search words
+pub fn search_file_index(state: &AppState, query: &IndexSearchQuery) -> Result<IndexSearchResponse, (StatusCode, Json<CommandError>)> {
+    let fts = indexer::fts_query(&query.q).ok_or_else(|| file_error(StatusCode::BAD_REQUEST, "Search text cannot be empty"))?;
+    let limit = query.limit.unwrap_or(DEFAULT_INDEX_RESULTS as i64).clamp(1, MAX_INDEX_RESULTS as i64);
+    let db = state.db.get().unwrap();
+    let results = crate::db::queries::search_file_content(&db, &fts, query.root, limit, query.offset.unwrap_or(0).max(0))
+        .map_err(|e| file_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(IndexSearchResponse { results })
+}
+
+/// Indexed files whose path matches a glob or regular expression
+/// 
+/// # Returns
+/// * `Ok(IndexedFileListResponse)` - Matching files by path
+/// * `Err((StatusCode, Json<CommandError>))` - 400 without exactly one valid pattern
+pub fn find_indexed_files(
+    state: &AppState,
+    query: &IndexedFileQuery,
+) -> Result<IndexedFileListResponse, (StatusCode, Json<CommandError>)> {
+    let matcher = match (query.glob.as_deref(), query.regex.as_deref()) {
+        (Some(glob), None) => indexer::name_matcher(glob),
+        (None, Some(pattern)) => regex::Regex::new(pattern).map_err(|e| format!("Invalid regex {:?}: {}", pattern, e)),
+        _ => Err("Give either glob or regex".to_string()),
+    }
+    .map_err(|error| file_error(StatusCode::BAD_REQUEST, error))?;
+
+    let db = state.db.get().unwrap();
+    let files: Vec<IndexedFile> = crate::db::queries::get_indexed_files(&db, query.root)
+        .map_err(|e| file_error(StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .into_iter()
+        .filter(|file| matcher.is_match(&file.path))
+        .collect();
+    let total = files.len();
+    let limit = query.limit.unwrap_or(DEFAULT_INDEX_RESULTS).clamp(1, MAX_INDEX_RESULTS);
+    Ok(IndexedFileListResponse { files: files.into_iter().take(limit).collect(), total })
+}
+
+/// Search the text of indexed files
+/// 
+/// # Arguments
+/// * `query` - Search words, indexed directory and page
+/// 
+/// # Returns
+/// * `Ok(Json<IndexSearchResponse>)` - Matching files with the text around each match
+/// * `Err((StatusCode, Json<CommandError>))` - 400 without search words
+pub async fn search_index(
+    State(state): State<AppState>,
+    Query(query): Query<IndexSearchQuery>,
+) -> Result<Json<IndexSearchResponse>, (StatusCode, Json<CommandError>)> {
+    search_file_index(&state, &query).map(Json)
+}
+
+/// Find indexed files by name
+/// 
+/// # Arguments
+/// * `query` - `glob` or `regex`, indexed directory and limit
+/// 
+/// # Returns
+/// * `Ok(Json<IndexedFileListResponse>)` - Matching files
+/// * `Err((StatusCode, Json<CommandError>))` - 400 for a missing or invalid pattern
+pub async fn get_indexed_files(
+    State(state): State<AppState>,
+    Query(query): Query<IndexedFileQuery>,
+) -> Result<Json<IndexedFileListResponse>, (StatusCode, Json<CommandError>)> {
+    find_indexed_files(&state, &query).map(Json)
+}
+
+/// Request structure for executing system commands
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ExecuteCommandRequest {
+    /// The command to execute
+    pub command: String,
+    /// Optional arguments for the command
+    pub args: Option<Vec<String>>,
+    /// Working directory for the command (optional)
+    pub working_dir: Option<String>,
+    /// Whether to require user confirmation for this command
+    pub require_confirmation: Option<bool>,
+    /// Wall-clock timeout in seconds, capped by the policy's `max_timeout_secs`
+    pub timeout_secs: Option<u64>,
+    /// Conversation that asked for the command, recorded in the history
+    pub conversation_id: Option<String>,
+    /// Run `command` as a script with `/bin/sh`; `args` become the script's
+    /// positional parameters (`"$1"`, `"$@"`) instead of being interpolated
+    pub shell: Option<bool>,
+    /// Environment profile from the policy's `[environment]` section
+    pub profile: Option<String>,
+    /// Variables to set for this command; `null` removes an inherited one
+    pub env: Option<BTreeMap<String, Option<String>>>,
+}
+
+/// Response structure for command execution
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExecuteCommandResponse {
+    /// Whether the command executed successfully
+    pub success: bool,
+    /// The command that was executed
+    pub command: String,
+    /// Standard output from the command
+    pub stdout: String,
+    /// Standard error from the command
+    pub stderr: String,
+    /// Exit code from the command
+    pub exit_code: i32,
+    /// Execution time in milliseconds
+    pub execution_time_ms: u64,
+    /// Timestamp when the command was executed
+    pub timestamp: String,
+    /// Whether the command was killed because it exceeded its timeout
+    pub timed_out: bool,
+    /// Whether stdout was cut off at the output limit
+    pub stdout_truncated: bool,
+    /// Whether stderr was cut off at the output limit
+    pub stderr_truncated: bool,
+    /// Command history entry of this run
+    pub history_id: Option<i64>,
+}
+
+/// Error response for command execution
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandError {
+    /// Human-readable error message
+    pub error: String,
+    /// Whether the command was blocked for safety reasons
+    pub blocked: bool,
+}
+
+/// Command history entry
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandHistory {
+    pub id: i64,
+    pub command: String,
+    pub args: Option<String>,
+    pub working_dir: Option<String>,
+    pub success: bool,
+    pub exit_code: i32,
+    pub execution_time_ms: u64,
+    pub timestamp: String,
+    pub user_confirmed: bool,
+    /// Terminal session whose shell this entry records
+    pub terminal_session_id: Option<String>,
+    /// Captured output, cut at the policy's `history_output_bytes`; only
+    /// included in lists when `include_output` is set
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+    pub timed_out: bool,
+    /// Environment variables that differed from the inherited ones; secrets
+    /// are redacted and `null` marks a variable that was removed
+    pub env: BTreeMap<String, Option<String>>,
+    /// Conversation that asked for the command
+    pub conversation_id: Option<String>,
+    /// History entry this run replayed
+    pub replay_of: Option<i64>,
+    /// Environment profile the command ran with
+    pub profile: Option<String>,
+}
+
+/// A finished command to record in the history
+#[derive(Debug, Clone, Default)]
+pub struct CommandRecord {
+    pub command: String,
+    pub args: Vec<String>,
+    pub working_dir: String,
+    pub success: bool,
+    pub exit_code: i32,
+    pub execution_time_ms: u64,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+    pub timed_out: bool,
+    pub env: BTreeMap<String, Option<String>>,
+    pub profile: Option<String>,
+    pub origin: CommandOrigin,
+}
+
+impl CommandRecord {
+    /// Record of a command about to run, to be completed with its outcome
+    pub fn new(spec: &CommandSpec, origin: CommandOrigin) -> Self {
+        CommandRecord {
+            command: spec.program.clone(),
+            args: spec.args.clone(),
+            working_dir: spec.cwd.display().to_string(),
+            env: spec.env.changes.clone(),
+            profile: spec.env.profile.clone(),
+            origin,
+            ..Default::default()
+        }
+    }
+
+    /// Attach output, cutting each stream at `cap` bytes
+    pub fn with_output(mut self, stdout: &str, stderr: &str, cap: usize) -> Self {
+        let (stdout, stdout_cut) = truncate_output(stdout, cap);
+        let (stderr, stderr_cut) = truncate_output(stderr, cap);
+        self.stdout = Some(stdout);
+        self.stderr = Some(stderr);
+        self.stdout_truncated |= stdout_cut;
+        self.stderr_truncated |= stderr_cut;
+        self
+    }
+}
+
+/// Who asked for a command, carried from the request to its history entry
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CommandOrigin {
+    /// Whether the user explicitly approved the run
+    pub user_confirmed: bool,
+    pub conversation_id: Option<String>,
+    /// History entry being replayed
+    pub replay_of: Option<i64>,
+    /// Application being launched
+    pub app_id: Option<String>,
+    /// Start time of the process a signal is for, stored with a pending signal
+    pub process_start: Option<u64>,
+}
+
+/// Query structure for command history
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandHistoryQuery {
+    pub limit: Option<i32>,
+    pub offset: Option<i32>,
+    pub success_only: Option<bool>,
+    /// Program name or path; `git` also matches `/usr/bin/git`
+    pub program: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub exit_code: Option<i32>,
+    /// Text to find in the command line, working directory or output
+    pub q: Option<String>,
+    pub conversation_id: Option<String>,
+    /// Include stdout and stderr in the listed entries
+    pub include_output: Option<bool>,
+}
+
+/// Request structure for replaying a history entry
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReplayCommandRequest {
+    /// Wall-clock timeout in seconds, capped by the policy
+    pub timeout_secs: Option<u64>,
+    /// Whether to require user confirmation even if the policy allows the command
+    pub require_confirmation: Option<bool>,
+    /// Run as a background job instead of waiting for the output
+    pub background: Option<bool>,
+    /// Conversation that asked for the replay
+    pub conversation_id: Option<String>,
+}
+
+/// Response structure for command history
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandHistoryResponse {
+    pub commands: Vec<CommandHistory>,
+    pub total: i64,
+}
+
+/// A command waiting for the user to approve or reject it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingCommand {
+    pub id: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub working_dir: String,
+    /// Human-readable explanation of why confirmation is needed
+    pub explanation: String,
+    /// Individual reasons given by the policy rules
+    pub reasons: Vec<String>,
+    /// Names of the policy rules that asked for confirmation
+    pub rules: Vec<String>,
+    /// pending, approved, rejected or expired
+    pub status: String,
+    pub created_at: String,
+    /// After this time the command can no longer be approved
+    pub expires_at: String,
+    pub decided_at: Option<String>,
+    /// Command history entry of the approved run
+    pub history_id: Option<i64>,
+    /// Timeout requested together with the command
+    pub timeout_secs: Option<u64>,
+    /// Whether the command runs as a background job once approved
+    pub background: bool,
+    /// Whether the command is the program of a terminal session, started by
+    /// the waiting WebSocket once approved
+    pub terminal: bool,
+    /// Conversation that asked for the command
+    pub conversation_id: Option<String>,
+    /// History entry this command replays
+    pub replay_of: Option<i64>,
+    /// Environment overrides requested with the command
+    pub env: BTreeMap<String, Option<String>>,
+    /// Environment profile the command runs with
+    pub profile: Option<String>,
+    /// Whether the command is an application launch, started detached once approved
+    pub detached: bool,
+    /// Application the launch belongs to
+    pub app_id: Option<String>,
+    /// Start time of the process a signal is for (see `process_start_ticks`);
+    /// the signal is not sent if the PID belongs to another process by then
+    pub process_start: Option<u64>,
+}
+
+/// Query parameters for the application list
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppListQuery {
+    /// Include entries that ask not to be shown in menus
+    pub all: Option<bool>,
+}
+
+/// Response structure for the application list
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppListResponse {
+    pub applications: Vec<App>,
+}
+
+/// Query parameters for searching applications
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AppSearchQuery {
+    /// Search text; without it the most used applications are listed
+    pub q: Option<String>,
+    /// Only return applications in this category
+    pub category: Option<String>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    /// Include entries that ask not to be shown in menus
+    pub all: Option<bool>,
+}
+
+/// Response structure for an application search
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppSearchResponse {
+    pub query: Option<String>,
+    /// Terms the query was reduced to
+    pub terms: Vec<String>,
+    /// Number of matches across all pages
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub results: Vec<AppMatch>,
+    /// Matches per category, ignoring the category filter
+    pub facets: Vec<CategoryFacet>,
+}
+
+/// Request structure for launching an application
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LaunchAppRequest {
+    /// Files or URLs to open with the application
+    #[serde(default)]
+    pub targets: Vec<String>,
+    /// Working directory; defaults to the entry's `Path`, then the home directory
+    pub working_dir: Option<String>,
+    /// Whether to require user confirmation even if the policy allows the launch
+    pub require_confirmation: Option<bool>,
+    /// Conversation that asked for the launch
+    pub conversation_id: Option<String>,
+    /// Environment profile for the application
+    pub profile: Option<String>,
+}
+
+/// A started application process
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LaunchedApp {
+    pub pid: u32,
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+/// Response structure for an application launch
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LaunchAppResponse {
+    pub app_id: String,
+    /// Processes started right away
+    pub launched: Vec<LaunchedApp>,
+    /// Launches waiting for confirmation
+    pub pending: Vec<PendingCommand>,
+}
+
+/// Query structure for pending commands
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingCommandQuery {
+    /// pending (default), approved, rejected, expired or all
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Response structure for pending commands
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingCommandResponse {
+    pub commands: Vec<PendingCommand>,
+}
+
+/// Request structure for writing to a job's standard input
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobStdinRequest {
+    /// Text to write, sent as-is (include a trailing newline for line input)
+    pub data: String,
+    /// Close stdin after writing
+    pub close: Option<bool>,
+}
+
+/// Response structure for the job list
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobListResponse {
+    pub jobs: Vec<JobInfo>,
+}
+
+/// A job together with its recorded output
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobDetails {
+    #[serde(flatten)]
+    pub job: JobInfo,
+    pub output: Vec<JobEvent>,
+}
+
+/// Query parameters for opening a terminal session
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TerminalRequest {
+    /// Program to run; defaults to the user's shell (`$SHELL`, else `/bin/sh`)
+    pub shell: Option<String>,
+    /// Start the shell as a login shell (`-l`)
+    pub login: Option<bool>,
+    /// Working directory; defaults to the home directory
+    pub working_dir: Option<String>,
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
+    /// Whether to require user confirmation even if the policy allows the program
+    pub require_confirmation: Option<bool>,
+    /// Conversation that opened the session, recorded in the history
+    pub conversation_id: Option<String>,
+    /// Environment profile for the shell
+    pub profile: Option<String>,
+}
+
+/// An interactive terminal session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalSession {
+    pub id: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub working_dir: String,
+    /// running, completed, failed, closed (by the client) or interrupted (by a restart)
+    pub status: String,
+    pub exit_code: Option<i32>,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    pub user_confirmed: bool,
+    /// Command history entry written when the session ended
+    pub history_id: Option<i64>,
+    /// Whether the transcript was cut off at the output limit
+    pub transcript_truncated: bool,
+}
+
+/// Control message sent by a terminal client as a text frame
+/// 
+/// Binary frames are passed to the terminal as input unchanged.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TerminalClientMessage {
+    Input { data: String },
+    Resize { cols: u16, rows: u16 },
+}
+
+/// Control message sent to a terminal client as a text frame
+/// 
+/// Terminal output is sent as binary frames.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TerminalServerMessage {
+    /// The program needs confirmation; the session starts once it is approved
+    Pending { command: Box<PendingCommand> },
+    Started { session: TerminalSession },
+    /// Always the last message of a session
+    Exit { session: TerminalSession },
+    Error { message: String },
+}
+
+/// Query structure for terminal sessions
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TerminalSessionQuery {
+    pub limit: Option<i64>,
+}
+
+/// Query structure for a terminal session's transcript
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TerminalTranscriptQuery {
+    /// Keep terminal escape sequences instead of returning plain text
+    pub raw: Option<bool>,
+}
+
+/// Response structure for the terminal session list
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TerminalSessionListResponse {
+    pub sessions: Vec<TerminalSession>,
+}
+
+/// A terminal session together with its transcript
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TerminalSessionDetails {
+    #[serde(flatten)]
+    pub session: TerminalSession,
+    /// Output of the session; `None` while it is still running
+    pub transcript: Option<String>,
+}
+
+/// Request structure for checking a command against the policy
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PolicyCheckRequest {
+    pub command: String,
+    pub args: Option<Vec<String>>,
+    pub working_dir: Option<String>,
+    /// Check `command` as a shell-mode script
+    pub shell: Option<bool>,
+}
+
+/// Response structure describing the active command policy
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PolicyResponse {
+    /// Path of the policy file
+    pub path: String,
+    /// Whether the rules came from the file or the built-in default
+    pub source: PolicySource,
+    pub policy: PolicyConfig,
+}
+
+/// Execute a system command with safety measures
+/// 
+/// This endpoint allows Leara to execute system commands. The program, its
+/// arguments and the working directory are checked against the command
+/// policy first. Denied commands are rejected with 403. Commands that need
+/// confirmation are not run; instead a pending command is returned with 202
+/// and runs only once it is approved through `/pending/:id/approve`.
+/// 
+/// # Arguments
+/// * `payload` - Command execution request with safety parameters
+/// 
+/// # Returns
+/// * `Ok(Response)` - `ExecuteCommandResponse` (200) or `PendingCommand` (202)
+/// * `Err((StatusCode, Json<CommandError>))` - Error response
+pub async fn execute_command(
+    State(state): State<AppState>,
+    Json(payload): Json<ExecuteCommandRequest>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    match submit_command(&state, &payload).await? {
+        CommandSubmission::Pending(pending) => Ok((StatusCode::ACCEPTED, Json(pending)).into_response()),
+        CommandSubmission::Completed(response) => Ok(Json(response).into_response()),
+    }
+}
+
+/// Outcome of a command submitted through the policy
+/// 
+/// Endpoints that parse a command's output carry the parsed result instead
+/// of the raw `ExecuteCommandResponse`.
+#[derive(Debug)]
+pub enum CommandSubmission<T = ExecuteCommandResponse> {
+    /// The command ran
+    Completed(T),
+    /// The command awaits confirmation
+    Pending(Box<PendingCommand>),
+}
+
+impl<T> CommandSubmission<T> {
+    /// Replace the result of a completed command
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> CommandSubmission<U> {
+        match self {
+            CommandSubmission::Completed(result) => CommandSubmission::Completed(f(result)),
+            CommandSubmission::Pending(pending) => CommandSubmission::Pending(pending),
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for CommandSubmission<T> {
+    /// The result (200), or the pending command (202)
+    fn into_response(self) -> Response {
+        match self {
+            CommandSubmission::Completed(result) => Json(result).into_response(),
+            CommandSubmission::Pending(pending) => (StatusCode::ACCEPTED, Json(pending)).into_response(),
+        }
+    }
+}
+
+/// Check a command against the policy and run it if allowed
+/// 
+/// This is what `/execute` does, available to other parts of Leara such as
+/// the chat tools so every command goes through the same checks.
+/// 
+/// # Arguments
+/// * `state` - Application state
+/// * `payload` - Command execution request
+/// 
+/// # Returns
+/// * `Ok(CommandSubmission)` - The command's output, or the pending command awaiting confirmation
+/// * `Err((StatusCode, Json<CommandError>))` - Denied (403), invalid (400) or failed to start
+pub async fn submit_command(
+    state: &AppState,
+    payload: &ExecuteCommandRequest,
+) -> Result<CommandSubmission, (StatusCode, Json<CommandError>)> {
+    let spec = command_spec(state, payload)?;
+    let origin = CommandOrigin { conversation_id: payload.conversation_id.clone(), ..Default::default() };
+    match authorize_command(state, payload, &spec, CommandMode::Foreground, &origin)? {
+        Some(pending) => Ok(CommandSubmission::Pending(Box::new(pending))),
+        None => Ok(CommandSubmission::Completed(run_command(state, &spec, payload.timeout_secs, origin).await?)),
+    }
+}
+
+/// Resolve what a request runs
+/// 
+/// The working directory defaults to the server's directory, and relative
+/// ones are taken relative to it. In shell mode the command becomes a
+/// `/bin/sh -c` script with the arguments as its positional parameters.
+/// 
+/// # Returns
+/// * `Ok(CommandSpec)` - Program, arguments, working directory and environment
+/// * `Err((StatusCode, Json<CommandError>))` - 400 for an unknown profile or invalid variable
+fn command_spec(state: &AppState, payload: &ExecuteCommandRequest) -> Result<CommandSpec, (StatusCode, Json<CommandError>)> {
+    let args = payload.args.clone().unwrap_or_default();
+    let (program, args) = if payload.shell == Some(true) {
+        shell_command(&payload.command, &args)
+    } else {
+        (payload.command.clone(), args)
+    };
+    let cwd = working_dir(payload.working_dir.as_deref());
+    let env = resolve_environment(state, payload.profile.as_deref(), &payload.env.clone().unwrap_or_default())?;
+    Ok(CommandSpec { program, args, cwd, env })
+}
+
+/// Absolute working directory for a request
+/// 
+/// The path is resolved against the server's directory and normalized, so
+/// the policy judges exactly the directory the command starts in.
+fn working_dir(requested: Option<&str>) -> PathBuf {
+    let server_dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+    match requested {
+        Some(dir) => normalize_lexically(&server_dir.join(dir)),
+        None => server_dir,
+    }
+}
+
+/// Build a command's environment under the current policy
+fn resolve_environment(
+    state: &AppState,
+    profile: Option<&str>,
+    overrides: &BTreeMap<String, Option<String>>,
+) -> Result<CommandEnvironment, (StatusCode, Json<CommandError>)> {
+    state.policy.current().config().environment.resolve(profile, overrides).map_err(|error| {
+        (StatusCode::BAD_REQUEST, Json(CommandError { error, blocked: false }))
+    })
+}
+
+/// How a command that passed the policy is run
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CommandMode {
+    /// Run now and return the output
+    Foreground,
+    /// Run as a background job
+    Background,
+    /// Run on a pseudo-terminal relayed over a WebSocket
+    Terminal,
+    /// Start detached from the server, like an application launcher
+    Launch,
+}
+
+/// Check a request against the execution policy
+/// 
+/// Commands that need confirmation are stored as pending commands.
+/// Overriding protected environment variables such as `LD_PRELOAD` always
+/// needs confirmation.
+/// 
+/// # Arguments
+/// * `state` - Application state
+/// * `payload` - The request
+/// * `spec` - Resolved program, arguments, working directory and environment
+/// * `mode` - How the command runs once approved
+/// * `origin` - Conversation and replayed entry, stored with a pending command
+/// 
+/// # Returns
+/// * `Ok(None)` - The command may run now
+/// * `Ok(Some(PendingCommand))` - The command awaits confirmation
+/// * `Err((StatusCode, Json<CommandError>))` - The command is denied (403) or could not be stored
+fn authorize_command(
+    state: &AppState,
+    payload: &ExecuteCommandRequest,
+    spec: &CommandSpec,
+    mode: CommandMode,
+    origin: &CommandOrigin,
+) -> Result<Option<PendingCommand>, (StatusCode, Json<CommandError>)> {
+    // Evaluate the full command line against the execution policy
+    let policy = state.policy.current();
+    let mut decision = policy.evaluate(&spec.program, &spec.args, &spec.cwd);
+    if payload.require_confirmation == Some(true) && decision.action == PolicyAction::Allow {
+        decision.action = PolicyAction::Confirm;
+        decision.rules.clear();
+        decision.reasons = vec!["Confirmation was requested by the caller".to_string()];
+    }
+    if !spec.env.protected.is_empty() && decision.action != PolicyAction::Deny {
+        if decision.action == PolicyAction::Allow {
+            decision.action = PolicyAction::Confirm;
+            decision.rules.clear();
+            decision.reasons.clear();
+        }
+        decision.reasons.push(format!(
+            "Sets {}, which changes how programs are found or loaded",
+            spec.env.protected.join(", ")
+        ));
+    }
+
+    match decision.action {
+        PolicyAction::Deny => {
+            info!("Blocked command {} {:?}: {}", spec.program, spec.args, decision.explanation());
+            Err((StatusCode::FORBIDDEN, Json(CommandError {
+                error: format!("Command blocked by policy: {}", decision.explanation()),
+                blocked: true,
+            })))
+        }
+        PolicyAction::Confirm => {
+            let now = chrono::Utc::now();
+            let ttl = chrono::Duration::seconds(policy.config().confirmation_ttl_secs as i64);
+            let pending = PendingCommand {
+                id: uuid::Uuid::new_v4().to_string(),
+                explanation: format!("`{}` needs your confirmation: {}", command_line(&spec.program, &spec.args), decision.explanation()),
+                command: spec.program.clone(),
+                args: spec.args.clone(),
+                working_dir: spec.cwd.display().to_string(),
+                reasons: decision.reasons,
+                rules: decision.rules,
+                status: "pending".to_string(),
+                created_at: now.to_rfc3339(),
+                expires_at: (now + ttl).to_rfc3339(),
+                decided_at: None,
+                history_id: None,
+                timeout_secs: payload.timeout_secs,
+                background: mode == CommandMode::Background,
+                terminal: mode == CommandMode::Terminal,
+                conversation_id: origin.conversation_id.clone(),
+                replay_of: origin.replay_of,
+                env: spec.env.overrides.clone(),
+                profile: spec.env.profile.clone(),
+                detached: mode == CommandMode::Launch,
+                app_id: origin.app_id.clone(),
+                process_start: origin.process_start,
+            };
+
+            let db = state.db.get().unwrap();
+            if let Err(e) = crate::db::queries::insert_pending_command(&db, &pending) {
+                return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+                    error: e.to_string(),
+                    blocked: false,
+                })));
+            }
+            info!("Command {} awaits confirmation as {}", pending.command, pending.id);
+            Ok(Some(pending))
+        }
+        PolicyAction::Allow => Ok(None),
+    }
+}
+
+/// Run a command that passed the policy and record it in the history
+/// 
+/// The command runs under the limits of the current policy: a wall-clock
+/// timeout, capped output and optional rlimits.
+/// 
+/// # Arguments
+/// * `state` - Application state holding the database pool and policy
+/// * `spec` - Program, arguments, working directory and environment
+/// * `timeout_secs` - Timeout requested by the caller
+/// * `origin` - Who asked for the run, recorded in the history
+/// 
+/// # Returns
+/// * `Ok(ExecuteCommandResponse)` - Command output and its history entry
+/// * `Err((StatusCode, Json<CommandError>))` - The program could not be started
+async fn run_command(
+    state: &AppState,
+    spec: &CommandSpec,
+    timeout_secs: Option<u64>,
+    origin: CommandOrigin,
+) -> Result<ExecuteCommandResponse, (StatusCode, Json<CommandError>)> {
+    let policy = state.policy.current();
+    let limits = &policy.config().limits;
+    let timeout = limits.timeout_for(&spec.program, timeout_secs);
+    
+    match run_limited(spec, timeout, limits).await {
+        Ok(output) => {
+            let success = output.status.success() && !output.timed_out;
+            let exit_code = output.status.code().unwrap_or(-1);
+            let execution_time = output.duration.as_millis() as u64;
+            if output.timed_out {
+                info!("Command {} timed out after {}s", spec.program, timeout.as_secs());
+            }
+            
+            // Store command in history
+            let record = CommandRecord {
+                success,
+                exit_code,
+                execution_time_ms: execution_time,
+                stdout_truncated: output.stdout_truncated,
+                stderr_truncated: output.stderr_truncated,
+                timed_out: output.timed_out,
+                ..CommandRecord::new(spec, origin)
+            }
+            .with_output(&output.stdout, &output.stderr, limits.history_output_bytes);
+            let db = state.db.get().unwrap();
+            let history_id = crate::db::queries::store_command_history(&db, &record).ok();
+            
+            Ok(ExecuteCommandResponse {
+                success,
+                command: spec.program.clone(),
+                stdout: output.stdout,
+                stderr: output.stderr,
+                exit_code,
+                execution_time_ms: execution_time,
+                timestamp: chrono::Utc::now().to_rfc3339(),
+                timed_out: output.timed_out,
+                stdout_truncated: output.stdout_truncated,
+                stderr_truncated: output.stderr_truncated,
+                history_id,
+            })
+        }
+        Err(e) => {
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+                error: format!("Failed to execute command: {}", e),
+                blocked: false,
+            })))
+        }
+    }
+}
+
+/// Render a command line for display, quoting arguments that need it
+fn command_line(program: &str, args: &[String]) -> String {
+    std::iter::once(program.to_string())
+        .chain(args.iter().map(|arg| {
+            if arg.is_empty() || arg.chars().any(|c| c.is_whitespace() || "'\"$`\\".contains(c)) {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            } else {
+                arg.clone()
+            }
+        }))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// List commands awaiting confirmation
+/// 
+/// Expired commands are marked as such before listing.
+/// 
+/// # Arguments
+/// * `query` - Optional status filter (default: pending) and limit
+/// 
+/// # Returns
+/// * `Ok(Json<PendingCommandResponse>)` - Matching commands, newest first
+/// * `Err((StatusCode, Json<CommandError>))` - Error response
+pub async fn get_pending_commands(
+    State(state): State<AppState>,
+    Query(query): Query<PendingCommandQuery>,
+) -> Result<Json<PendingCommandResponse>, (StatusCode, Json<CommandError>)> {
+    let status = query.status.unwrap_or_else(|| "pending".to_string());
+    let status = if status == "all" { None } else { Some(status) };
+    let db = state.db.get().unwrap();
+    match crate::db::queries::get_pending_commands(&db, status.as_deref(), query.limit.unwrap_or(50)) {
+        Ok(commands) => Ok(Json(PendingCommandResponse { commands })),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+            error: e.to_string(),
+            blocked: false,
+        }))),
+    }
+}
+
+/// Get a single pending command
+/// 
+/// # Arguments
+/// * `id` - Pending command ID
+/// 
+/// # Returns
+/// * `Ok(Json<PendingCommand>)` - The command and its current state
+/// * `Err((StatusCode, Json<CommandError>))` - 404 if unknown
+pub async fn get_pending_command(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<PendingCommand>, (StatusCode, Json<CommandError>)> {
+    let db = state.db.get().unwrap();
+    load_pending_command(&db, &id).map(Json)
+}
+
+fn load_pending_command(
+    conn: &rusqlite::Connection,
+    id: &str,
+) -> Result<PendingCommand, (StatusCode, Json<CommandError>)> {
+    match crate::db::queries::get_pending_command(conn, id) {
+        Ok(Some(pending)) => Ok(pending),
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(CommandError {
+            error: format!("Pending command not found: {}", id),
+            blocked: false,
+        }))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+            error: e.to_string(),
+            blocked: false,
+        }))),
+    }
+}
+
+/// Error for a pending command that can no longer be decided
+fn already_decided(pending: &PendingCommand) -> (StatusCode, Json<CommandError>) {
+    let status = if pending.status == "expired" { StatusCode::GONE } else { StatusCode::CONFLICT };
+    (status, Json(CommandError {
+        error: format!("Command {} is already {}", pending.id, pending.status),
+        blocked: false,
+    }))
+}
+
+/// Approve a pending command and run it
+/// 
+/// The command is checked against the current policy again, so rules added
+/// after the request was made still apply.
+/// 
+/// # Arguments
+/// * `id` - Pending command ID
+/// 
+/// # Returns
+/// * `Ok(Response)` - `ExecuteCommandResponse`, `JobInfo` (202) for background commands,
+///   or the approved `PendingCommand` for terminal sessions, which their WebSocket starts
+/// * `Err((StatusCode, Json<CommandError>))` - 404 unknown, 409 already decided or the
+///   process a signal is for exited, 410 expired, 403 now denied by policy
+pub async fn approve_pending_command(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    let pending = {
+        let db = state.db.get().unwrap();
+        load_pending_command(&db, &id)?
+    };
+    if pending.status != "pending" {
+        return Err(already_decided(&pending));
+    }
+
+    if let Err(error) = check_signal_target(&pending) {
+        let db = state.db.get().unwrap();
+        let _ = crate::db::queries::decide_pending_command(&db, &id, "rejected");
+        return Err(error);
+    }
+
+    let cwd = PathBuf::from(&pending.working_dir);
+    let decision = state.policy.current().evaluate(&pending.command, &pending.args, &cwd);
+    let env = resolve_environment(&state, pending.profile.as_deref(), &pending.env)?;
+    if decision.action == PolicyAction::Deny {
+        let db = state.db.get().unwrap();
+        let _ = crate::db::queries::decide_pending_command(&db, &id, "rejected");
+        return Err((StatusCode::FORBIDDEN, Json(CommandError {
+            error: format!("Command blocked by policy: {}", decision.explanation()),
+            blocked: true,
+        })));
+    }
+
+    // Claim the command atomically so concurrent approvals run it only once
+    let claimed = {
+        let db = state.db.get().unwrap();
+        crate::db::queries::decide_pending_command(&db, &id, "approved")
+    };
+    match claimed {
+        Ok(true) => {}
+        Ok(false) => {
+            let db = state.db.get().unwrap();
+            return Err(already_decided(&load_pending_command(&db, &id)?));
+        }
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+            error: e.to_string(),
+            blocked: false,
+        }))),
+    }
+
+    if pending.terminal {
+        info!("Approved terminal session {} ({})", pending.command, id);
+        let db = state.db.get().unwrap();
+        return Ok(Json(load_pending_command(&db, &id)?).into_response());
+    }
+
+    let origin = CommandOrigin {
+        user_confirmed: true,
+        conversation_id: pending.conversation_id.clone(),
+        replay_of: pending.replay_of,
+        app_id: pending.app_id.clone(),
+        process_start: pending.process_start,
+    };
+    let spec = CommandSpec { program: pending.command.clone(), args: pending.args.clone(), cwd, env };
+    if pending.detached {
+        let launched = launch_detached(&state, &spec, &origin)?;
+        return Ok(Json(launched).into_response());
+    }
+
+    info!("Running confirmed command {} ({})", pending.command, id);
+    if pending.background {
+        let job = start_job(&state, spec, pending.timeout_secs, origin)?;
+        return Ok((StatusCode::ACCEPTED, Json(job)).into_response());
+    }
+
+    let response = run_command(&state, &spec, pending.timeout_secs, origin).await?;
+    if let Some(history_id) = response.history_id {
+        let db = state.db.get().unwrap();
+        let _ = crate::db::queries::set_pending_command_history(&db, &id, history_id);
+    }
+    Ok(Json(response).into_response())
+}
+
+/// Reject a pending command
+/// 
+/// # Arguments
+/// * `id` - Pending command ID
+/// 
+/// # Returns
+/// * `Ok(Json<PendingCommand>)` - The rejected command
+/// * `Err((StatusCode, Json<CommandError>))` - 404 unknown, 409 already decided, 410 expired
+pub async fn reject_pending_command(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<PendingCommand>, (StatusCode, Json<CommandError>)> {
+    let db = state.db.get().unwrap();
+    let pending = load_pending_command(&db, &id)?;
+    match crate::db::queries::decide_pending_command(&db, &id, "rejected") {
+        Ok(true) => {
+            info!("Rejected command {} ({})", pending.command, id);
+            Ok(Json(load_pending_command(&db, &id)?))
+        }
+        Ok(false) => Err(already_decided(&load_pending_command(&db, &id)?)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+            error: e.to_string(),
+            blocked: false,
+        }))),
+    }
+}
+
+/// Start a command as a background job
+/// 
+/// The command is checked against the policy like `/execute`. The job keeps
+/// running when the client disconnects; its output can be followed through
+/// `/jobs/:id/stream` and its final status is stored in the command history.
+/// 
+/// # Arguments
+/// * `payload` - Command to run; `timeout_secs` is capped by the policy's `job_timeout_secs`
+/// 
+/// # Returns
+/// * `Ok(Response)` - `JobInfo` (202) or a `PendingCommand` (202) awaiting confirmation
+/// * `Err((StatusCode, Json<CommandError>))` - Error response (403 when denied)
+pub async fn create_job(
+    State(state): State<AppState>,
+    Json(payload): Json<ExecuteCommandRequest>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    let spec = command_spec(&state, &payload)?;
+    let origin = CommandOrigin { conversation_id: payload.conversation_id.clone(), ..Default::default() };
+    match authorize_command(&state, &payload, &spec, CommandMode::Background, &origin)? {
+        Some(pending) => Ok((StatusCode::ACCEPTED, Json(pending)).into_response()),
+        None => {
+            let job = start_job(&state, spec, payload.timeout_secs, origin)?;
+            Ok((StatusCode::ACCEPTED, Json(job)).into_response())
+        }
+    }
+}
+
+/// Start a job for a command that passed the policy
+fn start_job(
+    state: &AppState,
+    spec: CommandSpec,
+    timeout_secs: Option<u64>,
+    origin: CommandOrigin,
+) -> Result<JobInfo, (StatusCode, Json<CommandError>)> {
+    let policy = state.policy.current();
+    let limits = &policy.config().limits;
+    let timeout = limits.job_timeout_for(timeout_secs);
+    match state.jobs.start(spec, timeout, limits, origin) {
+        Ok(job) => Ok(job.info()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+            error: format!("Failed to execute command: {}", e),
+            blocked: false,
+        }))),
+    }
+}
+
+/// List background jobs, newest first
+/// 
+/// # Returns
+/// * `Json<JobListResponse>` - Running and recently finished jobs
+pub async fn get_jobs(State(state): State<AppState>) -> Json<JobListResponse> {
+    Json(JobListResponse { jobs: state.jobs.list() })
+}
+
+fn find_job(state: &AppState, id: &str) -> Result<std::sync::Arc<Job>, (StatusCode, Json<CommandError>)> {
+    state.jobs.get(id).ok_or_else(|| (StatusCode::NOT_FOUND, Json(CommandError {
+        error: format!("Job not found: {}", id),
+        blocked: false,
+    })))
+}
+
+/// Get a job with the output recorded so far
+/// 
+/// # Arguments
+/// * `id` - Job ID
+/// 
+/// # Returns
+/// * `Ok(Json<JobDetails>)` - Job summary and output backlog
+/// * `Err((StatusCode, Json<CommandError>))` - 404 if unknown
+pub async fn get_job(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<JobDetails>, (StatusCode, Json<CommandError>)> {
+    let job = find_job(&state, &id)?;
+    let (_, output) = job.subscribe();
+    Ok(Json(JobDetails { job: job.info(), output }))
+}
+
+/// Stream a job's output as server-sent events
+/// 
+/// The backlog is replayed first, followed by live `stdout`, `stderr` and a
+/// final `finished` event, after which the stream ends. Each event's data is
+/// a JSON `JobEvent`.
+/// 
+/// # Arguments
+/// * `id` - Job ID
+/// 
+/// # Returns
+/// * `Ok(Sse<...>)` - Event stream
+/// * `Err((StatusCode, Json<CommandError>))` - 404 if unknown
+pub async fn stream_job(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Sse<impl futures::Stream<Item = Result<Event, std::convert::Infallible>>>, (StatusCode, Json<CommandError>)> {
+    let job = find_job(&state, &id)?;
+    let (receiver, backlog) = job.subscribe();
+    let last_seq = backlog.last().map(|event| event.seq);
+    let finished = backlog.iter().any(|event| matches!(event.kind, JobEventKind::Finished { .. }));
+
+    let live = futures::stream::unfold((receiver, finished), move |(mut receiver, done)| async move {
+        if done {
+            return None;
+        }
+        loop {
+            match receiver.recv().await {
+                Ok(event) if last_seq.is_some_and(|seq| event.seq <= seq) => continue,
+                Ok(event) => {
+                    let done = matches!(event.kind, JobEventKind::Finished { .. });
+                    return Some((event, (receiver, done)));
+                }
+                // Slow clients skip what they missed rather than stalling the job
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    let stream = futures::stream::iter(backlog).chain(live).map(|event| {
+        Ok(Event::default()
+            .event(event.kind.name())
+            .id(event.seq.to_string())
+            .json_data(&event)
+            .unwrap_or_default())
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Cancel a running job
+/// 
+/// The job's process group receives SIGTERM, then SIGKILL if it does not exit.
+/// 
+/// # Arguments
+/// * `id` - Job ID
+/// 
+/// # Returns
+/// * `Ok(Json<JobInfo>)` - The job (its status changes once it has exited)
+/// * `Err((StatusCode, Json<CommandError>))` - 404 unknown, 409 already finished
+pub async fn cancel_job(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<JobInfo>, (StatusCode, Json<CommandError>)> {
+    let job = find_job(&state, &id)?;
+    let info = job.info();
+    if info.status != JobStatus::Running {
+        return Err((StatusCode::CONFLICT, Json(CommandError {
+            error: format!("Job {} is already {}", id, info.status.as_str()),
+            blocked: false,
+        })));
+    }
+    job.cancel();
+    info!("Cancelling job {}", id);
+    Ok(Json(info))
+}
+
+/// Send input to a running job
+/// 
+/// # Arguments
+/// * `id` - Job ID
+/// * `payload` - Text to write and whether to close stdin afterwards
+/// 
+/// # Returns
+/// * `Ok(Json<JobInfo>)` - The job
+/// * `Err((StatusCode, Json<CommandError>))` - 404 unknown, 409 stdin closed
+pub async fn write_job_stdin(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<JobStdinRequest>,
+) -> Result<Json<JobInfo>, (StatusCode, Json<CommandError>)> {
+    let job = find_job(&state, &id)?;
+    match job.write_stdin(payload.data.as_bytes(), payload.close.unwrap_or(false)).await {
+        Ok(()) => Ok(Json(job.info())),
+        Err(e) => Err((StatusCode::CONFLICT, Json(CommandError {
+            error: format!("Cannot write to job {}: {}", id, e),
+            blocked: false,
+        }))),
+    }
+}
+
+/// Open an interactive terminal session over a WebSocket
+/// 
+/// The shell (or `shell` program) is checked against the command policy
+/// before the connection is upgraded; denied programs get 403. When the
+/// policy asks for confirmation the socket is still opened and receives a
+/// `pending` message; the session starts once the command is approved
+/// through `/pending/:id/approve`, and ends with an `error` message if it
+/// is rejected or expires.
+/// 
+/// Terminal output is sent as binary frames. Clients send input as binary
+/// frames or `TerminalClientMessage` text frames, which also carry resize
+/// events. When the session ends its transcript is stored and linked to
+/// the command history entry of the shell.
+/// 
+/// # Arguments
+/// * `request` - Program, working directory and initial terminal size
+/// 
+/// # Returns
+/// * `Ok(Response)` - Switching to the WebSocket protocol
+/// * `Err((StatusCode, Json<CommandError>))` - Error response (403 when denied)
+pub async fn open_terminal(
+    State(state): State<AppState>,
+    Query(request): Query<TerminalRequest>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    let shell = request
+        .shell
+        .or_else(|| env::var("SHELL").ok().filter(|shell| !shell.is_empty()))
+        .unwrap_or_else(|| "/bin/sh".to_string());
+    let payload = ExecuteCommandRequest {
+        command: shell,
+        args: Some(if request.login == Some(true) { vec!["-l".to_string()] } else { Vec::new() }),
+        working_dir: request.working_dir.or_else(|| env::var("HOME").ok()),
+        require_confirmation: request.require_confirmation,
+        timeout_secs: None,
+        conversation_id: request.conversation_id,
+        shell: None,
+        profile: request.profile,
+        env: Some(BTreeMap::from([("TERM".to_string(), Some(TERM.to_string()))])),
+    };
+    let spec = command_spec(&state, &payload)?;
+    let origin = CommandOrigin { conversation_id: payload.conversation_id.clone(), ..Default::default() };
+    let pending = authorize_command(&state, &payload, &spec, CommandMode::Terminal, &origin)?;
+
+    let defaults = TerminalSize::default();
+    let size = TerminalSize {
+        cols: request.cols.unwrap_or(defaults.cols),
+        rows: request.rows.unwrap_or(defaults.rows),
+    };
+    Ok(ws.on_upgrade(move |socket| run_terminal(state, socket, spec, origin, size, pending)))
+}
+
+/// Relay a terminal session over an upgraded WebSocket
+async fn run_terminal(
+    state: AppState,
+    mut socket: WebSocket,
+    spec: CommandSpec,
+    mut origin: CommandOrigin,
+    size: TerminalSize,
+    pending: Option<PendingCommand>,
+) {
+    if let Some(ref pending) = pending {
+        let _ = send_terminal_message(&mut socket, &TerminalServerMessage::Pending { command: Box::new(pending.clone()) }).await;
+        if let Err(message) = await_terminal_approval(&state, &mut socket, &pending.id).await {
+            let _ = send_terminal_message(&mut socket, &TerminalServerMessage::Error { message }).await;
+            return;
+        }
+    }
+
+    let limits = state.policy.current().config().limits.clone();
+    let mut process = match TerminalProcess::spawn(&spec, size, &limits) {
+        Ok(process) => process,
+        Err(e) => {
+            let message = format!("Failed to start terminal: {}", e);
+            let _ = send_terminal_message(&mut socket, &TerminalServerMessage::Error { message }).await;
+            return;
+        }
+    };
+
+    let mut session = TerminalSession {
+        id: uuid::Uuid::new_v4().to_string(),
+        command: spec.program.clone(),
+        args: spec.args.clone(),
+        working_dir: spec.cwd.display().to_string(),
+        status: "running".to_string(),
+        exit_code: None,
+        started_at: chrono::Utc::now().to_rfc3339(),
+        ended_at: None,
+        user_confirmed: pending.is_some(),
+        history_id: None,
+        transcript_truncated: false,
+    };
+    {
+        let db = state.db.get().unwrap();
+        if let Err(e) = crate::db::queries::insert_terminal_session(&db, &session) {
+            warn!("Failed to record terminal session {}: {}", session.id, e);
+        }
+    }
+    info!("Started terminal session {}: {}", session.id, session.command);
+    let _ = send_terminal_message(&mut socket, &TerminalServerMessage::Started { session: session.clone() }).await;
+
+    let start = std::time::Instant::now();
+    let mut transcript = CappedBuffer::new(limits.max_output_bytes);
+    let mut buffer = vec![0u8; 8192];
+    let mut exit = None;
+    let mut drain_deadline = tokio::time::Instant::now();
+    let mut connected = true;
+    loop {
+        tokio::select! {
+            read = process.pty.read(&mut buffer) => match read {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    transcript.push(&buffer[..n]);
+                    if socket.send(Message::Binary(buffer[..n].to_vec())).await.is_err() {
+                        connected = false;
+                        break;
+                    }
+                }
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Binary(data))) => {
+                    let _ = process.pty.write(&data).await;
+                }
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<TerminalClientMessage>(&text) {
+                    Ok(TerminalClientMessage::Input { data }) => {
+                        let _ = process.pty.write(data.as_bytes()).await;
+                    }
+                    Ok(TerminalClientMessage::Resize { cols, rows }) => {
+                        let _ = process.pty.resize(TerminalSize { cols, rows });
+                    }
+                    Err(e) => {
+                        let message = format!("Invalid terminal message: {}", e);
+                        let _ = send_terminal_message(&mut socket, &TerminalServerMessage::Error { message }).await;
+                    }
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                    connected = false;
+                    break;
+                }
+                Some(Ok(_)) => {}
+            },
+            status = process.child.wait(), if exit.is_none() => {
+                exit = Some(status);
+                drain_deadline = tokio::time::Instant::now() + std::time::Duration::from_millis(500);
+            }
+            // Background programs may keep the terminal open after the shell exited
+            _ = tokio::time::sleep_until(drain_deadline), if exit.is_some() => break,
+        }
+    }
+
+    // Closing the terminal hangs up whatever is still running on it
+    let hangup = process.hangup().await;
+    let exited = exit.is_some();
+    let status = match exit {
+        Some(status) => status,
+        None => hangup,
+    };
+    let (transcript, truncated) = transcript.finish();
+    session.exit_code = status.as_ref().ok().and_then(|status| status.code());
+    let success = status.as_ref().is_ok_and(|status| status.success());
+    session.status = match (exited, success) {
+        (false, _) => "closed",
+        (true, true) => "completed",
+        (true, false) => "failed",
+    }
+    .to_string();
+    session.ended_at = Some(chrono::Utc::now().to_rfc3339());
+    session.transcript_truncated = truncated;
+
+    {
+        let db = state.db.get().unwrap();
+        // The output is kept as the session transcript rather than in the entry
+        origin.user_confirmed = session.user_confirmed;
+        let record = CommandRecord {
+            success,
+            exit_code: session.exit_code.unwrap_or(-1),
+            execution_time_ms: start.elapsed().as_millis() as u64,
+            ..CommandRecord::new(&spec, origin)
+        };
+        session.history_id = crate::db::queries::store_command_history(&db, &record).ok();
+        if let (Some(pending), Some(history_id)) = (&pending, session.history_id) {
+            let _ = crate::db::queries::set_pending_command_history(&db, &pending.id, history_id);
+        }
+        if let Err(e) = crate::db::queries::finish_terminal_session(&db, &session, &transcript) {
+            warn!("Failed to store transcript of terminal session {}: {}", session.id, e);
+        }
+    }
+    info!("Terminal session {} {}", session.id, session.status);
+
+    if connected {
+        let _ = send_terminal_message(&mut socket, &TerminalServerMessage::Exit { session }).await;
+        let _ = socket.send(Message::Close(None)).await;
+    }
+}
+
+/// Wait until a terminal's pending command is approved
+/// 
+/// Input sent before the session starts is dropped. A client that
+/// disconnects while waiting rejects the command, as nothing could use the
+/// approval any more.
+/// 
+/// # Returns
+/// * `Ok(())` - The command was approved
+/// * `Err(String)` - It was rejected, expired or the client went away
+async fn await_terminal_approval(state: &AppState, socket: &mut WebSocket, id: &str) -> Result<(), String> {
+    let mut poll = tokio::time::interval(std::time::Duration::from_millis(500));
+    loop {
+        tokio::select! {
+            _ = poll.tick() => {
+                let db = state.db.get().unwrap();
+                match crate::db::queries::get_pending_command(&db, id) {
+                    Ok(Some(pending)) if pending.status == "approved" => return Ok(()),
+                    Ok(Some(pending)) if pending.status == "pending" => {}
+                    Ok(Some(pending)) => return Err(format!("Terminal session was {}", pending.status)),
+                    Ok(None) => return Err(format!("Pending command not found: {}", id)),
+                    Err(e) => return Err(e.to_string()),
+                }
+            }
+            message = socket.recv() => {
+                if matches!(message, Some(Ok(Message::Close(_))) | Some(Err(_)) | None) {
+                    let db = state.db.get().unwrap();
+                    let _ = crate::db::queries::decide_pending_command(&db, id, "rejected");
+                    return Err("Client disconnected".to_string());
+                }
+            }
+        }
+    }
+}
+
+async fn send_terminal_message(socket: &mut WebSocket, message: &TerminalServerMessage) -> Result<(), axum::Error> {
+    socket.send(Message::Text(serde_json::to_string(message).unwrap_or_default())).await
+}
+
+/// List terminal sessions, newest first
+/// 
+/// # Arguments
+/// * `query` - Optional limit (default: 50)
+/// 
+/// # Returns
+/// * `Ok(Json<TerminalSessionListResponse>)` - Sessions without their transcripts
+/// * `Err((StatusCode, Json<CommandError>))` - Error response
+pub async fn get_terminal_sessions(
+    State(state): State<AppState>,
+    Query(query): Query<TerminalSessionQuery>,
+) -> Result<Json<TerminalSessionListResponse>, (StatusCode, Json<CommandError>)> {
+    let db = state.db.get().unwrap();
+    match crate::db::queries::get_terminal_sessions(&db, query.limit.unwrap_or(50)) {
+        Ok(sessions) => Ok(Json(TerminalSessionListResponse { sessions })),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+            error: e.to_string(),
+            blocked: false,
+        }))),
+    }
+}
+
+/// Get a terminal session with its transcript
+/// 
+/// The transcript is returned as plain text unless `raw=true` is given.
+/// 
+/// # Arguments
+/// * `id` - Session ID
+/// * `query` - Whether to keep escape sequences
+/// 
+/// # Returns
+/// * `Ok(Json<TerminalSessionDetails>)` - The session and its transcript
+/// * `Err((StatusCode, Json<CommandError>))` - 404 if unknown
+pub async fn get_terminal_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<TerminalTranscriptQuery>,
+) -> Result<Json<TerminalSessionDetails>, (StatusCode, Json<CommandError>)> {
+    let db = state.db.get().unwrap();
+    match crate::db::queries::get_terminal_session(&db, &id) {
+        Ok(Some((session, transcript))) => {
+            let transcript = if query.raw == Some(true) { transcript } else { transcript.as_deref().map(plain_text) };
+            Ok(Json(TerminalSessionDetails { session, transcript }))
+        }
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(CommandError {
+            error: format!("Terminal session not found: {}", id),
+            blocked: false,
+        }))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+            error: e.to_string(),
+            blocked: false,
+        }))),
+    }
+}
+
+/// Get the active command policy
+/// 
+/// # Returns
+/// * `Json<PolicyResponse>` - Policy rules and where they were loaded from
+pub async fn get_command_policy(State(state): State<AppState>) -> Json<PolicyResponse> {
+    let policy = state.policy.current();
+    Json(PolicyResponse {
+        path: state.policy.path().display().to_string(),
+        source: state.policy.source(),
+        policy: policy.config().clone(),
+    })
+}
+
+/// Reload the command policy file
+/// 
+/// The policy is also reloaded automatically when the file changes; this
+/// endpoint reports parse errors instead of only logging them.
+/// 
+/// # Returns
+/// * `Ok(Json<PolicyResponse>)` - The policy now in effect
+/// * `Err((StatusCode, Json<CommandError>))` - The file is invalid (422); the previous policy stays active
+pub async fn reload_command_policy(
+    State(state): State<AppState>,
+) -> Result<Json<PolicyResponse>, (StatusCode, Json<CommandError>)> {
+    if let Err(e) = state.policy.reload() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(CommandError {
+            error: format!("Invalid command policy: {}", e),
+            blocked: false,
+        })));
+    }
+    Ok(get_command_policy(State(state)).await)
+}
+
+/// Check a command against the policy without running it
+/// 
+/// # Arguments
+/// * `payload` - Command, arguments and working directory to check
+/// 
+/// # Returns
+/// * `Json<PolicyDecision>` - The action the policy would take and why
+pub async fn check_command_policy(
+    State(state): State<AppState>,
+    Json(payload): Json<PolicyCheckRequest>,
+) -> Json<PolicyDecision> {
+    let cwd = working_dir(payload.working_dir.as_deref());
+    let args = payload.args.unwrap_or_default();
+    let (program, args) = if payload.shell == Some(true) {
+        shell_command(&payload.command, &args)
+    } else {
+        (payload.command, args)
+    };
+    Json(state.policy.current().evaluate(&program, &args, &cwd))
+}
+
+/// Get command execution history
+/// 
+/// This endpoint retrieves the history of commands executed by Leara,
+/// useful for auditing and debugging purposes. Output is left out unless
+/// `include_output=true`; `GET /history/:id` always includes it.
+/// 
+/// # Arguments
+/// * `query` - Query parameters for filtering history
+/// 
+/// # Returns
+/// * `Ok(Json<CommandHistoryResponse>)` - Command history
+/// * `Err((StatusCode, Json<CommandError>))` - Error response
+pub async fn get_command_history(
+    State(state): State<AppState>,
+    Query(query): Query<CommandHistoryQuery>,
+) -> Result<Json<CommandHistoryResponse>, (StatusCode, Json<CommandError>)> {
+    let db = state.db.get().unwrap();
+    match crate::db::queries::get_command_history(&db, &query) {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+            error: e.to_string(),
+            blocked: false,
+        }))),
+    }
+}
+
+/// Get a single command history entry with its output
+/// 
+/// # Arguments
+/// * `id` - History entry ID
+/// 
+/// # Returns
+/// * `Ok(Json<CommandHistory>)` - The entry
+/// * `Err((StatusCode, Json<CommandError>))` - 404 if unknown
+pub async fn get_command_history_entry(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<CommandHistory>, (StatusCode, Json<CommandError>)> {
+    let db = state.db.get().unwrap();
+    load_history_entry(&db, id).map(Json)
+}
+
+fn load_history_entry(
+    conn: &rusqlite::Connection,
+    id: i64,
+) -> Result<CommandHistory, (StatusCode, Json<CommandError>)> {
+    match crate::db::queries::get_command_history_entry(conn, id) {
+        Ok(Some(entry)) => Ok(entry),
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(CommandError {
+            error: format!("Command history entry not found: {}", id),
+            blocked: false,
+        }))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+            error: e.to_string(),
+            blocked: false,
+        }))),
+    }
+}
+
+/// Run a command from the history again
+/// 
+/// The command, its arguments and working directory are checked against
+/// the current policy, not the one in force when it first ran, so it may
+/// now be denied or need confirmation. The new history entry records the
+/// one it replays.
+/// 
+/// # Arguments
+/// * `id` - History entry ID
+/// * `payload` - Optional timeout, confirmation, background and conversation settings
+/// 
+/// # Returns
+/// * `Ok(Response)` - `ExecuteCommandResponse` (200), `JobInfo` (202) or a `PendingCommand` (202)
+/// * `Err((StatusCode, Json<CommandError>))` - 404 unknown, 400 for terminal sessions, 403 denied
+pub async fn replay_command(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    payload: Option<Json<ReplayCommandRequest>>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    let entry = {
+        let db = state.db.get().unwrap();
+        load_history_entry(&db, id)?
+    };
+    if entry.terminal_session_id.is_some() {
+        return Err((StatusCode::BAD_REQUEST, Json(CommandError {
+            error: "Terminal sessions cannot be replayed; open a new terminal instead".to_string(),
+            blocked: false,
+        })));
+    }
+
+    // Secrets were never recorded; variables the profile sets come from the profile again
+    let policy = state.policy.current();
+    let profile_vars = entry.profile.as_ref().and_then(|name| policy.config().environment.profiles.get(name));
+    let env = entry
+        .env
+        .into_iter()
+        .filter(|(name, value)| match value {
+            Some(value) => value != REDACTED && profile_vars.and_then(|vars| vars.get(name)) != Some(value),
+            None => true,
+        })
+        .collect();
+
+    let options = payload.map(|Json(options)| options).unwrap_or_default();
+    let request = ExecuteCommandRequest {
+        command: entry.command,
+        args: entry.args.and_then(|args| serde_json::from_str(&args).ok()),
+        working_dir: entry.working_dir,
+        require_confirmation: options.require_confirmation,
+        timeout_secs: options.timeout_secs,
+        conversation_id: options.conversation_id.or(entry.conversation_id),
+        shell: None,
+        profile: entry.profile,
+        env: Some(env),
+    };
+    let spec = command_spec(&state, &request)?;
+    let origin = CommandOrigin {
+        conversation_id: request.conversation_id.clone(),
+        replay_of: Some(id),
+        ..Default::default()
+    };
+    let mode = if options.background == Some(true) { CommandMode::Background } else { CommandMode::Foreground };
+
+    info!("Replaying command history entry {}: {}", id, command_line(&spec.program, &spec.args));
+    match authorize_command(&state, &request, &spec, mode, &origin)? {
+        Some(pending) => Ok((StatusCode::ACCEPTED, Json(pending)).into_response()),
+        None if mode == CommandMode::Background => {
+            let job = start_job(&state, spec, request.timeout_secs, origin)?;
+            Ok((StatusCode::ACCEPTED, Json(job)).into_response())
+        }
+        None => {
+            let response = run_command(&state, &spec, request.timeout_secs, origin).await?;
+            Ok(Json(response).into_response())
+        }
+    }
+}
+
+/// List installed applications
+/// 
+/// Applications come from desktop entries and executables on PATH. The list
+/// is cached and rescanned when inotify reports changes; entries marked
+/// `NoDisplay` or meant for other desktops are left out unless `all=true`.
+/// 
+/// # Arguments
+/// * `query` - Whether to include hidden entries
+/// 
+/// # Returns
+/// * `Ok(Json<AppListResponse>)` - Installed applications
+/// * `Err((StatusCode, Json<CommandError>))` - Error response
+pub async fn get_available_apps(
+    State(state): State<AppState>,
+    Query(query): Query<AppListQuery>,
+) -> Result<Json<AppListResponse>, (StatusCode, Json<CommandError>)> {
+    let apps = state.apps.clone();
+    let all = query.all.unwrap_or(false);
+    let applications = tokio::task::spawn_blocking(move || {
+        apps.apps().iter().filter(|app| all || !app.no_display).cloned().collect()
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+        error: e.to_string(),
+        blocked: false,
+    })))?;
+    Ok(Json(AppListResponse { applications }))
+}
+
+/// Search installed applications
+/// 
+/// Terms are matched fuzzily against names, commands, descriptions,
+/// keywords and categories, so "open my browser" finds the web browser and
+/// small typos are forgiven. Results are ranked by relevance and by how often
+/// the application was launched or its program run, and come with category
+/// facets for narrowing the search.
+/// 
+/// # Arguments
+/// * `query` - Search text, category filter and page
+/// 
+/// # Returns
+/// * `Ok(Json<AppSearchResponse>)` - One page of matches with facets
+/// * `Err((StatusCode, Json<CommandError>))` - Error response
+pub async fn search_apps(
+    State(state): State<AppState>,
+    Query(query): Query<AppSearchQuery>,
+) -> Result<Json<AppSearchResponse>, (StatusCode, Json<CommandError>)> {
+    let (launches, runs) = {
+        let db = state.db.get().unwrap();
+        crate::db::queries::get_app_usage(&db).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+            error: e.to_string(),
+            blocked: false,
+        })))?
+    };
+    let catalog = state.apps.clone();
+    let all = query.all.unwrap_or(false);
+    let terms = query_terms(query.q.as_deref().unwrap_or(""));
+    let limit = query.limit.unwrap_or(DEFAULT_APP_SEARCH_LIMIT).clamp(1, MAX_APP_SEARCH_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    let category = query.category.clone();
+
+    let search_terms = terms.clone();
+    let (matches, facets) = tokio::task::spawn_blocking(move || {
+        let apps = catalog.apps();
+        let visible: Vec<App> = apps.iter().filter(|app| all || !app.no_display).cloned().collect();
+        search(&visible, &search_terms, category.as_deref(), |app| {
+            let mut usage = launches.get(&app.id).cloned().unwrap_or_default();
+            let command = app.command.rsplit('/').next().unwrap_or(&app.command);
+            if let Some(runs) = runs.get(command) {
+                usage.add(runs);
+            }
+            usage
+        })
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+        error: e.to_string(),
+        blocked: false,
+    })))?;
+
+    Ok(Json(AppSearchResponse {
+        query: query.q,
+        terms,
+        total: matches.len(),
+        offset,
+        limit,
+        results: matches.into_iter().skip(offset).take(limit).collect(),
+        facets,
+    }))
+}
+
+/// Launch an installed application
+/// 
+/// The command lines built from the application's `Exec` entry are checked
+/// against the execution policy like any other command. Allowed ones start
+/// detached from the server; the others become pending commands that start
+/// the application once approved.
+/// 
+/// # Arguments
+/// * `id` - Application ID, as listed by `GET /api/system/apps`
+/// * `request` - Files or URLs to open and launch options
+/// 
+/// # Returns
+/// * `Ok(Response)` - 200 with the started processes, or 202 if any launch awaits confirmation
+/// * `Err((StatusCode, Json<CommandError>))` - 404 unknown application, 400 invalid targets, 403 blocked
+pub async fn launch_app(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<LaunchAppRequest>,
+) -> Result<Response, (StatusCode, Json<CommandError>)> {
+    let catalog = state.apps.clone();
+    let lookup = id.clone();
+    let app = tokio::task::spawn_blocking(move || catalog.get(&lookup))
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, Json(CommandError {
+            error: format!("Unknown application: {}", id),
+            blocked: false,
+        })))?;
+    let command_lines = app.command_lines(&request.targets).map_err(|error| {
+        (StatusCode::BAD_REQUEST, Json(CommandError { error, blocked: false }))
+    })?;
+
+    let origin = CommandOrigin {
+        conversation_id: request.conversation_id.clone(),
+        app_id: Some(app.id.clone()),
+        ..Default::default()
+    };
+    let working_dir = request
+        .working_dir
+        .clone()
+        .or_else(|| app.working_dir.clone())
+        .or_else(|| env::var("HOME").ok());
+    let mut authorized = Vec::new();
+    let mut pending = Vec::new();
+    for argv in command_lines {
+        let payload = ExecuteCommandRequest {
+            command: argv[0].clone(),
+            args: Some(argv[1..].to_vec()),
+            working_dir: working_dir.clone(),
+            require_confirmation: request.require_confirmation,
+            conversation_id: request.conversation_id.clone(),
+            profile: request.profile.clone(),
+            ..Default::default()
+        };
+        let spec = command_spec(&state, &payload)?;
+        match authorize_command(&state, &payload, &spec, CommandMode::Launch, &origin)? {
+            Some(command) => pending.push(command),
+            None => authorized.push(spec),
+        }
+    }
+
+    let mut launched = Vec::new();
+    for spec in authorized {
+        launched.push(launch_detached(&state, &spec, &origin)?);
+    }
+    let status = if pending.is_empty() { StatusCode::OK } else { StatusCode::ACCEPTED };
+    Ok((status, Json(LaunchAppResponse { app_id: app.id, launched, pending })).into_response())
+}
+
+/// Start a launch command detached from the server and record the launch
+fn launch_detached(
+    state: &AppState,
+    spec: &CommandSpec,
+    origin: &CommandOrigin,
+) -> Result<LaunchedApp, (StatusCode, Json<CommandError>)> {
+    match spawn_detached(spec) {
+        Ok(pid) => {
+            info!("Launched {} (pid {})", command_line(&spec.program, &spec.args), pid);
+            let launched = LaunchedApp { pid, command: spec.program.clone(), args: spec.args.clone() };
+            if let Some(app_id) = &origin.app_id {
+                let db = state.db.get().unwrap();
+                if let Err(e) = crate::db::queries::insert_app_launch(&db, app_id, &launched, origin.conversation_id.as_deref()) {
+                    warn!("Failed to record launch of {}: {}", app_id, e);
+                }
+            }
+            Ok(launched)
+        }
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(CommandError {
+            error: format!("Failed to launch {}: {}", spec.program, e),
+            blocked: false,
+        }))),
+    }
+}
+
+/// Create router for system-related endpoints
+pub fn create_router() -> Router<AppState> {
+    Router::new()
+        .route("/info", get(get_system_info))
+        .route("/resources", get(get_resource_usage))
+        .route("/resources/history", get(get_resource_history))
+        .route("/memory", get(get_memory_info))
+        .route("/disks", get(get_disk_info))
+        .route("/network", get(get_network_info))
+        .route("/network/interfaces", get(get_network_interfaces))
+        .route("/network/routes", get(get_network_routes))
+        .route("/network/sockets", get(get_network_sockets))
+        .route("/network/dns", get(get_dns_config))
+        .route("/network/probe", post(probe_network_latency))
+        .route("/sensors", get(get_sensors))
+        .route("/metrics", get(get_metrics))
+        .route("/alerts", get(get_active_alerts))
+        .route("/alerts/history", get(get_alert_history))
+        .route("/alerts/rules", get(get_alert_rules).post(create_alert_rule))
+        .route("/alerts/rules/:id", get(get_alert_rule).put(update_alert_rule).delete(delete_alert_rule))
+        .route("/processes", get(get_processes))
+        .route("/processes/tree", get(get_process_tree))
+        .route("/processes/:pid", get(get_process))
+        .route("/processes/:pid/signal", post(signal_process))
+        .route("/services", get(get_services))
+        .route("/services/:unit", get(get_service))
+        .route("/services/:unit/:action", post(control_service))
+        .route("/journal", get(get_journal))
+        .route("/files", get(list_files).delete(delete_file))
+        .route("/files/roots", get(get_file_roots))
+        .route("/files/stat", get(get_file_info))
+        .route("/files/read", get(read_file))
+        .route("/files/checksum", get(get_file_checksum))
+        .route("/files/write", post(write_file))
+        .route("/files/mkdir", post(create_directory))
+        .route("/files/move", post(move_file))
+        .route("/files/rename", post(rename_file))
+        .route("/index", get(get_index_status))
+        .route("/index/roots", get(get_index_roots).post(add_index_root))
+        .route("/index/roots/:id", axum::routing::delete(remove_index_root))
+        .route("/index/refresh", post(refresh_index))
+        .route("/index/search", get(search_index))
+        .route("/index/files", get(get_indexed_files))
+        .route("/execute", post(execute_command))
+        .route("/history", get(get_command_history))
+        .route("/history/:id", get(get_command_history_entry))
+        .route("/history/:id/replay", post(replay_command))
+        .route("/jobs", get(get_jobs).post(create_job))
+        .route("/jobs/:id", get(get_job))
+        .route("/jobs/:id/stream", get(stream_job))
+        .route("/jobs/:id/cancel", post(cancel_job))
+        .route("/jobs/:id/stdin", post(write_job_stdin))
+        .route("/pending", get(get_pending_commands))
+        .route("/pending/:id", get(get_pending_command))
+        .route("/pending/:id/approve", post(approve_pending_command))
+        .route("/pending/:id/reject", post(reject_pending_command))
+        .route("/terminal", get(open_terminal))
+        .route("/terminal/sessions", get(get_terminal_sessions))
+        .route("/terminal/sessions/:id", get(get_terminal_session))
+        .route("/policy", get(get_command_policy))
+        .route("/policy/reload", post(reload_command_policy))
+        .route("/policy/check", post(check_command_policy))
+        .route("/apps", get(get_available_apps))
+        .route("/apps/search", get(search_apps))
+        .route("/apps/:id/launch", post(launch_app))
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app_state, TestDatabase};
+
+    #[tokio::test]
+    async fn relative_working_directories_are_resolved_against_the_server_directory() {
+        let db = TestDatabase::new();
+        let state = app_state(&db, PathBuf::from("/nonexistent/policy.toml")).await;
+        let server_dir = env::current_dir().unwrap();
+        let spec = |dir: Option<&str>| {
+            let payload = ExecuteCommandRequest { command: "ls".to_string(), working_dir: dir.map(str::to_string), ..Default::default() };
+            command_spec(&state, &payload).unwrap().cwd
+        };
+        assert_eq!(spec(None), server_dir);
+        assert_eq!(spec(Some("src/../config")), server_dir.join("config"));
+        assert_eq!(spec(Some("/tmp/./x")), PathBuf::from("/tmp/x"));
+    }
+}
@@ -0,0 +1,153 @@
+/*
+ * Leara AI Assistant - Command Line Interface
+ *
+ * This module defines the command line interface of the backend binary.
+ * Without a subcommand the API server is started; subcommands run one-off
+ * maintenance tasks such as bulk task import and export.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/cli.rs
+ * Purpose: Command line argument parsing and subcommands
+ */
+
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use crate::utils::ical::{parse_ics, tasks_to_ics, CalendarComponent};
+use crate::utils::todotxt::TaskListFormat;
+
+/// Leara AI Assistant backend
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Start the API server (default)
+    Serve,
+    /// Manage tasks from the command line
+    Tasks {
+        #[command(subcommand)]
+        command: TasksCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TasksCommand {
+    /// Import tasks from a file ("-" reads standard input)
+    Import {
+        /// Format of the input file
+        #[arg(short, long, value_enum)]
+        format: TaskFileFormat,
+        /// File to read
+        file: PathBuf,
+    },
+    /// Export tasks to standard output or a file
+    Export {
+        /// Format of the output
+        #[arg(short, long, value_enum)]
+        format: TaskFileFormat,
+        /// File to write instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Leave out completed and cancelled tasks
+        #[arg(long)]
+        open_only: bool,
+    },
+}
+
+/// File formats supported by `tasks import` and `tasks export`
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum TaskFileFormat {
+    /// todo.txt, one task per line
+    Todotxt,
+    /// Markdown checklist
+    Markdown,
+    /// iCalendar VTODO items
+    Ics,
+}
+
+/// Run a task subcommand against the database
+///
+/// # Arguments
+/// * `db_path` - Path of the (already migrated) SQLite database
+/// * `command` - Subcommand to run
+///
+/// # Returns
+/// * `Ok(())` - Command completed
+/// * `Err(anyhow::Error)` - I/O, parse or database error
+pub fn run_tasks_command(db_path: &str, command: TasksCommand) -> anyhow::Result<()> {
+    let conn = crate::db::open_connection(db_path)?;
+
+    match command {
+        TasksCommand::Import { format, file } => {
+            let input = if file.as_os_str() == "-" {
+                std::io::read_to_string(std::io::stdin())?
+            } else {
+                std::fs::read_to_string(&file)?
+            };
+            let tasks = match format {
+                TaskFileFormat::Todotxt => TaskListFormat::TodoTxt.parse(&input),
+                TaskFileFormat::Markdown => TaskListFormat::Markdown.parse(&input),
+                TaskFileFormat::Ics => parse_ics(&input)?,
+            };
+            let (imported, updated) = crate::db::queries::insert_tasks(&conn, tasks)?;
+            println!("Imported {} tasks ({} updated)", imported.len(), updated);
+        }
+        TasksCommand::Export { format, output, open_only } => {
+            let rendered = match format {
+                TaskFileFormat::Todotxt => TaskListFormat::TodoTxt.render(&crate::db::queries::get_all_tasks(&conn, !open_only)?),
+                TaskFileFormat::Markdown => TaskListFormat::Markdown.render(&crate::db::queries::get_all_tasks(&conn, !open_only)?),
+                TaskFileFormat::Ics => tasks_to_ics(&crate::db::queries::get_tasks_with_due_dates(&conn, !open_only)?, CalendarComponent::Todo),
+            };
+            match output {
+                Some(path) => std::fs::write(path, rendered)?,
+                None => print!("{}", rendered),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempTree;
+
+    #[test]
+    fn task_lists_survive_import_and_export() {
+        let tree = TempTree::new();
+        let db_path = tree.join("leara.db").display().to_string();
+        crate::db::migrations::run_migrations(&crate::db::open_connection(&db_path).unwrap()).unwrap();
+        let todo = "(A) 2024-06-01 Write report +work @office due:2024-06-10\nx 2024-06-03 2024-06-01 Buy milk pri:C\n";
+        let input = tree.write("todo.txt", todo);
+
+        run_tasks_command(&db_path, TasksCommand::Import { format: TaskFileFormat::Todotxt, file: input }).unwrap();
+        let export = |format: TaskFileFormat, name: &str, open_only: bool| {
+            let output = tree.join(name);
+            run_tasks_command(&db_path, TasksCommand::Export { format, output: Some(output.clone()), open_only }).unwrap();
+            std::fs::read_to_string(output).unwrap()
+        };
+
+        assert_eq!(export(TaskFileFormat::Todotxt, "all.txt", false), todo);
+        assert_eq!(
+            export(TaskFileFormat::Markdown, "open.md", true),
+            "# Leara Tasks\n\n- [ ] (A) Write report +work @office due:2024-06-10\n"
+        );
+        assert!(export(TaskFileFormat::Ics, "due.ics", true).contains("SUMMARY:Write report\r\n"));
+    }
+}
@@ -86,6 +86,22 @@ pub fn run_migrations(conn: &Connection) -> Result<()> {
         [],
     )?;
 
+    // Add recurrence rules to tasks created before recurrence support
+    add_column_if_missing(conn, "tasks", "recurrence", "TEXT")?;
+
+    // Create time tracking sessions for tasks
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS task_time_entries (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            task_id INTEGER NOT NULL,
+            started_at DATETIME NOT NULL,
+            ended_at DATETIME,
+            note TEXT,
+            FOREIGN KEY (task_id) REFERENCES tasks (id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
     // Create session context table for maintaining conversation context
     conn.execute(
         "CREATE TABLE IF NOT EXISTS session_context (
@@ -116,6 +132,223 @@ pub fn run_migrations(conn: &Connection) -> Result<()> {
         [],
     )?;
 
+    // Keep command output, environment changes and origin with each entry
+    add_column_if_missing(conn, "command_history", "stdout", "TEXT")?;
+    add_column_if_missing(conn, "command_history", "stderr", "TEXT")?;
+    add_column_if_missing(conn, "command_history", "stdout_truncated", "BOOLEAN NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "command_history", "stderr_truncated", "BOOLEAN NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "command_history", "timed_out", "BOOLEAN NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "command_history", "env_diff", "TEXT")?;
+    add_column_if_missing(conn, "command_history", "conversation_id", "TEXT")?;
+    add_column_if_missing(conn, "command_history", "replay_of", "INTEGER REFERENCES command_history (id)")?;
+    add_column_if_missing(conn, "command_history", "profile", "TEXT")?;
+
+    // Create table for commands awaiting user confirmation
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS pending_commands (
+            id TEXT PRIMARY KEY,
+            command TEXT NOT NULL,
+            args TEXT NOT NULL,
+            working_dir TEXT NOT NULL,
+            explanation TEXT NOT NULL,
+            reasons TEXT NOT NULL,
+            rules TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            created_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            decided_at TEXT,
+            history_id INTEGER,
+            FOREIGN KEY (history_id) REFERENCES command_history (id)
+        )",
+        [],
+    )?;
+
+    // Add caller-requested timeouts to commands pending before limits existed
+    add_column_if_missing(conn, "pending_commands", "timeout_secs", "INTEGER")?;
+    add_column_if_missing(conn, "pending_commands", "background", "BOOLEAN NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "pending_commands", "terminal", "BOOLEAN NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "pending_commands", "conversation_id", "TEXT")?;
+    add_column_if_missing(conn, "pending_commands", "replay_of", "INTEGER")?;
+    add_column_if_missing(conn, "pending_commands", "env", "TEXT")?;
+    add_column_if_missing(conn, "pending_commands", "profile", "TEXT")?;
+    add_column_if_missing(conn, "pending_commands", "detached", "BOOLEAN NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "pending_commands", "app_id", "TEXT")?;
+    add_column_if_missing(conn, "pending_commands", "process_start", "INTEGER")?;
+
+    // Create table for interactive terminal sessions and their transcripts
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS terminal_sessions (
+            id TEXT PRIMARY KEY,
+            command TEXT NOT NULL,
+            args TEXT NOT NULL,
+            working_dir TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'running',
+            exit_code INTEGER,
+            started_at TEXT NOT NULL,
+            ended_at TEXT,
+            user_confirmed BOOLEAN NOT NULL DEFAULT 0,
+            history_id INTEGER,
+            transcript TEXT,
+            transcript_truncated BOOLEAN NOT NULL DEFAULT 0,
+            FOREIGN KEY (history_id) REFERENCES command_history (id)
+        )",
+        [],
+    )?;
+
+    // Create table recording application launches, used to rank app search results
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS app_launches (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            app_id TEXT NOT NULL,
+            command TEXT NOT NULL,
+            args TEXT NOT NULL,
+            pid INTEGER NOT NULL,
+            conversation_id TEXT,
+            launched_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // Create tags table shared by tasks and memories
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            created_at DATETIME NOT NULL
+        )",
+        [],
+    )?;
+
+    // Create task <-> tag link table
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS task_tags (
+            task_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            PRIMARY KEY (task_id, tag_id),
+            FOREIGN KEY (task_id) REFERENCES tasks (id) ON DELETE CASCADE,
+            FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    // Create memory <-> tag link table
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS memory_tags (
+            memory_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            PRIMARY KEY (memory_id, tag_id),
+            FOREIGN KEY (memory_id) REFERENCES memory (id) ON DELETE CASCADE,
+            FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    // Create table for the system metrics history and its rollups
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS metric_samples (
+            resolution TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            samples INTEGER NOT NULL,
+            cpu_usage REAL NOT NULL,
+            cpu_max REAL NOT NULL,
+            memory_used INTEGER NOT NULL,
+            memory_max INTEGER NOT NULL,
+            memory_total INTEGER NOT NULL,
+            swap_used INTEGER NOT NULL,
+            disk_used INTEGER NOT NULL,
+            load_average REAL NOT NULL,
+            network_rx_rate REAL NOT NULL,
+            network_tx_rate REAL NOT NULL,
+            top_processes TEXT NOT NULL,
+            PRIMARY KEY (resolution, timestamp)
+        )",
+        [],
+    )?;
+
+    // Keep sensor readings with each point
+    add_column_if_missing(conn, "metric_samples", "temperature", "REAL")?;
+    add_column_if_missing(conn, "metric_samples", "temperature_max", "REAL")?;
+    add_column_if_missing(conn, "metric_samples", "battery_percent", "REAL")?;
+    add_column_if_missing(conn, "metric_samples", "power_watts", "REAL")?;
+
+    // Create table for user-defined alert rules
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS alert_rules (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            condition TEXT NOT NULL,
+            for_secs INTEGER NOT NULL DEFAULT 0,
+            enabled BOOLEAN NOT NULL DEFAULT 1,
+            create_task BOOLEAN NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // Create table for the history of pending, firing and resolved alerts
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS alerts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            rule_id INTEGER NOT NULL,
+            rule_name TEXT NOT NULL,
+            status TEXT NOT NULL,
+            value REAL,
+            message TEXT NOT NULL,
+            started_at TEXT NOT NULL,
+            fired_at TEXT,
+            resolved_at TEXT,
+            task_id INTEGER
+        )",
+        [],
+    )?;
+
+    // Create table for directories covered by the file index
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS index_roots (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            path TEXT NOT NULL UNIQUE,
+            created_at TEXT NOT NULL,
+            last_scan_at TEXT
+        )",
+        [],
+    )?;
+
+    // Create table for indexed files; their text lives in file_content under the same rowid
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS indexed_files (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            root_id INTEGER NOT NULL,
+            path TEXT NOT NULL UNIQUE,
+            name TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            modified INTEGER NOT NULL,
+            mime TEXT NOT NULL,
+            has_content BOOLEAN NOT NULL,
+            indexed_at TEXT NOT NULL,
+            FOREIGN KEY (root_id) REFERENCES index_roots (id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    // Create full-text index over file paths and text
+    conn.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS file_content USING fts5 (path, content)",
+        [],
+    )?;
+
+    // Move legacy comma-joined task tags into the normalized tables
+    migrate_legacy_task_tags(conn)?;
+
+    // Drop links left behind while foreign keys were not enforced
+    for sql in [
+        "DELETE FROM task_tags WHERE task_id NOT IN (SELECT id FROM tasks) OR tag_id NOT IN (SELECT id FROM tags)",
+        "DELETE FROM memory_tags WHERE memory_id NOT IN (SELECT id FROM memory) OR tag_id NOT IN (SELECT id FROM tags)",
+        "DELETE FROM task_time_entries WHERE task_id NOT IN (SELECT id FROM tasks)",
+    ] {
+        conn.execute(sql, [])?;
+    }
+
     // Create indexes for better performance
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_messages_conversation_id ON messages (conversation_id)",
@@ -162,6 +395,116 @@ pub fn run_migrations(conn: &Connection) -> Result<()> {
         [],
     )?;
 
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_task_time_entries_task_id ON task_time_entries (task_id)",
+        [],
+    )?;
+
+    // One running session per task; older duplicates end when the next one started
+    conn.execute(
+        "UPDATE task_time_entries SET ended_at = (
+             SELECT MIN(later.started_at) FROM task_time_entries later
+             WHERE later.task_id = task_time_entries.task_id AND later.ended_at IS NULL AND later.id > task_time_entries.id
+         )
+         WHERE ended_at IS NULL AND EXISTS (
+             SELECT 1 FROM task_time_entries later
+             WHERE later.task_id = task_time_entries.task_id AND later.ended_at IS NULL AND later.id > task_time_entries.id
+         )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_task_time_entries_running ON task_time_entries (task_id) WHERE ended_at IS NULL",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_pending_commands_status ON pending_commands (status)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_command_history_created_at ON command_history (created_at)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_command_history_conversation_id ON command_history (conversation_id)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_terminal_sessions_history_id ON terminal_sessions (history_id)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_app_launches_app_id ON app_launches (app_id)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_task_tags_tag_id ON task_tags (tag_id)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_memory_tags_tag_id ON memory_tags (tag_id)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_alerts_rule_id ON alerts (rule_id)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_indexed_files_root_id ON indexed_files (root_id)",
+        [],
+    )?;
+
     info!("Database migrations completed successfully");
     Ok(())
+}
+
+/// Add a column to an existing table unless it is already present
+/// 
+/// SQLite has no `ADD COLUMN IF NOT EXISTS`, so the table schema is inspected first.
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+        info!("Added column {}.{}", table, column);
+    }
+    Ok(())
+}
+
+/// Link tasks that still carry a comma-joined `tags` column to the tags table
+///
+/// The column is cleared once its tags have been linked, so this is a no-op
+/// on databases that have already been migrated.
+fn migrate_legacy_task_tags(conn: &Connection) -> Result<()> {
+    let legacy: Vec<(i64, String)> = {
+        let mut stmt = conn.prepare("SELECT id, tags FROM tasks WHERE tags IS NOT NULL AND tags != ''")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+
+    if legacy.is_empty() {
+        return Ok(());
+    }
+
+    for (task_id, tags) in &legacy {
+        let names = crate::db::queries::parse_tag_list(tags);
+        crate::db::queries::set_task_tags(conn, *task_id, &names)?;
+    }
+    conn.execute("UPDATE tasks SET tags = NULL WHERE tags IS NOT NULL", [])?;
+
+    info!("Migrated tags for {} legacy tasks", legacy.len());
+    Ok(())
 } 
\ No newline at end of file
@@ -23,6 +23,8 @@
 pub mod migrations;
 pub mod queries;
 
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::{Connection, Result};
 use std::path::Path;
 use tracing::info;
@@ -53,10 +55,13 @@ pub async fn init_database(db_path: &str) -> anyhow::Result<()> {
         std::fs::create_dir_all(parent).map_err(|e| anyhow::anyhow!("Failed to create directory: {}", e))?;
     }
 
-    let conn = Connection::open(db_path)?;
+    let conn = open_connection(db_path)?;
     
     // Run migrations
     migrations::run_migrations(&conn)?;
+
+    // Terminal sessions end with the process that relayed them
+    queries::interrupt_stale_terminal_sessions(&conn)?;
     
     info!("Database init successfully");
     Ok(())
@@ -64,5 +69,25 @@ pub async fn init_database(db_path: &str) -> anyhow::Result<()> {
 
 /// Get a database connection
 pub fn get_connection(db_path: &str) -> Result<Connection> {
-    Connection::open(db_path)
+    open_connection(db_path)
+}
+
+/// Settings every connection needs
+///
+/// SQLite only enforces foreign keys, and with them `ON DELETE CASCADE` on
+/// the tag links and time entries, on connections that turn them on.
+fn configure_connection(conn: &mut Connection) -> Result<()> {
+    conn.execute_batch("PRAGMA foreign_keys = ON;")
+}
+
+/// Open a single configured connection
+pub fn open_connection(db_path: &str) -> Result<Connection> {
+    let mut conn = Connection::open(db_path)?;
+    configure_connection(&mut conn)?;
+    Ok(conn)
+}
+
+/// Open a connection pool whose connections are all configured
+pub fn open_pool(db_path: &str) -> std::result::Result<Pool<SqliteConnectionManager>, r2d2::Error> {
+    Pool::new(SqliteConnectionManager::file(db_path).with_init(configure_connection))
 } 
\ No newline at end of file
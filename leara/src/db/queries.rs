@@ -22,12 +22,15 @@
 
 // Import rusqlite for SQLite database operations
 use rusqlite::{Connection, Result, params};
+use rusqlite::types::Value;
 // Import our local models for type safety
 use crate::models::{chat::*, memory::*};
 // Import chrono for timestamp handling
 use chrono::{Utc, DateTime};
 // Import uuid for unique identifier handling
 use uuid;
+use std::collections::HashMap;
+use crate::system::apps::AppUsage;
 
 /// Insert a new conversation into the database
 /// 
@@ -196,19 +199,29 @@ pub fn insert_memory(conn: &Connection, entry: &MemoryEntry) -> Result<()> {
 /// Insert or update an enhanced memory entry in the database
 /// 
 /// This function stores memory entries with enhanced features like categories,
-/// priorities, expiration dates, and active status.
+/// priorities, expiration dates, and active status. Existing keys are updated in
+/// place so the row id (and any tag links) survive the update. When `memory.tags`
+/// is set, the entry's tag links are replaced with the given list.
 /// 
 /// # Arguments
 /// * `conn` - Active database connection
 /// * `memory` - Memory struct containing the enhanced data to store
 /// 
 /// # Returns
-/// * `Ok(())` - Successfully stored memory entry
+/// * `Ok(i64)` - ID of the stored memory entry
 /// * `Err(rusqlite::Error)` - Database error
-pub fn insert_enhanced_memory(conn: &Connection, memory: &Memory) -> Result<()> {
+pub fn insert_enhanced_memory(conn: &Connection, memory: &Memory) -> Result<i64> {
     conn.execute(
-        "INSERT OR REPLACE INTO memory (key, value, category, priority, metadata, created_at, updated_at, expires_at, is_active) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        "INSERT INTO memory (key, value, category, priority, metadata, created_at, updated_at, expires_at, is_active) 
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+         ON CONFLICT(key) DO UPDATE SET
+            value = excluded.value,
+            category = excluded.category,
+            priority = excluded.priority,
+            metadata = excluded.metadata,
+            updated_at = excluded.updated_at,
+            expires_at = excluded.expires_at,
+            is_active = excluded.is_active",
         params![
             memory.key,
             memory.value,
@@ -221,7 +234,12 @@ pub fn insert_enhanced_memory(conn: &Connection, memory: &Memory) -> Result<()>
             memory.is_active,
         ],
     )?;
-    Ok(())
+
+    let memory_id: i64 = conn.query_row("SELECT id FROM memory WHERE key = ?", params![memory.key], |row| row.get(0))?;
+    if let Some(ref tags) = memory.tags {
+        set_memory_tags(conn, memory_id, &parse_tag_list(tags))?;
+    }
+    Ok(memory_id)
 }
 
 /// Retrieve a memory entry by its key
@@ -308,6 +326,7 @@ pub fn get_memory_by_key(conn: &Connection, key: &str) -> Result<Option<MemoryEn
 /// 
 /// # Returns
 /// * `Ok(MemoryResponse)` - Memory entries and total count
+/// * `Err(rusqlite::Error::InvalidParameterName)` - `tag_match` is neither `any` nor `all`
 /// * `Err(rusqlite::Error)` - Database error
 pub fn get_enhanced_memories(conn: &Connection, query: &MemoryQuery) -> Result<MemoryResponse> {
     let mut conditions = Vec::new();
@@ -337,6 +356,17 @@ pub fn get_enhanced_memories(conn: &Connection, query: &MemoryQuery) -> Result<M
     
     conditions.push("is_active = 1");
     
+    let tag_condition;
+    if let Some(ref tags) = query.tags {
+        let names = parse_tag_list(tags);
+        if !names.is_empty() {
+            let match_all = match_all_tags(query.tag_match.as_deref()).map_err(rusqlite::Error::InvalidParameterName)?;
+            tag_condition = tag_filter_condition("memory_tags", "memory_id", "memory.id", names.len(), match_all);
+            conditions.push(&tag_condition);
+            params_vec.extend(names);
+        }
+    }
+    
     let where_clause = if conditions.is_empty() {
         String::new()
     } else {
@@ -358,10 +388,11 @@ pub fn get_enhanced_memories(conn: &Connection, query: &MemoryQuery) -> Result<M
     
     // Build the main query
     let sql = format!(
-        "SELECT id, key, value, category, priority, metadata, created_at, updated_at, expires_at, is_active 
+        "SELECT id, key, value, category, priority, metadata, created_at, updated_at, expires_at, is_active, {} 
          FROM memory {} 
          ORDER BY priority DESC, updated_at DESC 
          LIMIT ? OFFSET ?",
+        MEMORY_TAGS_COLUMN,
         where_clause
     );
     
@@ -399,6 +430,7 @@ pub fn get_enhanced_memories(conn: &Connection, query: &MemoryQuery) -> Result<M
                     .map(|dt| dt.with_timezone(&Utc))
             }),
             is_active: row.get(9)?,
+            tags: row.get(10)?,
         })
     })?
     .collect::<Result<Vec<_>>>()?;
@@ -408,17 +440,20 @@ pub fn get_enhanced_memories(conn: &Connection, query: &MemoryQuery) -> Result<M
 
 /// Insert a new task into the database
 /// 
+/// Tags given as a comma-separated list in `task.tags` are linked through the
+/// normalized tags table rather than stored on the task row.
+/// 
 /// # Arguments
 /// * `conn` - Active database connection
 /// * `task` - Task struct containing the task data
 /// 
 /// # Returns
-/// * `Ok(())` - Successfully inserted task
+/// * `Ok(i64)` - ID of the inserted task
 /// * `Err(rusqlite::Error)` - Database error
-pub fn insert_task(conn: &Connection, task: &Task) -> Result<()> {
+pub fn insert_task(conn: &Connection, task: &Task) -> Result<i64> {
     conn.execute(
-        "INSERT INTO tasks (title, description, status, priority, due_date, created_at, updated_at, context, tags) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        "INSERT INTO tasks (title, description, status, priority, due_date, created_at, updated_at, completed_at, context, recurrence) 
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
         params![
             task.title,
             task.description,
@@ -427,38 +462,209 @@ pub fn insert_task(conn: &Connection, task: &Task) -> Result<()> {
             task.due_date.map(|dt| dt.to_rfc3339()),
             task.created_at.to_rfc3339(),
             task.updated_at.to_rfc3339(),
+            task.completed_at.map(|dt| dt.to_rfc3339()),
             task.context,
-            task.tags,
+            task.recurrence,
         ],
     )?;
-    Ok(())
+
+    let task_id = conn.last_insert_rowid();
+    if let Some(ref tags) = task.tags {
+        set_task_tags(conn, task_id, &parse_tag_list(tags))?;
+    }
+    Ok(task_id)
+}
+
+/// Columns selected for a full `Task` row, in the order `task_from_row` expects
+const TASK_COLUMNS: &str = "id, title, description, status, priority, due_date, created_at, updated_at, completed_at, context, recurrence";
+
+/// Build a `Task` from a row selected with `TASK_COLUMNS` followed by `TASK_TAGS_COLUMN`
+fn task_from_row(row: &rusqlite::Row) -> Result<Task> {
+    let created_at_str: String = row.get(6)?;
+    let updated_at_str: String = row.get(7)?;
+    let due_date_str: Option<String> = row.get(5)?;
+    let completed_at_str: Option<String> = row.get(8)?;
+
+    Ok(Task {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        description: row.get(2)?,
+        status: row.get(3)?,
+        priority: row.get(4)?,
+        due_date: due_date_str.and_then(|dt_str| {
+            chrono::DateTime::parse_from_rfc3339(&dt_str)
+                .ok()
+                .map(|dt| dt.with_timezone(&Utc))
+        }),
+        created_at: chrono::DateTime::parse_from_rfc3339(&created_at_str)
+            .unwrap_or_else(|_| Utc::now().into())
+            .with_timezone(&Utc),
+        updated_at: chrono::DateTime::parse_from_rfc3339(&updated_at_str)
+            .unwrap_or_else(|_| Utc::now().into())
+            .with_timezone(&Utc),
+        completed_at: completed_at_str.and_then(|dt_str| {
+            chrono::DateTime::parse_from_rfc3339(&dt_str)
+                .ok()
+                .map(|dt| dt.with_timezone(&Utc))
+        }),
+        context: row.get(9)?,
+        recurrence: row.get(10)?,
+        tags: row.get(11)?,
+    })
+}
+
+/// SQL expression used to order tasks by the given field
+/// 
+/// Missing due dates sort after every real date in both directions, so the
+/// expression never yields NULL and can be used for keyset pagination.
+fn task_sort_expression(field: TaskSortField, descending: bool) -> &'static str {
+    match (field, descending) {
+        (TaskSortField::Priority, _) => "COALESCE(priority, 0)",
+        (TaskSortField::DueDate, false) => "COALESCE(due_date, '~')",
+        (TaskSortField::DueDate, true) => "COALESCE(due_date, '')",
+        (TaskSortField::UpdatedAt, _) => "updated_at",
+        (TaskSortField::CreatedAt, _) => "created_at",
+    }
+}
+
+/// Sorting of a task query and where its page starts
+pub struct TaskOrder {
+    pub field: TaskSortField,
+    pub descending: bool,
+    /// Sort value and id of the last task of the previous page
+    pub after: Option<(String, i64)>,
+}
+
+fn sort_direction(descending: bool) -> &'static str {
+    if descending { "desc" } else { "asc" }
+}
+
+/// Resolve and check the `sort_by`, `order` and `cursor` of a task query
+/// 
+/// # Returns
+/// * `Ok(TaskOrder)` - Sort field, direction and cursor position
+/// * `Err(String)` - Unknown sort field or order, a malformed cursor or one issued for another sorting
+pub fn task_order(query: &TaskQuery) -> std::result::Result<TaskOrder, String> {
+    let field = match query.sort_by.as_deref() {
+        Some(name) => TaskSortField::from_str(name).ok_or_else(|| format!("Unknown sort field: {}", name))?,
+        None => TaskSortField::Priority,
+    };
+    let descending = match query.order.as_deref().map(|o| o.to_lowercase()) {
+        Some(ref o) if o == "asc" => false,
+        Some(ref o) if o == "desc" => true,
+        Some(o) => return Err(format!("Unknown sort order: {}", o)),
+        None => field.default_descending(),
+    };
+    let after = match query.cursor {
+        Some(ref cursor) => {
+            let (sorting, sort_value, id) = decode_cursor(cursor).ok_or_else(|| "Malformed cursor".to_string())?;
+            let expected = format!("{} {}", field.as_str(), sort_direction(descending));
+            if sorting != expected {
+                return Err(format!("Cursor was issued for sorting by {}, not {}", sorting, expected));
+            }
+            Some((sort_value, id))
+        }
+        None => None,
+    };
+    Ok(TaskOrder { field, descending, after })
+}
+
+/// Encode a keyset pagination cursor from the sorting and the last row's sort value and id
+fn encode_cursor(order: &TaskOrder, sort_value: &str, id: i64) -> String {
+    format!("{} {}\n{}\n{}", order.field.as_str(), sort_direction(order.descending), sort_value, id)
+        .bytes()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Decode a cursor produced by `encode_cursor` into sorting, sort value and id
+fn decode_cursor(cursor: &str) -> Option<(String, String, i64)> {
+    if !cursor.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(cursor.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let decoded = String::from_utf8(bytes).ok()?;
+    let (sorting, rest) = decoded.split_once('\n')?;
+    let (sort_value, id) = rest.rsplit_once('\n')?;
+    Some((sorting.to_string(), sort_value.to_string(), id.parse().ok()?))
 }
 
-/// Retrieve tasks with filtering and pagination
+/// Retrieve tasks with filtering, sorting and pagination
+/// 
+/// All filter values are bound as parameters. Pagination is either
+/// offset-based (`limit`/`offset`) or keyset-based via `cursor`, in which case
+/// the response's `next_cursor` continues from the last returned task.
 /// 
 /// # Arguments
 /// * `conn` - Active database connection
 /// * `query` - TaskQuery struct containing filter parameters
 /// 
 /// # Returns
-/// * `Ok(TaskResponse)` - Tasks and total count
+/// * `Ok(TaskResponse)` - Tasks, total matching count and next page cursor
+/// * `Err(rusqlite::Error::InvalidParameterName)` - The sorting, cursor or `tag_match` is invalid (see
+///   `task_order` and `match_all_tags`, which request handlers call first to tell these apart from
+///   database errors)
 /// * `Err(rusqlite::Error)` - Database error
 pub fn get_tasks(conn: &Connection, query: &TaskQuery) -> Result<TaskResponse> {
-    let mut conditions = Vec::new();
-    let mut params_vec = Vec::new();
+    let mut conditions: Vec<String> = Vec::new();
+    let mut params_vec: Vec<Value> = Vec::new();
     
     if let Some(ref status) = query.status {
-        conditions.push("status = ?");
-        params_vec.push(status.clone());
+        conditions.push("status = ?".to_string());
+        params_vec.push(Value::Text(status.clone()));
     }
     
     if let Some(priority) = query.priority {
-        conditions.push("priority = ?");
-        params_vec.push(priority.to_string());
+        conditions.push("priority = ?".to_string());
+        params_vec.push(Value::Integer(priority as i64));
     }
     
     if !query.include_completed.unwrap_or(false) {
-        conditions.push("status != 'completed'");
+        conditions.push("status != 'completed'".to_string());
+    }
+    
+    if let Some(ref tags) = query.tags {
+        let names = parse_tag_list(tags);
+        if !names.is_empty() {
+            let match_all = match_all_tags(query.tag_match.as_deref()).map_err(rusqlite::Error::InvalidParameterName)?;
+            conditions.push(tag_filter_condition("task_tags", "task_id", "tasks.id", names.len(), match_all));
+            params_vec.extend(names.into_iter().map(Value::Text));
+        }
+    }
+
+    let ranges = [
+        ("due_date >= ?", query.due_after),
+        ("due_date < ?", query.due_before),
+        ("created_at >= ?", query.created_after),
+        ("created_at < ?", query.created_before),
+        ("updated_at >= ?", query.updated_after),
+        ("updated_at < ?", query.updated_before),
+    ];
+    for (condition, bound) in ranges {
+        if let Some(bound) = bound {
+            conditions.push(condition.to_string());
+            params_vec.push(Value::Text(bound.to_rfc3339()));
+        }
+    }
+
+    if query.overdue.unwrap_or(false) {
+        conditions.push("due_date IS NOT NULL AND due_date < ? AND status NOT IN ('completed', 'cancelled')".to_string());
+        params_vec.push(Value::Text(Utc::now().to_rfc3339()));
+    }
+
+    if let Some(ref text) = query.q {
+        let pattern = format!("%{}%", text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        conditions.push("(title LIKE ? ESCAPE '\\' OR description LIKE ? ESCAPE '\\')".to_string());
+        params_vec.push(Value::Text(pattern.clone()));
+        params_vec.push(Value::Text(pattern));
+    }
+
+    if let Some(ref context) = query.context {
+        conditions.push("context = ?".to_string());
+        params_vec.push(Value::Text(context.clone()));
     }
     
     let where_clause = if conditions.is_empty() {
@@ -467,71 +673,204 @@ pub fn get_tasks(conn: &Connection, query: &TaskQuery) -> Result<TaskResponse> {
         format!("WHERE {}", conditions.join(" AND "))
     };
     
-    let limit = query.limit.unwrap_or(50);
-    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(50).max(1) as i64;
+    let offset = query.offset.unwrap_or(0).max(0) as i64;
     
     // Count total records
     let count_sql = format!("SELECT COUNT(*) FROM tasks {}", where_clause);
-    let total: i64 = if params_vec.is_empty() {
-        conn.query_row(&count_sql, [], |row| row.get(0))?
+    let total: i64 = conn.query_row(&count_sql, rusqlite::params_from_iter(params_vec.iter()), |row| row.get(0))?;
+
+    // Resolve sorting
+    let order = task_order(query).map_err(rusqlite::Error::InvalidParameterName)?;
+    let sort_expr = task_sort_expression(order.field, order.descending);
+    let (direction, comparison) = if order.descending { ("DESC", "<") } else { ("ASC", ">") };
+
+    // Keyset pagination continues strictly after the cursor row
+    let mut page_conditions = conditions.clone();
+    let mut page_params = params_vec.clone();
+    let use_cursor = order.after.is_some();
+    if let Some((ref sort_value, last_id)) = order.after {
+        let sort_value = match order.field {
+            TaskSortField::Priority => Value::Integer(sort_value.parse().unwrap_or(0)),
+            _ => Value::Text(sort_value.clone()),
+        };
+        page_conditions.push(format!("({0} {1} ? OR ({0} = ? AND id {1} ?))", sort_expr, comparison));
+        page_params.push(sort_value.clone());
+        page_params.push(sort_value);
+        page_params.push(Value::Integer(last_id));
+    }
+    let page_where = if page_conditions.is_empty() {
+        String::new()
     } else {
-        // Use parameterized count query
-        let mut stmt = conn.prepare(&count_sql)?;
-        stmt.query_row(rusqlite::params_from_iter(params_vec.iter()), |row| row.get(0))?
+        format!("WHERE {}", page_conditions.join(" AND "))
     };
     
     // Build the main query
     let sql = format!(
-        "SELECT id, title, description, status, priority, due_date, created_at, updated_at, completed_at, context, tags 
+        "SELECT {}, {}, {} 
          FROM tasks {} 
-         ORDER BY priority DESC, created_at DESC 
+         ORDER BY {} {}, id {} 
          LIMIT ? OFFSET ?",
-        where_clause
+        TASK_COLUMNS,
+        TASK_TAGS_COLUMN,
+        sort_expr,
+        page_where,
+        sort_expr,
+        direction,
+        direction
     );
     
     // Execute the query with parameters
     let mut stmt = conn.prepare(&sql)?;
+    page_params.push(Value::Integer(limit));
+    page_params.push(Value::Integer(if use_cursor { 0 } else { offset }));
     
-    // Combine parameters for the main query
-    let mut all_params = params_vec.clone();
-    all_params.push(limit.to_string());
-    all_params.push(offset.to_string());
-    
-    let tasks = stmt.query_map(rusqlite::params_from_iter(all_params.iter()), |row| {
-        let created_at_str: String = row.get(6)?;
-        let updated_at_str: String = row.get(7)?;
-        let due_date_str: Option<String> = row.get(5)?;
-        let completed_at_str: Option<String> = row.get(8)?;
-        
-        Ok(Task {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            description: row.get(2)?,
-            status: row.get(3)?,
-            priority: row.get(4)?,
-            due_date: due_date_str.and_then(|dt_str| {
-                chrono::DateTime::parse_from_rfc3339(&dt_str)
-                    .ok()
-                    .map(|dt| dt.with_timezone(&Utc))
-            }),
-            created_at: chrono::DateTime::parse_from_rfc3339(&created_at_str)
-                .unwrap_or_else(|_| Utc::now().into())
-                .with_timezone(&Utc),
-            updated_at: chrono::DateTime::parse_from_rfc3339(&updated_at_str)
-                .unwrap_or_else(|_| Utc::now().into())
-                .with_timezone(&Utc),
-            completed_at: completed_at_str.and_then(|dt_str| {
-                chrono::DateTime::parse_from_rfc3339(&dt_str)
-                    .ok()
-                    .map(|dt| dt.with_timezone(&Utc))
-            }),
-            context: row.get(9)?,
-            tags: row.get(10)?,
-        })
+    let rows = stmt.query_map(rusqlite::params_from_iter(page_params.iter()), |row| {
+        let sort_value = match row.get_ref(12)? {
+            rusqlite::types::ValueRef::Integer(i) => i.to_string(),
+            other => other.as_str().unwrap_or_default().to_string(),
+        };
+        Ok((task_from_row(row)?, sort_value))
     })?
     .collect::<Result<Vec<_>>>()?;
+
+    let next_cursor = match rows.last() {
+        Some((task, sort_value)) if rows.len() as i64 == limit => Some(encode_cursor(&order, sort_value, task.id)),
+        _ => None,
+    };
+    let tasks = rows.into_iter().map(|(task, _)| task).collect();
     
-    Ok(TaskResponse { tasks, total })
+    Ok(TaskResponse { tasks, total, next_cursor })
+}
+
+/// Insert several tasks in one transaction
+/// 
+/// Used by the importers so a malformed batch leaves no partial import behind.
+/// A task whose `id` names an existing task (an iCalendar export imported
+/// again) replaces that task instead of being added twice.
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `tasks` - Tasks to insert; `id` 0 always inserts
+/// 
+/// # Returns
+/// * `Ok((Vec<Task>, usize))` - The stored tasks with their IDs and normalized tags, and how many of them were updated
+/// * `Err(rusqlite::Error)` - Database error
+pub fn insert_tasks(conn: &Connection, tasks: Vec<Task>) -> Result<(Vec<Task>, usize)> {
+    let tx = conn.unchecked_transaction()?;
+    let mut stored = Vec::with_capacity(tasks.len());
+    let mut updated = 0;
+    for mut task in tasks {
+        if task.id > 0 && update_task(&tx, &task)? {
+            updated += 1;
+        } else {
+            task.id = insert_task(&tx, &task)?;
+        }
+        task.tags = task.tags.as_deref().map(parse_tag_list).map(|names| names.join(",")).filter(|t| !t.is_empty());
+        stored.push(task);
+    }
+    tx.commit()?;
+    Ok((stored, updated))
+}
+
+/// Replace the fields and tags of an existing task
+/// 
+/// The creation time and context are kept. A running timer is stopped when
+/// the new status is completed or cancelled.
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `task` - New values, for the task with ID `task.id`
+/// 
+/// # Returns
+/// * `Ok(bool)` - Whether the task exists
+/// * `Err(rusqlite::Error)` - Database error
+pub fn update_task(conn: &Connection, task: &Task) -> Result<bool> {
+    let changed = conn.execute(
+        "UPDATE tasks SET title = ?2, description = ?3, status = ?4, priority = ?5, due_date = ?6, updated_at = ?7,
+         completed_at = ?8, recurrence = ?9 WHERE id = ?1",
+        params![
+            task.id,
+            task.title,
+            task.description,
+            task.status,
+            task.priority,
+            task.due_date.map(|dt| dt.to_rfc3339()),
+            task.updated_at.to_rfc3339(),
+            task.completed_at.map(|dt| dt.to_rfc3339()),
+            task.recurrence,
+        ],
+    )?;
+    if changed == 0 {
+        return Ok(false);
+    }
+    if is_finished_status(&task.status) {
+        stop_running_time_entries(conn, task.id)?;
+    }
+    set_task_tags(conn, task.id, &task.tags.as_deref().map(parse_tag_list).unwrap_or_default())?;
+    Ok(true)
+}
+
+/// Whether a task status means the task is done with (completed or cancelled)
+fn is_finished_status(status: &str) -> bool {
+    status == "completed" || status == "cancelled"
+}
+
+/// End the running time entries of a task, so they stop accruing time
+fn stop_running_time_entries(conn: &Connection, task_id: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE task_time_entries SET ended_at = ? WHERE task_id = ? AND ended_at IS NULL",
+        params![Utc::now().to_rfc3339(), task_id],
+    )?;
+    Ok(())
+}
+
+/// Retrieve every task, oldest first
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `include_completed` - Whether completed and cancelled tasks are included
+/// 
+/// # Returns
+/// * `Ok(Vec<Task>)` - All matching tasks
+/// * `Err(rusqlite::Error)` - Database error
+pub fn get_all_tasks(conn: &Connection, include_completed: bool) -> Result<Vec<Task>> {
+    let status_filter = if include_completed {
+        ""
+    } else {
+        " WHERE status NOT IN ('completed', 'cancelled')"
+    };
+    let sql = format!(
+        "SELECT {}, {} FROM tasks{} ORDER BY created_at ASC, id ASC",
+        TASK_COLUMNS, TASK_TAGS_COLUMN, status_filter
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let tasks = stmt.query_map([], task_from_row)?.collect::<Result<Vec<_>>>()?;
+    Ok(tasks)
+}
+
+/// Retrieve every task that has a due date, earliest first
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `include_completed` - Whether completed and cancelled tasks are included
+/// 
+/// # Returns
+/// * `Ok(Vec<Task>)` - Tasks with due dates
+/// * `Err(rusqlite::Error)` - Database error
+pub fn get_tasks_with_due_dates(conn: &Connection, include_completed: bool) -> Result<Vec<Task>> {
+    let status_filter = if include_completed {
+        ""
+    } else {
+        " AND status NOT IN ('completed', 'cancelled')"
+    };
+    let sql = format!(
+        "SELECT {}, {} FROM tasks WHERE due_date IS NOT NULL{} ORDER BY due_date ASC, id ASC",
+        TASK_COLUMNS, TASK_TAGS_COLUMN, status_filter
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let tasks = stmt.query_map([], task_from_row)?.collect::<Result<Vec<_>>>()?;
+    Ok(tasks)
 }
 
 /// Update task status
@@ -545,6 +884,11 @@ pub fn get_tasks(conn: &Connection, query: &TaskQuery) -> Result<TaskResponse> {
 /// * `Ok(())` - Successfully updated task
 /// * `Err(rusqlite::Error)` - Database error
 pub fn update_task_status(conn: &Connection, task_id: i64, status: &str) -> Result<()> {
+    // Finished tasks stop accruing tracked time
+    if is_finished_status(status) {
+        stop_running_time_entries(conn, task_id)?;
+    }
+
     let completed_at = if status == "completed" {
         Some(Utc::now().to_rfc3339())
     } else {
@@ -653,13 +997,16 @@ pub fn search_memories(conn: &Connection, query: &str) -> Result<MemoryResponse>
     let count_sql = "SELECT COUNT(*) FROM memory WHERE key LIKE ? OR value LIKE ? OR category LIKE ?";
     let total: i64 = conn.query_row(count_sql, params![search_pattern, search_pattern, search_pattern], |row| row.get(0))?;
     
-    let sql = "SELECT id, key, value, category, priority, metadata, created_at, updated_at, expires_at, is_active 
-               FROM memory 
-               WHERE key LIKE ? OR value LIKE ? OR category LIKE ? 
-               ORDER BY priority DESC, updated_at DESC 
-               LIMIT 50";
+    let sql = format!(
+        "SELECT id, key, value, category, priority, metadata, created_at, updated_at, expires_at, is_active, {} 
+         FROM memory 
+         WHERE key LIKE ? OR value LIKE ? OR category LIKE ? 
+         ORDER BY priority DESC, updated_at DESC 
+         LIMIT 50",
+        MEMORY_TAGS_COLUMN
+    );
     
-    let mut stmt = conn.prepare(sql)?;
+    let mut stmt = conn.prepare(&sql)?;
     let memory_iter = stmt.query_map(params![search_pattern, search_pattern, search_pattern], |row| {
         let metadata_str: Option<String> = row.get(5)?;
         let metadata = metadata_str
@@ -680,6 +1027,7 @@ pub fn search_memories(conn: &Connection, query: &str) -> Result<MemoryResponse>
                 .with_timezone(&Utc),
             expires_at: row.get::<_, Option<String>>(8)?.map(|s| DateTime::parse_from_rfc3339(&s).unwrap().with_timezone(&Utc)),
             is_active: row.get(9)?,
+            tags: row.get(10)?,
         })
     })?;
     
@@ -689,6 +1037,9 @@ pub fn search_memories(conn: &Connection, query: &str) -> Result<MemoryResponse>
 
 /// Get a summary of all stored memories
 /// 
+/// Task figures come from `get_task_stats` over the last 30 days so the
+/// summary agrees with the statistics endpoint.
+/// 
 /// # Arguments
 /// * `conn` - Active database connection
 /// 
@@ -696,17 +1047,23 @@ pub fn search_memories(conn: &Connection, query: &str) -> Result<MemoryResponse>
 /// * `Ok(serde_json::Value)` - Memory summary
 /// * `Err(rusqlite::Error)` - Database error
 pub fn get_memory_summary(conn: &Connection) -> Result<serde_json::Value> {
-    let total_memories: i64 = conn.query_row("SELECT COUNT(*) FROM memories", params![], |row| row.get(0))?;
-    let active_memories: i64 = conn.query_row("SELECT COUNT(*) FROM memories WHERE is_active = 1", params![], |row| row.get(0))?;
+    let total_memories: i64 = conn.query_row("SELECT COUNT(*) FROM memory", params![], |row| row.get(0))?;
+    let active_memories: i64 = conn.query_row("SELECT COUNT(*) FROM memory WHERE is_active = 1", params![], |row| row.get(0))?;
     let total_tasks: i64 = conn.query_row("SELECT COUNT(*) FROM tasks", params![], |row| row.get(0))?;
     let completed_tasks: i64 = conn.query_row("SELECT COUNT(*) FROM tasks WHERE status = 'completed'", params![], |row| row.get(0))?;
+
+    let now = Utc::now();
+    let stats = get_task_stats(conn, StatsPeriod::Day, now - chrono::Duration::days(30), now)?;
     
     Ok(serde_json::json!({
         "total_memories": total_memories,
         "active_memories": active_memories,
         "total_tasks": total_tasks,
         "completed_tasks": completed_tasks,
-        "completion_rate": if total_tasks > 0 { (completed_tasks as f64 / total_tasks as f64) * 100.0 } else { 0.0 }
+        "completion_rate_30d": stats.completion_rate,
+        "average_lead_time_hours_30d": stats.average_lead_time_hours,
+        "overdue_tasks": stats.overdue,
+        "tracked_seconds_30d": stats.tracked_seconds,
     }))
 }
 
@@ -785,55 +1142,81 @@ pub fn store_session_context(conn: &Connection, request: &SessionContextRequest)
 /// Store command execution history
 /// 
 /// This function stores information about commands executed by the system,
-/// including success status, execution time, and user confirmation status.
+/// including their output, environment changes, where the request came
+/// from and whether the user confirmed it.
 /// 
 /// # Arguments
 /// * `conn` - Database connection
-/// * `command` - The command that was executed
-/// * `args` - Optional arguments passed to the command
-/// * `working_dir` - Optional working directory
-/// * `success` - Whether the command executed successfully
-/// * `exit_code` - Exit code from the command
-/// * `execution_time_ms` - Execution time in milliseconds
-/// * `user_confirmed` - Whether the user confirmed the command
+/// * `record` - The finished command
 /// 
 /// # Returns
-/// * `Ok(())` - Successfully stored command history
+/// * `Ok(i64)` - ID of the new history entry
 /// * `Err(rusqlite::Error)` - Database error
-pub fn store_command_history(
-    conn: &Connection,
-    command: &str,
-    args: &Option<Vec<String>>,
-    working_dir: &Option<String>,
-    success: bool,
-    exit_code: i32,
-    execution_time_ms: u64,
-    user_confirmed: bool,
-) -> Result<()> {
-    let args_json = args.as_ref().map(|a| serde_json::to_string(a).unwrap_or_default());
-    
+pub fn store_command_history(conn: &Connection, record: &crate::api::system::CommandRecord) -> Result<i64> {
     conn.execute(
-        "INSERT INTO command_history (command, args, working_dir, success, exit_code, execution_time_ms, user_confirmed, created_at) 
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        "INSERT INTO command_history (command, args, working_dir, success, exit_code, execution_time_ms, user_confirmed, created_at,
+                                      stdout, stderr, stdout_truncated, stderr_truncated, timed_out, env_diff, conversation_id, replay_of, profile) 
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         params![
-            command,
-            args_json,
-            working_dir,
-            success,
-            exit_code,
-            execution_time_ms,
-            user_confirmed,
-            chrono::Utc::now().to_rfc3339()
+            record.command,
+            serde_json::to_string(&record.args).unwrap_or_default(),
+            record.working_dir,
+            record.success,
+            record.exit_code,
+            record.execution_time_ms,
+            record.origin.user_confirmed,
+            chrono::Utc::now().to_rfc3339(),
+            record.stdout,
+            record.stderr,
+            record.stdout_truncated,
+            record.stderr_truncated,
+            record.timed_out,
+            serde_json::to_string(&record.env).unwrap_or_default(),
+            record.origin.conversation_id,
+            record.origin.replay_of,
+            record.profile,
         ],
     )?;
     
-    Ok(())
+    Ok(conn.last_insert_rowid())
+}
+
+/// Columns of `command_history` in the order `command_history_from_row` expects
+const COMMAND_HISTORY_COLUMNS: &str =
+    "id, command, args, working_dir, success, exit_code, execution_time_ms, user_confirmed, created_at,
+     (SELECT ts.id FROM terminal_sessions ts WHERE ts.history_id = command_history.id),
+     stdout, stderr, stdout_truncated, stderr_truncated, timed_out, env_diff, conversation_id, replay_of, profile";
+
+fn command_history_from_row(row: &rusqlite::Row, include_output: bool) -> Result<crate::api::system::CommandHistory> {
+    let env: Option<String> = row.get(15)?;
+    Ok(crate::api::system::CommandHistory {
+        id: row.get(0)?,
+        command: row.get(1)?,
+        args: row.get(2)?,
+        working_dir: row.get(3)?,
+        success: row.get(4)?,
+        exit_code: row.get(5)?,
+        execution_time_ms: row.get(6)?,
+        user_confirmed: row.get(7)?,
+        timestamp: row.get(8)?,
+        terminal_session_id: row.get(9)?,
+        stdout: if include_output { row.get(10)? } else { None },
+        stderr: if include_output { row.get(11)? } else { None },
+        stdout_truncated: row.get(12)?,
+        stderr_truncated: row.get(13)?,
+        timed_out: row.get(14)?,
+        env: env.and_then(|env| serde_json::from_str(&env).ok()).unwrap_or_default(),
+        conversation_id: row.get(16)?,
+        replay_of: row.get(17)?,
+        profile: row.get(18)?,
+    })
 }
 
 /// Get command execution history
 /// 
 /// This function retrieves the history of commands executed by the system,
-/// with optional filtering by success status and pagination.
+/// filtered by outcome, program, time range, exit code, conversation and
+/// text, with pagination.
 /// 
 /// # Arguments
 /// * `conn` - Database connection
@@ -846,15 +1229,47 @@ pub fn get_command_history(
     conn: &Connection,
     query: &crate::api::system::CommandHistoryQuery,
 ) -> Result<crate::api::system::CommandHistoryResponse> {
-    let mut conditions = Vec::new();
-    let mut params_vec = Vec::new();
+    let mut conditions: Vec<String> = Vec::new();
+    let mut params_vec: Vec<Value> = Vec::new();
     
-    if let Some(success_only) = query.success_only {
-        if success_only {
-            conditions.push("success = ?");
-            params_vec.push(1);
+    if query.success_only == Some(true) {
+        conditions.push("success = 1".to_string());
+    }
+
+    if let Some(ref program) = query.program {
+        // Match the program as given or by basename, so `git` finds `/usr/bin/git`
+        conditions.push("(command = ? OR command LIKE ? ESCAPE '\\')".to_string());
+        params_vec.push(Value::Text(program.clone()));
+        params_vec.push(Value::Text(format!("%/{}", escape_like(program))));
+    }
+
+    let ranges = [("created_at >= ?", query.since), ("created_at < ?", query.until)];
+    for (condition, bound) in ranges {
+        if let Some(bound) = bound {
+            conditions.push(condition.to_string());
+            params_vec.push(Value::Text(bound.to_rfc3339()));
         }
     }
+
+    if let Some(exit_code) = query.exit_code {
+        conditions.push("exit_code = ?".to_string());
+        params_vec.push(Value::Integer(exit_code as i64));
+    }
+
+    if let Some(ref conversation_id) = query.conversation_id {
+        conditions.push("conversation_id = ?".to_string());
+        params_vec.push(Value::Text(conversation_id.clone()));
+    }
+
+    if let Some(ref text) = query.q {
+        let pattern = format!("%{}%", escape_like(text));
+        let columns = ["command", "args", "working_dir", "stdout", "stderr"];
+        conditions.push(format!(
+            "({})",
+            columns.iter().map(|c| format!("{} LIKE ? ESCAPE '\\'", c)).collect::<Vec<_>>().join(" OR ")
+        ));
+        params_vec.extend(columns.iter().map(|_| Value::Text(pattern.clone())));
+    }
     
     let where_clause = if conditions.is_empty() {
         String::new()
@@ -862,57 +1277,1708 @@ pub fn get_command_history(
         format!("WHERE {}", conditions.join(" AND "))
     };
     
-    let limit = query.limit.unwrap_or(50);
-    let offset = query.offset.unwrap_or(0);
-    
-    let count_sql = format!(
-        "SELECT COUNT(*) FROM command_history {}",
-        where_clause
-    );
+    let limit = query.limit.unwrap_or(50).max(1) as i64;
+    let offset = query.offset.unwrap_or(0).max(0) as i64;
     
-    let total: i64 = if params_vec.is_empty() {
-        conn.query_row(&count_sql, [], |row| row.get(0))?
-    } else {
-        let mut stmt = conn.prepare(&count_sql)?;
-        stmt.query_row(rusqlite::params_from_iter(params_vec.iter()), |row| row.get(0))?
-    };
+    let count_sql = format!("SELECT COUNT(*) FROM command_history {}", where_clause);
+    let total: i64 = conn.query_row(&count_sql, rusqlite::params_from_iter(params_vec.iter()), |row| row.get(0))?;
     
     let sql = format!(
-        "SELECT id, command, args, working_dir, success, exit_code, execution_time_ms, user_confirmed, created_at 
-         FROM command_history {} 
-         ORDER BY created_at DESC 
-         LIMIT ? OFFSET ?",
-        where_clause
+        "SELECT {} FROM command_history {} ORDER BY created_at DESC, id DESC LIMIT ? OFFSET ?",
+        COMMAND_HISTORY_COLUMNS, where_clause
     );
-    
+    params_vec.push(Value::Integer(limit));
+    params_vec.push(Value::Integer(offset));
+
+    let include_output = query.include_output.unwrap_or(false);
     let mut stmt = conn.prepare(&sql)?;
-    let mut rows = if params_vec.is_empty() {
-        stmt.query(params![limit, offset])?
-    } else {
-        let mut all_params = params_vec.clone();
-        all_params.push(limit);
-        all_params.push(offset);
-        stmt.query(rusqlite::params_from_iter(all_params.iter()))?
-    };
-    
-    let mut commands = Vec::new();
-    while let Some(row) = rows.next()? {
-        let command = crate::api::system::CommandHistory {
-            id: row.get(0)?,
-            command: row.get(1)?,
-            args: row.get(2)?,
-            working_dir: row.get(3)?,
-            success: row.get(4)?,
-            exit_code: row.get(5)?,
-            execution_time_ms: row.get(6)?,
-            user_confirmed: row.get(7)?,
-            timestamp: row.get(8)?,
-        };
-        commands.push(command);
-    }
+    let commands = stmt
+        .query_map(rusqlite::params_from_iter(params_vec.iter()), |row| command_history_from_row(row, include_output))?
+        .collect::<Result<Vec<_>>>()?;
     
     Ok(crate::api::system::CommandHistoryResponse {
         commands,
         total,
     })
-} 
\ No newline at end of file
+}
+
+/// Look up a single history entry, including its output
+pub fn get_command_history_entry(conn: &Connection, id: i64) -> Result<Option<crate::api::system::CommandHistory>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM command_history WHERE id = ?", COMMAND_HISTORY_COLUMNS))?;
+    let mut rows = stmt.query(params![id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(command_history_from_row(row, true)?)),
+        None => Ok(None),
+    }
+}
+
+/// Escape `%`, `_` and `\` for use in a LIKE pattern with `ESCAPE '\'`
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Correlated subquery yielding a task's comma-joined tag names
+const TASK_TAGS_COLUMN: &str = "(SELECT GROUP_CONCAT(tg.name, ',') FROM task_tags tt JOIN tags tg ON tg.id = tt.tag_id WHERE tt.task_id = tasks.id)";
+
+/// Correlated subquery yielding a memory's comma-joined tag names
+const MEMORY_TAGS_COLUMN: &str = "(SELECT GROUP_CONCAT(tg.name, ',') FROM memory_tags mt JOIN tags tg ON tg.id = mt.tag_id WHERE mt.memory_id = memory.id)";
+
+/// Normalize a single tag name
+/// 
+/// Tags are case-insensitive, may be written with a leading `#`, and collapse
+/// inner whitespace to `-` so "Side Project" and "side-project" are the same tag.
+/// 
+/// # Returns
+/// * `Some(String)` - Normalized tag name
+/// * `None` - The input was empty after normalization
+pub fn normalize_tag(name: &str) -> Option<String> {
+    let name = name.trim().trim_start_matches('#').trim().to_lowercase();
+    let name = name.split_whitespace().collect::<Vec<_>>().join("-");
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Parse a comma-separated tag list into unique, normalized tag names
+pub fn parse_tag_list(tags: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in tags.split(',').filter_map(normalize_tag) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Whether a `tag_match` query parameter asks for all tags to match
+/// 
+/// # Returns
+/// * `Ok(bool)` - `true` for `all`, `false` for `any` or no parameter
+/// * `Err(String)` - Any other value
+pub fn match_all_tags(tag_match: Option<&str>) -> std::result::Result<bool, String> {
+    match tag_match.map(str::to_lowercase).as_deref() {
+        None | Some("any") => Ok(false),
+        Some("all") => Ok(true),
+        Some(_) => Err(format!("Unknown tag_match: {} (expected any or all)", tag_match.unwrap_or_default())),
+    }
+}
+
+/// Build a WHERE condition restricting rows to those linked to the given tags
+/// 
+/// The condition expects `count` tag names to be bound as parameters, in order.
+/// 
+/// # Arguments
+/// * `link_table` - Link table name (`task_tags` or `memory_tags`)
+/// * `link_column` - Owner id column in the link table
+/// * `owner_id` - Qualified id column of the owning table
+/// * `count` - Number of tag names that will be bound
+/// * `match_all` - Require every tag (true) or any tag (false)
+fn tag_filter_condition(link_table: &str, link_column: &str, owner_id: &str, count: usize, match_all: bool) -> String {
+    let placeholders = vec!["?"; count].join(", ");
+    let having = if match_all {
+        format!(" GROUP BY l.{} HAVING COUNT(DISTINCT l.tag_id) = {}", link_column, count)
+    } else {
+        String::new()
+    };
+    format!(
+        "{} IN (SELECT l.{} FROM {} l JOIN tags tg ON tg.id = l.tag_id WHERE tg.name IN ({}){})",
+        owner_id, link_column, link_table, placeholders, having
+    )
+}
+
+/// Look up a tag by name, creating it if it does not exist
+/// 
+/// # Returns
+/// * `Ok(i64)` - ID of the existing or newly created tag
+/// * `Err(rusqlite::Error)` - Database error
+pub fn get_or_create_tag(conn: &Connection, name: &str) -> Result<i64> {
+    conn.execute(
+        "INSERT OR IGNORE INTO tags (name, created_at) VALUES (?, ?)",
+        params![name, Utc::now().to_rfc3339()],
+    )?;
+    conn.query_row("SELECT id FROM tags WHERE name = ?", params![name], |row| row.get(0))
+}
+
+/// Replace the tags linked to a task
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `task_id` - ID of the task
+/// * `names` - Normalized tag names (see `parse_tag_list`)
+pub fn set_task_tags(conn: &Connection, task_id: i64, names: &[String]) -> Result<()> {
+    conn.execute("DELETE FROM task_tags WHERE task_id = ?", params![task_id])?;
+    for name in names {
+        let tag_id = get_or_create_tag(conn, name)?;
+        conn.execute(
+            "INSERT OR IGNORE INTO task_tags (task_id, tag_id) VALUES (?, ?)",
+            params![task_id, tag_id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Replace the tags linked to a memory entry
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `memory_id` - ID of the memory entry
+/// * `names` - Normalized tag names (see `parse_tag_list`)
+pub fn set_memory_tags(conn: &Connection, memory_id: i64, names: &[String]) -> Result<()> {
+    conn.execute("DELETE FROM memory_tags WHERE memory_id = ?", params![memory_id])?;
+    for name in names {
+        let tag_id = get_or_create_tag(conn, name)?;
+        conn.execute(
+            "INSERT OR IGNORE INTO memory_tags (memory_id, tag_id) VALUES (?, ?)",
+            params![memory_id, tag_id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Retrieve all tags with the number of tasks and memories using each
+/// 
+/// # Returns
+/// * `Ok(TagResponse)` - Tags ordered by total usage, then name
+/// * `Err(rusqlite::Error)` - Database error
+pub fn get_tags(conn: &Connection) -> Result<TagResponse> {
+    let mut stmt = conn.prepare(
+        "SELECT t.id, t.name, t.created_at,
+                (SELECT COUNT(*) FROM task_tags tt JOIN tasks ON tasks.id = tt.task_id WHERE tt.tag_id = t.id) AS task_count,
+                (SELECT COUNT(*) FROM memory_tags mt JOIN memory ON memory.id = mt.memory_id WHERE mt.tag_id = t.id) AS memory_count
+         FROM tags t
+         ORDER BY task_count + memory_count DESC, t.name ASC"
+    )?;
+
+    let tags = stmt.query_map([], |row| {
+        let created_at_str: String = row.get(2)?;
+        Ok(Tag {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                .unwrap_or_else(|_| Utc::now().into())
+                .with_timezone(&Utc),
+            task_count: row.get(3)?,
+            memory_count: row.get(4)?,
+        })
+    })?
+    .collect::<Result<Vec<_>>>()?;
+
+    let total = tags.len() as i64;
+    Ok(TagResponse { tags, total })
+}
+
+/// Merge several tags into a target tag
+/// 
+/// Every task and memory linked to a source tag is relinked to `target`
+/// (created if missing), and the source tags are deleted. Runs in a single
+/// transaction.
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `sources` - Normalized names of the tags to fold in
+/// * `target` - Normalized name of the tag receiving the links
+/// 
+/// # Returns
+/// * `Ok(usize)` - Number of source tags that existed and were merged
+/// * `Err(rusqlite::Error)` - Database error
+pub fn merge_tags(conn: &Connection, sources: &[String], target: &str) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let target_id = get_or_create_tag(&tx, target)?;
+    let mut merged = 0;
+
+    for source in sources {
+        let source_id: Option<i64> = tx
+            .query_row("SELECT id FROM tags WHERE name = ?", params![source], |row| row.get(0))
+            .ok();
+        let Some(source_id) = source_id else { continue };
+        if source_id == target_id {
+            continue;
+        }
+
+        tx.execute(
+            "INSERT OR IGNORE INTO task_tags (task_id, tag_id) SELECT task_id, ? FROM task_tags WHERE tag_id = ?",
+            params![target_id, source_id],
+        )?;
+        tx.execute(
+            "INSERT OR IGNORE INTO memory_tags (memory_id, tag_id) SELECT memory_id, ? FROM memory_tags WHERE tag_id = ?",
+            params![target_id, source_id],
+        )?;
+        tx.execute("DELETE FROM task_tags WHERE tag_id = ?", params![source_id])?;
+        tx.execute("DELETE FROM memory_tags WHERE tag_id = ?", params![source_id])?;
+        tx.execute("DELETE FROM tags WHERE id = ?", params![source_id])?;
+        merged += 1;
+    }
+
+    tx.commit()?;
+    Ok(merged)
+}
+
+/// Rename a tag
+/// 
+/// If another tag already has the new name, the two are merged.
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `old_name` - Normalized current tag name
+/// * `new_name` - Normalized new tag name
+/// 
+/// # Returns
+/// * `Ok(())` - Tag renamed or merged
+/// * `Err(rusqlite::Error::QueryReturnedNoRows)` - No tag named `old_name`
+/// * `Err(rusqlite::Error)` - Database error
+pub fn rename_tag(conn: &Connection, old_name: &str, new_name: &str) -> Result<()> {
+    let old_id: i64 = conn.query_row("SELECT id FROM tags WHERE name = ?", params![old_name], |row| row.get(0))?;
+    let existing: Option<i64> = conn
+        .query_row("SELECT id FROM tags WHERE name = ?", params![new_name], |row| row.get(0))
+        .ok();
+
+    match existing {
+        Some(id) if id != old_id => {
+            merge_tags(conn, &[old_name.to_string()], new_name)?;
+        }
+        Some(_) => {}
+        None => {
+            conn.execute("UPDATE tags SET name = ? WHERE id = ?", params![new_name, old_id])?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse a stored RFC3339 timestamp
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value).ok().map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Look up a single task by ID
+/// 
+/// # Returns
+/// * `Ok(Some(Task))` - Found task
+/// * `Ok(None)` - No task with this ID
+/// * `Err(rusqlite::Error)` - Database error
+pub fn get_task_by_id(conn: &Connection, task_id: i64) -> Result<Option<Task>> {
+    let sql = format!("SELECT {}, {} FROM tasks WHERE id = ?", TASK_COLUMNS, TASK_TAGS_COLUMN);
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query(params![task_id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(task_from_row(row)?)),
+        None => Ok(None),
+    }
+}
+
+/// Build a `TimeEntry` from `id, task_id, started_at, ended_at, note`
+fn time_entry_from_row(row: &rusqlite::Row) -> Result<TimeEntry> {
+    let started_at_str: String = row.get(2)?;
+    let ended_at_str: Option<String> = row.get(3)?;
+    let started_at = parse_timestamp(&started_at_str).unwrap_or_else(Utc::now);
+    let ended_at = ended_at_str.as_deref().and_then(parse_timestamp);
+
+    Ok(TimeEntry {
+        id: row.get(0)?,
+        task_id: row.get(1)?,
+        started_at,
+        ended_at,
+        duration_seconds: (ended_at.unwrap_or_else(Utc::now) - started_at).num_seconds().max(0),
+        note: row.get(4)?,
+    })
+}
+
+/// Get the running time entry of a task, if any
+pub fn get_running_time_entry(conn: &Connection, task_id: i64) -> Result<Option<TimeEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, task_id, started_at, ended_at, note FROM task_time_entries WHERE task_id = ? AND ended_at IS NULL"
+    )?;
+    let mut rows = stmt.query(params![task_id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(time_entry_from_row(row)?)),
+        None => Ok(None),
+    }
+}
+
+/// Start a timer on a task
+/// 
+/// Pending tasks are moved to `in_progress`. A task has at most one running
+/// session, which a unique index enforces even for concurrent starts, and
+/// completed or cancelled tasks cannot be timed.
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `task_id` - ID of the task
+/// * `note` - Optional note describing the session
+/// 
+/// # Returns
+/// * `Ok(Some(TimeEntry))` - The started entry
+/// * `Ok(None)` - A timer is already running on the task, or the task is finished
+/// * `Err(rusqlite::Error)` - Database error
+pub fn start_time_entry(conn: &Connection, task_id: i64, note: Option<&str>) -> Result<Option<TimeEntry>> {
+    let now = Utc::now();
+    let tx = conn.unchecked_transaction()?;
+    let inserted = tx.execute(
+        "INSERT OR IGNORE INTO task_time_entries (task_id, started_at, note)
+         SELECT id, ?2, ?3 FROM tasks WHERE id = ?1 AND status NOT IN ('completed', 'cancelled')",
+        params![task_id, now.to_rfc3339(), note],
+    )?;
+    if inserted == 0 {
+        return Ok(None);
+    }
+    let id = tx.last_insert_rowid();
+    tx.execute(
+        "UPDATE tasks SET status = 'in_progress', updated_at = ? WHERE id = ? AND status = 'pending'",
+        params![now.to_rfc3339(), task_id],
+    )?;
+    tx.commit()?;
+
+    Ok(Some(TimeEntry {
+        id,
+        task_id,
+        started_at: now,
+        ended_at: None,
+        duration_seconds: 0,
+        note: note.map(|n| n.to_string()),
+    }))
+}
+
+/// Stop a running time entry
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `entry_id` - ID of the running entry
+/// * `note` - Replaces the entry's note when given
+/// 
+/// # Returns
+/// * `Ok(TimeEntry)` - The finished entry
+/// * `Err(rusqlite::Error)` - Database error
+pub fn stop_time_entry(conn: &Connection, entry_id: i64, note: Option<&str>) -> Result<TimeEntry> {
+    conn.execute(
+        "UPDATE task_time_entries SET ended_at = ?, note = COALESCE(?, note) WHERE id = ?",
+        params![Utc::now().to_rfc3339(), note, entry_id],
+    )?;
+    conn.query_row(
+        "SELECT id, task_id, started_at, ended_at, note FROM task_time_entries WHERE id = ?",
+        params![entry_id],
+        time_entry_from_row,
+    )
+}
+
+/// Get all time entries of a task, newest first
+pub fn get_time_entries(conn: &Connection, task_id: i64) -> Result<TimeEntryResponse> {
+    let mut stmt = conn.prepare(
+        "SELECT id, task_id, started_at, ended_at, note FROM task_time_entries WHERE task_id = ? ORDER BY started_at DESC"
+    )?;
+    let entries = stmt.query_map(params![task_id], time_entry_from_row)?.collect::<Result<Vec<_>>>()?;
+
+    let total_seconds = entries.iter().map(|e| e.duration_seconds).sum();
+    let running = entries.iter().any(|e| e.ended_at.is_none());
+    Ok(TimeEntryResponse { entries, total_seconds, running })
+}
+
+/// Time tracked within a window, in total and per tag
+/// 
+/// Sessions overlapping the window are clipped to it; running sessions count
+/// up to now. A session on a task with several tags counts towards each tag.
+fn tracked_time(conn: &Connection, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<(i64, Vec<TagTime>)> {
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.started_at, e.ended_at, tg.name
+         FROM task_time_entries e
+         LEFT JOIN task_tags tt ON tt.task_id = e.task_id
+         LEFT JOIN tags tg ON tg.id = tt.tag_id
+         WHERE e.started_at < ? AND (e.ended_at IS NULL OR e.ended_at > ?)"
+    )?;
+    let rows = stmt.query_map(params![until.to_rfc3339(), since.to_rfc3339()], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, Option<String>>(3)?))
+    })?
+    .collect::<Result<Vec<_>>>()?;
+
+    let now = Utc::now();
+    let mut counted = std::collections::HashSet::new();
+    let mut total = 0;
+    let mut by_tag: std::collections::BTreeMap<String, i64> = std::collections::BTreeMap::new();
+
+    for (entry_id, started_at, ended_at, tag) in rows {
+        let Some(start) = parse_timestamp(&started_at) else { continue };
+        let end = ended_at.as_deref().and_then(parse_timestamp).unwrap_or(now);
+        let seconds = (end.min(until) - start.max(since)).num_seconds().max(0);
+
+        if counted.insert(entry_id) {
+            total += seconds;
+        }
+        if let Some(tag) = tag {
+            *by_tag.entry(tag).or_insert(0) += seconds;
+        }
+    }
+
+    let mut time_by_tag: Vec<TagTime> = by_tag.into_iter().map(|(tag, seconds)| TagTime { tag, seconds }).collect();
+    time_by_tag.sort_by_key(|t| std::cmp::Reverse(t.seconds));
+    Ok((total, time_by_tag))
+}
+
+/// Compute productivity statistics for a reporting window
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `period` - Bucket size for the completion series
+/// * `since` - Start of the window (inclusive); moved forward so the window spans at most `MAX_STATS_BUCKETS` buckets
+/// * `until` - End of the window (exclusive)
+/// 
+/// # Returns
+/// * `Ok(TaskStats)` - Statistics for the window
+/// * `Err(rusqlite::Error)` - Database error
+pub fn get_task_stats(conn: &Connection, period: StatsPeriod, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<TaskStats> {
+    let since = since.max(until - period.duration() * MAX_STATS_BUCKETS);
+    let first = period.bucket_start(since);
+    let mut buckets = Vec::new();
+    let mut start = first;
+    while start < until {
+        buckets.push((start, 0i64, 0i64, 0i64));
+        start += period.duration();
+    }
+    // Buckets are of equal length, so the position follows from the bucket's start
+    let bucket_count = buckets.len();
+    let bucket_index = |dt: DateTime<Utc>| {
+        let i = (period.bucket_start(dt) - first).num_seconds().div_euclid(period.duration().num_seconds());
+        (0..bucket_count as i64).contains(&i).then_some(i as usize)
+    };
+
+    let mut stmt = conn.prepare("SELECT created_at, completed_at, status FROM tasks")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, String>(2)?))
+    })?
+    .collect::<Result<Vec<_>>>()?;
+
+    let mut increments = Vec::new();
+    let (mut created, mut completed, mut cohort_completed) = (0, 0, 0);
+    let mut lead_times = Vec::new();
+
+    for (created_at, completed_at, status) in rows {
+        let created_at = parse_timestamp(&created_at);
+        let completed_at = completed_at.as_deref().and_then(parse_timestamp);
+        let is_completed = status == "completed";
+
+        if let Some(created_at) = created_at.filter(|dt| *dt >= since && *dt < until) {
+            created += 1;
+            if is_completed {
+                cohort_completed += 1;
+            }
+            if let Some(i) = bucket_index(created_at) {
+                increments.push((i, 1, 0, is_completed as i64));
+            }
+        }
+        if let Some(completed_at) = completed_at.filter(|dt| is_completed && *dt >= since && *dt < until) {
+            completed += 1;
+            if let Some(created_at) = created_at {
+                lead_times.push((completed_at - created_at).num_seconds() as f64 / 3600.0);
+            }
+            if let Some(i) = bucket_index(completed_at) {
+                increments.push((i, 0, 1, 0));
+            }
+        }
+    }
+
+    for (i, c, d, cohort) in increments {
+        buckets[i].1 += c;
+        buckets[i].2 += d;
+        buckets[i].3 += cohort;
+    }
+
+    let overdue: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM tasks WHERE due_date IS NOT NULL AND due_date < ? AND status NOT IN ('completed', 'cancelled')",
+        params![Utc::now().to_rfc3339()],
+        |row| row.get(0),
+    )?;
+    let (tracked_seconds, time_by_tag) = tracked_time(conn, since, until)?;
+
+    Ok(TaskStats {
+        period: period.as_str().to_string(),
+        since,
+        until,
+        buckets: buckets
+            .into_iter()
+            .map(|(start, created, completed, cohort)| CompletionBucket {
+                start,
+                created,
+                completed,
+                completion_rate: percentage(cohort, created),
+            })
+            .collect(),
+        created,
+        completed,
+        completion_rate: percentage(cohort_completed, created),
+        average_lead_time_hours: if lead_times.is_empty() {
+            None
+        } else {
+            Some(lead_times.iter().sum::<f64>() / lead_times.len() as f64)
+        },
+        overdue,
+        tracked_seconds,
+        time_by_tag,
+    })
+}
+
+/// `part` as a percentage of `whole`, 0 when `whole` is 0
+fn percentage(part: i64, whole: i64) -> f64 {
+    if whole > 0 {
+        (part as f64 / whole as f64) * 100.0
+    } else {
+        0.0
+    }
+}
+
+/// Build a digest of the current day or week
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `period` - Day or week; the digest covers the period containing now
+/// 
+/// # Returns
+/// * `Ok(TaskDigest)` - Completed, overdue and upcoming tasks plus a text summary
+/// * `Err(rusqlite::Error)` - Database error
+pub fn get_task_digest(conn: &Connection, period: StatsPeriod) -> Result<TaskDigest> {
+    let until = Utc::now();
+    let since = period.bucket_start(until);
+    let open = "status NOT IN ('completed', 'cancelled')";
+
+    let select = |condition: &str, order: &str, bounds: &[String]| -> Result<Vec<Task>> {
+        let sql = format!("SELECT {}, {} FROM tasks WHERE {} ORDER BY {}", TASK_COLUMNS, TASK_TAGS_COLUMN, condition, order);
+        let mut stmt = conn.prepare(&sql)?;
+        let tasks = stmt.query_map(rusqlite::params_from_iter(bounds.iter()), task_from_row)?.collect::<Result<Vec<_>>>()?;
+        Ok(tasks)
+    };
+
+    let completed = select("status = 'completed' AND completed_at >= ?", "completed_at ASC", &[since.to_rfc3339()])?;
+    let overdue = select(&format!("{} AND due_date < ?", open), "due_date ASC", &[until.to_rfc3339()])?;
+    let upcoming = select(
+        &format!("{} AND due_date >= ? AND due_date < ?", open),
+        "due_date ASC",
+        &[until.to_rfc3339(), (until + period.duration()).to_rfc3339()],
+    )?;
+    let created: i64 = conn.query_row("SELECT COUNT(*) FROM tasks WHERE created_at >= ?", params![since.to_rfc3339()], |row| row.get(0))?;
+    let (tracked_seconds, _) = tracked_time(conn, since, until)?;
+
+    let label = match period {
+        StatsPeriod::Day => "Today",
+        StatsPeriod::Week => "This week",
+    };
+    let mut summary = format!(
+        "{}: {} tasks completed, {} created, {} tracked.",
+        label,
+        completed.len(),
+        created,
+        format_duration(tracked_seconds)
+    );
+    if !overdue.is_empty() {
+        let titles: Vec<&str> = overdue.iter().take(5).map(|t| t.title.as_str()).collect();
+        summary.push_str(&format!(" {} overdue: {}.", overdue.len(), titles.join(", ")));
+    }
+    if !upcoming.is_empty() {
+        let titles: Vec<&str> = upcoming.iter().take(5).map(|t| t.title.as_str()).collect();
+        summary.push_str(&format!(" Coming up: {}.", titles.join(", ")));
+    }
+
+    Ok(TaskDigest {
+        period: period.as_str().to_string(),
+        since,
+        until,
+        completed,
+        created,
+        overdue,
+        upcoming,
+        tracked_seconds,
+        summary,
+    })
+}
+
+/// Format seconds as "1h 05m" / "12m"
+fn format_duration(seconds: i64) -> String {
+    let minutes = seconds / 60;
+    if minutes >= 60 {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// Columns of `pending_commands` in the order `pending_command_from_row` expects
+const PENDING_COMMAND_COLUMNS: &str =
+    "id, command, args, working_dir, explanation, reasons, rules, status, created_at, expires_at, decided_at, history_id, timeout_secs, background, terminal, conversation_id, replay_of, env, profile, detached, app_id, process_start";
+
+fn pending_command_from_row(row: &rusqlite::Row) -> Result<crate::api::system::PendingCommand> {
+    let json_list = |idx: usize| -> Result<Vec<String>> {
+        let raw: String = row.get(idx)?;
+        Ok(serde_json::from_str(&raw).unwrap_or_default())
+    };
+    Ok(crate::api::system::PendingCommand {
+        id: row.get(0)?,
+        command: row.get(1)?,
+        args: json_list(2)?,
+        working_dir: row.get(3)?,
+        explanation: row.get(4)?,
+        reasons: json_list(5)?,
+        rules: json_list(6)?,
+        status: row.get(7)?,
+        created_at: row.get(8)?,
+        expires_at: row.get(9)?,
+        decided_at: row.get(10)?,
+        history_id: row.get(11)?,
+        timeout_secs: row.get::<_, Option<i64>>(12)?.map(|t| t as u64),
+        background: row.get(13)?,
+        terminal: row.get(14)?,
+        conversation_id: row.get(15)?,
+        replay_of: row.get(16)?,
+        env: row
+            .get::<_, Option<String>>(17)?
+            .and_then(|env| serde_json::from_str(&env).ok())
+            .unwrap_or_default(),
+        profile: row.get(18)?,
+        detached: row.get(19)?,
+        app_id: row.get(20)?,
+        process_start: row.get::<_, Option<i64>>(21)?.map(|t| t as u64),
+    })
+}
+
+/// Store a command awaiting user confirmation
+pub fn insert_pending_command(conn: &Connection, pending: &crate::api::system::PendingCommand) -> Result<()> {
+    conn.execute(
+        &format!("INSERT INTO pending_commands ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)", PENDING_COMMAND_COLUMNS),
+        params![
+            pending.id,
+            pending.command,
+            serde_json::to_string(&pending.args).unwrap_or_default(),
+            pending.working_dir,
+            pending.explanation,
+            serde_json::to_string(&pending.reasons).unwrap_or_default(),
+            serde_json::to_string(&pending.rules).unwrap_or_default(),
+            pending.status,
+            pending.created_at,
+            pending.expires_at,
+            pending.decided_at,
+            pending.history_id,
+            pending.timeout_secs.map(|t| t as i64),
+            pending.background,
+            pending.terminal,
+            pending.conversation_id,
+            pending.replay_of,
+            serde_json::to_string(&pending.env).unwrap_or_default(),
+            pending.profile,
+            pending.detached,
+            pending.app_id,
+            pending.process_start.map(|t| t as i64),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Mark pending commands whose approval window has passed as expired
+pub fn expire_pending_commands(conn: &Connection) -> Result<usize> {
+    let now = Utc::now().to_rfc3339();
+    conn.execute(
+        "UPDATE pending_commands SET status = 'expired', decided_at = ? WHERE status = 'pending' AND expires_at <= ?",
+        params![now, now],
+    )
+}
+
+/// Look up a pending command by ID
+pub fn get_pending_command(conn: &Connection, id: &str) -> Result<Option<crate::api::system::PendingCommand>> {
+    expire_pending_commands(conn)?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM pending_commands WHERE id = ?", PENDING_COMMAND_COLUMNS))?;
+    let mut rows = stmt.query(params![id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(pending_command_from_row(row)?)),
+        None => Ok(None),
+    }
+}
+
+/// List pending commands, newest first
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `status` - Only return commands in this state (pending, approved, rejected, expired)
+/// * `limit` - Maximum number of commands
+pub fn get_pending_commands(conn: &Connection, status: Option<&str>, limit: i64) -> Result<Vec<crate::api::system::PendingCommand>> {
+    expire_pending_commands(conn)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM pending_commands WHERE (?1 IS NULL OR status = ?1) ORDER BY created_at DESC LIMIT ?2",
+        PENDING_COMMAND_COLUMNS
+    ))?;
+    let commands = stmt.query_map(params![status, limit], pending_command_from_row)?.collect::<Result<Vec<_>>>()?;
+    Ok(commands)
+}
+
+/// Move a pending command to a decided state
+/// 
+/// The update only succeeds while the command is still pending and
+/// unexpired, so concurrent approvals cannot run a command twice.
+/// 
+/// # Returns
+/// * `Ok(true)` - The state was changed by this call
+/// * `Ok(false)` - The command was already decided or has expired
+pub fn decide_pending_command(conn: &Connection, id: &str, status: &str) -> Result<bool> {
+    let now = Utc::now().to_rfc3339();
+    let changed = conn.execute(
+        "UPDATE pending_commands SET status = ?, decided_at = ? WHERE id = ? AND status = 'pending' AND expires_at > ?",
+        params![status, now, id, now],
+    )?;
+    Ok(changed == 1)
+}
+
+/// Link an approved command to the history entry of its execution
+pub fn set_pending_command_history(conn: &Connection, id: &str, history_id: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE pending_commands SET history_id = ? WHERE id = ?",
+        params![history_id, id],
+    )?;
+    Ok(())
+}
+
+/// Columns of `terminal_sessions` in the order `terminal_session_from_row` expects
+const TERMINAL_SESSION_COLUMNS: &str =
+    "id, command, args, working_dir, status, exit_code, started_at, ended_at, user_confirmed, history_id, transcript_truncated";
+
+fn terminal_session_from_row(row: &rusqlite::Row) -> Result<crate::api::system::TerminalSession> {
+    let args: String = row.get(2)?;
+    Ok(crate::api::system::TerminalSession {
+        id: row.get(0)?,
+        command: row.get(1)?,
+        args: serde_json::from_str(&args).unwrap_or_default(),
+        working_dir: row.get(3)?,
+        status: row.get(4)?,
+        exit_code: row.get(5)?,
+        started_at: row.get(6)?,
+        ended_at: row.get(7)?,
+        user_confirmed: row.get(8)?,
+        history_id: row.get(9)?,
+        transcript_truncated: row.get(10)?,
+    })
+}
+
+/// Record a terminal session that has just started
+pub fn insert_terminal_session(conn: &Connection, session: &crate::api::system::TerminalSession) -> Result<()> {
+    conn.execute(
+        &format!("INSERT INTO terminal_sessions ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)", TERMINAL_SESSION_COLUMNS),
+        params![
+            session.id,
+            session.command,
+            serde_json::to_string(&session.args).unwrap_or_default(),
+            session.working_dir,
+            session.status,
+            session.exit_code,
+            session.started_at,
+            session.ended_at,
+            session.user_confirmed,
+            session.history_id,
+            session.transcript_truncated,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Store the outcome and transcript of a terminal session that has ended
+/// 
+/// # Arguments
+/// * `conn` - Active database connection
+/// * `session` - Session with its final status, exit code and history entry
+/// * `transcript` - Raw terminal output
+pub fn finish_terminal_session(conn: &Connection, session: &crate::api::system::TerminalSession, transcript: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE terminal_sessions
+         SET status = ?, exit_code = ?, ended_at = ?, history_id = ?, transcript = ?, transcript_truncated = ?
+         WHERE id = ?",
+        params![
+            session.status,
+            session.exit_code,
+            session.ended_at,
+            session.history_id,
+            transcript,
+            session.transcript_truncated,
+            session.id,
+        ],
+    )?;
+    Ok(())
+}
+
+/// List terminal sessions, newest first
+pub fn get_terminal_sessions(conn: &Connection, limit: i64) -> Result<Vec<crate::api::system::TerminalSession>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM terminal_sessions ORDER BY started_at DESC LIMIT ?",
+        TERMINAL_SESSION_COLUMNS
+    ))?;
+    let sessions = stmt.query_map(params![limit], terminal_session_from_row)?.collect::<Result<Vec<_>>>()?;
+    Ok(sessions)
+}
+
+/// Look up a terminal session together with its raw transcript
+/// 
+/// # Returns
+/// * `Ok(Some((TerminalSession, Option<String>)))` - The session; the transcript is `None` while it is running
+/// * `Ok(None)` - No such session
+pub fn get_terminal_session(conn: &Connection, id: &str) -> Result<Option<(crate::api::system::TerminalSession, Option<String>)>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, transcript FROM terminal_sessions WHERE id = ?",
+        TERMINAL_SESSION_COLUMNS
+    ))?;
+    let mut rows = stmt.query(params![id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some((terminal_session_from_row(row)?, row.get(11)?))),
+        None => Ok(None),
+    }
+}
+
+/// Mark sessions left running by a previous server process as interrupted
+pub fn interrupt_stale_terminal_sessions(conn: &Connection) -> Result<usize> {
+    conn.execute(
+        "UPDATE terminal_sessions SET status = 'interrupted', ended_at = ? WHERE status = 'running'",
+        params![Utc::now().to_rfc3339()],
+    )
+}
+
+/// Record an application started through the launcher
+pub fn insert_app_launch(
+    conn: &Connection,
+    app_id: &str,
+    launched: &crate::api::system::LaunchedApp,
+    conversation_id: Option<&str>,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO app_launches (app_id, command, args, pid, conversation_id, launched_at) VALUES (?, ?, ?, ?, ?, ?)",
+        params![
+            app_id,
+            launched.command,
+            serde_json::to_string(&launched.args).unwrap_or_default(),
+            launched.pid,
+            conversation_id,
+            Utc::now().to_rfc3339(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// How often each application was launched and each program was run
+///
+/// # Returns
+/// * `(launches, runs)` - Usage by application ID and by program name from the command history
+pub fn get_app_usage(
+    conn: &Connection,
+) -> Result<(HashMap<String, AppUsage>, HashMap<String, AppUsage>)> {
+    let usage = |sql: &str| -> Result<HashMap<String, AppUsage>> {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, AppUsage { count: row.get(1)?, last_used: row.get(2)? }))
+        })?;
+        let mut usage: HashMap<String, AppUsage> = HashMap::new();
+        for row in rows {
+            let (key, stats) = row?;
+            // Programs run by path count for their file name
+            let key = key.rsplit('/').next().unwrap_or(&key).to_string();
+            usage.entry(key).or_default().add(&stats);
+        }
+        Ok(usage)
+    };
+    Ok((
+        usage("SELECT app_id, COUNT(*), MAX(launched_at) FROM app_launches GROUP BY app_id")?,
+        usage("SELECT command, COUNT(*), MAX(created_at) FROM command_history GROUP BY command")?,
+    ))
+}
+
+fn metric_point_from_row(row: &rusqlite::Row) -> Result<crate::models::system::MetricPoint> {
+    let top_processes: String = row.get(12)?;
+    Ok(crate::models::system::MetricPoint {
+        timestamp: DateTime::from_timestamp(row.get(0)?, 0).unwrap_or_default(),
+        samples: row.get(1)?,
+        cpu_usage: row.get(2)?,
+        cpu_max: row.get(3)?,
+        memory_used: row.get(4)?,
+        memory_max: row.get(5)?,
+        memory_total: row.get(6)?,
+        swap_used: row.get(7)?,
+        disk_used: row.get(8)?,
+        load_average: row.get(9)?,
+        network_rx_rate: row.get(10)?,
+        network_tx_rate: row.get(11)?,
+        temperature: row.get(13)?,
+        temperature_max: row.get(14)?,
+        battery_percent: row.get(15)?,
+        power_watts: row.get(16)?,
+        top_processes: serde_json::from_str(&top_processes).unwrap_or_default(),
+    })
+}
+
+/// Store a point of the metrics history, replacing one for the same period
+pub fn insert_metric_point(
+    conn: &Connection,
+    resolution: crate::models::system::MetricResolution,
+    point: &crate::models::system::MetricPoint,
+) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO metric_samples (resolution, timestamp, samples, cpu_usage, cpu_max, memory_used, memory_max,
+         memory_total, swap_used, disk_used, load_average, network_rx_rate, network_tx_rate, top_processes,
+         temperature, temperature_max, battery_percent, power_watts)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            resolution.as_str(),
+            point.timestamp.timestamp(),
+            point.samples,
+            point.cpu_usage,
+            point.cpu_max,
+            point.memory_used,
+            point.memory_max,
+            point.memory_total,
+            point.swap_used,
+            point.disk_used,
+            point.load_average,
+            point.network_rx_rate,
+            point.network_tx_rate,
+            serde_json::to_string(&point.top_processes).unwrap_or_else(|_| "[]".to_string()),
+            point.temperature,
+            point.temperature_max,
+            point.battery_percent,
+            point.power_watts,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Points of the metrics history starting in `[since, until)`, oldest first
+///
+/// # Arguments
+/// * `since` / `until` - Seconds since the Unix epoch
+pub fn get_metric_points(
+    conn: &Connection,
+    resolution: crate::models::system::MetricResolution,
+    since: i64,
+    until: i64,
+) -> Result<Vec<crate::models::system::MetricPoint>> {
+    let mut stmt = conn.prepare(
+        "SELECT timestamp, samples, cpu_usage, cpu_max, memory_used, memory_max, memory_total, swap_used, disk_used,
+         load_average, network_rx_rate, network_tx_rate, top_processes, temperature, temperature_max,
+         battery_percent, power_watts
+         FROM metric_samples WHERE resolution = ? AND timestamp >= ? AND timestamp < ? ORDER BY timestamp",
+    )?;
+    let points = stmt.query_map(params![resolution.as_str(), since, until], metric_point_from_row)?;
+    points.collect()
+}
+
+/// Start of the newest stored point of a resolution
+pub fn latest_metric_timestamp(conn: &Connection, resolution: crate::models::system::MetricResolution) -> Result<Option<i64>> {
+    conn.query_row(
+        "SELECT MAX(timestamp) FROM metric_samples WHERE resolution = ?",
+        params![resolution.as_str()],
+        |row| row.get(0),
+    )
+}
+
+/// Delete points of a resolution that started before `before`
+pub fn delete_metric_points_before(
+    conn: &Connection,
+    resolution: crate::models::system::MetricResolution,
+    before: i64,
+) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM metric_samples WHERE resolution = ? AND timestamp < ?",
+        params![resolution.as_str(), before],
+    )
+}
+
+fn alert_rule_from_row(row: &rusqlite::Row) -> Result<crate::models::system::AlertRule> {
+    let condition: String = row.get(2)?;
+    let created_at: String = row.get(6)?;
+    let updated_at: String = row.get(7)?;
+    Ok(crate::models::system::AlertRule {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        condition: serde_json::from_str(&condition)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e)))?,
+        for_secs: row.get(3)?,
+        enabled: row.get(4)?,
+        create_task: row.get(5)?,
+        created_at: parse_timestamp(&created_at).unwrap_or_else(Utc::now),
+        updated_at: parse_timestamp(&updated_at).unwrap_or_else(Utc::now),
+    })
+}
+
+const ALERT_RULE_COLUMNS: &str = "id, name, condition, for_secs, enabled, create_task, created_at, updated_at";
+
+/// Store a new alert rule and return its ID
+pub fn insert_alert_rule(conn: &Connection, rule: &crate::models::system::AlertRule) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO alert_rules (name, condition, for_secs, enabled, create_task, created_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+        params![
+            rule.name,
+            serde_json::to_string(&rule.condition).unwrap_or_default(),
+            rule.for_secs,
+            rule.enabled,
+            rule.create_task,
+            rule.created_at.to_rfc3339(),
+            rule.updated_at.to_rfc3339(),
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Replace an alert rule's settings
+///
+/// # Returns
+/// * `Ok(bool)` - Whether the rule exists
+pub fn update_alert_rule(conn: &Connection, rule: &crate::models::system::AlertRule) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE alert_rules SET name = ?, condition = ?, for_secs = ?, enabled = ?, create_task = ?, updated_at = ? WHERE id = ?",
+        params![
+            rule.name,
+            serde_json::to_string(&rule.condition).unwrap_or_default(),
+            rule.for_secs,
+            rule.enabled,
+            rule.create_task,
+            rule.updated_at.to_rfc3339(),
+            rule.id,
+        ],
+    )?;
+    Ok(updated > 0)
+}
+
+/// Delete an alert rule; its alert history is kept
+///
+/// # Returns
+/// * `Ok(bool)` - Whether the rule existed
+pub fn delete_alert_rule(conn: &Connection, id: i64) -> Result<bool> {
+    Ok(conn.execute("DELETE FROM alert_rules WHERE id = ?", params![id])? > 0)
+}
+
+/// Look up an alert rule by ID
+pub fn get_alert_rule(conn: &Connection, id: i64) -> Result<Option<crate::models::system::AlertRule>> {
+    let sql = format!("SELECT {} FROM alert_rules WHERE id = ?", ALERT_RULE_COLUMNS);
+    match conn.query_row(&sql, params![id], alert_rule_from_row) {
+        Ok(rule) => Ok(Some(rule)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Every alert rule, oldest first
+pub fn get_alert_rules(conn: &Connection) -> Result<Vec<crate::models::system::AlertRule>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM alert_rules ORDER BY id", ALERT_RULE_COLUMNS))?;
+    let rules = stmt.query_map([], alert_rule_from_row)?;
+    rules.collect()
+}
+
+fn alert_from_row(row: &rusqlite::Row) -> Result<crate::models::system::Alert> {
+    let status: String = row.get(3)?;
+    let started_at: String = row.get(6)?;
+    let fired_at: Option<String> = row.get(7)?;
+    let resolved_at: Option<String> = row.get(8)?;
+    Ok(crate::models::system::Alert {
+        id: row.get(0)?,
+        rule_id: row.get(1)?,
+        rule_name: row.get(2)?,
+        status: crate::models::system::AlertStatus::from_str(&status).unwrap_or(crate::models::system::AlertStatus::Resolved),
+        value: row.get(4)?,
+        message: row.get(5)?,
+        started_at: parse_timestamp(&started_at).unwrap_or_else(Utc::now),
+        fired_at: fired_at.as_deref().and_then(parse_timestamp),
+        resolved_at: resolved_at.as_deref().and_then(parse_timestamp),
+        task_id: row.get(9)?,
+    })
+}
+
+const ALERT_COLUMNS: &str = "id, rule_id, rule_name, status, value, message, started_at, fired_at, resolved_at, task_id";
+
+/// Record a new alert and return its ID
+pub fn insert_alert(conn: &Connection, alert: &crate::models::system::Alert) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO alerts (rule_id, rule_name, status, value, message, started_at, fired_at, resolved_at, task_id)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            alert.rule_id,
+            alert.rule_name,
+            alert.status.as_str(),
+            alert.value,
+            alert.message,
+            alert.started_at.to_rfc3339(),
+            alert.fired_at.map(|dt| dt.to_rfc3339()),
+            alert.resolved_at.map(|dt| dt.to_rfc3339()),
+            alert.task_id,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Update the status, value, times and task of a recorded alert
+pub fn update_alert(conn: &Connection, alert: &crate::models::system::Alert) -> Result<()> {
+    conn.execute(
+        "UPDATE alerts SET status = ?, value = ?, message = ?, fired_at = ?, resolved_at = ?, task_id = ? WHERE id = ?",
+        params![
+            alert.status.as_str(),
+            alert.value,
+            alert.message,
+            alert.fired_at.map(|dt| dt.to_rfc3339()),
+            alert.resolved_at.map(|dt| dt.to_rfc3339()),
+            alert.task_id,
+            alert.id,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Recorded alerts, newest first
+///
+/// # Arguments
+/// * `rule_id` - Only alerts of this rule
+/// * `status` - Only alerts with this status
+/// * `limit` - Maximum number of alerts
+pub fn get_alerts(
+    conn: &Connection,
+    rule_id: Option<i64>,
+    status: Option<crate::models::system::AlertStatus>,
+    limit: i64,
+) -> Result<Vec<crate::models::system::Alert>> {
+    let mut conditions = Vec::new();
+    let mut values: Vec<Value> = Vec::new();
+    if let Some(rule_id) = rule_id {
+        conditions.push("rule_id = ?");
+        values.push(Value::Integer(rule_id));
+    }
+    if let Some(status) = status {
+        conditions.push("status = ?");
+        values.push(Value::Text(status.as_str().to_string()));
+    }
+    let where_clause = if conditions.is_empty() { String::new() } else { format!("WHERE {}", conditions.join(" AND ")) };
+    values.push(Value::Integer(limit));
+
+    let sql = format!("SELECT {} FROM alerts {} ORDER BY started_at DESC, id DESC LIMIT ?", ALERT_COLUMNS, where_clause);
+    let mut stmt = conn.prepare(&sql)?;
+    let alerts = stmt.query_map(rusqlite::params_from_iter(values.iter()), alert_from_row)?;
+    alerts.collect()
+}
+
+fn index_root_from_row(row: &rusqlite::Row) -> Result<crate::models::system::IndexRoot> {
+    let created_at: String = row.get(2)?;
+    let last_scan_at: Option<String> = row.get(3)?;
+    Ok(crate::models::system::IndexRoot {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        created_at: parse_timestamp(&created_at).unwrap_or_else(Utc::now),
+        last_scan_at: last_scan_at.as_deref().and_then(parse_timestamp),
+        file_count: row.get(4)?,
+    })
+}
+
+const INDEX_ROOT_COLUMNS: &str =
+    "id, path, created_at, last_scan_at, (SELECT COUNT(*) FROM indexed_files f WHERE f.root_id = index_roots.id)";
+
+/// Add a directory to the file index and return its ID
+pub fn insert_index_root(conn: &Connection, path: &str) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO index_roots (path, created_at) VALUES (?, ?)",
+        params![path, Utc::now().to_rfc3339()],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Every indexed directory, by path
+pub fn get_index_roots(conn: &Connection) -> Result<Vec<crate::models::system::IndexRoot>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM index_roots ORDER BY path", INDEX_ROOT_COLUMNS))?;
+    let roots = stmt.query_map([], index_root_from_row)?;
+    roots.collect()
+}
+
+/// Look up an indexed directory by ID
+pub fn get_index_root(conn: &Connection, id: i64) -> Result<Option<crate::models::system::IndexRoot>> {
+    let sql = format!("SELECT {} FROM index_roots WHERE id = ?", INDEX_ROOT_COLUMNS);
+    match conn.query_row(&sql, params![id], index_root_from_row) {
+        Ok(root) => Ok(Some(root)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Remove a directory and everything recorded below it from the index
+///
+/// # Returns
+/// * `Ok(bool)` - Whether the directory was indexed
+pub fn delete_index_root(conn: &Connection, id: i64) -> Result<bool> {
+    conn.execute(
+        "DELETE FROM file_content WHERE rowid IN (SELECT id FROM indexed_files WHERE root_id = ?)",
+        params![id],
+    )?;
+    conn.execute("DELETE FROM indexed_files WHERE root_id = ?", params![id])?;
+    Ok(conn.execute("DELETE FROM index_roots WHERE id = ?", params![id])? > 0)
+}
+
+/// Record that a directory was scanned completely
+pub fn set_index_root_scanned(conn: &Connection, id: i64, scanned_at: DateTime<Utc>) -> Result<()> {
+    conn.execute(
+        "UPDATE index_roots SET last_scan_at = ? WHERE id = ?",
+        params![scanned_at.to_rfc3339(), id],
+    )?;
+    Ok(())
+}
+
+fn indexed_file_from_row(row: &rusqlite::Row) -> Result<crate::models::system::IndexedFile> {
+    let indexed_at: String = row.get(8)?;
+    Ok(crate::models::system::IndexedFile {
+        id: row.get(0)?,
+        root_id: row.get(1)?,
+        path: row.get(2)?,
+        name: row.get(3)?,
+        size: row.get::<_, i64>(4)? as u64,
+        modified: DateTime::from_timestamp_millis(row.get(5)?).unwrap_or_default(),
+        mime: row.get(6)?,
+        has_content: row.get(7)?,
+        indexed_at: parse_timestamp(&indexed_at).unwrap_or_else(Utc::now),
+    })
+}
+
+const INDEXED_FILE_COLUMNS: &str = "id, root_id, path, name, size, modified, mime, has_content, indexed_at";
+
+/// Size and modification time (in milliseconds) of every file recorded below a root, by path
+pub fn get_indexed_file_stamps(conn: &Connection, root_id: i64) -> Result<HashMap<String, (i64, u64, i64)>> {
+    let mut stmt = conn.prepare("SELECT path, id, size, modified FROM indexed_files WHERE root_id = ?")?;
+    let rows = stmt.query_map(params![root_id], |row| {
+        Ok((row.get::<_, String>(0)?, (row.get(1)?, row.get::<_, i64>(2)? as u64, row.get(3)?)))
+    })?;
+    rows.collect()
+}
+
+/// Store a file and its text, replacing what was recorded for the same path
+///
+/// # Arguments
+/// * `content` - Extracted text; `None` indexes only the path
+///
+/// # Returns
+/// * `Ok(i64)` - ID of the file
+pub fn upsert_indexed_file(
+    conn: &Connection,
+    file: &crate::models::system::IndexedFile,
+    content: Option<&str>,
+) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO indexed_files (root_id, path, name, size, modified, mime, has_content, indexed_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT (path) DO UPDATE SET root_id = excluded.root_id, name = excluded.name, size = excluded.size,
+         modified = excluded.modified, mime = excluded.mime, has_content = excluded.has_content,
+         indexed_at = excluded.indexed_at",
+        params![
+            file.root_id,
+            file.path,
+            file.name,
+            file.size as i64,
+            file.modified.timestamp_millis(),
+            file.mime,
+            content.is_some(),
+            file.indexed_at.to_rfc3339(),
+        ],
+    )?;
+    let id: i64 = conn.query_row("SELECT id FROM indexed_files WHERE path = ?", params![file.path], |row| row.get(0))?;
+    conn.execute("DELETE FROM file_content WHERE rowid = ?", params![id])?;
+    conn.execute(
+        "INSERT INTO file_content (rowid, path, content) VALUES (?, ?, ?)",
+        params![id, file.path, content.unwrap_or_default()],
+    )?;
+    Ok(id)
+}
+
+/// Remove a file, or a directory and everything below it, from the index
+///
+/// # Returns
+/// * `Ok(usize)` - Files removed
+pub fn delete_indexed_path(conn: &Connection, path: &str) -> Result<usize> {
+    let below = format!("{}/", path.trim_end_matches('/'));
+    let matching = "path = ?1 OR substr(path, 1, length(?2)) = ?2";
+    conn.execute(
+        &format!("DELETE FROM file_content WHERE rowid IN (SELECT id FROM indexed_files WHERE {})", matching),
+        params![path, below],
+    )?;
+    conn.execute(&format!("DELETE FROM indexed_files WHERE {}", matching), params![path, below])
+}
+
+/// Indexed files, by path
+///
+/// # Arguments
+/// * `root_id` - Only files below this root
+pub fn get_indexed_files(conn: &Connection, root_id: Option<i64>) -> Result<Vec<crate::models::system::IndexedFile>> {
+    let sql = format!(
+        "SELECT {} FROM indexed_files WHERE ?1 IS NULL OR root_id = ?1 ORDER BY path",
+        INDEXED_FILE_COLUMNS
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let files = stmt.query_map(params![root_id], indexed_file_from_row)?;
+    files.collect()
+}
+
+/// Number of indexed files, and of those whose text is searchable
+pub fn count_indexed_files(conn: &Connection) -> Result<(i64, i64)> {
+    conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(has_content), 0) FROM indexed_files",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+}
+
+/// Files whose path or text match a full-text query, best first
+///
+/// # Arguments
+/// * `query` - FTS5 query
+/// * `root_id` - Only files below this root
+/// * `limit` / `offset` - Page of results
+pub fn search_file_content(
+    conn: &Connection,
+    query: &str,
+    root_id: Option<i64>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<crate::models::system::ContentMatch>> {
+    let columns: Vec<String> = INDEXED_FILE_COLUMNS.split(", ").map(|column| format!("f.{}", column)).collect();
+    let sql = format!(
+        "SELECT {}, snippet(file_content, 1, '[', ']', '...', 16), bm25(file_content)
+         FROM file_content JOIN indexed_files f ON f.id = file_content.rowid
+         WHERE file_content MATCH ?1 AND (?2 IS NULL OR f.root_id = ?2)
+         ORDER BY bm25(file_content) LIMIT ?3 OFFSET ?4",
+        columns.join(", ")
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let matches = stmt.query_map(params![query, root_id, limit, offset], |row| {
+        Ok(crate::models::system::ContentMatch {
+            file: indexed_file_from_row(row)?,
+            snippet: row.get(9)?,
+            rank: row.get(10)?,
+        })
+    })?;
+    matches.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, TimeZone};
+    use crate::testing::TestDatabase;
+
+    fn task(title: &str, tags: Option<&str>) -> Task {
+        Task {
+            id: 0,
+            title: title.to_string(),
+            description: None,
+            status: "pending".to_string(),
+            priority: 3,
+            due_date: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            completed_at: None,
+            context: None,
+            tags: tags.map(str::to_string),
+            recurrence: None,
+        }
+    }
+
+    fn memory(key: &str, tags: &str) -> Memory {
+        Memory {
+            id: 0,
+            key: key.to_string(),
+            value: "value".to_string(),
+            category: "general".to_string(),
+            priority: 1,
+            metadata: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            expires_at: None,
+            is_active: true,
+            tags: Some(tags.to_string()),
+        }
+    }
+
+    /// Tag names with their task and memory counts
+    fn tag_counts(conn: &Connection) -> Vec<(String, i64, i64)> {
+        let mut tags: Vec<(String, i64, i64)> =
+            get_tags(conn).unwrap().tags.into_iter().map(|t| (t.name, t.task_count, t.memory_count)).collect();
+        tags.sort();
+        tags
+    }
+
+    fn links(conn: &Connection) -> i64 {
+        conn.query_row("SELECT (SELECT COUNT(*) FROM task_tags) + (SELECT COUNT(*) FROM memory_tags)", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn tags_are_normalized() {
+        assert_eq!(normalize_tag("  #Side  Project "), Some("side-project".to_string()));
+        assert_eq!(normalize_tag("# rust"), Some("rust".to_string()));
+        assert_eq!(normalize_tag(" # "), None);
+        assert_eq!(parse_tag_list("Work, #work,home ,, Deep Work"), vec!["work", "home", "deep-work"]);
+    }
+
+    #[test]
+    fn merging_moves_links_to_the_target() {
+        let db = TestDatabase::new();
+        let conn = db.conn();
+        insert_task(&conn, &task("both", Some("todo, to-do"))).unwrap();
+        insert_task(&conn, &task("one", Some("to-do"))).unwrap();
+        insert_enhanced_memory(&conn, &memory("note", "todo-list")).unwrap();
+
+        let sources = vec!["to-do".to_string(), "todo-list".to_string(), "missing".to_string()];
+        assert_eq!(merge_tags(&conn, &sources, "todo").unwrap(), 2);
+        // The task tagged with both keeps a single link
+        assert_eq!(tag_counts(&conn), vec![("todo".to_string(), 2, 1)]);
+        assert_eq!(links(&conn), 3);
+    }
+
+    #[test]
+    fn renaming_onto_an_existing_tag_merges() {
+        let db = TestDatabase::new();
+        let conn = db.conn();
+        insert_task(&conn, &task("a", Some("work"))).unwrap();
+        insert_task(&conn, &task("b", Some("job"))).unwrap();
+
+        rename_tag(&conn, "work", "office").unwrap();
+        assert_eq!(tag_counts(&conn), vec![("job".to_string(), 1, 0), ("office".to_string(), 1, 0)]);
+
+        rename_tag(&conn, "job", "office").unwrap();
+        assert_eq!(tag_counts(&conn), vec![("office".to_string(), 2, 0)]);
+
+        assert!(matches!(rename_tag(&conn, "nothing", "office"), Err(rusqlite::Error::QueryReturnedNoRows)));
+    }
+
+    #[test]
+    fn deleting_a_tag_removes_its_links() {
+        let db = TestDatabase::new();
+        let conn = db.conn();
+        insert_task(&conn, &task("a", Some("work"))).unwrap();
+        insert_enhanced_memory(&conn, &memory("note", "work")).unwrap();
+
+        conn.execute("DELETE FROM tags WHERE name = 'work'", []).unwrap();
+        assert_eq!(links(&conn), 0);
+    }
+
+    #[test]
+    fn reimported_tasks_are_updated() {
+        let db = TestDatabase::new();
+        let conn = db.conn();
+        let (stored, updated) = insert_tasks(&conn, vec![task("Write report", Some("work")), task("Shop", None)]).unwrap();
+        assert_eq!(updated, 0);
+
+        let mut changed = stored[0].clone();
+        changed.title = "Write the report".to_string();
+        changed.status = "completed".to_string();
+        changed.tags = Some("work,urgent".to_string());
+        let mut foreign = task("Foreign", None);
+        foreign.id = 9999;
+        let (stored, updated) = insert_tasks(&conn, vec![changed, foreign]).unwrap();
+        assert_eq!(updated, 1);
+        assert_ne!(stored[1].id, 9999);
+
+        let tasks = get_all_tasks(&conn, true).unwrap();
+        let titles: Vec<(&str, &str, Option<&str>)> =
+            tasks.iter().map(|t| (t.title.as_str(), t.status.as_str(), t.tags.as_deref())).collect();
+        assert_eq!(
+            titles,
+            vec![("Write the report", "completed", Some("work,urgent")), ("Shop", "pending", None), ("Foreign", "pending", None)]
+        );
+    }
+
+    /// Tasks "t0".."t9" with priorities 1-5 and due dates a day apart, t8 completed and t9 tagged
+    fn seed_tasks(conn: &Connection) {
+        let due = Utc::now() - chrono::Duration::hours(60);
+        let tasks = (0..10)
+            .map(|i| {
+                let mut task = task(&format!("t{}", i), if i == 9 { Some("work,urgent") } else if i % 2 == 0 { Some("work") } else { None });
+                task.priority = i % 5 + 1;
+                task.due_date = Some(due + chrono::Duration::days(i as i64));
+                task.description = (i == 4).then(|| "buy 100% organic milk".to_string());
+                task.context = (i < 3).then(|| "home".to_string());
+                if i == 8 {
+                    task.status = "completed".to_string();
+                }
+                task
+            })
+            .collect();
+        insert_tasks(conn, tasks).unwrap();
+    }
+
+    fn titles(response: &TaskResponse) -> Vec<&str> {
+        response.tasks.iter().map(|task| task.title.as_str()).collect()
+    }
+
+    #[test]
+    fn tasks_are_filtered() {
+        let db = TestDatabase::new();
+        let conn = db.conn();
+        seed_tasks(&conn);
+        let query = |query: TaskQuery| {
+            let mut response = get_tasks(&conn, &TaskQuery { limit: Some(100), ..query }).unwrap();
+            assert_eq!(response.total as usize, response.tasks.len());
+            response.tasks.sort_by_key(|task| task.id);
+            response
+        };
+
+        assert_eq!(query(TaskQuery::default()).tasks.len(), 9);
+        assert_eq!(query(TaskQuery { include_completed: Some(true), ..Default::default() }).tasks.len(), 10);
+        assert_eq!(titles(&query(TaskQuery { priority: Some(5), ..Default::default() })), vec!["t4", "t9"]);
+        assert_eq!(titles(&query(TaskQuery { status: Some("completed".to_string()), include_completed: Some(true), ..Default::default() })), vec!["t8"]);
+        assert_eq!(titles(&query(TaskQuery { tags: Some("urgent".to_string()), ..Default::default() })), vec!["t9"]);
+        assert_eq!(
+            titles(&query(TaskQuery { tags: Some("work, urgent".to_string()), ..Default::default() })),
+            vec!["t0", "t2", "t4", "t6", "t9"]
+        );
+        assert_eq!(
+            titles(&query(TaskQuery { tags: Some("work,urgent".to_string()), tag_match: Some("all".to_string()), ..Default::default() })),
+            vec!["t9"]
+        );
+        assert_eq!(titles(&query(TaskQuery { q: Some("100%".to_string()), ..Default::default() })), vec!["t4"]);
+        assert_eq!(titles(&query(TaskQuery { q: Some("T1".to_string()), ..Default::default() })), vec!["t1"]);
+        assert_eq!(titles(&query(TaskQuery { context: Some("home".to_string()), ..Default::default() })), vec!["t0", "t1", "t2"]);
+        assert_eq!(titles(&query(TaskQuery { overdue: Some(true), ..Default::default() })), vec!["t0", "t1", "t2"]);
+
+        let t5 = query(TaskQuery { q: Some("t5".to_string()), ..Default::default() }).tasks[0].due_date.unwrap();
+        assert_eq!(
+            titles(&query(TaskQuery { due_after: Some(t5), due_before: Some(t5 + chrono::Duration::days(2)), ..Default::default() })),
+            vec!["t5", "t6"]
+        );
+    }
+
+    #[test]
+    fn cursors_page_through_every_task_once() {
+        let db = TestDatabase::new();
+        let conn = db.conn();
+        seed_tasks(&conn);
+
+        for (sort_by, order) in [("priority", None), ("due_date", None), ("due_date", Some("desc")), ("created_at", Some("asc"))] {
+            let query = |limit: i32, cursor: Option<String>| TaskQuery {
+                sort_by: Some(sort_by.to_string()),
+                order: order.map(str::to_string),
+                include_completed: Some(true),
+                limit: Some(limit),
+                cursor,
+                ..Default::default()
+            };
+            let all = get_tasks(&conn, &query(100, None)).unwrap();
+            let mut paged = Vec::new();
+            let mut cursor = None;
+            loop {
+                let page = get_tasks(&conn, &query(3, cursor)).unwrap();
+                assert_eq!(page.total, 10);
+                paged.extend(page.tasks.iter().map(|task| task.id));
+                cursor = page.next_cursor;
+                if cursor.is_none() {
+                    break;
+                }
+            }
+            assert_eq!(paged, all.tasks.iter().map(|task| task.id).collect::<Vec<_>>(), "{} {:?}", sort_by, order);
+        }
+
+        let offset = get_tasks(&conn, &TaskQuery { limit: Some(4), offset: Some(8), include_completed: Some(true), ..Default::default() }).unwrap();
+        assert_eq!(offset.tasks.len(), 2);
+        assert_eq!(offset.next_cursor, None);
+    }
+
+    #[test]
+    fn invalid_sorting_and_cursors_are_rejected() {
+        let db = TestDatabase::new();
+        let conn = db.conn();
+        seed_tasks(&conn);
+        let first = get_tasks(&conn, &TaskQuery { sort_by: Some("due".to_string()), limit: Some(2), ..Default::default() }).unwrap();
+        let cursor = first.next_cursor.unwrap();
+
+        let same = TaskQuery { sort_by: Some("due_date".to_string()), order: Some("ASC".to_string()), cursor: Some(cursor.clone()), ..Default::default() };
+        assert!(task_order(&same).is_ok());
+        for query in [
+            TaskQuery { cursor: Some(cursor.clone()), ..Default::default() },
+            TaskQuery { sort_by: Some("due_date".to_string()), order: Some("desc".to_string()), cursor: Some(cursor), ..Default::default() },
+            TaskQuery { cursor: Some("zz".to_string()), ..Default::default() },
+            TaskQuery { sort_by: Some("title".to_string()), ..Default::default() },
+            TaskQuery { order: Some("up".to_string()), ..Default::default() },
+        ] {
+            assert!(task_order(&query).is_err(), "{:?}", query);
+            assert!(matches!(get_tasks(&conn, &query), Err(rusqlite::Error::InvalidParameterName(_))));
+        }
+    }
+
+    #[test]
+    fn unknown_tag_matching_is_rejected() {
+        let db = TestDatabase::new();
+        let conn = db.conn();
+        seed_tasks(&conn);
+        assert_eq!(match_all_tags(None), Ok(false));
+        assert_eq!(match_all_tags(Some("ANY")), Ok(false));
+        assert_eq!(match_all_tags(Some("all")), Ok(true));
+        assert!(match_all_tags(Some("al")).is_err());
+
+        let tags = Some("work".to_string());
+        let query = TaskQuery { tags: tags.clone(), tag_match: Some("al".to_string()), ..Default::default() };
+        assert!(matches!(get_tasks(&conn, &query), Err(rusqlite::Error::InvalidParameterName(_))));
+        let query = MemoryQuery { tags, tag_match: Some("al".to_string()), ..Default::default() };
+        assert!(matches!(get_enhanced_memories(&conn, &query), Err(rusqlite::Error::InvalidParameterName(_))));
+    }
+
+    #[test]
+    fn a_task_has_one_running_timer() {
+        let db = TestDatabase::new();
+        let conn = db.conn();
+        let (stored, _) = insert_tasks(&conn, vec![task("Write", None), task("Read", None)]).unwrap();
+        let (write, read) = (stored[0].id, stored[1].id);
+
+        let running = start_time_entry(&conn, write, Some("draft")).unwrap().unwrap();
+        assert!(start_time_entry(&conn, write, None).unwrap().is_none());
+        assert!(start_time_entry(&conn, read, None).unwrap().is_some());
+        assert_eq!(get_task_by_id(&conn, write).unwrap().unwrap().status, "in_progress");
+
+        stop_time_entry(&conn, running.id, None).unwrap();
+        assert!(get_running_time_entry(&conn, write).unwrap().is_none());
+        assert!(start_time_entry(&conn, write, None).unwrap().is_some());
+        assert_eq!(get_time_entries(&conn, write).unwrap().entries.len(), 2);
+
+        update_task_status(&conn, read, "cancelled").unwrap();
+        assert!(get_running_time_entry(&conn, read).unwrap().is_none());
+        assert!(start_time_entry(&conn, read, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn reimporting_a_completed_task_stops_its_timer() {
+        let db = TestDatabase::new();
+        let conn = db.conn();
+        let (stored, _) = insert_tasks(&conn, vec![task("Write", None)]).unwrap();
+        let id = stored[0].id;
+        start_time_entry(&conn, id, None).unwrap().unwrap();
+
+        let mut finished = get_task_by_id(&conn, id).unwrap().unwrap();
+        finished.status = "completed".to_string();
+        finished.completed_at = Some(Utc::now());
+        let (_, updated) = insert_tasks(&conn, vec![finished]).unwrap();
+        assert_eq!(updated, 1);
+
+        assert!(get_running_time_entry(&conn, id).unwrap().is_none());
+        assert!(get_time_entries(&conn, id).unwrap().entries.iter().all(|entry| entry.ended_at.is_some()));
+        assert!(start_time_entry(&conn, id, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn stats_count_tasks_per_bucket() {
+        let db = TestDatabase::new();
+        let conn = db.conn();
+        let day = |d: u32, h: u32| Utc.with_ymd_and_hms(2024, 6, d, h, 0, 0).unwrap();
+        let mut tasks = Vec::new();
+        for (created, completed) in [(day(3, 9), Some(day(3, 21))), (day(3, 10), None), (day(4, 8), Some(day(6, 8))), (day(1, 0), Some(day(4, 0)))] {
+            let mut task = task("t", None);
+            task.created_at = created;
+            if let Some(completed) = completed {
+                task.status = "completed".to_string();
+                task.completed_at = Some(completed);
+            }
+            tasks.push(task);
+        }
+        insert_tasks(&conn, tasks).unwrap();
+
+        let stats = get_task_stats(&conn, StatsPeriod::Day, day(3, 0), day(7, 0)).unwrap();
+        let buckets: Vec<(u32, i64, i64)> = stats.buckets.iter().map(|b| (b.start.day(), b.created, b.completed)).collect();
+        assert_eq!(buckets, vec![(3, 2, 1), (4, 1, 1), (5, 0, 0), (6, 0, 1)]);
+        assert_eq!((stats.created, stats.completed), (3, 3));
+        assert!((stats.completion_rate - 200.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.buckets[0].completion_rate, 50.0);
+        assert_eq!(stats.average_lead_time_hours, Some((12.0 + 48.0 + 72.0) / 3.0));
+
+        let weekly = get_task_stats(&conn, StatsPeriod::Week, day(1, 0), day(14, 0)).unwrap();
+        let buckets: Vec<(u32, i64)> = weekly.buckets.iter().map(|b| (b.start.day(), b.created)).collect();
+        assert_eq!(buckets, vec![(27, 1), (3, 3), (10, 0)]);
+
+        let long = get_task_stats(&conn, StatsPeriod::Day, day(1, 0) - chrono::Duration::days(100_000), day(7, 0)).unwrap();
+        assert!(long.buckets.len() <= MAX_STATS_BUCKETS as usize + 1);
+        assert_eq!(long.created, 4);
+    }
+}
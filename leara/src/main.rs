@@ -20,32 +20,44 @@
  * Purpose: Main application entry point and server setup
  */
 
-use axum::{
-    http::Method,
-    Router,
-};
+use axum::Router;
 use std::net::SocketAddr;
-use tower_http::cors::{Any, CorsLayer};
 use tracing::info;
 use tokio::net::TcpListener;
 use std::sync::{Arc, Mutex};
 use rusqlite::Connection;
 use crate::system::MemoryService;
 use crate::models::AppState;
-use r2d2::{Pool};
-use r2d2_sqlite::SqliteConnectionManager;
+use crate::api::origin::FrontendOrigin;
+use crate::system::alerts::AlertManager;
+use crate::system::apps::AppCatalog;
+use crate::system::events::EventBus;
+use crate::system::indexer::{FileIndexer, IndexConfig};
+use crate::system::jobs::JobManager;
+use crate::system::metrics::{MetricsConfig, MetricsHistory};
+use crate::system::policy::PolicyStore;
+use crate::system::sampler::{SamplerConfig, SystemSampler};
+use clap::Parser;
+use crate::cli::{Cli, Command};
 
 mod api;
+mod cli;
 mod db;
 mod system;
 mod models;
 mod utils;
+#[cfg(test)]
+mod testing;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize logging
-    tracing_subscriber::fmt::init();
-    info!("Starting Leara AI Assistant Backend...");
+    let cli = Cli::parse();
+
+    // Initialize logging; subcommands log to stderr so their output can be piped
+    match cli.command {
+        Some(Command::Tasks { .. }) => tracing_subscriber::fmt().with_writer(std::io::stderr).init(),
+        _ => tracing_subscriber::fmt::init(),
+    }
 
     // Load environment variables
     dotenv::dotenv().ok();
@@ -55,25 +67,49 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     db::init_database(&db_path).await?;
     info!("Database initialized at: {}", db_path);
 
+    if let Some(Command::Tasks { command }) = cli.command {
+        cli::run_tasks_command(&db_path, command)?;
+        return Ok(());
+    }
+    info!("Starting Leara AI Assistant Backend...");
+
     // Open SQLite connection pool (r2d2)
-    let manager = SqliteConnectionManager::file(&db_path);
-    let db = Pool::new(manager)?;
+    let db = db::open_pool(&db_path)?;
     
     // Create a separate connection for MemoryService if needed
     let memory_service = Arc::new(MemoryService::new(db.clone()));
     
-    let app_state = AppState { db, memory_service };
+    // Load the command execution policy
+    let policy = Arc::new(PolicyStore::from_env());
+    
+    let jobs = Arc::new(JobManager::new(db.clone()));
+    
+    // Discover installed applications and watch for changes
+    let apps = AppCatalog::new();
+    
+    // Sample system resources in the background
+    let sampler = SystemSampler::start(SamplerConfig::from_env()).await;
+    
+    // Record the samples into the metrics history
+    let metrics = MetricsHistory::start(MetricsConfig::from_env(), db.clone(), sampler.clone());
+    
+    // Evaluate alert rules and notify the frontend
+    let events = Arc::new(EventBus::new());
+    let alerts = AlertManager::start(db.clone(), sampler.clone(), events.clone());
+    
+    // Index the chosen directories and keep the index current
+    let indexer = FileIndexer::start(IndexConfig::from_env(), db.clone(), policy.clone());
+    
+    let app_state = AppState { db, memory_service, policy, jobs, apps, sampler, metrics, events, alerts, indexer };
 
-    // Configure CORS
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-        .allow_headers(Any);
+    // Only the frontend may use the API from a browser
+    let origin = FrontendOrigin::from_env();
+    info!("Accepting browser requests from {:?}", origin);
 
     // Create router with all API routes
     let app = Router::new()
-        .nest("/api", api::create_router().with_state(app_state.clone()))
-        .layer(cors);
+        .nest("/api", api::create_router(origin.clone()).with_state(app_state.clone()))
+        .layer(origin.cors());
 
     // Start server
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
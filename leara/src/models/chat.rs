@@ -79,6 +79,37 @@ pub struct ChatResponse {
     /// Optional context information that influenced the response
     /// Can include conversation history, user preferences, or system context
     pub context: Option<String>,
+    /// Tools the assistant called while producing the response, in order
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCallTrace>,
+}
+
+/// Record of a tool the assistant called while answering
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallTrace {
+    /// Name of the tool, e.g. `run_command`
+    pub tool: String,
+    /// Arguments the model passed
+    pub arguments: serde_json::Value,
+    pub status: ToolCallStatus,
+    /// What the tool returned to the model
+    pub result: serde_json::Value,
+    /// How long the tool took in milliseconds
+    pub duration_ms: u64,
+}
+
+/// Outcome of a tool call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolCallStatus {
+    /// The tool ran and returned a result
+    Ok,
+    /// The tool failed or its arguments were invalid
+    Error,
+    /// The command policy blocked the call
+    Denied,
+    /// The call awaits the user's confirmation as a pending command
+    Pending,
 }
 
 /// Complete conversation thread containing multiple messages
@@ -21,7 +21,7 @@
  */
 
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Utc};
 
 /// Enhanced memory entry with better organization and categorization
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -36,6 +36,7 @@ pub struct Memory {
     pub updated_at: DateTime<Utc>,
     pub expires_at: Option<DateTime<Utc>>,
     pub is_active: bool,
+    pub tags: Option<String>,
 }
 
 /// Request structure for storing memory entries
@@ -50,7 +51,7 @@ pub struct MemoryRequest {
 }
 
 /// Query structure for retrieving memory entries
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct MemoryQuery {
     pub key: Option<String>,
     pub category: Option<String>,
@@ -58,6 +59,10 @@ pub struct MemoryQuery {
     pub limit: Option<i32>,
     pub offset: Option<i32>,
     pub include_expired: Option<bool>,
+    /// Comma-separated list of tag names to filter by
+    pub tags: Option<String>,
+    /// How to combine `tags`: "any" (default) or "all"
+    pub tag_match: Option<String>,
 }
 
 /// Response structure for memory operations
@@ -81,6 +86,8 @@ pub struct Task {
     pub completed_at: Option<DateTime<Utc>>,
     pub context: Option<String>,
     pub tags: Option<String>,
+    /// iCalendar recurrence rule (e.g. "FREQ=WEEKLY;BYDAY=MO")
+    pub recurrence: Option<String>,
 }
 
 /// Request structure for creating or updating tasks
@@ -92,16 +99,45 @@ pub struct TaskRequest {
     pub due_date: Option<DateTime<Utc>>,
     pub context: Option<String>,
     pub tags: Option<String>,
+    pub recurrence: Option<String>,
 }
 
 /// Query structure for retrieving tasks
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct TaskQuery {
     pub status: Option<String>,
     pub priority: Option<i32>,
     pub limit: Option<i32>,
     pub offset: Option<i32>,
     pub include_completed: Option<bool>,
+    /// Comma-separated list of tag names to filter by
+    pub tags: Option<String>,
+    /// How to combine `tags`: "any" (default) or "all"
+    pub tag_match: Option<String>,
+    /// Only tasks due strictly before this time
+    pub due_before: Option<DateTime<Utc>>,
+    /// Only tasks due at or after this time
+    pub due_after: Option<DateTime<Utc>>,
+    /// Only open tasks whose due date has passed
+    pub overdue: Option<bool>,
+    /// Only tasks created at or after this time
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only tasks created strictly before this time
+    pub created_before: Option<DateTime<Utc>>,
+    /// Only tasks updated at or after this time
+    pub updated_after: Option<DateTime<Utc>>,
+    /// Only tasks updated strictly before this time
+    pub updated_before: Option<DateTime<Utc>>,
+    /// Case-insensitive text match against title and description
+    pub q: Option<String>,
+    /// Exact match on the task context
+    pub context: Option<String>,
+    /// Sort field: "priority" (default), "due_date", "updated_at" or "created_at"
+    pub sort_by: Option<String>,
+    /// Sort direction: "asc" or "desc" (default depends on `sort_by`)
+    pub order: Option<String>,
+    /// Opaque cursor from a previous response's `next_cursor`, for the same `sort_by` and `order`; overrides `offset`
+    pub cursor: Option<String>,
 }
 
 /// Response structure for task operations
@@ -109,6 +145,250 @@ pub struct TaskQuery {
 pub struct TaskResponse {
     pub tasks: Vec<Task>,
     pub total: i64,
+    /// Cursor for fetching the next page, if there may be more results
+    pub next_cursor: Option<String>,
+}
+
+/// Query structure for the task calendar feed
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TaskCalendarQuery {
+    /// Calendar component to emit: "vtodo" (default) or "vevent"
+    pub component: Option<String>,
+    /// Whether completed and cancelled tasks are included (default true)
+    pub include_completed: Option<bool>,
+}
+
+/// Query structure for plain-text task list exports
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TaskListExportQuery {
+    /// Whether completed and cancelled tasks are included (default true)
+    pub include_completed: Option<bool>,
+}
+
+/// Result of importing tasks from an external format
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskImportResponse {
+    /// Number of tasks created or updated
+    pub imported: usize,
+    /// Number of those that replaced an existing task with the same ID
+    pub updated: usize,
+    /// The created and updated tasks
+    pub tasks: Vec<Task>,
+}
+
+/// A tracked work session on a task
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TimeEntry {
+    pub id: i64,
+    pub task_id: i64,
+    pub started_at: DateTime<Utc>,
+    /// None while the timer is still running
+    pub ended_at: Option<DateTime<Utc>>,
+    /// Length of the session; running sessions are measured up to now
+    pub duration_seconds: i64,
+    pub note: Option<String>,
+}
+
+/// Request structure for starting or stopping a task timer
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TimerRequest {
+    pub note: Option<String>,
+}
+
+/// Response structure for a task's time entries
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimeEntryResponse {
+    pub entries: Vec<TimeEntry>,
+    pub total_seconds: i64,
+    /// Whether a timer is currently running on the task
+    pub running: bool,
+}
+
+/// Query structure for task statistics and digests
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TaskStatsQuery {
+    /// Bucket size: "day" (default) or "week"
+    pub period: Option<String>,
+    /// Start of the reporting window (default: 30 days or 12 weeks ago)
+    pub since: Option<DateTime<Utc>>,
+    /// End of the reporting window (default: now)
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Query parameters for the task digest
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TaskDigestQuery {
+    /// "day" (default) or "week"
+    pub period: Option<String>,
+}
+
+/// Task activity within one day or week
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompletionBucket {
+    pub start: DateTime<Utc>,
+    /// Tasks created in this bucket
+    pub created: i64,
+    /// Tasks completed in this bucket
+    pub completed: i64,
+    /// Share of the tasks created in this bucket that are now completed (0-100)
+    pub completion_rate: f64,
+}
+
+/// Time tracked against one tag
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagTime {
+    pub tag: String,
+    pub seconds: i64,
+}
+
+/// Productivity statistics over a reporting window
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskStats {
+    pub period: String,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub buckets: Vec<CompletionBucket>,
+    pub created: i64,
+    pub completed: i64,
+    /// Share of the tasks created in the window that are now completed (0-100)
+    pub completion_rate: f64,
+    /// Mean time from creation to completion for tasks completed in the window
+    pub average_lead_time_hours: Option<f64>,
+    /// Open tasks whose due date has passed (as of now)
+    pub overdue: i64,
+    pub tracked_seconds: i64,
+    pub time_by_tag: Vec<TagTime>,
+}
+
+/// Summary of the current day or week
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskDigest {
+    pub period: String,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub completed: Vec<Task>,
+    pub created: i64,
+    pub overdue: Vec<Task>,
+    /// Open tasks due before the end of the next period
+    pub upcoming: Vec<Task>,
+    pub tracked_seconds: i64,
+    /// Human-readable digest suitable for the assistant or a notification
+    pub summary: String,
+}
+
+/// Most buckets a statistics window may span
+pub const MAX_STATS_BUCKETS: i32 = 366;
+
+/// Bucket size for statistics and digests
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum StatsPeriod {
+    Day,
+    Week,
+}
+
+impl StatsPeriod {
+    pub fn as_str(&self) -> &str {
+        match self {
+            StatsPeriod::Day => "day",
+            StatsPeriod::Week => "week",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "day" | "daily" => Some(StatsPeriod::Day),
+            "week" | "weekly" => Some(StatsPeriod::Week),
+            _ => None,
+        }
+    }
+
+    /// Length of one bucket
+    pub fn duration(&self) -> chrono::Duration {
+        match self {
+            StatsPeriod::Day => chrono::Duration::days(1),
+            StatsPeriod::Week => chrono::Duration::weeks(1),
+        }
+    }
+
+    /// Start of the bucket containing `dt` (UTC midnight, weeks start on Monday)
+    pub fn bucket_start(&self, dt: DateTime<Utc>) -> DateTime<Utc> {
+        let date = dt.date_naive();
+        let date = match self {
+            StatsPeriod::Day => date,
+            StatsPeriod::Week => date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64),
+        };
+        date.and_hms_opt(0, 0, 0).unwrap().and_utc()
+    }
+}
+
+/// Sortable task fields
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum TaskSortField {
+    Priority,
+    DueDate,
+    UpdatedAt,
+    CreatedAt,
+}
+
+impl TaskSortField {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "priority" => Some(TaskSortField::Priority),
+            "due_date" | "due" => Some(TaskSortField::DueDate),
+            "updated_at" | "updated" => Some(TaskSortField::UpdatedAt),
+            "created_at" | "created" => Some(TaskSortField::CreatedAt),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            TaskSortField::Priority => "priority",
+            TaskSortField::DueDate => "due_date",
+            TaskSortField::UpdatedAt => "updated_at",
+            TaskSortField::CreatedAt => "created_at",
+        }
+    }
+
+    /// Direction used when the query does not specify `order`
+    pub fn default_descending(&self) -> bool {
+        !matches!(self, TaskSortField::DueDate)
+    }
+}
+
+/// Normalized tag shared between tasks and memories
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// Number of tasks linked to this tag
+    pub task_count: i64,
+    /// Number of memories linked to this tag
+    pub memory_count: i64,
+}
+
+/// Response structure for tag listings
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagResponse {
+    pub tags: Vec<Tag>,
+    pub total: i64,
+}
+
+/// Request structure for renaming a tag
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagRenameRequest {
+    /// New name for the tag; renaming onto an existing tag merges the two
+    pub name: String,
+}
+
+/// Request structure for merging several tags into one
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagMergeRequest {
+    /// Tags to fold into `target` and then remove
+    pub sources: Vec<String>,
+    /// Tag that receives all links (created if missing)
+    pub target: String,
 }
 
 /// Session context structure for maintaining conversation context
@@ -31,6 +31,14 @@ pub use system::*;
 use std::sync::{Arc, Mutex};
 use rusqlite::Connection;
 use crate::system::MemoryService;
+use crate::system::alerts::AlertManager;
+use crate::system::apps::AppCatalog;
+use crate::system::events::EventBus;
+use crate::system::indexer::FileIndexer;
+use crate::system::jobs::JobManager;
+use crate::system::metrics::MetricsHistory;
+use crate::system::policy::PolicyStore;
+use crate::system::sampler::SystemSampler;
 use r2d2::{Pool};
 use r2d2_sqlite::SqliteConnectionManager;
 
@@ -38,4 +46,20 @@ use r2d2_sqlite::SqliteConnectionManager;
 pub struct AppState {
     pub db: Pool<SqliteConnectionManager>,
     pub memory_service: Arc<MemoryService>,
+    /// Command execution policy, reloaded when its file changes
+    pub policy: Arc<PolicyStore>,
+    /// Background command jobs
+    pub jobs: Arc<JobManager>,
+    /// Installed applications, rescanned when they change
+    pub apps: Arc<AppCatalog>,
+    /// Latest system resource samples, refreshed in the background
+    pub sampler: Arc<SystemSampler>,
+    /// Persisted resource usage history
+    pub metrics: Arc<MetricsHistory>,
+    /// Notifications streamed to the frontend
+    pub events: Arc<EventBus>,
+    /// Alert rule evaluation and active alerts
+    pub alerts: Arc<AlertManager>,
+    /// Searchable index of the directories the user picked
+    pub indexer: Arc<FileIndexer>,
 } 
\ No newline at end of file
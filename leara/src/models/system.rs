@@ -20,29 +20,69 @@
  * Purpose: System-related data models and structures
  */
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemInfo {
     pub hostname: String,
     pub os_name: String,
     pub os_version: String,
     pub kernel_version: String,
     pub cpu_count: usize,
+    /// Logical CPUs, including hyperthreads
+    pub logical_cpu_count: usize,
+    pub cpu_brand: String,
     pub total_memory: u64,
     pub uptime: u64,
+    /// Seconds since the Unix epoch when the system booted
+    pub boot_time: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Live resource usage
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResourceUsage {
+    /// Average usage across all CPUs, in percent
     pub cpu_usage: f32,
+    pub cpus: Vec<CpuCoreUsage>,
+    pub load_average: LoadAverage,
+    /// Used memory in bytes
     pub memory_usage: u64,
+    pub memory: MemoryInfo,
+    pub swap: MemoryInfo,
+    /// Used space in bytes across all mounted disks
     pub disk_usage: u64,
+    /// Bytes received by all interfaces since boot
     pub network_rx: u64,
+    /// Bytes sent by all interfaces since boot
     pub network_tx: u64,
+    /// Bytes per second received by all interfaces since the previous sample
+    pub network_rx_rate: f64,
+    /// Bytes per second sent by all interfaces since the previous sample
+    pub network_tx_rate: f64,
+    pub timestamp: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Usage of one logical CPU
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpuCoreUsage {
+    pub name: String,
+    /// Usage in percent
+    pub usage: f32,
+    pub frequency_mhz: u64,
+}
+
+/// Average number of runnable processes over 1, 5 and 15 minutes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadAverage {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+}
+
+/// Memory or swap usage in bytes
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MemoryInfo {
     pub total: u64,
     pub used: u64,
@@ -50,19 +90,643 @@ pub struct MemoryInfo {
     pub usage_percentage: f32,
 }
 
+/// Physical memory and swap usage
 #[derive(Debug, Serialize, Deserialize)]
+pub struct MemoryUsage {
+    pub memory: MemoryInfo,
+    pub swap: MemoryInfo,
+}
+
+/// Space on one mounted disk, in bytes
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiskInfo {
+    pub name: String,
+    pub mount_point: String,
+    pub file_system: String,
+    /// HDD, SSD or Unknown
+    pub kind: String,
+    pub removable: bool,
     pub total: u64,
     pub used: u64,
     pub available: u64,
     pub usage_percentage: f32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Traffic counters of one network interface since boot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkInterfaceInfo {
+    pub name: String,
+    pub mac_address: String,
+    pub received: u64,
+    pub transmitted: u64,
+    pub packets_received: u64,
+    pub packets_transmitted: u64,
+    pub errors_received: u64,
+    pub errors_transmitted: u64,
+    /// Bytes per second received since the previous sample
+    pub receive_rate: f64,
+    /// Bytes per second sent since the previous sample
+    pub transmit_rate: f64,
+}
+
+/// A running process as of the latest sample
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessInfo {
     pub pid: u32,
+    pub parent_pid: Option<u32>,
     pub name: String,
+    /// Command line, empty for kernel threads and when it cannot be read
+    pub command: Vec<String>,
+    /// Owner's user name, or the numeric user ID if it has no name
+    pub user: Option<String>,
+    /// Usage in percent of one CPU
     pub cpu_usage: f32,
+    /// Resident memory in bytes
     pub memory_usage: u64,
+    pub virtual_memory: u64,
     pub status: String,
-} 
\ No newline at end of file
+    /// Seconds since the Unix epoch when the process started
+    pub start_time: u64,
+    /// Seconds the process has been running
+    pub run_time: u64,
+}
+
+/// A process with everything that can be read about it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProcessDetails {
+    #[serde(flatten)]
+    pub process: ProcessInfo,
+    pub executable: Option<String>,
+    pub working_dir: Option<String>,
+    pub root_dir: Option<String>,
+    /// Open file descriptors, if they can be listed
+    pub open_files: Option<usize>,
+    /// Environment with secrets redacted, if it can be read
+    pub environment: Option<BTreeMap<String, String>>,
+}
+
+/// A process and the processes it started
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProcessTreeNode {
+    #[serde(flatten)]
+    pub process: ProcessInfo,
+    pub children: Vec<ProcessTreeNode>,
+}
+
+/// Sortable process fields
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ProcessSortField {
+    Cpu,
+    Memory,
+    Name,
+    Pid,
+    User,
+    StartTime,
+}
+
+impl ProcessSortField {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "cpu" | "cpu_usage" => Some(ProcessSortField::Cpu),
+            "memory" | "memory_usage" => Some(ProcessSortField::Memory),
+            "name" => Some(ProcessSortField::Name),
+            "pid" => Some(ProcessSortField::Pid),
+            "user" => Some(ProcessSortField::User),
+            "start_time" | "started" => Some(ProcessSortField::StartTime),
+            _ => None,
+        }
+    }
+
+    /// Direction used when the query does not specify `order`
+    pub fn default_descending(&self) -> bool {
+        matches!(self, ProcessSortField::Cpu | ProcessSortField::Memory | ProcessSortField::StartTime)
+    }
+}
+
+/// How finely stored metrics are resolved
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricResolution {
+    /// One point per recorded sample
+    Raw,
+    /// One point per minute
+    Minute,
+    /// One point per hour
+    Hour,
+}
+
+impl MetricResolution {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "raw" => Some(MetricResolution::Raw),
+            "minute" | "1m" => Some(MetricResolution::Minute),
+            "hour" | "1h" => Some(MetricResolution::Hour),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MetricResolution::Raw => "raw",
+            MetricResolution::Minute => "minute",
+            MetricResolution::Hour => "hour",
+        }
+    }
+}
+
+/// Resource usage over one period of the metrics history
+///
+/// Usage values are averages over the samples of the period; the `_max`
+/// fields hold the highest sample.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricPoint {
+    /// Start of the period
+    pub timestamp: DateTime<Utc>,
+    /// Recorded samples merged into this point
+    pub samples: u32,
+    /// Average CPU usage in percent
+    pub cpu_usage: f32,
+    pub cpu_max: f32,
+    /// Used memory in bytes
+    pub memory_used: u64,
+    pub memory_max: u64,
+    pub memory_total: u64,
+    pub swap_used: u64,
+    /// Used space in bytes across all mounted disks, at the end of the period
+    pub disk_used: u64,
+    /// One-minute load average
+    pub load_average: f64,
+    /// Bytes per second received by all interfaces
+    pub network_rx_rate: f64,
+    /// Bytes per second sent by all interfaces
+    pub network_tx_rate: f64,
+    /// Average of the hottest sensor in degrees Celsius, absent without sensors
+    pub temperature: Option<f32>,
+    pub temperature_max: Option<f32>,
+    /// Charge of all batteries, at the end of the period
+    pub battery_percent: Option<f32>,
+    /// Average power draw in watts, from the batteries or power meters
+    pub power_watts: Option<f32>,
+    /// Heaviest programs by CPU and by memory
+    pub top_processes: Vec<ProcessUsage>,
+}
+
+/// Usage of all processes sharing a name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessUsage {
+    pub name: String,
+    /// Average CPU usage in percent of one CPU
+    pub cpu_usage: f32,
+    pub cpu_max: f32,
+    /// Highest resident memory in bytes
+    pub memory_usage: u64,
+}
+
+/// Metrics history over a time range
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricsResponse {
+    pub resolution: MetricResolution,
+    /// Seconds covered by each point
+    pub step_secs: i64,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub points: Vec<MetricPoint>,
+    /// All points merged into one, absent if the range holds none
+    pub summary: Option<MetricPoint>,
+}
+
+/// Value an alert rule watches
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    /// Average usage across all CPUs, in percent
+    CpuUsage,
+    /// Used memory in percent
+    MemoryUsage,
+    /// Used swap in percent
+    SwapUsage,
+    /// Used space in percent of the disk mounted at `target`, or of the fullest disk
+    DiskUsage,
+    /// One-minute load average
+    LoadAverage,
+    /// Bytes per second received by all interfaces, or by the interface `target`
+    NetworkRxRate,
+    /// Bytes per second sent by all interfaces, or by the interface `target`
+    NetworkTxRate,
+    /// CPU usage in percent of one CPU of all processes named `target`
+    ProcessCpu,
+    /// Resident memory in bytes of all processes named `target`
+    ProcessMemory,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertComparison {
+    #[default]
+    Above,
+    Below,
+}
+
+/// When an alert rule matches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// A metric is above or below a threshold
+    Threshold {
+        metric: AlertMetric,
+        #[serde(default)]
+        comparison: AlertComparison,
+        threshold: f64,
+        /// Mount point, interface or process name, depending on the metric
+        target: Option<String>,
+    },
+    /// No process with this name is running
+    ProcessNotRunning { name: String },
+}
+
+/// A user-defined alert rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub id: i64,
+    pub name: String,
+    pub condition: AlertCondition,
+    /// Seconds the condition must hold before the alert fires
+    pub for_secs: i64,
+    pub enabled: bool,
+    /// Create a task when the alert fires
+    pub create_task: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request structure for creating or replacing an alert rule
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlertRuleRequest {
+    pub name: String,
+    pub condition: AlertCondition,
+    pub for_secs: Option<i64>,
+    pub enabled: Option<bool>,
+    pub create_task: Option<bool>,
+}
+
+/// Life cycle of an alert
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertStatus {
+    /// The condition holds, but not yet for the rule's `for_secs`
+    Pending,
+    Firing,
+    /// The condition no longer holds
+    Resolved,
+}
+
+impl AlertStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertStatus::Pending => "pending",
+            AlertStatus::Firing => "firing",
+            AlertStatus::Resolved => "resolved",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "pending" => Some(AlertStatus::Pending),
+            "firing" => Some(AlertStatus::Firing),
+            "resolved" => Some(AlertStatus::Resolved),
+            _ => None,
+        }
+    }
+}
+
+/// One occurrence of an alert rule matching
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    /// History entry, written when the alert fires
+    pub id: Option<i64>,
+    pub rule_id: i64,
+    pub rule_name: String,
+    pub status: AlertStatus,
+    /// Latest observed value, absent for conditions without one
+    pub value: Option<f64>,
+    pub message: String,
+    /// When the condition started to hold
+    pub started_at: DateTime<Utc>,
+    pub fired_at: Option<DateTime<Utc>>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    /// Task created when the alert fired
+    pub task_id: Option<i64>,
+}
+
+/// A systemd unit as listed by `systemctl list-units`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemdUnit {
+    pub name: String,
+    /// Whether the unit file was loaded: "loaded", "not-found", "masked", ...
+    pub load_state: String,
+    /// "active", "inactive", "failed", "activating", ...
+    pub active_state: String,
+    /// Type-specific state such as "running" or "exited"
+    pub sub_state: String,
+    pub description: String,
+}
+
+/// Status of one systemd unit
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnitStatus {
+    pub name: String,
+    pub description: Option<String>,
+    pub load_state: String,
+    pub active_state: String,
+    pub sub_state: String,
+    /// "enabled", "disabled", "static", ...
+    pub unit_file_state: Option<String>,
+    pub fragment_path: Option<String>,
+    pub main_pid: Option<u32>,
+    pub active_since: Option<DateTime<Utc>>,
+    pub state_changed_at: Option<DateTime<Utc>>,
+    /// Bytes, if memory accounting is enabled
+    pub memory_current: Option<u64>,
+    /// Nanoseconds, if CPU accounting is enabled
+    pub cpu_usage_nsec: Option<u64>,
+    pub tasks_current: Option<u64>,
+    pub restarts: Option<u64>,
+    /// Result of the last run, "success" or why it failed
+    pub result: Option<String>,
+    /// Latest journal entries of the unit, oldest first
+    pub recent_logs: Vec<JournalEntry>,
+}
+
+/// One journal entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub timestamp: DateTime<Utc>,
+    /// 0 (emerg) to 7 (debug)
+    pub priority: u8,
+    pub priority_name: String,
+    pub unit: Option<String>,
+    /// Syslog identifier or command name
+    pub identifier: Option<String>,
+    pub pid: Option<u32>,
+    pub message: String,
+}
+
+/// A temperature sensor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemperatureReading {
+    pub label: String,
+    /// Degrees Celsius
+    pub temperature: f32,
+    /// Highest temperature seen since the server started, if the sensor reports one
+    pub max: Option<f32>,
+    /// Temperature at which the hardware shuts down or throttles
+    pub critical: Option<f32>,
+}
+
+/// A fan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FanReading {
+    pub label: String,
+    pub rpm: u32,
+}
+
+/// A power meter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PowerReading {
+    pub label: String,
+    pub watts: f32,
+}
+
+/// A battery
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatteryInfo {
+    pub name: String,
+    /// "Charging", "Discharging", "Full", "Not charging" or "Unknown"
+    pub status: String,
+    pub charge_percent: Option<f32>,
+    /// Full capacity as a share of the design capacity
+    pub health_percent: Option<f32>,
+    pub energy_wh: Option<f32>,
+    pub energy_full_wh: Option<f32>,
+    pub energy_full_design_wh: Option<f32>,
+    /// Power flowing into or out of the battery
+    pub power_watts: Option<f32>,
+    /// Estimated seconds until empty, while discharging
+    pub time_to_empty_secs: Option<u64>,
+    /// Estimated seconds until full, while charging
+    pub time_to_full_secs: Option<u64>,
+    pub cycle_count: Option<u32>,
+    pub technology: Option<String>,
+    pub model: Option<String>,
+}
+
+/// Temperatures, fans, power meters and batteries
+///
+/// Every list is empty on machines without the sensor, such as containers.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HardwareSensors {
+    pub temperatures: Vec<TemperatureReading>,
+    pub fans: Vec<FanReading>,
+    pub power: Vec<PowerReading>,
+    pub batteries: Vec<BatteryInfo>,
+    /// Whether an AC adapter is plugged in, if the machine has one
+    pub on_ac_power: Option<bool>,
+}
+
+/// An address assigned to an interface
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterfaceAddress {
+    /// "ipv4" or "ipv6"
+    pub family: String,
+    pub address: String,
+    pub prefix_len: u8,
+}
+
+/// A network interface with its addresses and link state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkInterfaceDetails {
+    pub name: String,
+    pub mac_address: Option<String>,
+    pub mtu: Option<u32>,
+    /// Operational state: "up", "down", "unknown", ...
+    pub state: String,
+    pub is_up: bool,
+    pub is_loopback: bool,
+    pub addresses: Vec<InterfaceAddress>,
+    /// Bytes per second received over the last sampling interval
+    pub receive_rate: f64,
+    /// Bytes per second sent over the last sampling interval
+    pub transmit_rate: f64,
+}
+
+/// An entry of the routing table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkRoute {
+    /// "ipv4" or "ipv6"
+    pub family: String,
+    /// Destination network in CIDR notation
+    pub destination: String,
+    /// Next hop, absent for directly connected networks
+    pub gateway: Option<String>,
+    pub interface: String,
+    pub metric: u32,
+    pub is_default: bool,
+}
+
+/// An open TCP or UDP socket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocketInfo {
+    /// "tcp", "tcp6", "udp" or "udp6"
+    pub protocol: String,
+    pub local_address: String,
+    pub local_port: u16,
+    pub remote_address: Option<String>,
+    pub remote_port: Option<u16>,
+    /// TCP state such as "listen" or "established"; bound UDP sockets are "listen"
+    pub state: String,
+    pub uid: Option<u32>,
+    pub inode: u64,
+    /// Owning process, if the server may inspect it
+    pub pid: Option<u32>,
+    pub process: Option<String>,
+}
+
+/// DNS resolver configuration from resolv.conf
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DnsConfig {
+    pub nameservers: Vec<String>,
+    /// Domains appended to short names
+    pub search: Vec<String>,
+    /// Resolver options such as `ndots`, with empty values for flags
+    pub options: BTreeMap<String, String>,
+}
+
+/// Result of timing TCP connections to a host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyProbe {
+    pub host: String,
+    pub port: u16,
+    /// Address the host resolved to
+    pub address: Option<String>,
+    pub attempts: u32,
+    pub successes: u32,
+    /// Connect time of each attempt in milliseconds, absent for failed ones
+    pub samples_ms: Vec<Option<f64>>,
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    /// Why the last attempt failed, or why none could be made
+    pub error: Option<String>,
+}
+
+/// Kind of a directory entry
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+    /// Sockets, FIFOs and devices
+    Other,
+}
+
+/// A file or directory with its metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub name: String,
+    pub path: String,
+    pub kind: FileKind,
+    /// Bytes; for symlinks the size of the link itself
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    /// Permission bits in octal, e.g. "644"
+    pub permissions: String,
+    pub uid: u32,
+    pub gid: u32,
+    /// Name starts with a dot
+    pub hidden: bool,
+    /// MIME type of files, guessed from the name in listings and from the content otherwise
+    pub mime: Option<String>,
+    /// Where a symlink points
+    pub link_target: Option<String>,
+}
+
+/// A page of a text file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileContent {
+    pub path: String,
+    pub content: String,
+    /// Byte offset the content starts at
+    pub offset: u64,
+    /// Bytes of the file the content covers
+    pub length: u64,
+    /// Size of the whole file in bytes
+    pub size: u64,
+    /// Offset to read the next page from, absent at the end of the file
+    pub next_offset: Option<u64>,
+    pub mime: String,
+}
+
+/// Digest of a file's content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChecksum {
+    pub path: String,
+    pub algorithm: String,
+    /// Lowercase hexadecimal digest
+    pub checksum: String,
+    pub size: u64,
+}
+
+/// A directory the file index covers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexRoot {
+    pub id: i64,
+    pub path: String,
+    pub created_at: DateTime<Utc>,
+    /// When the directory was last scanned completely
+    pub last_scan_at: Option<DateTime<Utc>>,
+    /// Files recorded below the directory
+    pub file_count: i64,
+}
+
+/// A file recorded in the index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedFile {
+    pub id: i64,
+    pub root_id: i64,
+    pub path: String,
+    pub name: String,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    pub mime: String,
+    /// Whether the text of the file is searchable; false for binary and oversized files
+    pub has_content: bool,
+    pub indexed_at: DateTime<Utc>,
+}
+
+/// A file whose text matched a search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentMatch {
+    pub file: IndexedFile,
+    /// Text around the match, with matched terms in [brackets]
+    pub snippet: String,
+    /// Relevance; lower is better
+    pub rank: f64,
+}
+
+/// What the file indexer is doing
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexStatus {
+    /// A full scan is running
+    pub scanning: bool,
+    /// inotify is watching the indexed directories
+    pub watching: bool,
+    pub watched_directories: usize,
+    pub last_scan_at: Option<DateTime<Utc>>,
+    pub last_scan_ms: Option<u64>,
+    /// Why the last scan failed, if it did
+    pub last_error: Option<String>,
+    pub files: i64,
+    pub files_with_content: i64,
+    pub roots: Vec<IndexRoot>,
+}
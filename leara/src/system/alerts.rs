@@ -0,0 +1,635 @@
+/*
+ * Leara AI Assistant - Alerts
+ *
+ * This module evaluates the user's alert rules against every background
+ * system sample. An alert is pending while its condition holds for less
+ * than the rule's duration, fires once it has held long enough and is
+ * resolved when the condition stops holding. Every transition is recorded
+ * in the database and announced on the event bus; fired alerts may also
+ * create a task.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/alerts.rs
+ * Purpose: Alert rule evaluation, alert state and notifications
+ */
+
+use chrono::{DateTime, Utc};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+use crate::db::queries;
+use crate::models::memory::Task;
+use crate::models::system::{
+    Alert, AlertComparison, AlertCondition, AlertMetric, AlertRule, AlertRuleRequest, AlertStatus, DiskInfo,
+    NetworkInterfaceInfo, ProcessInfo, ResourceUsage,
+};
+use crate::system::events::{EventBus, ServerEvent};
+use crate::system::sampler::SystemSampler;
+
+/// Priority of tasks created by alerts
+const ALERT_TASK_PRIORITY: i32 = 2;
+
+/// Check an alert rule request before it is stored
+pub fn validate_rule(request: &AlertRuleRequest) -> Result<(), String> {
+    if request.name.trim().is_empty() {
+        return Err("Alert rule name cannot be empty".to_string());
+    }
+    if request.for_secs.is_some_and(|secs| secs < 0) {
+        return Err("`for_secs` cannot be negative".to_string());
+    }
+    match &request.condition {
+        AlertCondition::Threshold { metric, threshold, target, .. } => {
+            if !threshold.is_finite() {
+                return Err("`threshold` must be a number".to_string());
+            }
+            let named = target.as_deref().is_some_and(|target| !target.trim().is_empty());
+            if matches!(metric, AlertMetric::ProcessCpu | AlertMetric::ProcessMemory) && !named {
+                return Err("Process metrics need the process name as `target`".to_string());
+            }
+        }
+        AlertCondition::ProcessNotRunning { name } if name.trim().is_empty() => {
+            return Err("`name` of the process cannot be empty".to_string());
+        }
+        AlertCondition::ProcessNotRunning { .. } => {}
+    }
+    Ok(())
+}
+
+/// Processes whose name is `name`, ignoring case
+fn named<'a>(processes: &'a [ProcessInfo], name: &'a str) -> impl Iterator<Item = &'a ProcessInfo> {
+    processes.iter().filter(move |process| process.name.eq_ignore_ascii_case(name))
+}
+
+fn percent(used: u64, total: u64) -> Option<f64> {
+    (total > 0).then(|| used as f64 / total as f64 * 100.0)
+}
+
+/// Current value of a metric, if it can be measured
+///
+/// # Arguments
+/// * `metric` - What to measure
+/// * `target` - Mount point, interface or process name the metric refers to
+/// * `usage` / `disks` / `networks` / `processes` - The latest sample
+pub fn observe(
+    metric: AlertMetric,
+    target: Option<&str>,
+    usage: &ResourceUsage,
+    disks: &[DiskInfo],
+    networks: &[NetworkInterfaceInfo],
+    processes: &[ProcessInfo],
+) -> Option<f64> {
+    let interface = |rate: fn(&NetworkInterfaceInfo) -> f64| match target {
+        Some(name) => networks.iter().find(|interface| interface.name == name).map(rate),
+        None => Some(networks.iter().map(rate).sum()),
+    };
+    match metric {
+        AlertMetric::CpuUsage => Some(usage.cpu_usage as f64),
+        AlertMetric::MemoryUsage => percent(usage.memory.used, usage.memory.total),
+        AlertMetric::SwapUsage => percent(usage.swap.used, usage.swap.total),
+        AlertMetric::DiskUsage => match target {
+            Some(mount_point) => disks
+                .iter()
+                .find(|disk| disk.mount_point == mount_point)
+                .and_then(|disk| percent(disk.used, disk.total)),
+            None => disks.iter().filter_map(|disk| percent(disk.used, disk.total)).reduce(f64::max),
+        },
+        AlertMetric::LoadAverage => Some(usage.load_average.one),
+        AlertMetric::NetworkRxRate => interface(|interface| interface.receive_rate),
+        AlertMetric::NetworkTxRate => interface(|interface| interface.transmit_rate),
+        AlertMetric::ProcessCpu => {
+            let mut matching = named(processes, target?).peekable();
+            matching.peek()?;
+            Some(matching.map(|process| process.cpu_usage as f64).sum())
+        }
+        AlertMetric::ProcessMemory => {
+            let mut matching = named(processes, target?).peekable();
+            matching.peek()?;
+            Some(matching.map(|process| process.memory_usage as f64).sum())
+        }
+    }
+}
+
+/// Whether a condition holds for a sample, and the value it was decided on
+pub fn evaluate(
+    condition: &AlertCondition,
+    usage: &ResourceUsage,
+    disks: &[DiskInfo],
+    networks: &[NetworkInterfaceInfo],
+    processes: &[ProcessInfo],
+) -> (bool, Option<f64>) {
+    match condition {
+        AlertCondition::Threshold { metric, comparison, threshold, target } => {
+            let value = observe(*metric, target.as_deref(), usage, disks, networks, processes);
+            let matched = value.is_some_and(|value| match comparison {
+                AlertComparison::Above => value > *threshold,
+                AlertComparison::Below => value < *threshold,
+            });
+            (matched, value)
+        }
+        AlertCondition::ProcessNotRunning { name } => {
+            let running = named(processes, name).count();
+            (running == 0, Some(running as f64))
+        }
+    }
+}
+
+/// Human-readable name of a metric
+fn metric_name(metric: AlertMetric, target: Option<&str>) -> String {
+    let subject = |what: &str, default: &str| match target {
+        Some(target) => format!("{} of {}", what, target),
+        None => default.to_string(),
+    };
+    match metric {
+        AlertMetric::CpuUsage => "CPU usage".to_string(),
+        AlertMetric::MemoryUsage => "Memory usage".to_string(),
+        AlertMetric::SwapUsage => "Swap usage".to_string(),
+        AlertMetric::DiskUsage => subject("Disk usage", "Disk usage of the fullest disk"),
+        AlertMetric::LoadAverage => "Load average".to_string(),
+        AlertMetric::NetworkRxRate => subject("Download rate", "Download rate"),
+        AlertMetric::NetworkTxRate => subject("Upload rate", "Upload rate"),
+        AlertMetric::ProcessCpu => subject("CPU usage", "Process CPU usage"),
+        AlertMetric::ProcessMemory => subject("Memory usage", "Process memory usage"),
+    }
+}
+
+/// Describe what a condition observed, e.g. "Disk usage of / is 93.1%, above 90%"
+pub fn describe(condition: &AlertCondition, value: Option<f64>) -> String {
+    match condition {
+        AlertCondition::Threshold { metric, comparison, threshold, target } => {
+            let unit = match metric {
+                AlertMetric::CpuUsage
+                | AlertMetric::MemoryUsage
+                | AlertMetric::SwapUsage
+                | AlertMetric::DiskUsage
+                | AlertMetric::ProcessCpu => "%",
+                AlertMetric::NetworkRxRate | AlertMetric::NetworkTxRate => " B/s",
+                AlertMetric::ProcessMemory => " bytes",
+                AlertMetric::LoadAverage => "",
+            };
+            let direction = match comparison {
+                AlertComparison::Above => "above",
+                AlertComparison::Below => "below",
+            };
+            let name = metric_name(*metric, target.as_deref());
+            match value {
+                Some(value) => format!("{} is {:.1}{}, {} {}{}", name, value, unit, direction, threshold, unit),
+                None => format!("{} cannot be measured", name),
+            }
+        }
+        AlertCondition::ProcessNotRunning { name } => match value {
+            Some(count) if count > 0.0 => format!("{} is running", name),
+            _ => format!("{} is not running", name),
+        },
+    }
+}
+
+/// Move a rule's alert along after an evaluation
+///
+/// # Arguments
+/// * `active` - The rule's pending or firing alert, updated in place
+/// * `rule` - The rule
+/// * `matched` / `value` - Result of evaluating the rule now
+/// * `now` - Time of the evaluation
+///
+/// # Returns
+/// * `Some(Alert)` - The alert, if its status changed
+pub fn advance(
+    active: &mut Option<Alert>,
+    rule: &AlertRule,
+    matched: bool,
+    value: Option<f64>,
+    now: DateTime<Utc>,
+) -> Option<Alert> {
+    let message = format!("{}: {}", rule.name, describe(&rule.condition, value));
+    if !matched {
+        let mut alert = active.take()?;
+        alert.status = AlertStatus::Resolved;
+        alert.value = value;
+        alert.message = message;
+        alert.resolved_at = Some(now);
+        return Some(alert);
+    }
+
+    let was = active.as_ref().map(|alert| alert.status);
+    let alert = active.get_or_insert_with(|| Alert {
+        id: None,
+        rule_id: rule.id,
+        rule_name: rule.name.clone(),
+        status: AlertStatus::Pending,
+        value,
+        message: message.clone(),
+        started_at: now,
+        fired_at: None,
+        resolved_at: None,
+        task_id: None,
+    });
+    alert.value = value;
+    alert.message = message;
+    if alert.status == AlertStatus::Pending && (now - alert.started_at).num_seconds() >= rule.for_secs {
+        alert.status = AlertStatus::Firing;
+        alert.fired_at = Some(now);
+    }
+    (was != Some(alert.status)).then(|| alert.clone())
+}
+
+/// Evaluates alert rules and keeps the state of their alerts
+pub struct AlertManager {
+    db: Pool<SqliteConnectionManager>,
+    events: Arc<EventBus>,
+    /// Pending and firing alerts by rule ID
+    active: Mutex<HashMap<i64, Alert>>,
+}
+
+impl AlertManager {
+    /// Evaluate the rules after every sample in the background
+    ///
+    /// Alerts still firing when the server stopped are picked up again and
+    /// resolve once their condition no longer holds. Pending alerts are
+    /// resolved, since nobody watched their condition in the meantime. Must
+    /// be called inside the Tokio runtime.
+    pub fn start(db: Pool<SqliteConnectionManager>, sampler: Arc<SystemSampler>, events: Arc<EventBus>) -> Arc<Self> {
+        let firing = db
+            .get()
+            .map_err(anyhow::Error::from)
+            .and_then(|conn| {
+                resolve_stale_pending(&conn)?;
+                Ok(queries::get_alerts(&conn, None, Some(AlertStatus::Firing), i64::MAX)?)
+            })
+            .unwrap_or_else(|e| {
+                warn!("Failed to load firing alerts: {}", e);
+                Vec::new()
+            });
+        let active = firing.into_iter().map(|alert| (alert.rule_id, alert)).collect();
+        let manager = Arc::new(AlertManager { db, events, active: Mutex::new(active) });
+
+        let task_manager = manager.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(sampler.interval());
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let manager = task_manager.clone();
+                let sampler = sampler.clone();
+                let evaluated = tokio::task::spawn_blocking(move || manager.evaluate_rules(&sampler)).await;
+                if let Ok(Err(e)) = evaluated {
+                    warn!("Failed to evaluate alert rules: {}", e);
+                }
+            }
+        });
+        manager
+    }
+
+    /// Pending and firing alerts, oldest first
+    pub fn active(&self) -> Vec<Alert> {
+        let mut alerts: Vec<Alert> = self.active.lock().unwrap().values().cloned().collect();
+        alerts.sort_by_key(|alert| (alert.started_at, alert.rule_id));
+        alerts
+    }
+
+    /// Evaluate every enabled rule against the latest sample
+    fn evaluate_rules(&self, sampler: &SystemSampler) -> anyhow::Result<()> {
+        let conn = self.db.get()?;
+        let rules: HashMap<i64, AlertRule> = queries::get_alert_rules(&conn)?
+            .into_iter()
+            .filter(|rule| rule.enabled)
+            .map(|rule| (rule.id, rule))
+            .collect();
+        let sample = sampler.latest();
+        let processes = sampler.processes();
+        let now = Utc::now();
+
+        let mut changed = Vec::new();
+        {
+            let mut active = self.active.lock().unwrap();
+            // Alerts of deleted or disabled rules resolve right away
+            let orphaned: Vec<i64> = active.keys().filter(|id| !rules.contains_key(id)).copied().collect();
+            for rule_id in orphaned {
+                if let Some(mut alert) = active.remove(&rule_id) {
+                    alert.status = AlertStatus::Resolved;
+                    alert.resolved_at = Some(now);
+                    changed.push(alert);
+                }
+            }
+            for rule in rules.values() {
+                let (matched, value) = evaluate(&rule.condition, &sample.usage, &sample.disks, &sample.networks, &processes);
+                let mut alert = active.remove(&rule.id);
+                if let Some(update) = advance(&mut alert, rule, matched, value, now) {
+                    changed.push(update);
+                }
+                if let Some(alert) = alert {
+                    active.insert(rule.id, alert);
+                }
+            }
+        }
+
+        self.record(&conn, &rules, changed);
+        Ok(())
+    }
+
+    /// Store and announce alert transitions
+    ///
+    /// Each transition is stored on its own, so a failure is logged and the
+    /// remaining alerts are still recorded and published.
+    ///
+    /// # Arguments
+    /// * `conn` - Database connection
+    /// * `rules` - Enabled rules by ID
+    /// * `changed` - Alerts whose status just changed
+    fn record(&self, conn: &Connection, rules: &HashMap<i64, AlertRule>, changed: Vec<Alert>) {
+        for mut alert in changed {
+            match alert.status {
+                AlertStatus::Pending => info!("Alert pending: {}", alert.message),
+                AlertStatus::Firing => info!("Alert firing: {}", alert.message),
+                AlertStatus::Resolved => info!("Alert resolved: {}", alert.message),
+            }
+            let create_task = rules.get(&alert.rule_id).is_some_and(|rule| rule.create_task);
+            if let Err(e) = store_alert(conn, &mut alert, create_task) {
+                warn!("Failed to record alert {}: {}", alert.message, e);
+            }
+            if alert.status != AlertStatus::Resolved {
+                if let Some(active) = self.active.lock().unwrap().get_mut(&alert.rule_id) {
+                    active.id = alert.id;
+                    active.task_id = alert.task_id;
+                }
+            }
+            self.events.publish(ServerEvent::Alert { alert });
+        }
+    }
+}
+
+/// Insert or update an alert's row and create its task once it fires
+///
+/// # Arguments
+/// * `conn` - Database connection
+/// * `alert` - The alert; its ID and task ID are filled in as they are created
+/// * `create_task` - Whether the rule asks for a task
+fn store_alert(conn: &Connection, alert: &mut Alert, create_task: bool) -> rusqlite::Result<()> {
+    match alert.id {
+        Some(_) => queries::update_alert(conn, alert)?,
+        None => alert.id = Some(queries::insert_alert(conn, alert)?),
+    }
+    if alert.status == AlertStatus::Firing && create_task && alert.task_id.is_none() {
+        alert.task_id = Some(queries::insert_task(conn, &alert_task(alert))?);
+        queries::update_alert(conn, alert)?;
+    }
+    Ok(())
+}
+
+/// Resolve alerts left pending when the server stopped
+fn resolve_stale_pending(conn: &Connection) -> rusqlite::Result<()> {
+    let now = Utc::now();
+    for mut alert in queries::get_alerts(conn, None, Some(AlertStatus::Pending), i64::MAX)? {
+        alert.status = AlertStatus::Resolved;
+        alert.resolved_at = Some(now);
+        queries::update_alert(conn, &alert)?;
+    }
+    Ok(())
+}
+
+/// Task asking the user to look into a fired alert
+fn alert_task(alert: &Alert) -> Task {
+    let now = Utc::now();
+    Task {
+        id: 0,
+        title: format!("Alert: {}", alert.rule_name),
+        description: Some(alert.message.clone()),
+        status: "pending".to_string(),
+        priority: ALERT_TASK_PRIORITY,
+        due_date: None,
+        created_at: now,
+        updated_at: now,
+        completed_at: None,
+        context: Some("alert".to_string()),
+        tags: Some("alert".to_string()),
+        recurrence: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::system::{LoadAverage, MemoryInfo};
+    use crate::testing::TestDatabase;
+
+    fn usage() -> ResourceUsage {
+        let memory = |used: u64, total: u64| MemoryInfo { total, used, available: total - used, usage_percentage: 0.0 };
+        ResourceUsage {
+            cpu_usage: 42.0,
+            cpus: Vec::new(),
+            load_average: LoadAverage { one: 3.5, five: 2.0, fifteen: 1.0 },
+            memory_usage: 600,
+            memory: memory(600, 1000),
+            swap: memory(0, 0),
+            disk_usage: 0,
+            network_rx: 0,
+            network_tx: 0,
+            network_rx_rate: 0.0,
+            network_tx_rate: 0.0,
+            timestamp: String::new(),
+        }
+    }
+
+    fn disk(mount_point: &str, used: u64) -> DiskInfo {
+        DiskInfo {
+            name: mount_point.to_string(),
+            mount_point: mount_point.to_string(),
+            file_system: "ext4".to_string(),
+            kind: "SSD".to_string(),
+            removable: false,
+            total: 100,
+            used,
+            available: 100 - used,
+            usage_percentage: used as f32,
+        }
+    }
+
+    fn process(name: &str, cpu_usage: f32) -> ProcessInfo {
+        ProcessInfo {
+            pid: 1,
+            parent_pid: None,
+            name: name.to_string(),
+            command: Vec::new(),
+            user: None,
+            cpu_usage,
+            memory_usage: 100,
+            virtual_memory: 0,
+            status: "Run".to_string(),
+            start_time: 0,
+            run_time: 0,
+        }
+    }
+
+    fn threshold(metric: AlertMetric, comparison: AlertComparison, threshold: f64, target: Option<&str>) -> AlertCondition {
+        AlertCondition::Threshold { metric, comparison, threshold, target: target.map(str::to_string) }
+    }
+
+    fn rule(for_secs: i64) -> AlertRule {
+        AlertRule {
+            id: 7,
+            name: "Disk full".to_string(),
+            condition: threshold(AlertMetric::DiskUsage, AlertComparison::Above, 90.0, Some("/")),
+            for_secs,
+            enabled: true,
+            create_task: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn evaluates_thresholds_and_processes() {
+        let disks = [disk("/", 95), disk("/home", 50)];
+        let processes = [process("cargo", 80.0), process("rustc", 150.0), process("rustc", 50.0)];
+        let check = |condition: AlertCondition| evaluate(&condition, &usage(), &disks, &[], &processes);
+
+        assert_eq!(check(threshold(AlertMetric::DiskUsage, AlertComparison::Above, 90.0, Some("/"))), (true, Some(95.0)));
+        assert!(!check(threshold(AlertMetric::DiskUsage, AlertComparison::Above, 90.0, Some("/home"))).0);
+        assert_eq!(check(threshold(AlertMetric::DiskUsage, AlertComparison::Above, 90.0, None)).1, Some(95.0));
+        assert_eq!(check(threshold(AlertMetric::MemoryUsage, AlertComparison::Below, 50.0, None)), (false, Some(60.0)));
+        assert!(check(threshold(AlertMetric::LoadAverage, AlertComparison::Above, 3.0, None)).0);
+        assert_eq!(check(threshold(AlertMetric::ProcessCpu, AlertComparison::Above, 100.0, Some("RUSTC"))), (true, Some(200.0)));
+        assert_eq!(check(threshold(AlertMetric::ProcessCpu, AlertComparison::Below, 100.0, Some("gone"))), (false, None));
+        assert_eq!(check(AlertCondition::ProcessNotRunning { name: "syncthing".to_string() }), (true, Some(0.0)));
+        assert!(!check(AlertCondition::ProcessNotRunning { name: "cargo".to_string() }).0);
+        assert_eq!(check(threshold(AlertMetric::SwapUsage, AlertComparison::Below, 10.0, None)), (false, None));
+    }
+
+    #[test]
+    fn alert_fires_after_duration_and_resolves() {
+        let rule = rule(300);
+        let start = Utc::now();
+        let at = |secs: i64| start + chrono::Duration::seconds(secs);
+        let mut active = None;
+
+        let pending = advance(&mut active, &rule, true, Some(95.0), at(0)).unwrap();
+        assert_eq!(pending.status, AlertStatus::Pending);
+        assert_eq!(pending.message, "Disk full: Disk usage of / is 95.0%, above 90%");
+        assert!(advance(&mut active, &rule, true, Some(96.0), at(120)).is_none());
+        let firing = advance(&mut active, &rule, true, Some(97.0), at(300)).unwrap();
+        assert_eq!(firing.status, AlertStatus::Firing);
+        assert_eq!(firing.started_at, start);
+        assert!(advance(&mut active, &rule, true, Some(97.0), at(400)).is_none());
+        let resolved = advance(&mut active, &rule, false, Some(80.0), at(500)).unwrap();
+        assert_eq!(resolved.status, AlertStatus::Resolved);
+        assert_eq!(resolved.resolved_at, Some(at(500)));
+        assert!(active.is_none());
+        assert!(advance(&mut active, &rule, false, Some(80.0), at(600)).is_none());
+    }
+
+    #[test]
+    fn alert_without_duration_fires_at_once() {
+        let mut active = None;
+        let firing = advance(&mut active, &rule(0), true, Some(95.0), Utc::now()).unwrap();
+        assert_eq!(firing.status, AlertStatus::Firing);
+    }
+
+    #[test]
+    fn pending_alert_that_clears_is_resolved() {
+        let start = Utc::now();
+        let mut active = None;
+        advance(&mut active, &rule(60), true, Some(95.0), start);
+        let cleared = advance(&mut active, &rule(60), false, Some(10.0), start + chrono::Duration::seconds(30)).unwrap();
+        assert_eq!(cleared.status, AlertStatus::Resolved);
+        assert!(cleared.fired_at.is_none());
+    }
+
+    #[test]
+    fn every_transition_is_recorded_even_when_one_fails() {
+        let db = TestDatabase::new();
+        let conn = db.conn();
+        let events = Arc::new(EventBus::new());
+        let mut received = events.subscribe();
+        let manager = AlertManager { db: db.pool.clone(), events, active: Mutex::new(HashMap::new()) };
+        let rules: HashMap<i64, AlertRule> = [(7, 60, true), (8, 0, true), (9, 0, false)]
+            .into_iter()
+            .map(|(id, for_secs, create_task)| (id, AlertRule { id, create_task, ..rule(for_secs) }))
+            .collect();
+        // Evaluate the given rules the way `evaluate_rules` does
+        let step = |rule_ids: &[i64], now: DateTime<Utc>| {
+            let mut changed = Vec::new();
+            {
+                let mut active = manager.active.lock().unwrap();
+                for rule_id in rule_ids {
+                    let mut alert = active.remove(rule_id);
+                    changed.extend(advance(&mut alert, &rules[rule_id], true, Some(95.0), now));
+                    if let Some(alert) = alert {
+                        active.insert(*rule_id, alert);
+                    }
+                }
+            }
+            manager.record(&conn, &rules, changed);
+        };
+        let recorded = |rule_id: i64| queries::get_alerts(&db.conn(), Some(rule_id), None, 10).unwrap();
+        let start = Utc::now();
+
+        step(&[7], start);
+        let pending = recorded(7);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].status, AlertStatus::Pending);
+        assert_eq!(manager.active()[0].id, pending[0].id);
+
+        step(&[7], start + chrono::Duration::seconds(60));
+        let firing = recorded(7);
+        assert_eq!(firing.len(), 1);
+        assert_eq!(firing[0].status, AlertStatus::Firing);
+        assert!(firing[0].fired_at.is_some());
+        assert!(firing[0].task_id.is_some());
+
+        // Rule 8 cannot create its task; rule 9 is still recorded after it
+        conn.execute("DROP TABLE tasks", []).unwrap();
+        step(&[8, 9], start + chrono::Duration::seconds(120));
+        assert_eq!(recorded(8)[0].status, AlertStatus::Firing);
+        assert_eq!(recorded(8)[0].task_id, None);
+        assert_eq!(recorded(9)[0].status, AlertStatus::Firing);
+
+        let published: Vec<(i64, AlertStatus)> = std::iter::from_fn(|| received.try_recv().ok())
+            .map(|ServerEvent::Alert { alert }| (alert.rule_id, alert.status))
+            .collect();
+        assert_eq!(
+            published,
+            [(7, AlertStatus::Pending), (7, AlertStatus::Firing), (8, AlertStatus::Firing), (9, AlertStatus::Firing)]
+        );
+    }
+
+    #[test]
+    fn pending_alerts_left_by_a_stopped_server_are_resolved() {
+        let db = TestDatabase::new();
+        let conn = db.conn();
+        let mut active = None;
+        let mut pending = advance(&mut active, &rule(60), true, Some(95.0), Utc::now()).unwrap();
+        pending.id = Some(queries::insert_alert(&conn, &pending).unwrap());
+
+        resolve_stale_pending(&conn).unwrap();
+        let stored = queries::get_alerts(&conn, None, None, 10).unwrap();
+        assert_eq!(stored[0].status, AlertStatus::Resolved);
+        assert!(stored[0].resolved_at.is_some());
+    }
+
+    #[test]
+    fn rejects_incomplete_rules() {
+        let request = |condition: AlertCondition| AlertRuleRequest {
+            name: "rule".to_string(),
+            condition,
+            for_secs: None,
+            enabled: None,
+            create_task: None,
+        };
+        assert!(validate_rule(&request(threshold(AlertMetric::CpuUsage, AlertComparison::Above, 90.0, None))).is_ok());
+        assert!(validate_rule(&request(threshold(AlertMetric::ProcessMemory, AlertComparison::Above, 1e9, None))).is_err());
+        assert!(validate_rule(&request(AlertCondition::ProcessNotRunning { name: " ".to_string() })).is_err());
+    }
+}
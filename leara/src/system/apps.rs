@@ -0,0 +1,1018 @@
+/*
+ * Leara AI Assistant - Application Discovery
+ *
+ * This module finds the applications installed on the system: desktop
+ * entries following the freedesktop.org Desktop Entry specification and
+ * executables on PATH. The catalog is cached and rescanned when inotify
+ * reports changes in the directories it was built from.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/apps.rs
+ * Purpose: Desktop entry parsing, application catalog and launch commands
+ */
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use crate::system::watch::{DirectoryWatcher, WatchEvent};
+
+/// Age after which the catalog is rescanned even without change notifications
+const RESCAN_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Age after which the catalog is rescanned when inotify is unavailable
+const UNWATCHED_RESCAN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Terminal emulators tried for `Terminal=true` entries, with the option
+/// that makes them run a command
+const TERMINAL_EMULATORS: &[(&str, &[&str])] = &[
+    ("x-terminal-emulator", &["-e"]),
+    ("gnome-terminal", &["--"]),
+    ("konsole", &["-e"]),
+    ("xfce4-terminal", &["-x"]),
+    ("alacritty", &["-e"]),
+    ("kitty", &[]),
+    ("foot", &[]),
+    ("wezterm", &["start", "--"]),
+    ("xterm", &["-e"]),
+];
+
+/// Words that say what to do with an application rather than which one
+const FILLER_WORDS: &[&str] = &[
+    "open", "launch", "start", "run", "show", "my", "the", "a", "an", "app", "application", "program", "please",
+];
+
+/// Where an application was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppSource {
+    /// A `.desktop` file
+    Desktop,
+    /// An executable on PATH without a desktop entry
+    Path,
+}
+
+/// An installed application
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct App {
+    /// Desktop file ID without `.desktop` (`org.gnome.Terminal`), or the executable name
+    pub id: String,
+    /// Name in the user's language
+    pub name: String,
+    pub generic_name: Option<String>,
+    /// The entry's `Comment`
+    pub description: String,
+    /// Program the application runs
+    pub command: String,
+    /// The entry's `Exec` line, with field codes
+    pub exec: Option<String>,
+    /// Icon name or path
+    pub icon: Option<String>,
+    /// Whether the program needs a terminal
+    pub terminal: bool,
+    /// First of `categories`, or `cli` for executables on PATH
+    pub category: String,
+    pub categories: Vec<String>,
+    pub keywords: Vec<String>,
+    /// MIME types of files the application opens
+    pub mime_types: Vec<String>,
+    /// Working directory from the entry's `Path` key
+    pub working_dir: Option<String>,
+    /// Whether the entry asks not to be shown in menus (`NoDisplay`, `OnlyShowIn`, `NotShowIn`)
+    pub no_display: bool,
+    pub source: AppSource,
+    /// Path of the `.desktop` file
+    pub desktop_file: Option<String>,
+}
+
+impl App {
+    /// Command lines that open `targets` with this application
+    ///
+    /// Field codes in `Exec` are expanded: `%f`/`%u` take one file or URL,
+    /// so several targets start one instance each, while `%F`/`%U` take
+    /// them all. Executables on PATH receive the targets as arguments.
+    /// Targets starting with `-` are refused, since the application would
+    /// take them as options.
+    ///
+    /// # Arguments
+    /// * `targets` - Files or URLs to open
+    ///
+    /// # Returns
+    /// * `Ok(Vec<Vec<String>>)` - One argument vector per instance to start
+    /// * `Err(String)` - The Exec line is invalid or the application cannot open the targets
+    pub fn command_lines(&self, targets: &[String]) -> Result<Vec<Vec<String>>, String> {
+        if let Some(option) = targets.iter().find(|target| target.starts_with('-')) {
+            return Err(format!("{} looks like an option; give files as paths or URLs (./{})", option, option));
+        }
+        let Some(exec) = &self.exec else {
+            let mut argv = vec![self.command.clone()];
+            argv.extend_from_slice(targets);
+            return Ok(vec![argv]);
+        };
+
+        let args = split_exec(exec)?;
+        let takes_all = args.iter().any(|arg| arg.contains("%F") || arg.contains("%U"));
+        let takes_one = args.iter().any(|arg| arg.contains("%f") || arg.contains("%u"));
+        if !targets.is_empty() && !takes_all && !takes_one {
+            return Err(format!("{} does not open files or URLs", self.name));
+        }
+
+        let instances: Vec<&[String]> = if takes_all || targets.len() <= 1 {
+            vec![targets]
+        } else {
+            targets.chunks(1).collect()
+        };
+        instances.into_iter().map(|targets| self.expand(&args, targets)).collect()
+    }
+
+    /// Replace the field codes of an Exec line for one instance
+    fn expand(&self, args: &[String], targets: &[String]) -> Result<Vec<String>, String> {
+        let files = || -> Result<Vec<String>, String> { targets.iter().map(|target| local_path(target)).collect() };
+        let mut argv = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "%F" => argv.extend(files()?),
+                "%U" => argv.extend(targets.iter().cloned()),
+                "%f" => argv.extend(files()?.into_iter().take(1)),
+                "%u" => argv.extend(targets.iter().take(1).cloned()),
+                "%i" => {
+                    if let Some(icon) = &self.icon {
+                        argv.extend(["--icon".to_string(), icon.clone()]);
+                    }
+                }
+                _ => {
+                    let mut expanded = String::new();
+                    let mut chars = arg.chars();
+                    while let Some(c) = chars.next() {
+                        if c != '%' {
+                            expanded.push(c);
+                            continue;
+                        }
+                        match chars.next() {
+                            Some('%') => expanded.push('%'),
+                            Some('c') => expanded.push_str(&self.name),
+                            Some('k') => expanded.push_str(self.desktop_file.as_deref().unwrap_or("")),
+                            Some('f') => expanded.push_str(&files()?.into_iter().next().unwrap_or_default()),
+                            Some('u') => expanded.push_str(targets.first().map(String::as_str).unwrap_or("")),
+                            // %d, %D, %n, %N, %v and %m are deprecated and dropped
+                            Some('d' | 'D' | 'n' | 'N' | 'v' | 'm') => {}
+                            Some(code) => return Err(format!("Unknown field code %{} in Exec of {}", code, self.id)),
+                            None => return Err(format!("Incomplete field code in Exec of {}", self.id)),
+                        }
+                    }
+                    // Arguments made up of dropped field codes disappear entirely
+                    if !expanded.is_empty() || arg.is_empty() {
+                        argv.push(expanded);
+                    }
+                }
+            }
+        }
+        if argv.is_empty() {
+            return Err(format!("Exec of {} is empty", self.id));
+        }
+        if self.terminal {
+            argv = wrap_in_terminal(argv)?;
+        }
+        Ok(argv)
+    }
+}
+
+/// How often an application was used
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppUsage {
+    pub count: i64,
+    pub last_used: Option<String>,
+}
+
+impl AppUsage {
+    /// Combine usage counted from two sources
+    pub fn add(&mut self, other: &AppUsage) {
+        self.count += other.count;
+        if other.last_used > self.last_used {
+            self.last_used = other.last_used.clone();
+        }
+    }
+}
+
+/// An application found by a search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppMatch {
+    #[serde(flatten)]
+    pub app: App,
+    /// Relevance weighted by usage; higher is better
+    pub score: f64,
+    pub usage: AppUsage,
+}
+
+/// Number of matching applications in a category
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryFacet {
+    pub category: String,
+    pub count: usize,
+}
+
+/// Split a search query into lowercase terms, dropping words like "open" or "my"
+///
+/// Filler words are kept when the query consists of nothing else.
+pub fn query_terms(query: &str) -> Vec<String> {
+    let terms: Vec<String> = query
+        .split(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | '"' | '\'' | '?' | '!'))
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let meaningful: Vec<String> = terms.iter().filter(|term| !FILLER_WORDS.contains(&term.as_str())).cloned().collect();
+    if meaningful.is_empty() { terms } else { meaningful }
+}
+
+/// How well an application matches search terms, from 0 to 1
+///
+/// Each term is scored against the name, generic name, ID, command,
+/// keywords, categories and description, weighted in that order, and the
+/// best field counts. Terms that match nothing lower the score.
+///
+/// # Returns
+/// * `None` - No term matches
+pub fn relevance(app: &App, terms: &[String]) -> Option<f64> {
+    let command = Path::new(&app.command).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let mut fields: Vec<(f64, &str)> = vec![(1.0, &app.name), (0.85, &app.id), (0.85, &command), (0.4, &app.description)];
+    if let Some(generic_name) = &app.generic_name {
+        fields.push((0.9, generic_name));
+    }
+    fields.extend(app.keywords.iter().map(|keyword| (0.8, keyword.as_str())));
+    fields.extend(app.categories.iter().map(|category| (0.6, category.as_str())));
+
+    let mut total = 0.0;
+    let mut matched = 0;
+    for term in terms {
+        let best = fields
+            .iter()
+            .map(|(weight, text)| weight * text_score(term, text))
+            .fold(0.0, f64::max);
+        if best > 0.0 {
+            matched += 1;
+        }
+        total += best;
+    }
+    (matched > 0).then(|| total / terms.len() as f64)
+}
+
+/// How well one lowercase term matches a text, from 0 to 1
+///
+/// Exact matches beat prefixes, then word prefixes, substrings, near
+/// misses (one or two typos) and finally scattered letters in short texts.
+fn text_score(term: &str, text: &str) -> f64 {
+    let text = text.to_lowercase();
+    let mut words = text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty());
+    if text == term {
+        1.0
+    } else if text.starts_with(term) {
+        0.9
+    } else if words.clone().any(|word| word.starts_with(term)) {
+        0.8
+    } else if text.contains(term) {
+        0.6
+    } else if term.len() >= 4 && words.any(|word| edit_distance(term, word) <= if term.len() >= 8 { 2 } else { 1 }) {
+        0.5
+    } else if term.len() >= 3 && text.len() <= 40 {
+        subsequence_score(term, &text) * 0.4
+    } else {
+        0.0
+    }
+}
+
+/// Edit distance between two words, counting swapped neighbours as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// How tightly the letters of a term appear in order in a text, from 0 to 1
+fn subsequence_score(term: &str, text: &str) -> f64 {
+    let mut letters = term.chars().peekable();
+    let (mut first, mut last) = (None, 0);
+    for (i, c) in text.chars().enumerate() {
+        if letters.peek() == Some(&c) {
+            letters.next();
+            first.get_or_insert(i);
+            last = i;
+        }
+    }
+    match (letters.peek(), first) {
+        (None, Some(first)) => term.chars().count() as f64 / (last - first + 1) as f64,
+        _ => 0.0,
+    }
+}
+
+/// Search applications and rank them by relevance and usage
+///
+/// Without terms every application matches and the most used come first.
+/// Category facets count the matches before `category` is applied.
+///
+/// # Arguments
+/// * `apps` - Applications to search
+/// * `terms` - Terms from `query_terms`
+/// * `category` - Only return applications in this category (case-insensitive)
+/// * `usage` - How often an application was used
+///
+/// # Returns
+/// * `(Vec<AppMatch>, Vec<CategoryFacet>)` - Matches, best first, and category counts, largest first
+pub fn search(
+    apps: &[App],
+    terms: &[String],
+    category: Option<&str>,
+    usage: impl Fn(&App) -> AppUsage,
+) -> (Vec<AppMatch>, Vec<CategoryFacet>) {
+    let mut facets: HashMap<&str, usize> = HashMap::new();
+    let mut matches = Vec::new();
+    for app in apps {
+        let relevance = if terms.is_empty() { Some(1.0) } else { relevance(app, terms) };
+        let Some(relevance) = relevance else {
+            continue;
+        };
+        for category in &app.categories {
+            *facets.entry(category.as_str()).or_default() += 1;
+        }
+        if category.is_some_and(|category| !app.categories.iter().any(|c| c.eq_ignore_ascii_case(category))) {
+            continue;
+        }
+
+        let usage = usage(app);
+        // Frequently used applications rise, with diminishing returns;
+        // desktop entries beat bare executables of the same relevance
+        let source_weight = if app.source == AppSource::Desktop { 1.0 } else { 0.85 };
+        let score = relevance * source_weight * (1.0 + 0.2 * (1.0 + usage.count as f64).ln());
+        matches.push(AppMatch { app: app.clone(), score, usage });
+    }
+
+    matches.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| b.usage.count.cmp(&a.usage.count))
+            .then_with(|| a.app.name.to_lowercase().cmp(&b.app.name.to_lowercase()))
+    });
+    let mut facets: Vec<CategoryFacet> = facets
+        .into_iter()
+        .map(|(category, count)| CategoryFacet { category: category.to_string(), count })
+        .collect();
+    facets.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.category.cmp(&b.category)));
+    (matches, facets)
+}
+
+/// Path of a local file given as a path or `file://` URL
+fn local_path(target: &str) -> Result<String, String> {
+    match target.strip_prefix("file://") {
+        Some(rest) => {
+            // Skip an optional host part (`file://localhost/...`)
+            let path = &rest[rest.find('/').unwrap_or(rest.len())..];
+            Ok(percent_decode(path))
+        }
+        None if target.contains("://") => Err(format!("{} is not a local file", target)),
+        None => Ok(target.to_string()),
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 3 <= bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Run a command in the user's terminal emulator (`$TERMINAL`, then well-known ones)
+fn wrap_in_terminal(argv: Vec<String>) -> Result<Vec<String>, String> {
+    let configured = std::env::var("TERMINAL").ok().filter(|t| !t.is_empty());
+    let (emulator, options): (String, &[&str]) = match configured {
+        Some(terminal) => {
+            let name = Path::new(&terminal).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let options = TERMINAL_EMULATORS.iter().find(|(known, _)| *known == name).map(|(_, o)| *o).unwrap_or(&["-e"]);
+            (terminal, options)
+        }
+        None => TERMINAL_EMULATORS
+            .iter()
+            .find(|(name, _)| find_in_path(name).is_some())
+            .map(|(name, options)| (name.to_string(), *options))
+            .ok_or_else(|| "No terminal emulator found; set TERMINAL".to_string())?,
+    };
+    let mut wrapped = vec![emulator];
+    wrapped.extend(options.iter().map(|o| o.to_string()));
+    wrapped.extend(argv);
+    Ok(wrapped)
+}
+
+/// Split an `Exec` value into arguments following the spec's quoting rules
+///
+/// Arguments containing reserved characters are enclosed in double quotes,
+/// inside which `"`, `` ` ``, `$` and `\` are escaped with a backslash.
+pub fn split_exec(exec: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped @ ('"' | '`' | '$' | '\\')) => current.push(escaped),
+                            Some(other) => {
+                                current.push('\\');
+                                current.push(other);
+                            }
+                            None => return Err("Unterminated escape in Exec".to_string()),
+                        },
+                        Some(other) => current.push(other),
+                        None => return Err("Unterminated quote in Exec".to_string()),
+                    }
+                }
+            }
+            ' ' | '\t' | '\n' => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+/// Keys of the `[Desktop Entry]` group, with localized keys kept as `Name[de]`
+pub fn parse_desktop_entry(content: &str) -> HashMap<String, String> {
+    let mut entries = HashMap::new();
+    let mut in_main_group = false;
+    for line in content.lines() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            in_main_group = line.trim_end() == "[Desktop Entry]";
+            continue;
+        }
+        if !in_main_group {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            // The first occurrence of a key wins
+            entries.entry(key.trim().to_string()).or_insert_with(|| unescape(value.trim()));
+        }
+    }
+    entries
+}
+
+/// Decode `\s`, `\n`, `\t`, `\r` and `\\` in a string value
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => result.push(' '),
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('\\') => result.push('\\'),
+            // `\;` separates list items and is resolved by `string_list`
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Split a `;`-separated list, honouring `\;`
+fn string_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(';') => current.push(';'),
+                Some(other) => {
+                    current.push('\\');
+                    current.push(other);
+                }
+                None => current.push('\\'),
+            },
+            ';' => items.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    items.push(current);
+    items.into_iter().map(|item| item.trim().to_string()).filter(|item| !item.is_empty()).collect()
+}
+
+/// Locale keys to try for localized values, most specific first
+///
+/// `de_AT.UTF-8@euro` yields `de_AT@euro`, `de_AT`, `de@euro` and `de`.
+fn locale_candidates() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale.to_string(), Some(modifier.to_string())),
+        None => (locale, None),
+    };
+    let locale = locale.split('.').next().unwrap_or("").to_string();
+    let (lang, country) = match locale.split_once('_') {
+        Some((lang, country)) => (lang.to_string(), Some(country.to_string())),
+        None => (locale, None),
+    };
+    if lang.is_empty() || lang == "C" || lang == "POSIX" {
+        return Vec::new();
+    }
+
+    let mut candidates = Vec::new();
+    if let (Some(country), Some(modifier)) = (&country, &modifier) {
+        candidates.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+    if let Some(country) = &country {
+        candidates.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = &modifier {
+        candidates.push(format!("{}@{}", lang, modifier));
+    }
+    candidates.push(lang);
+    candidates
+}
+
+fn localized(entry: &HashMap<String, String>, key: &str, locales: &[String]) -> Option<String> {
+    locales
+        .iter()
+        .find_map(|locale| entry.get(&format!("{}[{}]", key, locale)))
+        .or_else(|| entry.get(key))
+        .cloned()
+}
+
+/// Find an executable by name or path
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        path.is_file() && path.metadata().map(|m| m.permissions().mode() & 0o111 != 0).unwrap_or(false)
+    };
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return is_executable(&path).then_some(path);
+    }
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+/// Build an application from a desktop entry
+///
+/// # Returns
+/// * `Some(Ok(App))` - An application
+/// * `Some(Err(()))` - The entry is `Hidden` and hides entries with the same ID
+/// * `None` - Not an application, or its `TryExec` program is missing
+fn app_from_entry(
+    id: &str,
+    file: &Path,
+    entry: &HashMap<String, String>,
+    locales: &[String],
+    desktops: &[String],
+) -> Option<Result<App, ()>> {
+    let flag = |key: &str| entry.get(key).is_some_and(|value| value == "true");
+    if flag("Hidden") {
+        return Some(Err(()));
+    }
+    if entry.get("Type").map(String::as_str) != Some("Application") {
+        return None;
+    }
+    if let Some(try_exec) = entry.get("TryExec") {
+        find_in_path(try_exec)?;
+    }
+    let exec = entry.get("Exec").cloned()?;
+    let command = split_exec(&exec).ok()?.into_iter().next()?;
+
+    let only_show_in = entry.get("OnlyShowIn").map(|value| string_list(value));
+    let not_show_in = entry.get("NotShowIn").map(|value| string_list(value)).unwrap_or_default();
+    let shown_here = only_show_in.is_none_or(|only| only.iter().any(|d| desktops.contains(d)))
+        && !not_show_in.iter().any(|d| desktops.contains(d));
+
+    let list = |key: &str| localized(entry, key, locales).map(|value| string_list(&value)).unwrap_or_default();
+    let categories = list("Categories");
+    Some(Ok(App {
+        id: id.to_string(),
+        name: localized(entry, "Name", locales)?,
+        generic_name: localized(entry, "GenericName", locales),
+        description: localized(entry, "Comment", locales).unwrap_or_default(),
+        command,
+        exec: Some(exec),
+        icon: entry.get("Icon").cloned(),
+        terminal: flag("Terminal"),
+        category: categories.first().cloned().unwrap_or_default(),
+        categories,
+        keywords: list("Keywords"),
+        mime_types: list("MimeType"),
+        working_dir: entry.get("Path").filter(|path| !path.is_empty()).cloned(),
+        no_display: flag("NoDisplay") || !shown_here,
+        source: AppSource::Desktop,
+        desktop_file: Some(file.display().to_string()),
+    }))
+}
+
+/// Directories holding desktop entries, highest precedence first
+pub fn application_dirs() -> Vec<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+
+    let mut dirs: Vec<PathBuf> = data_home.into_iter().collect();
+    dirs.extend(data_dirs.split(':').filter(|dir| !dir.is_empty()).map(PathBuf::from));
+    let mut seen = HashSet::new();
+    dirs.into_iter()
+        .map(|dir| dir.join("applications"))
+        .filter(|dir| seen.insert(dir.clone()))
+        .collect()
+}
+
+/// Directories on PATH
+fn path_dirs() -> Vec<PathBuf> {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect())
+        .unwrap_or_default()
+}
+
+/// Desktop files below an applications directory with their IDs
+///
+/// Files in subdirectories get IDs with `-` for `/` (`kde4/dolphin.desktop`
+/// becomes `kde4-dolphin`).
+fn desktop_files(root: &Path) -> Vec<(String, PathBuf)> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "desktop") {
+                let Ok(relative) = path.strip_prefix(root) else {
+                    continue;
+                };
+                let id = relative.with_extension("").to_string_lossy().replace('/', "-");
+                files.push((id, path));
+            }
+        }
+    }
+    files
+}
+
+/// Scan the system for applications
+///
+/// Desktop entries come first, in precedence order; executables on PATH
+/// without an entry follow, named after the file.
+pub fn scan() -> Vec<App> {
+    let locales = locale_candidates();
+    let desktops: Vec<String> = std::env::var("XDG_CURRENT_DESKTOP")
+        .map(|value| value.split(':').map(str::to_string).collect())
+        .unwrap_or_default();
+
+    let mut apps = Vec::new();
+    let mut seen_ids = HashSet::new();
+    let mut commands = HashSet::new();
+    for dir in application_dirs() {
+        let mut files = desktop_files(&dir);
+        files.sort();
+        for (id, file) in files {
+            if seen_ids.contains(&id) {
+                continue;
+            }
+            let Ok(content) = std::fs::read_to_string(&file) else {
+                continue;
+            };
+            match app_from_entry(&id, &file, &parse_desktop_entry(&content), &locales, &desktops) {
+                Some(Ok(app)) => {
+                    seen_ids.insert(id);
+                    commands.insert(Path::new(&app.command).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default());
+                    apps.push(app);
+                }
+                Some(Err(())) => {
+                    seen_ids.insert(id);
+                }
+                None => {}
+            }
+        }
+    }
+
+    for dir in path_dirs() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut names: Vec<(String, PathBuf)> = entries
+            .flatten()
+            .map(|entry| (entry.file_name().to_string_lossy().to_string(), entry.path()))
+            .collect();
+        names.sort();
+        for (name, path) in names {
+            if commands.contains(&name) || seen_ids.contains(&name) {
+                continue;
+            }
+            if !path.is_file() || path.metadata().map(|m| m.permissions().mode() & 0o111 == 0).unwrap_or(true) {
+                continue;
+            }
+            commands.insert(name.clone());
+            apps.push(App {
+                id: name.clone(),
+                name: name.clone(),
+                generic_name: None,
+                description: String::new(),
+                command: name,
+                exec: None,
+                icon: None,
+                terminal: false,
+                category: "cli".to_string(),
+                categories: vec!["cli".to_string()],
+                keywords: Vec::new(),
+                mime_types: Vec::new(),
+                working_dir: None,
+                no_display: false,
+                source: AppSource::Path,
+                desktop_file: None,
+            });
+        }
+    }
+    apps
+}
+
+/// Cached list of installed applications
+pub struct AppCatalog {
+    apps: RwLock<Option<(Arc<Vec<App>>, Instant)>>,
+    /// Set by the watcher when a watched directory changed
+    stale: AtomicBool,
+    /// Whether inotify is watching the application directories
+    watching: AtomicBool,
+}
+
+impl AppCatalog {
+    /// Create an empty catalog and start watching for installed or removed applications
+    ///
+    /// Must be called inside the Tokio runtime.
+    pub fn new() -> Arc<Self> {
+        let catalog = Arc::new(AppCatalog {
+            apps: RwLock::new(None),
+            stale: AtomicBool::new(false),
+            watching: AtomicBool::new(false),
+        });
+        tokio::spawn(watch(catalog.clone()));
+        catalog
+    }
+
+    /// All applications, rescanned if anything changed since the last scan
+    pub fn apps(&self) -> Arc<Vec<App>> {
+        let max_age = if self.watching.load(Ordering::Relaxed) { RESCAN_INTERVAL } else { UNWATCHED_RESCAN_INTERVAL };
+        if let Some((apps, scanned_at)) = self.apps.read().unwrap().as_ref() {
+            if !self.stale.load(Ordering::Relaxed) && scanned_at.elapsed() < max_age {
+                return apps.clone();
+            }
+        }
+        self.refresh()
+    }
+
+    /// Look up an application by ID; `firefox.desktop` and `firefox` are the same
+    pub fn get(&self, id: &str) -> Option<App> {
+        let id = id.strip_suffix(".desktop").unwrap_or(id);
+        self.apps().iter().find(|app| app.id == id).cloned()
+    }
+
+    /// Rescan now
+    pub fn refresh(&self) -> Arc<Vec<App>> {
+        self.stale.store(false, Ordering::Relaxed);
+        let apps = Arc::new(scan());
+        *self.apps.write().unwrap() = Some((apps.clone(), Instant::now()));
+        apps
+    }
+}
+
+/// Mark the catalog stale whenever an application or PATH directory changes
+async fn watch(catalog: Arc<AppCatalog>) {
+    let mut watcher = match DirectoryWatcher::new() {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            warn!("Not watching application directories, rescanning periodically: {}", e);
+            return;
+        }
+    };
+    let app_dirs = application_dirs();
+    for root in &app_dirs {
+        for dir in subdirectories(root) {
+            let _ = watcher.watch(&dir);
+        }
+    }
+    for dir in path_dirs() {
+        let _ = watcher.watch(&dir);
+    }
+    catalog.watching.store(true, Ordering::Relaxed);
+    info!("Watching {} directories for application changes", watcher.watched().count());
+
+    loop {
+        let events = match watcher.next_events().await {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Stopped watching application directories: {}", e);
+                catalog.watching.store(false, Ordering::Relaxed);
+                return;
+            }
+        };
+        if events.iter().any(WatchEvent::is_overflow) {
+            // Changes were lost, new subdirectories among them
+            warn!("Missed changes to application directories; rescanning");
+            for root in &app_dirs {
+                for dir in subdirectories(root) {
+                    let _ = watcher.watch(&dir);
+                }
+            }
+        }
+        for event in &events {
+            // New subdirectories of application directories can hold entries too
+            if event.is_dir() && !event.is_removal() && app_dirs.iter().any(|root| event.path.starts_with(root)) {
+                let _ = watcher.watch(&event.path);
+            }
+        }
+        if !events.is_empty() {
+            catalog.stale.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A directory and every directory below it
+fn subdirectories(root: &Path) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        pending.extend(entries.flatten().map(|entry| entry.path()).filter(|path| path.is_dir()));
+        dirs.push(dir);
+    }
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(exec: &str) -> App {
+        let entry = parse_desktop_entry(&format!("[Desktop Entry]\nType=Application\nName=Viewer\nIcon=viewer\nExec={}\n", exec));
+        app_from_entry("viewer", Path::new("/apps/viewer.desktop"), &entry, &[], &[]).unwrap().unwrap()
+    }
+
+    #[test]
+    fn desktop_entries_are_parsed() {
+        let content = "# comment\n[Desktop Entry]\nName=Files\nName[de]=Dateien\nComment=Browse\\sfiles\nCategories=System;FileTools;\nKeywords=a\\;b;c\n\n[Desktop Action New]\nName=Other\n";
+        let entry = parse_desktop_entry(content);
+        assert_eq!(entry.get("Name").map(String::as_str), Some("Files"));
+        assert_eq!(entry.get("Comment").map(String::as_str), Some("Browse files"));
+        assert_eq!(localized(&entry, "Name", &["de_DE".to_string(), "de".to_string()]).as_deref(), Some("Dateien"));
+        assert_eq!(string_list(&entry["Categories"]), vec!["System", "FileTools"]);
+        assert_eq!(string_list(&entry["Keywords"]), vec!["a;b", "c"]);
+    }
+
+    #[test]
+    fn exec_lines_are_split_with_quoting() {
+        assert_eq!(
+            split_exec(r#""/opt/my app/run" --title "say \"hi\"" %U"#).unwrap(),
+            vec!["/opt/my app/run", "--title", "say \"hi\"", "%U"]
+        );
+        assert!(split_exec("\"unterminated").is_err());
+    }
+
+    #[test]
+    fn field_codes_are_expanded() {
+        let targets = vec!["file:///tmp/a%20b.txt".to_string(), "/tmp/c.txt".to_string()];
+        assert_eq!(
+            app("viewer %i --name=%c %F").command_lines(&targets).unwrap(),
+            vec![vec!["viewer", "--icon", "viewer", "--name=Viewer", "/tmp/a b.txt", "/tmp/c.txt"]]
+        );
+        assert_eq!(
+            app("viewer %u").command_lines(&targets).unwrap(),
+            vec![vec!["viewer", "file:///tmp/a%20b.txt"], vec!["viewer", "/tmp/c.txt"]]
+        );
+        assert_eq!(app("viewer %%x %d").command_lines(&[]).unwrap(), vec![vec!["viewer", "%x"]]);
+        assert!(app("viewer").command_lines(&targets).is_err());
+        assert!(app("viewer %f").command_lines(&["https://example.com".to_string()]).is_err());
+    }
+
+    #[test]
+    fn options_are_not_targets() {
+        let option = vec!["--renderer-cmd-prefix=/bin/sh".to_string()];
+        assert!(app("browser %U").command_lines(&option).is_err());
+        assert!(app("browser %f").command_lines(&option).is_err());
+        assert!(App { exec: None, ..app("browser") }.command_lines(&option).is_err());
+        assert_eq!(
+            app("browser %U").command_lines(&["./-notes.txt".to_string()]).unwrap(),
+            vec![vec!["browser", "./-notes.txt"]]
+        );
+    }
+
+    #[test]
+    fn file_urls_are_percent_decoded() {
+        assert_eq!(local_path("file:///tmp/a%41%2").unwrap(), "/tmp/aA%2");
+        assert_eq!(local_path("file://localhost/tmp/%C3%A9%41").unwrap(), "/tmp/éA");
+        // Not a character boundary after the escape
+        assert_eq!(local_path("file:///tmp/%aé").unwrap(), "/tmp/%aé");
+        assert_eq!(local_path("file:///tmp/%é").unwrap(), "/tmp/%é");
+    }
+
+    #[test]
+    fn search_ranks_by_relevance_and_usage() {
+        let mut firefox = app("firefox %u");
+        firefox.id = "firefox".to_string();
+        firefox.name = "Firefox".to_string();
+        firefox.generic_name = Some("Web Browser".to_string());
+        firefox.categories = vec!["Network".to_string(), "WebBrowser".to_string()];
+        let mut chromium = firefox.clone();
+        chromium.id = "chromium".to_string();
+        chromium.name = "Chromium".to_string();
+        let mut editor = app("editor %F");
+        editor.categories = vec!["Utility".to_string()];
+        let apps = vec![firefox, chromium, editor];
+
+        let terms = query_terms("open my browser");
+        assert_eq!(terms, vec!["browser"]);
+        let usage = |app: &App| AppUsage { count: if app.id == "chromium" { 5 } else { 0 }, last_used: None };
+        let (matches, facets) = search(&apps, &terms, None, usage);
+        let ids: Vec<&str> = matches.iter().map(|m| m.app.id.as_str()).collect();
+        assert_eq!(ids, vec!["chromium", "firefox"]);
+        assert_eq!(facets[0].count, 2);
+
+        let (matches, _) = search(&apps, &query_terms("firfox"), None, |_| AppUsage::default());
+        assert_eq!(matches[0].app.id, "firefox");
+        let (matches, _) = search(&apps, &[], Some("utility"), |_| AppUsage::default());
+        assert_eq!(matches.len(), 1);
+    }
+}
@@ -0,0 +1,231 @@
+/*
+ * Leara AI Assistant - Command Environment
+ *
+ * This module decides which environment variables executed commands see.
+ * Only an allow-list of the server's variables is inherited, secrets are
+ * scrubbed even when allowed, and named profiles or per-request overrides
+ * add to the result. What changed is recorded with the command, with the
+ * values of secrets redacted.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/environment.rs
+ * Purpose: Environment variables for executed commands
+ */
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tokio::process::Command as TokioCommand;
+use crate::system::policy::wildcard_match;
+
+/// Value recorded instead of a secret
+pub const REDACTED: &str = "[redacted]";
+
+/// Environment settings, configured in the `[environment]` section of the policy file
+///
+/// Variable names are matched with `*` wildcards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentConfig {
+    /// Server variables passed on to commands; `*` inherits everything
+    pub inherit: Vec<String>,
+    /// Secrets that are never inherited and whose values are redacted in the history
+    pub deny: Vec<String>,
+    /// Variables that change how programs are found or loaded; overriding
+    /// them in a request needs confirmation
+    pub protected: Vec<String>,
+    /// Profile applied when a request does not name one
+    pub default_profile: Option<String>,
+    /// Named sets of variables requests can ask for
+    pub profiles: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+        EnvironmentConfig {
+            inherit: names(&[
+                "PATH", "HOME", "USER", "LOGNAME", "SHELL", "LANG", "LANGUAGE", "LC_*", "TZ", "TERM",
+                "TMPDIR", "XDG_*", "DISPLAY", "WAYLAND_DISPLAY", "DBUS_SESSION_BUS_ADDRESS",
+            ]),
+            deny: names(&[
+                "*TOKEN*", "*SECRET*", "*PASSWORD*", "*PASSWD*", "*API_KEY*", "*APIKEY*", "*CREDENTIAL*",
+                "*PRIVATE_KEY*", "AWS_*", "LEARA_*", "DATABASE_*",
+            ]),
+            protected: names(&[
+                "PATH", "LD_*", "DYLD_*", "BASH_ENV", "ENV", "IFS", "PYTHONPATH", "PYTHONSTARTUP",
+                "PERL5LIB", "PERL5OPT", "RUBYOPT", "RUBYLIB", "NODE_OPTIONS", "GIT_SSH*", "GIT_EXEC_PATH",
+                "GIT_CONFIG*",
+            ]),
+            default_profile: None,
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+/// Environment a command runs with
+#[derive(Debug, Clone, Default)]
+pub struct CommandEnvironment {
+    /// Every variable the program sees
+    pub vars: BTreeMap<String, String>,
+    /// Profile that was applied
+    pub profile: Option<String>,
+    /// Overrides given with the request; `None` removes a variable
+    pub overrides: BTreeMap<String, Option<String>>,
+    /// Differences to the inherited variables, with secrets redacted
+    pub changes: BTreeMap<String, Option<String>>,
+    /// Overridden variables that are protected
+    pub protected: Vec<String>,
+}
+
+impl CommandEnvironment {
+    /// Replace the environment of a command with this one
+    pub fn apply(&self, cmd: &mut TokioCommand) {
+        cmd.env_clear().envs(&self.vars);
+    }
+}
+
+impl EnvironmentConfig {
+    /// Build the environment for a command
+    ///
+    /// The allowed server variables come first, then the profile, then the
+    /// request's overrides.
+    ///
+    /// # Arguments
+    /// * `profile` - Profile asked for; `default_profile` when `None`
+    /// * `overrides` - Variables to set, or to remove when `None`
+    ///
+    /// # Returns
+    /// * `Ok(CommandEnvironment)` - The resolved environment
+    /// * `Err(String)` - Unknown profile or invalid variable
+    pub fn resolve(
+        &self,
+        profile: Option<&str>,
+        overrides: &BTreeMap<String, Option<String>>,
+    ) -> Result<CommandEnvironment, String> {
+        let inherited: BTreeMap<String, String> = std::env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .filter(|(name, _)| self.inherits(name))
+            .collect();
+
+        let profile = profile.map(str::to_string).or_else(|| self.default_profile.clone());
+        let mut vars = inherited.clone();
+        if let Some(name) = &profile {
+            let Some(profile_vars) = self.profiles.get(name) else {
+                return Err(format!("Unknown environment profile: {}", name));
+            };
+            for (name, value) in profile_vars {
+                vars.insert(name.clone(), value.clone());
+            }
+        }
+
+        for (name, value) in overrides {
+            validate(name, value.as_deref())?;
+            match value {
+                Some(value) => vars.insert(name.clone(), value.clone()),
+                None => vars.remove(name),
+            };
+        }
+
+        let mut changes = BTreeMap::new();
+        for (name, value) in &vars {
+            if inherited.get(name) != Some(value) {
+                let value = if self.is_secret(name) { REDACTED.to_string() } else { value.clone() };
+                changes.insert(name.clone(), Some(value));
+            }
+        }
+        for name in inherited.keys().filter(|name| !vars.contains_key(*name)) {
+            changes.insert(name.clone(), None);
+        }
+
+        let protected = overrides
+            .keys()
+            .filter(|name| self.protected.iter().any(|pattern| wildcard_match(pattern, name)))
+            .cloned()
+            .collect();
+
+        Ok(CommandEnvironment { vars, profile, overrides: overrides.clone(), changes, protected })
+    }
+
+    /// Whether a server variable is passed on to commands
+    pub fn inherits(&self, name: &str) -> bool {
+        self.inherit.iter().any(|pattern| wildcard_match(pattern, name)) && !self.is_secret(name)
+    }
+
+    /// Whether a variable holds a secret
+    pub fn is_secret(&self, name: &str) -> bool {
+        let upper = name.to_ascii_uppercase();
+        self.deny.iter().any(|pattern| wildcard_match(&pattern.to_ascii_uppercase(), &upper))
+    }
+}
+
+/// Reject names and values the operating system cannot represent
+fn validate(name: &str, value: Option<&str>) -> Result<(), String> {
+    if name.is_empty() || name.contains('=') || name.contains('\0') {
+        return Err(format!("Invalid environment variable name: {:?}", name));
+    }
+    if value.is_some_and(|value| value.contains('\0')) {
+        return Err(format!("Environment variable {} contains a NUL byte", name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> EnvironmentConfig {
+        let mut config = EnvironmentConfig::default();
+        config.profiles.insert("rust".to_string(), BTreeMap::from([("RUST_BACKTRACE".to_string(), "1".to_string())]));
+        config
+    }
+
+    #[test]
+    fn secrets_are_not_inherited() {
+        let config = config();
+        assert!(config.inherits("PATH"));
+        assert!(config.inherits("LC_ALL"));
+        assert!(!config.inherits("GITHUB_TOKEN"));
+        assert!(!config.inherits("CARGO_HOME"));
+        assert!(config.is_secret("my_api_key"));
+    }
+
+    #[test]
+    fn changes_are_recorded_with_secrets_redacted() {
+        let overrides = BTreeMap::from([
+            ("GITHUB_TOKEN".to_string(), Some("ghp_example".to_string())),
+            ("GREETING".to_string(), Some("hello".to_string())),
+            ("HOME".to_string(), None),
+        ]);
+        let env = config().resolve(Some("rust"), &overrides).unwrap();
+        assert_eq!(env.vars.get("GITHUB_TOKEN").map(String::as_str), Some("ghp_example"));
+        assert_eq!(env.changes.get("GITHUB_TOKEN"), Some(&Some(REDACTED.to_string())));
+        assert_eq!(env.changes.get("GREETING"), Some(&Some("hello".to_string())));
+        assert_eq!(env.changes.get("RUST_BACKTRACE"), Some(&Some("1".to_string())));
+        assert!(!env.vars.contains_key("HOME"));
+        assert!(env.protected.is_empty());
+    }
+
+    #[test]
+    fn protected_overrides_are_reported() {
+        let overrides = BTreeMap::from([("LD_PRELOAD".to_string(), Some("/tmp/x.so".to_string()))]);
+        let env = config().resolve(None, &overrides).unwrap();
+        assert_eq!(env.protected, vec!["LD_PRELOAD".to_string()]);
+    }
+
+    #[test]
+    fn invalid_requests_are_rejected() {
+        assert!(config().resolve(Some("missing"), &BTreeMap::new()).is_err());
+        assert!(config().resolve(None, &BTreeMap::from([("A=B".to_string(), None)])).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+/*
+ * Leara AI Assistant - Event Bus
+ *
+ * This module carries notifications from background work, such as alerts
+ * changing state, to every connected client. Events are broadcast live
+ * only; clients that connect later fetch the current state from the API.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/events.rs
+ * Purpose: Server-wide notification events for the frontend
+ */
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use crate::models::system::Alert;
+
+/// Events kept for subscribers that fall behind
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// A notification for the frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// An alert became pending, fired or resolved
+    Alert { alert: Alert },
+}
+
+impl ServerEvent {
+    /// Event name used on the SSE stream
+    pub fn name(&self) -> &str {
+        match self {
+            ServerEvent::Alert { .. } => "alert",
+        }
+    }
+}
+
+/// Broadcasts events to every subscriber
+pub struct EventBus {
+    sender: broadcast::Sender<ServerEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        EventBus { sender }
+    }
+
+    /// Send an event to everyone subscribed right now
+    pub fn publish(&self, event: ServerEvent) {
+        // Nobody listening is not an error
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,513 @@
+/*
+ * Leara AI Assistant - Command Executor
+ *
+ * This module runs commands that passed the execution policy under resource
+ * limits: a wall-clock timeout that kills the whole process group, caps on
+ * captured output and, on Linux, rlimits applied before the program starts.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/executor.rs
+ * Purpose: Time- and resource-limited command execution
+ */
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command as TokioCommand};
+use tracing::warn;
+use crate::system::environment::CommandEnvironment;
+
+/// Time a timed-out process group gets to exit after SIGTERM before SIGKILL
+const KILL_GRACE: Duration = Duration::from_secs(2);
+
+/// Time to wait for output still buffered in pipes after the program exited
+///
+/// Background children can keep the pipes open indefinitely, so reading
+/// stops after this grace period.
+const DRAIN_GRACE: Duration = Duration::from_millis(500);
+
+/// Resource limits for executed commands, configured in the policy file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExecutionLimits {
+    /// Wall-clock timeout when neither the request nor `program_timeouts` set one
+    pub timeout_secs: u64,
+    /// Upper bound for timeouts requested by callers
+    pub max_timeout_secs: u64,
+    /// Wall-clock limit for background jobs, also the default for them
+    pub job_timeout_secs: u64,
+    /// Timeouts for specific programs (by basename)
+    pub program_timeouts: HashMap<String, u64>,
+    /// Bytes of stdout and of stderr kept each; the rest is discarded
+    pub max_output_bytes: usize,
+    /// Bytes of stdout and of stderr stored in the command history each
+    pub history_output_bytes: usize,
+    /// RLIMIT_CPU in seconds (Linux only)
+    pub cpu_seconds: Option<u64>,
+    /// RLIMIT_AS in bytes (Linux only)
+    pub memory_bytes: Option<u64>,
+    /// RLIMIT_NOFILE (Linux only)
+    pub open_files: Option<u64>,
+}
+
+impl Default for ExecutionLimits {
+    fn default() -> Self {
+        ExecutionLimits {
+            timeout_secs: 30,
+            max_timeout_secs: 600,
+            job_timeout_secs: 3600,
+            program_timeouts: HashMap::new(),
+            max_output_bytes: 1024 * 1024,
+            history_output_bytes: 64 * 1024,
+            cpu_seconds: None,
+            memory_bytes: None,
+            open_files: None,
+        }
+    }
+}
+
+impl ExecutionLimits {
+    /// Wall-clock timeout for a program
+    ///
+    /// # Arguments
+    /// * `program` - Program as it will be executed
+    /// * `requested` - Timeout asked for by the caller, capped at `max_timeout_secs`
+    pub fn timeout_for(&self, program: &str, requested: Option<u64>) -> Duration {
+        let name = Path::new(program)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let secs = requested
+            .or_else(|| self.program_timeouts.get(&name).copied())
+            .unwrap_or(self.timeout_secs)
+            .clamp(1, self.max_timeout_secs.max(1));
+        Duration::from_secs(secs)
+    }
+
+    /// Wall-clock timeout for a background job
+    pub fn job_timeout_for(&self, requested: Option<u64>) -> Duration {
+        let limit = self.job_timeout_secs.max(1);
+        Duration::from_secs(requested.unwrap_or(limit).clamp(1, limit))
+    }
+}
+
+/// Captured result of a limited execution
+#[derive(Debug)]
+pub struct ExecutionOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+    /// The timeout expired and the process group was killed
+    pub timed_out: bool,
+    pub duration: Duration,
+}
+
+/// Output buffer that keeps the first `cap` bytes and counts the rest
+pub struct CappedBuffer {
+    data: Vec<u8>,
+    total: u64,
+    cap: usize,
+}
+
+impl CappedBuffer {
+    pub fn new(cap: usize) -> Self {
+        CappedBuffer { data: Vec::new(), total: 0, cap }
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.total += chunk.len() as u64;
+        let room = self.cap.saturating_sub(self.data.len());
+        self.data.extend_from_slice(&chunk[..chunk.len().min(room)]);
+    }
+
+    /// Lossy UTF-8 text with a marker when bytes were dropped
+    pub fn finish(&self) -> (String, bool) {
+        let mut text = String::from_utf8_lossy(&self.data).to_string();
+        let truncated = self.total > self.data.len() as u64;
+        if truncated {
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text.push_str(&format!(
+                "[output truncated: {} of {} bytes shown]\n",
+                self.data.len(),
+                self.total
+            ));
+        }
+        (text, truncated)
+    }
+}
+
+/// Shorten text to at most `cap` bytes of content, marking the cut
+///
+/// # Returns
+/// * `(String, bool)` - The text and whether anything was removed
+pub fn truncate_output(text: &str, cap: usize) -> (String, bool) {
+    let mut buffer = CappedBuffer::new(cap);
+    buffer.push(text.as_bytes());
+    buffer.finish()
+}
+
+/// Git settings that make read-only commands run programs
+///
+/// A repository's `.git/config` belongs to whoever created the repository,
+/// so these are cleared for every command through `GIT_CONFIG_*`, which
+/// also covers git run by wrappers and scripts.
+const GIT_CONFIG_OVERRIDES: &[&str] = &["core.fsmonitor", "diff.external"];
+
+/// Git subcommands that show diffs and accept `--no-ext-diff`
+const GIT_DIFF_COMMANDS: &[&str] = &["diff", "log", "show", "whatchanged"];
+
+/// Git options before the subcommand that take a separate value
+const GIT_VALUE_OPTIONS: &[&str] = &["-C", "-c", "--git-dir", "--work-tree", "--namespace", "--config-env"];
+
+/// Shell used for requests that opt into shell mode
+pub const SHELL: &str = "/bin/sh";
+
+/// A command that passed the policy, with everything needed to start it
+#[derive(Debug, Clone)]
+pub struct CommandSpec {
+    /// Program to execute
+    pub program: String,
+    pub args: Vec<String>,
+    /// Working directory
+    pub cwd: PathBuf,
+    pub env: CommandEnvironment,
+}
+
+/// Argument vector running `script` with the POSIX shell
+///
+/// The script is the only text the shell interprets; `args` become its
+/// positional parameters (`"$1"`, `"$@"`) and are never expanded again.
+pub fn shell_command(script: &str, args: &[String]) -> (String, Vec<String>) {
+    let mut argv = vec!["-c".to_string(), script.to_string(), "sh".to_string()];
+    argv.extend_from_slice(args);
+    (SHELL.to_string(), argv)
+}
+
+/// Position of the subcommand (`git -C repo log` -> 1)
+///
+/// Git's global options are known, so they are skipped together with the
+/// values of those that take one. Other programs' options may take values
+/// that cannot be told apart from a subcommand, so for them an option
+/// before the first word means there is no recognisable subcommand.
+pub fn subcommand_index(program: &str, args: &[String]) -> Option<usize> {
+    let git = Path::new(program).file_name().and_then(|name| name.to_str()) == Some("git");
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        if !arg.starts_with('-') {
+            return Some(i);
+        }
+        if !git {
+            return None;
+        }
+        i += if GIT_VALUE_OPTIONS.contains(&arg) { 2 } else { 1 };
+    }
+    None
+}
+
+/// Arguments a program is actually started with
+///
+/// Git diff commands get `--no-ext-diff --no-textconv` so diff drivers from
+/// the repository's configuration never run; a later `--ext-diff` from the
+/// caller still wins, which the policy only allows after confirmation.
+pub fn hardened_args(program: &str, args: &[String]) -> Vec<String> {
+    let mut args = args.to_vec();
+    if Path::new(program).file_name().and_then(|name| name.to_str()) != Some("git") {
+        return args;
+    }
+    if let Some(i) = subcommand_index(program, &args) {
+        if GIT_DIFF_COMMANDS.contains(&args[i].as_str()) {
+            args.splice(i + 1..i + 1, ["--no-ext-diff".to_string(), "--no-textconv".to_string()]);
+        }
+    }
+    args
+}
+
+/// Command for a spec with its environment, hardened arguments and working directory
+pub fn base_command(spec: &CommandSpec) -> TokioCommand {
+    let mut cmd = TokioCommand::new(&spec.program);
+    spec.env.apply(&mut cmd);
+    cmd.env("GIT_CONFIG_COUNT", GIT_CONFIG_OVERRIDES.len().to_string());
+    for (i, key) in GIT_CONFIG_OVERRIDES.iter().enumerate() {
+        cmd.env(format!("GIT_CONFIG_KEY_{}", i), key).env(format!("GIT_CONFIG_VALUE_{}", i), "");
+    }
+    cmd.args(hardened_args(&spec.program, &spec.args)).current_dir(&spec.cwd);
+    cmd
+}
+
+/// Run a program with limits and capture its output
+///
+/// The program runs in its own process group with stdin closed. When the
+/// timeout expires the group receives SIGTERM, then SIGKILL after a short
+/// grace period.
+///
+/// # Arguments
+/// * `spec` - Program, arguments, working directory and environment
+/// * `timeout` - Wall-clock limit
+/// * `limits` - Output caps and rlimits
+///
+/// # Returns
+/// * `Ok(ExecutionOutput)` - The program ran (possibly until killed)
+/// * `Err(std::io::Error)` - The program could not be started
+pub async fn run_limited(
+    spec: &CommandSpec,
+    timeout: Duration,
+    limits: &ExecutionLimits,
+) -> std::io::Result<ExecutionOutput> {
+    let mut cmd = limited_command(spec, limits);
+    cmd.stdin(Stdio::null());
+
+    let start = Instant::now();
+    let mut child = cmd.spawn()?;
+    let pid = child.id();
+
+    let stdout = Arc::new(Mutex::new(CappedBuffer::new(limits.max_output_bytes)));
+    let stderr = Arc::new(Mutex::new(CappedBuffer::new(limits.max_output_bytes)));
+    let stdout_reader = child.stdout.take().map(|pipe| tokio::spawn(drain(pipe, stdout.clone())));
+    let stderr_reader = child.stderr.take().map(|pipe| tokio::spawn(drain(pipe, stderr.clone())));
+
+    let (status, timed_out) = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => (status?, false),
+        Err(_) => (terminate(&mut child, pid).await?, true),
+    };
+    let duration = start.elapsed();
+
+    for reader in [stdout_reader, stderr_reader].into_iter().flatten() {
+        let abort = reader.abort_handle();
+        if tokio::time::timeout(DRAIN_GRACE, reader).await.is_err() {
+            abort.abort();
+        }
+    }
+
+    let (stdout, stdout_truncated) = stdout.lock().unwrap().finish();
+    let (stderr, stderr_truncated) = stderr.lock().unwrap().finish();
+    Ok(ExecutionOutput {
+        status,
+        stdout,
+        stderr,
+        stdout_truncated,
+        stderr_truncated,
+        timed_out,
+        duration,
+    })
+}
+
+/// Build a command in its own process group with piped output and rlimits
+///
+/// Stdin is left for the caller to configure.
+pub fn limited_command(spec: &CommandSpec, limits: &ExecutionLimits) -> TokioCommand {
+    let mut cmd = base_command(spec);
+    cmd.stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .kill_on_drop(true);
+    apply_rlimits(&mut cmd, limits);
+    cmd
+}
+
+/// Start a program in a new session, detached from the server
+///
+/// Used for launching applications: standard streams go to `/dev/null`,
+/// no timeout or rlimits apply and the program keeps running when the
+/// server stops. The child is reaped in the background.
+///
+/// # Returns
+/// * `Ok(u32)` - Process ID of the started program
+/// * `Err(std::io::Error)` - The program could not be started
+pub fn spawn_detached(spec: &CommandSpec) -> std::io::Result<u32> {
+    let mut cmd = base_command(spec);
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // SAFETY: setsid(2) is async-signal-safe
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = cmd.spawn()?;
+    let pid = child.id().unwrap_or_default();
+    tokio::spawn(async move {
+        let _ = child.wait().await;
+    });
+    Ok(pid)
+}
+
+/// Read a pipe to the end into a capped buffer
+async fn drain<R: AsyncRead + Unpin>(mut pipe: R, buffer: Arc<Mutex<CappedBuffer>>) {
+    let mut chunk = [0u8; 8192];
+    loop {
+        match pipe.read(&mut chunk).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => buffer.lock().unwrap().push(&chunk[..n]),
+        }
+    }
+}
+
+/// Stop a process group, escalating from SIGTERM to SIGKILL
+pub async fn terminate(child: &mut Child, pid: Option<u32>) -> std::io::Result<ExitStatus> {
+    let Some(pid) = pid else {
+        return child.wait().await;
+    };
+    signal_group(pid, libc::SIGTERM);
+    let status = match tokio::time::timeout(KILL_GRACE, child.wait()).await {
+        Ok(status) => status?,
+        Err(_) => {
+            signal_group(pid, libc::SIGKILL);
+            child.wait().await?
+        }
+    };
+    // Children that ignored SIGTERM may outlive the leader
+    signal_group(pid, libc::SIGKILL);
+    Ok(status)
+}
+
+/// Send a signal to a process group, ignoring groups that have already exited
+pub fn signal_group(pgid: u32, signal: libc::c_int) {
+    // SAFETY: kill(2) with a negative pid only sends a signal to that process group
+    let result = unsafe { libc::kill(-(pgid as libc::pid_t), signal) };
+    if result != 0 {
+        let error = std::io::Error::last_os_error();
+        if error.raw_os_error() != Some(libc::ESRCH) {
+            warn!("Failed to signal process group {}: {}", pgid, error);
+        }
+    }
+}
+
+/// Apply the configured rlimits in the child before exec
+#[cfg(target_os = "linux")]
+pub fn apply_rlimits(cmd: &mut TokioCommand, limits: &ExecutionLimits) {
+    let rlimits: Vec<(libc::c_int, u64)> = [
+        (libc::RLIMIT_CPU as libc::c_int, limits.cpu_seconds),
+        (libc::RLIMIT_AS as libc::c_int, limits.memory_bytes),
+        (libc::RLIMIT_NOFILE as libc::c_int, limits.open_files),
+    ]
+    .into_iter()
+    .filter_map(|(resource, value)| value.map(|v| (resource, v)))
+    .collect();
+
+    if rlimits.is_empty() {
+        return;
+    }
+
+    // SAFETY: the closure runs between fork and exec and only calls
+    // setrlimit(2), which is async-signal-safe, on pre-built data
+    unsafe {
+        cmd.pre_exec(move || {
+            for &(resource, value) in &rlimits {
+                let limit = libc::rlimit { rlim_cur: value as libc::rlim_t, rlim_max: value as libc::rlim_t };
+                if libc::setrlimit(resource as _, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply_rlimits(_cmd: &mut TokioCommand, _limits: &ExecutionLimits) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn shell(script: &str) -> CommandSpec {
+        let (program, args) = shell_command(&format!("PATH=/usr/bin:/bin; {}", script), &[]);
+        CommandSpec { program, args, cwd: std::env::temp_dir(), env: Default::default() }
+    }
+
+    /// Whether a process exists and is not a zombie
+    fn is_alive(pid: i32) -> bool {
+        match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            Ok(stat) => stat.rsplit_once(") ").is_some_and(|(_, rest)| !rest.starts_with('Z')),
+            Err(_) => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn output_and_status_are_captured() {
+        let output = run_limited(&shell("echo out; echo err >&2; exit 3"), Duration::from_secs(10), &ExecutionLimits::default())
+            .await
+            .unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+        assert!(!output.timed_out && !output.stdout_truncated && !output.stderr_truncated);
+    }
+
+    #[tokio::test]
+    async fn slow_programs_time_out() {
+        let output = run_limited(&shell("sleep 30"), Duration::from_millis(300), &ExecutionLimits::default())
+            .await
+            .unwrap();
+        assert!(output.timed_out);
+        assert!(!output.status.success());
+        assert!(output.duration < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn timeouts_kill_the_whole_process_group() {
+        let output = run_limited(&shell("sleep 30 & echo $!; wait"), Duration::from_millis(500), &ExecutionLimits::default())
+            .await
+            .unwrap();
+        assert!(output.timed_out);
+        let child: i32 = output.stdout.trim().parse().unwrap();
+        for _ in 0..50 {
+            if !is_alive(child) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("background child {} survived the timeout", child);
+    }
+
+    #[tokio::test]
+    async fn long_output_is_truncated_with_a_marker() {
+        let limits = ExecutionLimits { max_output_bytes: 1000, ..Default::default() };
+        let output = run_limited(&shell("yes | head -c 100000"), Duration::from_secs(10), &limits).await.unwrap();
+        assert!(output.status.success());
+        assert!(output.stdout_truncated);
+        assert!(!output.stderr_truncated);
+        let (kept, marker) = output.stdout.split_at(1000);
+        assert_eq!(kept, "y\n".repeat(500));
+        assert_eq!(marker, "[output truncated: 1000 of 100000 bytes shown]\n");
+    }
+
+    #[test]
+    fn git_diff_commands_never_run_external_diffs() {
+        assert_eq!(hardened_args("git", &strings(&["diff", "HEAD"])), strings(&["diff", "--no-ext-diff", "--no-textconv", "HEAD"]));
+        assert_eq!(
+            hardened_args("/usr/bin/git", &strings(&["-C", "repo", "--no-pager", "log", "-p"])),
+            strings(&["-C", "repo", "--no-pager", "log", "--no-ext-diff", "--no-textconv", "-p"])
+        );
+        assert_eq!(hardened_args("git", &strings(&["-c", "x=y", "status"])), strings(&["-c", "x=y", "status"]));
+        assert_eq!(hardened_args("ls", &strings(&["diff"])), strings(&["diff"]));
+    }
+}
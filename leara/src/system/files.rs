@@ -0,0 +1,633 @@
+/*
+ * Leara AI Assistant - File Access
+ *
+ * This module lets the file API browse, read and write files without
+ * leaving the directories the user configured. Paths are resolved against
+ * those roots with `..` and symlinks taken into account, protected paths
+ * like ~/.ssh are refused, and text is paged by byte ranges so large
+ * files can be read in pieces.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/files.rs
+ * Purpose: Confined file browsing, reading and writing
+ */
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::digest::DynDigest;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use crate::models::system::{FileChecksum, FileContent, FileEntry, FileKind};
+use crate::system::policy::{expand_home, normalize_lexically, PathPattern};
+
+/// Digest algorithms checksums can be computed with
+pub const CHECKSUM_ALGORITHMS: &[&str] = &["md5", "sha1", "sha256", "sha512"];
+
+/// Bytes looked at to detect a file's type
+const SNIFF_BYTES: usize = 512;
+
+/// File settings, configured in the `[files]` section of the policy file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilesConfig {
+    /// Directories the file API may access, `~` for the home directory
+    pub roots: Vec<String>,
+    /// Paths inside the roots that are never listed, read or changed,
+    /// with the same syntax as rule paths
+    pub deny: Vec<String>,
+    /// Largest page of a text file returned at once
+    pub max_read_bytes: u64,
+    /// Largest content accepted for a write
+    pub max_write_bytes: u64,
+    /// Ask before deleting, moving or overwriting even if the command
+    /// policy would allow it
+    pub confirm_changes: bool,
+}
+
+impl Default for FilesConfig {
+    fn default() -> Self {
+        FilesConfig {
+            roots: vec!["~".to_string()],
+            deny: ["~/.ssh/**", "~/.gnupg/**", "~/.password-store/**", "~/.aws/**", "~/.kube/**"]
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            max_read_bytes: 1024 * 1024,
+            max_write_bytes: 10 * 1024 * 1024,
+            confirm_changes: true,
+        }
+    }
+}
+
+/// The configured roots and protected paths, ready to check paths against
+#[derive(Debug)]
+pub struct FileAccess {
+    /// Roots as configured and with symlinks resolved
+    roots: Vec<PathBuf>,
+    deny: Vec<PathPattern>,
+    home: Option<PathBuf>,
+}
+
+impl FileAccess {
+    /// Resolve the configured roots for the current user
+    pub fn new(config: &FilesConfig) -> Self {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        Self::with_home(config, home.as_deref())
+    }
+
+    pub fn with_home(config: &FilesConfig, home: Option<&Path>) -> Self {
+        let mut roots = Vec::new();
+        for root in &config.roots {
+            let root = normalize_lexically(&expand_home(root, home));
+            // Roots that do not exist cannot contain anything
+            if let Ok(canonical) = fs::canonicalize(&root) {
+                if !roots.contains(&canonical) {
+                    roots.push(canonical);
+                }
+                if !roots.contains(&root) {
+                    roots.push(root);
+                }
+            }
+        }
+        let mut deny = Vec::new();
+        for pattern in &config.deny {
+            deny.push(PathPattern::parse(pattern, home));
+            // Also match the pattern with symlinks in its base resolved
+            let (base, suffix) = match pattern.strip_suffix("/**") {
+                Some(base) => (base, "/**"),
+                None => (pattern.as_str(), ""),
+            };
+            if let Ok(canonical) = fs::canonicalize(expand_home(base, home)) {
+                deny.push(PathPattern::parse(&format!("{}{}", canonical.display(), suffix), home));
+            }
+        }
+        FileAccess { roots, deny, home: home.map(Path::to_path_buf) }
+    }
+
+    /// Allowed root directories
+    pub fn roots(&self) -> Vec<PathBuf> {
+        // Only the resolved form of each root
+        self.roots.iter().filter(|root| fs::canonicalize(root).ok().as_ref() == Some(*root)).cloned().collect()
+    }
+
+    /// Whether a path is one of the roots themselves
+    pub fn is_root(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| root == path)
+    }
+
+    /// Whether a path matches the `deny` list
+    pub fn is_protected(&self, path: &Path) -> bool {
+        self.deny.iter().any(|pattern| pattern.matches(path))
+    }
+
+    /// Whether a path is inside a root and not protected
+    pub fn permits(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(root)) && !self.deny.iter().any(|pattern| pattern.matches(path))
+    }
+
+    /// Turn a requested path into an absolute path inside the roots
+    ///
+    /// Relative paths are taken relative to the first root. `..` and
+    /// symlinks in the directories leading to the path are resolved; a
+    /// symlink at the end is kept as is, so the link itself is what gets
+    /// renamed or deleted. Paths that do not exist yet are allowed.
+    ///
+    /// # Arguments
+    /// * `path` - Path as given by the client
+    ///
+    /// # Returns
+    /// * `Ok(PathBuf)` - The resolved path
+    /// * `Err(String)` - The path is empty, outside the roots or protected
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        if path.trim().is_empty() {
+            return Err("Path cannot be empty".to_string());
+        }
+        let first_root = self.roots.first().ok_or_else(|| "No file roots are configured".to_string())?;
+        let expanded = expand_home(path, self.home.as_deref());
+        let absolute = if expanded.is_absolute() { expanded } else { first_root.join(expanded) };
+        let lexical = normalize_lexically(&absolute);
+
+        let resolved = match fs::symlink_metadata(&lexical) {
+            Ok(metadata) if !metadata.file_type().is_symlink() => fs::canonicalize(&lexical).unwrap_or(lexical.clone()),
+            _ => match (lexical.parent(), lexical.file_name()) {
+                (Some(parent), Some(name)) => resolve_existing(parent).join(name),
+                _ => lexical.clone(),
+            },
+        };
+
+        if !self.roots.iter().any(|root| resolved.starts_with(root)) {
+            return Err(format!("{} is outside the allowed directories", path));
+        }
+        if self.deny.iter().any(|pattern| pattern.matches(&resolved) || pattern.matches(&lexical)) {
+            return Err(format!("{} is protected", path));
+        }
+        Ok(resolved)
+    }
+
+    /// Like `resolve`, but for operations that follow a final symlink
+    ///
+    /// The link's target has to be inside the roots too.
+    pub fn resolve_target(&self, path: &str) -> Result<PathBuf, String> {
+        let resolved = self.resolve(path)?;
+        match fs::canonicalize(&resolved) {
+            Ok(target) if target != resolved && !self.permits(&target) => {
+                Err(format!("{} links outside the allowed directories", path))
+            }
+            _ => Ok(resolved),
+        }
+    }
+}
+
+/// Canonicalize the longest existing prefix of a path and append the rest
+fn resolve_existing(path: &Path) -> PathBuf {
+    let mut missing = Vec::new();
+    let mut current = path.to_path_buf();
+    loop {
+        if let Ok(canonical) = fs::canonicalize(&current) {
+            return missing.iter().rev().fold(canonical, |path, part| path.join(part));
+        }
+        match (current.file_name().map(|name| name.to_os_string()), current.parent()) {
+            (Some(name), Some(parent)) => {
+                missing.push(name);
+                current = parent.to_path_buf();
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+/// Metadata of one path, without following a final symlink
+pub fn entry(path: &Path) -> io::Result<FileEntry> {
+    let metadata = fs::symlink_metadata(path)?;
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_symlink() {
+        FileKind::Symlink
+    } else if file_type.is_dir() {
+        FileKind::Directory
+    } else if file_type.is_file() {
+        FileKind::File
+    } else {
+        FileKind::Other
+    };
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string());
+    Ok(FileEntry {
+        hidden: name.starts_with('.'),
+        mime: (kind == FileKind::File).then(|| guess_mime(path).to_string()),
+        link_target: file_type
+            .is_symlink()
+            .then(|| fs::read_link(path).ok())
+            .flatten()
+            .map(|target| target.display().to_string()),
+        name,
+        path: path.display().to_string(),
+        kind,
+        size: metadata.len(),
+        modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+        permissions: format!("{:o}", metadata.mode() & 0o7777),
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+    })
+}
+
+/// Entries of a directory, directories first and then by name
+///
+/// Protected entries are left out. Entries that vanish while listing are
+/// skipped.
+///
+/// # Arguments
+/// * `path` - Directory to list
+/// * `hidden` - Include names starting with a dot
+/// * `access` - Roots and protected paths
+pub fn list_directory(path: &Path, hidden: bool, access: &FileAccess) -> io::Result<Vec<FileEntry>> {
+    let mut entries: Vec<FileEntry> = fs::read_dir(path)?
+        .filter_map(Result::ok)
+        .map(|dir_entry| dir_entry.path())
+        .filter(|path| access.permits(path))
+        .filter_map(|path| entry(&path).ok())
+        .filter(|entry| hidden || !entry.hidden)
+        .collect();
+    entries.sort_by(|a, b| {
+        (b.kind == FileKind::Directory)
+            .cmp(&(a.kind == FileKind::Directory))
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+    Ok(entries)
+}
+
+/// MIME type from the file name alone
+fn guess_mime(path: &Path) -> &'static str {
+    mime_guess::from_path(path).first_raw().unwrap_or("application/octet-stream")
+}
+
+/// MIME types recognised by their first bytes
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (8, b"WEBP", "image/webp"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"\xfd7zXZ\x00", "application/x-xz"),
+    (0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (0, b"\x7fELF", "application/x-executable"),
+    (0, b"SQLite format 3\x00", "application/vnd.sqlite3"),
+    (4, b"ftyp", "video/mp4"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (0, b"ID3", "audio/mpeg"),
+];
+
+/// Whether bytes look like text: UTF-8 without NUL bytes
+///
+/// A character cut off at the end of the bytes does not count against them.
+pub fn is_text(bytes: &[u8]) -> bool {
+    if bytes.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// MIME type of a file from its first bytes and its name
+///
+/// Known signatures win over the name, except that a ZIP signature defers
+/// to names of ZIP-based formats like `.docx` or `.jar`.
+///
+/// # Arguments
+/// * `path` - File name used for the guess by extension
+/// * `head` - First bytes of the file
+pub fn detect_mime(path: &Path, head: &[u8]) -> String {
+    let by_name = mime_guess::from_path(path).first_raw();
+    let by_content = MAGIC
+        .iter()
+        .find(|(offset, magic, _)| head.get(*offset..offset + magic.len()) == Some(*magic))
+        .map(|(_, _, mime)| *mime);
+    match (by_content, by_name) {
+        (Some("application/zip"), Some(name)) => name.to_string(),
+        (Some(content), _) => content.to_string(),
+        (None, Some(name)) => name.to_string(),
+        (None, None) if head.is_empty() => "application/x-empty".to_string(),
+        (None, None) if is_text(head) => "text/plain".to_string(),
+        (None, None) => "application/octet-stream".to_string(),
+    }
+}
+
+/// Detect the MIME type of a file on disk
+pub fn sniff(path: &Path) -> io::Result<String> {
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    fs::File::open(path)?.take(SNIFF_BYTES as u64).read_to_end(&mut head)?;
+    Ok(detect_mime(path, &head))
+}
+
+/// Read part of a text file
+///
+/// The range is adjusted to whole UTF-8 characters: continuation bytes at
+/// the start are skipped and a character cut off at the end is left for
+/// the next page.
+///
+/// # Arguments
+/// * `path` - File to read
+/// * `offset` - Byte to start at
+/// * `length` - Most bytes to read
+///
+/// # Returns
+/// * `Ok(FileContent)` - The page and where the next one starts
+/// * `Err(io::Error)` - `InvalidData` if the file is not text
+pub fn read_text(path: &Path, offset: u64, length: u64) -> io::Result<FileContent> {
+    let mut file = fs::File::open(path)?;
+    let metadata = file.metadata()?;
+    if metadata.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Is a directory"));
+    }
+    let size = metadata.len();
+    let mut offset = offset.min(size);
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buffer = Vec::new();
+    file.take(length).read_to_end(&mut buffer)?;
+
+    let skip = buffer.iter().take(3).take_while(|byte| (**byte & 0xc0) == 0x80).count();
+    buffer.drain(..skip);
+    offset += skip as u64;
+    if !is_text(&buffer) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a text file"));
+    }
+    let valid = match std::str::from_utf8(&buffer) {
+        Ok(_) => buffer.len(),
+        Err(e) => e.valid_up_to(),
+    };
+    buffer.truncate(valid);
+    let end = offset + buffer.len() as u64;
+
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    if offset == 0 {
+        head.extend_from_slice(&buffer[..buffer.len().min(SNIFF_BYTES)]);
+    }
+    Ok(FileContent {
+        path: path.display().to_string(),
+        content: String::from_utf8(buffer).unwrap_or_default(),
+        offset,
+        length: end - offset,
+        size,
+        next_offset: (end < size).then_some(end),
+        mime: if offset == 0 { detect_mime(path, &head) } else { sniff(path)? },
+    })
+}
+
+/// Look up a digest algorithm by name
+pub fn parse_algorithm(name: &str) -> Result<Box<dyn DynDigest>, String> {
+    match name.to_lowercase().as_str() {
+        "md5" => Ok(Box::new(md5::Md5::default())),
+        "sha1" => Ok(Box::new(sha1::Sha1::default())),
+        "sha256" => Ok(Box::new(sha2::Sha256::default())),
+        "sha512" => Ok(Box::new(sha2::Sha512::default())),
+        _ => Err(format!("Unknown algorithm {:?}; use one of {}", name, CHECKSUM_ALGORITHMS.join(", "))),
+    }
+}
+
+/// Digest of a file's content, read in chunks
+pub fn checksum(path: &Path, algorithm: &str) -> io::Result<FileChecksum> {
+    let mut hasher = parse_algorithm(algorithm).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut file = fs::File::open(path)?;
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut size = 0u64;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        size += read as u64;
+        hasher.update(&buffer[..read]);
+    }
+    let digest = hasher.finalize();
+    Ok(FileChecksum {
+        path: path.display().to_string(),
+        algorithm: algorithm.to_lowercase(),
+        checksum: digest.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        size,
+    })
+}
+
+/// Create a file that does not exist yet
+///
+/// # Arguments
+/// * `path` - File to create; fails with `AlreadyExists` if it is there
+/// * `content` - Bytes to write
+/// * `create_dirs` - Create missing parent directories
+pub fn create_file(path: &Path, content: &[u8], create_dirs: bool) -> io::Result<()> {
+    if create_dirs {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    let mut file = fs::OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+/// Directory holding content of overwrites that await confirmation
+///
+/// `$XDG_RUNTIME_DIR` belongs to the user alone. The shared temporary
+/// directory is only a fallback, where `stage` refuses a directory of the
+/// same name that someone else created.
+pub fn staging_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).filter(|dir| dir.is_absolute()) {
+        Some(runtime) => runtime.join("leara-staged"),
+        None => std::env::temp_dir().join(format!("leara-staged-{}", current_uid())),
+    }
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: getuid has no preconditions and cannot fail
+    unsafe { libc::getuid() }
+}
+
+/// Create a directory only the server's user can use, or check an existing one
+///
+/// Another user could create the directory first and then read or swap
+/// staged files, so an existing one must be a real directory owned by this
+/// user with mode 0700.
+fn private_dir(dir: &Path) -> io::Result<()> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != current_uid() || metadata.mode() & 0o777 != 0o700 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a private directory of this user", dir.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Keep content for an overwrite until it is confirmed
+///
+/// Staged files older than `max_age` are removed first; they belong to
+/// overwrites that were rejected or expired.
+///
+/// # Returns
+/// * `Ok(PathBuf)` - File holding the content, readable only by the server's user
+pub fn stage(dir: &Path, content: &[u8], max_age: Duration) -> io::Result<PathBuf> {
+    private_dir(dir)?;
+    if let Ok(entries) = fs::read_dir(dir) {
+        let now = SystemTime::now();
+        for stale in entries.filter_map(Result::ok) {
+            let expired = stale
+                .metadata()
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() > max_age);
+            if expired {
+                let _ = fs::remove_file(stale.path());
+            }
+        }
+    }
+    let path = dir.join(uuid::Uuid::new_v4().to_string());
+    let mut file = fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    Ok(path)
+}
+
+/// Whether a name is a single path component
+pub fn is_plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) && !name.contains('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempTree;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// File access confined to `root` below the tree, which is also the home directory
+    fn access(tree: &TempTree, root: &str) -> FileAccess {
+        let config = FilesConfig {
+            roots: vec![tree.join(root).display().to_string()],
+            deny: vec!["~/.ssh/**".to_string()],
+            ..Default::default()
+        };
+        FileAccess::with_home(&config, Some(&tree.join(root)))
+    }
+
+    #[test]
+    fn confines_paths_to_roots() {
+        let sandbox = TempTree::new();
+        sandbox.write("home/notes.txt", b"notes");
+        sandbox.write("home/.ssh/id_ed25519", b"key");
+        sandbox.write("outside/secret.txt", b"secret");
+        std::os::unix::fs::symlink(sandbox.join("outside"), sandbox.join("home/escape")).unwrap();
+        let access = access(&sandbox, "home");
+        let home = sandbox.join("home");
+
+        assert_eq!(access.resolve("notes.txt").unwrap(), home.join("notes.txt"));
+        assert_eq!(access.resolve(&format!("{}/new/file.txt", home.display())).unwrap(), home.join("new/file.txt"));
+        assert!(access.resolve("../outside/secret.txt").unwrap_err().contains("outside"));
+        assert!(access.resolve("sub/../../outside").is_err());
+        assert!(access.resolve("escape/secret.txt").is_err());
+        assert!(access.resolve(".ssh/id_ed25519").unwrap_err().contains("protected"));
+        assert!(access.resolve("").is_err());
+
+        // The link itself may be handled, but not followed
+        assert_eq!(access.resolve("escape").unwrap(), home.join("escape"));
+        assert!(access.resolve_target("escape").is_err());
+
+        let names: Vec<String> = list_directory(&home, true, &access).unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["escape", "notes.txt"]);
+        assert!(access.is_root(&home));
+    }
+
+    #[test]
+    fn detects_mime_types() {
+        assert_eq!(detect_mime(Path::new("image"), b"\x89PNG\r\n\x1a\n...."), "image/png");
+        assert_eq!(detect_mime(Path::new("photo.png"), b"%PDF-1.7"), "application/pdf");
+        assert_eq!(detect_mime(Path::new("report.docx"), b"PK\x03\x04"), "application/vnd.openxmlformats-officedocument.wordprocessingml.document");
+        assert_eq!(detect_mime(Path::new("archive"), b"PK\x03\x04"), "application/zip");
+        assert_eq!(detect_mime(Path::new("main.rs"), b"fn main() {}"), "text/x-rust");
+        assert_eq!(detect_mime(Path::new("README"), b"hello"), "text/plain");
+        assert_eq!(detect_mime(Path::new("blob"), b"\x00\x01\x02"), "application/octet-stream");
+        assert_eq!(detect_mime(Path::new("empty"), b""), "application/x-empty");
+    }
+
+    #[test]
+    fn pages_text_on_character_boundaries() {
+        let sandbox = TempTree::new();
+        // "é" is two bytes, so a page of 4 bytes cuts it in half
+        let path = sandbox.write("text.txt", "abcé fin".as_bytes());
+
+        let first = read_text(&path, 0, 4).unwrap();
+        assert_eq!(first.content, "abc");
+        assert_eq!(first.next_offset, Some(3));
+        assert_eq!(first.size, 9);
+
+        let second = read_text(&path, 3, 100).unwrap();
+        assert_eq!(second.content, "é fin");
+        assert_eq!(second.next_offset, None);
+
+        // Starting inside a character skips to the next one
+        let inside = read_text(&path, 4, 100).unwrap();
+        assert_eq!(inside.offset, 5);
+        assert_eq!(inside.content, " fin");
+
+        let binary = sandbox.write("binary", b"\x00\x01\x02");
+        assert_eq!(read_text(&binary, 0, 10).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn computes_checksums_and_stages_content() {
+        let sandbox = TempTree::new();
+        let path = sandbox.write("abc.txt", b"abc");
+        assert_eq!(
+            checksum(&path, "SHA256").unwrap().checksum,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(checksum(&path, "md5").unwrap().checksum, "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(checksum(&path, "sha1").unwrap().checksum, "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert!(checksum(&path, "sha512").unwrap().checksum.starts_with("ddaf35a193617abacc417349ae204131"));
+        assert!(parse_algorithm("crc32").is_err());
+
+        let staging = sandbox.join("staged");
+        let old = stage(&staging, b"old", Duration::from_secs(60)).unwrap();
+        let new = stage(&staging, b"new", Duration::ZERO).unwrap();
+        assert!(!old.exists());
+        assert_eq!(fs::read(&new).unwrap(), b"new");
+
+        // A directory others can write to, or a link to one, is refused
+        let shared = sandbox.join("shared");
+        fs::DirBuilder::new().mode(0o777).create(&shared).unwrap();
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o777)).unwrap();
+        assert_eq!(stage(&shared, b"x", Duration::ZERO).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        std::os::unix::fs::symlink(&staging, sandbox.join("link")).unwrap();
+        assert!(stage(&sandbox.join("link"), b"x", Duration::ZERO).is_err());
+
+        assert!(create_file(&path, b"again", false).is_err());
+        create_file(&sandbox.join("a/b/c.txt"), b"c", true).unwrap();
+        assert!(is_plain_name("c.txt"));
+        assert!(!is_plain_name("../c.txt") && !is_plain_name("a/b") && !is_plain_name(".."));
+    }
+}
@@ -0,0 +1,849 @@
+/*
+ * Leara AI Assistant - File Index
+ *
+ * This module keeps a searchable index of the directories the user picked:
+ * every file's path, size and modification time, and the text of files
+ * that are text, such as notes, Markdown, configuration and source code.
+ * Directories are scanned in the background, `.gitignore` rules and size
+ * limits decide what is indexed, and inotify keeps the index current
+ * between full rescans.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/indexer.rs
+ * Purpose: Background file indexing and full-text search
+ */
+
+use chrono::{DateTime, Utc};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use regex::Regex;
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+use crate::db::queries;
+use crate::models::system::{IndexRoot, IndexStatus, IndexedFile};
+use crate::system::files::{detect_mime, is_text, FileAccess};
+use crate::system::policy::PolicyStore;
+use crate::system::watch::{DirectoryWatcher, WatchEvent};
+
+/// Changes arriving within this time are handled together
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Files written to the database per transaction during a scan
+const SCAN_BATCH: usize = 500;
+
+/// Bytes looked at to detect a file's type
+const SNIFF_BYTES: usize = 512;
+
+/// File index settings
+#[derive(Debug, Clone)]
+pub struct IndexConfig {
+    /// Text is extracted only from files up to this size
+    pub max_file_bytes: u64,
+    /// Files recorded at most, over all directories
+    pub max_files: usize,
+    /// Time between full rescans, which catch changes inotify missed
+    pub rescan_interval: Duration,
+}
+
+impl IndexConfig {
+    /// Read `LEARA_INDEX_MAX_FILE_KB`, `LEARA_INDEX_MAX_FILES` and
+    /// `LEARA_INDEX_RESCAN_MINUTES`
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: u64| {
+            std::env::var(name).ok().and_then(|value| value.parse::<u64>().ok()).unwrap_or(default).max(1)
+        };
+        IndexConfig {
+            max_file_bytes: var("LEARA_INDEX_MAX_FILE_KB", 1024) * 1024,
+            max_files: var("LEARA_INDEX_MAX_FILES", 200_000) as usize,
+            rescan_interval: Duration::from_secs(var("LEARA_INDEX_RESCAN_MINUTES", 60) * 60),
+        }
+    }
+}
+
+/// Regular expression for a glob, without anchors
+///
+/// `*` and `?` stay within one path component, `**` spans components and
+/// `[...]` is a character class (`[!...]` negated).
+pub fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut regex = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let component_start = i == 0 || chars[i - 1] == '/';
+                if component_start && chars.get(i + 2) == Some(&'/') {
+                    // `**/` also matches no directories at all
+                    regex.push_str("(?:.*/)?");
+                    i += 3;
+                } else {
+                    regex.push_str(".*");
+                    i += 2;
+                }
+            }
+            '*' => {
+                regex.push_str("[^/]*");
+                i += 1;
+            }
+            '?' => {
+                regex.push_str("[^/]");
+                i += 1;
+            }
+            '[' => {
+                // A `]` right after the opening bracket belongs to the class
+                let close = (i + 2..chars.len()).find(|&j| chars[j] == ']');
+                match close {
+                    Some(close) => {
+                        regex.push('[');
+                        let mut class = &chars[i + 1..close];
+                        if let Some(('!' | '^', rest)) = class.split_first() {
+                            regex.push('^');
+                            class = rest;
+                        }
+                        for c in class {
+                            if matches!(c, '[' | '\\' | '&' | '~') {
+                                regex.push('\\');
+                            }
+                            regex.push(*c);
+                        }
+                        regex.push(']');
+                        i = close + 1;
+                    }
+                    None => {
+                        regex.push_str("\\[");
+                        i += 1;
+                    }
+                }
+            }
+            '\\' if i + 1 < chars.len() => {
+                regex.push_str(&regex::escape(&chars[i + 1].to_string()));
+                i += 2;
+            }
+            c => {
+                regex.push_str(&regex::escape(&c.to_string()));
+                i += 1;
+            }
+        }
+    }
+    regex
+}
+
+/// Matcher for file name searches
+///
+/// A glob without `/` is matched against the file name, one with `/`
+/// against the end of the path (`config/*.toml`), or the whole path if
+/// it starts with `/`.
+pub fn name_matcher(glob: &str) -> Result<Regex, String> {
+    let body = glob_to_regex(glob);
+    let pattern = if glob.starts_with('/') { format!("^{}$", body) } else { format!("(?:^|/){}$", body) };
+    Regex::new(&pattern).map_err(|e| format!("Invalid glob {:?}: {}", glob, e))
+}
+
+/// One line of a `.gitignore` file
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    /// Directory of the `.gitignore` file
+    base: PathBuf,
+    pattern: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl IgnoreRule {
+    fn parse(base: &Path, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        // A slash anywhere but at the end anchors the pattern to the .gitignore's directory
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+        let prefix = if anchored { "^" } else { "^(?:.*/)?" };
+        let pattern = Regex::new(&format!("{}{}$", prefix, glob_to_regex(line))).ok()?;
+        Some(IgnoreRule { base: base.to_path_buf(), pattern, negated, dir_only })
+    }
+}
+
+/// `.gitignore` rules in effect for a directory and its parents
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreRules {
+    /// Add the rules of `dir/.gitignore`, if there is one
+    pub fn load(&mut self, dir: &Path) {
+        if let Ok(content) = fs::read_to_string(dir.join(".gitignore")) {
+            self.add(dir, &content);
+        }
+    }
+
+    /// Add rules read from a `.gitignore` in `dir`
+    pub fn add(&mut self, dir: &Path, content: &str) {
+        self.rules.extend(content.lines().filter_map(|line| IgnoreRule::parse(dir, line)));
+    }
+
+    /// Whether a path is ignored; the last matching rule decides
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let Ok(relative) = path.strip_prefix(&rule.base) else {
+                continue;
+            };
+            if rule.pattern.is_match(&relative.to_string_lossy()) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+
+    /// Rules for a path below `root`, and whether the path or a directory above it is ignored
+    ///
+    /// The rules returned are those of `root` and the directories down to the path's parent.
+    pub fn for_path(root: &Path, path: &Path, is_dir: bool) -> (Self, bool) {
+        let mut rules = IgnoreRules::default();
+        let mut dir = root.to_path_buf();
+        rules.load(&dir);
+        let Ok(relative) = path.strip_prefix(root) else {
+            return (rules, false);
+        };
+        let components: Vec<_> = relative.components().collect();
+        for (i, component) in components.iter().enumerate() {
+            let child = dir.join(component);
+            let last = i + 1 == components.len();
+            if rules.is_ignored(&child, !last || is_dir) {
+                return (rules, true);
+            }
+            if !last {
+                rules.load(&child);
+            }
+            dir = child;
+        }
+        (rules, false)
+    }
+
+    fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.rules.truncate(len);
+    }
+}
+
+/// A file found while walking a directory
+#[derive(Debug, Clone)]
+pub struct FoundFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+/// Directories and files below `dir` that are not ignored
+///
+/// Symlinks are not followed, `.git` directories and paths the file
+/// access rules protect are skipped.
+///
+/// # Arguments
+/// * `dir` - Directory to walk
+/// * `rules` - `.gitignore` rules of the directories above `dir`
+/// * `access` - Roots and protected paths
+/// * `max_files` - Stop collecting files after this many
+pub fn walk(dir: &Path, rules: &mut IgnoreRules, access: &FileAccess, max_files: usize) -> (Vec<PathBuf>, Vec<FoundFile>) {
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    walk_dir(dir, rules, access, max_files, &mut dirs, &mut files);
+    (dirs, files)
+}
+
+fn walk_dir(
+    dir: &Path,
+    rules: &mut IgnoreRules,
+    access: &FileAccess,
+    max_files: usize,
+    dirs: &mut Vec<PathBuf>,
+    files: &mut Vec<FoundFile>,
+) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    dirs.push(dir.to_path_buf());
+    let inherited = rules.len();
+    rules.load(dir);
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_symlink() || !access.permits(&path) {
+            continue;
+        }
+        if file_type.is_dir() {
+            if !rules.is_ignored(&path, true) {
+                walk_dir(&path, rules, access, max_files, dirs, files);
+            }
+        } else if file_type.is_file() && files.len() < max_files && !rules.is_ignored(&path, false) {
+            if let Ok(metadata) = entry.metadata() {
+                files.push(FoundFile {
+                    path,
+                    size: metadata.len(),
+                    modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                });
+            }
+        }
+    }
+    rules.truncate(inherited);
+}
+
+/// MIME type of a file and its text, if it is text and small enough
+///
+/// # Arguments
+/// * `path` - File to read
+/// * `max_bytes` - Larger files are not read beyond their first bytes
+pub fn extract_text(path: &Path, max_bytes: u64) -> std::io::Result<(String, Option<String>)> {
+    let mut content = Vec::new();
+    fs::File::open(path)?.take(max_bytes + 1).read_to_end(&mut content)?;
+    let mime = detect_mime(path, &content[..content.len().min(SNIFF_BYTES)]);
+    if content.len() as u64 > max_bytes || !is_text(&content) {
+        return Ok((mime, None));
+    }
+    Ok((mime, Some(String::from_utf8_lossy(&content).into_owned())))
+}
+
+/// Turn what the user typed into an FTS5 query
+///
+/// Every word has to appear, `"quoted phrases"` have to appear together
+/// and a trailing `*` matches words starting with the rest. Operators in
+/// the text are taken literally.
+///
+/// # Returns
+/// * `Some(String)` - The query
+/// * `None` - The text has no words
+pub fn fts_query(text: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut term = String::new();
+        if c == '"' {
+            chars.next();
+            term.extend(chars.by_ref().take_while(|&c| c != '"'));
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                term.push(c);
+                chars.next();
+            }
+        }
+        let prefix = term.ends_with('*');
+        let word = term.trim_end_matches('*').replace('"', "");
+        if word.trim().is_empty() {
+            continue;
+        }
+        terms.push(format!("\"{}\"{}", word, if prefix { "*" } else { "" }));
+    }
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Keeps the file index of the chosen directories current
+pub struct FileIndexer {
+    db: Pool<SqliteConnectionManager>,
+    policy: Arc<PolicyStore>,
+    config: IndexConfig,
+    status: Mutex<IndexStatus>,
+    /// Wakes the background task for a full rescan
+    rescan: Notify,
+}
+
+impl FileIndexer {
+    /// Scan the indexed directories and watch them in the background
+    ///
+    /// Must be called inside the Tokio runtime.
+    pub fn start(config: IndexConfig, db: Pool<SqliteConnectionManager>, policy: Arc<PolicyStore>) -> Arc<Self> {
+        let indexer = Arc::new(FileIndexer {
+            db,
+            policy,
+            config,
+            status: Mutex::new(IndexStatus::default()),
+            rescan: Notify::new(),
+        });
+        tokio::spawn(run(indexer.clone()));
+        indexer
+    }
+
+    /// Ask for a full rescan, for example after directories were added
+    pub fn rescan(&self) {
+        self.rescan.notify_one();
+    }
+
+    /// What the indexer is doing, with the indexed directories and file counts
+    pub fn status(&self) -> anyhow::Result<IndexStatus> {
+        let conn = self.db.get()?;
+        let mut status = self.status.lock().unwrap().clone();
+        (status.files, status.files_with_content) = queries::count_indexed_files(&conn)?;
+        status.roots = queries::get_index_roots(&conn)?;
+        Ok(status)
+    }
+
+    /// The file API's roots and protected paths, which the index stays within
+    pub fn access(&self) -> FileAccess {
+        FileAccess::new(&self.policy.current().config().files)
+    }
+
+    /// Scan every indexed directory
+    ///
+    /// # Returns
+    /// * `Ok(Vec<PathBuf>)` - Directories to watch
+    fn scan(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let conn = self.db.get()?;
+        let access = self.access();
+        let mut budget = self.config.max_files;
+        let mut dirs = Vec::new();
+        for root in queries::get_index_roots(&conn)? {
+            dirs.extend(self.scan_root(&conn, &root, &access, &mut budget)?);
+        }
+        if budget == 0 {
+            warn!("File index is full at {} files; set LEARA_INDEX_MAX_FILES to index more", self.config.max_files);
+        }
+        Ok(dirs)
+    }
+
+    /// Bring the index of one directory up to date
+    ///
+    /// Files whose size and modification time are unchanged are not read
+    /// again; files that are gone are removed.
+    fn scan_root(&self, conn: &Connection, root: &IndexRoot, access: &FileAccess, budget: &mut usize) -> anyhow::Result<Vec<PathBuf>> {
+        let root_path = Path::new(&root.path);
+        // The file roots may have changed since the directory was added
+        if !access.permits(root_path) {
+            warn!("Not indexing {}: outside the file roots", root.path);
+            queries::delete_indexed_path(conn, &root.path)?;
+            return Ok(Vec::new());
+        }
+        let dirs = self.scan_dir(conn, root, root_path, IgnoreRules::default(), access, budget)?;
+        queries::set_index_root_scanned(conn, root.id, Utc::now())?;
+        Ok(dirs)
+    }
+
+    /// Bring the index of a directory below an indexed directory up to date
+    ///
+    /// # Arguments
+    /// * `root` - Indexed directory `dir` belongs to
+    /// * `dir` - Directory to scan
+    /// * `rules` - `.gitignore` rules of the directories above `dir`
+    ///
+    /// # Returns
+    /// * `Ok(Vec<PathBuf>)` - Directories to watch
+    fn scan_dir(
+        &self,
+        conn: &Connection,
+        root: &IndexRoot,
+        dir: &Path,
+        mut rules: IgnoreRules,
+        access: &FileAccess,
+        budget: &mut usize,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let (dirs, found) = walk(dir, &mut rules, access, *budget);
+        *budget -= found.len();
+
+        let below = format!("{}/", dir.to_string_lossy().trim_end_matches('/'));
+        let mut known = queries::get_indexed_file_stamps(conn, root.id)?;
+        known.retain(|path, _| path.starts_with(&below));
+        for batch in found.chunks(SCAN_BATCH) {
+            let tx = conn.unchecked_transaction()?;
+            for file in batch {
+                let path = file.path.to_string_lossy();
+                let modified = DateTime::<Utc>::from(file.modified).timestamp_millis();
+                match known.remove(path.as_ref()) {
+                    Some((_, size, stamp)) if size == file.size && stamp == modified => {}
+                    _ => self.index_file(&tx, root.id, file)?,
+                }
+            }
+            tx.commit()?;
+        }
+        // Whatever was not found any more is gone or ignored now
+        let tx = conn.unchecked_transaction()?;
+        for path in known.keys() {
+            queries::delete_indexed_path(&tx, path)?;
+        }
+        tx.commit()?;
+        Ok(dirs)
+    }
+
+    /// Record one file; files that cannot be read are left out
+    fn index_file(&self, conn: &Connection, root_id: i64, file: &FoundFile) -> rusqlite::Result<()> {
+        let Ok((mime, content)) = extract_text(&file.path, self.config.max_file_bytes) else {
+            return Ok(());
+        };
+        let indexed = IndexedFile {
+            id: 0,
+            root_id,
+            path: file.path.to_string_lossy().into_owned(),
+            name: file.path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+            size: file.size,
+            modified: DateTime::<Utc>::from(file.modified),
+            mime,
+            has_content: content.is_some(),
+            indexed_at: Utc::now(),
+        };
+        queries::upsert_indexed_file(conn, &indexed, content.as_deref())?;
+        Ok(())
+    }
+
+    /// Update the index for changes inotify reported
+    ///
+    /// # Returns
+    /// * `Ok(Vec<PathBuf>)` - New directories to watch
+    fn apply_changes(&self, events: Vec<WatchEvent>) -> anyhow::Result<Vec<PathBuf>> {
+        let conn = self.db.get()?;
+        let roots = queries::get_index_roots(&conn)?;
+        let access = self.access();
+        // Only the latest change of each path matters
+        let changes: BTreeMap<PathBuf, WatchEvent> = events.into_iter().map(|event| (event.path.clone(), event)).collect();
+        // Changed ignore rules can affect anything below their directory, which is rescanned
+        let mut rescans: Vec<PathBuf> = Vec::new();
+        for path in changes.keys().filter(|path| path.file_name().is_some_and(|name| name == ".gitignore")) {
+            let Some(dir) = path.parent() else {
+                continue;
+            };
+            // Sorted, so a directory comes before the directories below it
+            if !rescans.iter().any(|rescan| dir.starts_with(rescan)) {
+                rescans.push(dir.to_path_buf());
+            }
+        }
+        let mut new_dirs = Vec::new();
+
+        let tx = conn.unchecked_transaction()?;
+        for (path, event) in changes {
+            let Some(root) = roots.iter().find(|root| path.starts_with(&root.path)) else {
+                continue;
+            };
+            if rescans.iter().any(|dir| path.starts_with(dir)) {
+                continue;
+            }
+            let path_text = path.to_string_lossy().into_owned();
+            let metadata = match fs::symlink_metadata(&path) {
+                Ok(metadata) if !event.is_removal() => metadata,
+                _ => {
+                    queries::delete_indexed_path(&tx, &path_text)?;
+                    continue;
+                }
+            };
+            let (mut rules, ignored) = IgnoreRules::for_path(Path::new(&root.path), &path, metadata.is_dir());
+            if ignored || metadata.file_type().is_symlink() || !access.permits(&path) {
+                queries::delete_indexed_path(&tx, &path_text)?;
+                continue;
+            }
+            if metadata.is_dir() {
+                let (dirs, found) = walk(&path, &mut rules, &access, self.config.max_files);
+                for file in &found {
+                    self.index_file(&tx, root.id, file)?;
+                }
+                new_dirs.extend(dirs);
+            } else if metadata.is_file() {
+                let file = FoundFile {
+                    size: metadata.len(),
+                    modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                    path,
+                };
+                self.index_file(&tx, root.id, &file)?;
+            }
+        }
+        tx.commit()?;
+
+        for dir in rescans {
+            let Some(root) = roots.iter().find(|root| dir.starts_with(&root.path)) else {
+                continue;
+            };
+            let root_path = Path::new(&root.path);
+            let (rules, ignored) = if dir == root_path {
+                (IgnoreRules::default(), false)
+            } else {
+                IgnoreRules::for_path(root_path, &dir, true)
+            };
+            if ignored || !access.permits(&dir) {
+                queries::delete_indexed_path(&conn, &dir.to_string_lossy())?;
+                continue;
+            }
+            let mut budget = self.config.max_files;
+            new_dirs.extend(self.scan_dir(&conn, root, &dir, rules, &access, &mut budget)?);
+        }
+        Ok(new_dirs)
+    }
+}
+
+/// Wait for changes from a watcher, or forever without one
+async fn next_changes(watcher: &mut Option<DirectoryWatcher>) -> std::io::Result<Vec<WatchEvent>> {
+    match watcher {
+        Some(watcher) => {
+            let mut events = watcher.next_events().await?;
+            // Collect the rest of a burst, like a checkout or a build
+            tokio::time::sleep(DEBOUNCE).await;
+            while let Ok(more) = tokio::time::timeout(Duration::from_millis(1), watcher.next_events()).await {
+                events.extend(more?);
+            }
+            Ok(events)
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Scan, then follow changes until the next rescan is due or asked for
+async fn run(indexer: Arc<FileIndexer>) {
+    loop {
+        indexer.status.lock().unwrap().scanning = true;
+        let started = Instant::now();
+        let scanner = indexer.clone();
+        let scanned = tokio::task::spawn_blocking(move || scanner.scan()).await;
+        let dirs = {
+            let mut status = indexer.status.lock().unwrap();
+            status.scanning = false;
+            status.last_scan_at = Some(Utc::now());
+            status.last_scan_ms = Some(started.elapsed().as_millis() as u64);
+            match scanned {
+                Ok(Ok(dirs)) => {
+                    status.last_error = None;
+                    dirs
+                }
+                Ok(Err(e)) => {
+                    warn!("File index scan failed: {}", e);
+                    status.last_error = Some(e.to_string());
+                    Vec::new()
+                }
+                Err(e) => {
+                    status.last_error = Some(e.to_string());
+                    Vec::new()
+                }
+            }
+        };
+
+        let mut watcher = None;
+        if !dirs.is_empty() {
+            match DirectoryWatcher::new() {
+                Ok(mut created) => {
+                    let failed = dirs.iter().filter(|dir| created.watch(dir).is_err()).count();
+                    if failed > 0 {
+                        warn!("Could not watch {} indexed directories; they are updated by rescans only", failed);
+                    }
+                    watcher = Some(created);
+                }
+                Err(e) => warn!("Not watching indexed directories, rescanning periodically: {}", e),
+            }
+            info!("Indexed {} directories in {} ms", dirs.len(), started.elapsed().as_millis());
+        }
+        {
+            let mut status = indexer.status.lock().unwrap();
+            status.watched_directories = watcher.as_ref().map_or(0, |watcher| watcher.watched().count());
+            status.watching = status.watched_directories > 0;
+        }
+
+        let rescan_at = tokio::time::Instant::now() + indexer.config.rescan_interval;
+        loop {
+            tokio::select! {
+                _ = indexer.rescan.notified() => break,
+                _ = tokio::time::sleep_until(rescan_at) => break,
+                changes = next_changes(&mut watcher) => match changes {
+                    Ok(events) if events.iter().any(WatchEvent::is_overflow) => {
+                        warn!("Missed changes to indexed directories; rescanning");
+                        break;
+                    }
+                    Ok(events) => {
+                        let applier = indexer.clone();
+                        match tokio::task::spawn_blocking(move || applier.apply_changes(events)).await {
+                            Ok(Ok(new_dirs)) => {
+                                if let Some(watcher) = watcher.as_mut() {
+                                    for dir in new_dirs {
+                                        let _ = watcher.watch(&dir);
+                                    }
+                                }
+                            }
+                            Ok(Err(e)) => warn!("Failed to update the file index: {}", e),
+                            Err(e) => warn!("Failed to update the file index: {}", e),
+                        }
+                        let mut status = indexer.status.lock().unwrap();
+                        status.watched_directories = watcher.as_ref().map_or(0, |watcher| watcher.watched().count());
+                    }
+                    Err(e) => {
+                        warn!("Stopped watching indexed directories: {}", e);
+                        watcher = None;
+                        let mut status = indexer.status.lock().unwrap();
+                        status.watching = false;
+                        status.watched_directories = 0;
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TempTree, TestDatabase};
+    use crate::system::files::FilesConfig;
+
+    #[test]
+    fn globs_become_regexes() {
+        let matches = |glob: &str, path: &str| name_matcher(glob).unwrap().is_match(path);
+        assert!(matches("*.toml", "/home/u/project/Cargo.toml"));
+        assert!(!matches("*.toml", "/home/u/project/Cargo.lock"));
+        assert!(matches("config/*.yaml", "/srv/app/config/prod.yaml"));
+        assert!(!matches("config/*.yaml", "/srv/app/config/env/prod.yaml"));
+        assert!(matches("config/**/*.yaml", "/srv/app/config/env/prod.yaml"));
+        assert!(matches("config/**/*.yaml", "/srv/app/config/prod.yaml"));
+        assert!(matches("/srv/**", "/srv/app/x"));
+        assert!(matches("file[0-9].txt", "/a/file3.txt"));
+        assert!(!matches("file[!0-9].txt", "/a/file3.txt"));
+        assert!(matches("a?c", "/x/abc"));
+        assert!(!matches("a?c", "/x/a/c"));
+    }
+
+    #[test]
+    fn gitignore_rules_apply_in_order() {
+        let base = Path::new("/repo");
+        let mut rules = IgnoreRules::default();
+        rules.add(base, "# build output\ntarget/\n*.log\n!keep.log\n/secret.txt\ndocs/**/*.pdf\n");
+
+        assert!(rules.is_ignored(Path::new("/repo/target"), true));
+        assert!(!rules.is_ignored(Path::new("/repo/target"), false));
+        assert!(rules.is_ignored(Path::new("/repo/sub/target"), true));
+        assert!(rules.is_ignored(Path::new("/repo/a/debug.log"), false));
+        assert!(!rules.is_ignored(Path::new("/repo/a/keep.log"), false));
+        assert!(rules.is_ignored(Path::new("/repo/secret.txt"), false));
+        assert!(!rules.is_ignored(Path::new("/repo/sub/secret.txt"), false));
+        assert!(rules.is_ignored(Path::new("/repo/docs/a/b/manual.pdf"), false));
+        assert!(rules.is_ignored(Path::new("/repo/.git"), true));
+        assert!(!rules.is_ignored(Path::new("/repo/src/main.rs"), false));
+    }
+
+    #[test]
+    fn walks_without_ignored_files() {
+        let tree = TempTree::new();
+        tree.write(".gitignore", b"target/\n*.tmp\n");
+        tree.write("src/main.rs", b"fn main() {}");
+        tree.write("src/.gitignore", b"generated.rs\n");
+        tree.write("src/generated.rs", b"// generated");
+        tree.write("target/debug/app", b"\x7fELF");
+        tree.write("notes.tmp", b"scratch");
+        tree.write(".git/config", b"[core]");
+        tree.write("README.md", b"# Project");
+        std::os::unix::fs::symlink("/etc", tree.join("etc")).unwrap();
+
+        let config = FilesConfig { roots: vec![tree.path().display().to_string()], deny: Vec::new(), ..Default::default() };
+        let access = FileAccess::with_home(&config, None);
+        let (dirs, files) = walk(tree.path(), &mut IgnoreRules::default(), &access, 100);
+        let mut names: Vec<String> =
+            files.iter().map(|f| f.path.strip_prefix(tree.path()).unwrap().display().to_string()).collect();
+        names.sort();
+        assert_eq!(names, vec![".gitignore", "README.md", "src/.gitignore", "src/main.rs"]);
+        assert_eq!(dirs.len(), 2);
+
+        let (_, ignored) = IgnoreRules::for_path(tree.path(), &tree.join("src/generated.rs"), false);
+        assert!(ignored);
+        let (_, ignored) = IgnoreRules::for_path(tree.path(), &tree.join("target/debug/app"), false);
+        assert!(ignored);
+        let (_, ignored) = IgnoreRules::for_path(tree.path(), &tree.join("src/main.rs"), false);
+        assert!(!ignored);
+
+        let (_, files) = walk(tree.path(), &mut IgnoreRules::default(), &access, 1);
+        assert_eq!(files.len(), 1);
+    }
+
+    #[test]
+    fn changed_gitignore_files_rescan_their_directory() {
+        let tree = TempTree::new();
+        tree.write("repo/notes.txt", "notes");
+        tree.write("repo/sub/a.log", "log");
+        tree.write("repo/sub/b.txt", "text");
+        let policy = tree.write("policy.toml", format!("default_action = \"confirm\"\n[files]\nroots = [\"{}\"]\n", tree.join("repo").display()));
+        let db = TestDatabase::new();
+        let indexer = FileIndexer {
+            db: db.pool.clone(),
+            policy: Arc::new(PolicyStore::new(policy)),
+            config: IndexConfig::from_env(),
+            status: Mutex::new(IndexStatus::default()),
+            rescan: Notify::new(),
+        };
+        queries::insert_index_root(&db.conn(), &tree.join("repo").display().to_string()).unwrap();
+        indexer.scan().unwrap();
+        let indexed = || -> Vec<String> {
+            queries::get_indexed_files(&db.conn(), None)
+                .unwrap()
+                .into_iter()
+                .map(|file| file.path.strip_prefix(&tree.join("repo/").display().to_string()).unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(indexed(), vec!["notes.txt", "sub/a.log", "sub/b.txt"]);
+
+        let gitignore = tree.write("repo/sub/.gitignore", "*.log\n");
+        indexer.apply_changes(vec![WatchEvent { path: gitignore, mask: libc::IN_CLOSE_WRITE }]).unwrap();
+        assert_eq!(indexed(), vec!["notes.txt", "sub/.gitignore", "sub/b.txt"]);
+
+        let gitignore = tree.write("repo/.gitignore", "sub/\n");
+        indexer.apply_changes(vec![WatchEvent { path: gitignore, mask: libc::IN_CLOSE_WRITE }]).unwrap();
+        assert_eq!(indexed(), vec![".gitignore", "notes.txt"]);
+    }
+
+    #[test]
+    fn extracts_text_within_limits() {
+        let tree = TempTree::new();
+        tree.write("notes.md", b"# Notes\nport = 8080\n");
+        tree.write("image.png", b"\x89PNG\r\n\x1a\n\x00\x00");
+        tree.write("big.txt", [b'a'; 100]);
+
+        let (mime, text) = extract_text(&tree.join("notes.md"), 1024).unwrap();
+        assert_eq!(mime, "text/markdown");
+        assert_eq!(text.as_deref(), Some("# Notes\nport = 8080\n"));
+        assert_eq!(extract_text(&tree.join("image.png"), 1024).unwrap(), ("image/png".to_string(), None));
+        assert_eq!(extract_text(&tree.join("big.txt"), 99).unwrap().1, None);
+        assert!(extract_text(&tree.join("big.txt"), 100).unwrap().1.is_some());
+    }
+
+    #[test]
+    fn builds_fts_queries() {
+        assert_eq!(fts_query("database url").as_deref(), Some("\"database\" \"url\""));
+        assert_eq!(fts_query("\"listen port\" conf*").as_deref(), Some("\"listen port\" \"conf\"*"));
+        assert_eq!(fts_query("a\"b OR NOT").as_deref(), Some("\"ab\" \"OR\" \"NOT\""));
+        assert_eq!(fts_query("  * \"\" "), None);
+    }
+}
@@ -0,0 +1,585 @@
+/*
+ * Leara AI Assistant - Background Jobs
+ *
+ * This module runs long commands in the background. Each job keeps a
+ * bounded backlog of its output lines and broadcasts new lines to any
+ * number of listeners, so clients can disconnect and reattach while the
+ * command keeps running. Finished jobs are recorded in the command history.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/jobs.rs
+ * Purpose: Background command jobs with live output
+ */
+
+use chrono::{DateTime, Utc};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::ChildStdin;
+use tokio::sync::{broadcast, Notify};
+use tracing::{info, warn};
+use crate::api::system::{CommandOrigin, CommandRecord};
+use crate::system::executor::{limited_command, terminate, CommandSpec, ExecutionLimits};
+
+/// Number of finished jobs kept for inspection
+const FINISHED_JOBS_KEPT: usize = 50;
+
+/// Capacity of the live event channel per job
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Longest output line kept; longer lines are cut
+const MAX_LINE_BYTES: usize = 16 * 1024;
+
+/// Time a write to a job's stdin may take; programs that stop reading would block it forever
+const STDIN_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// State of a background job
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+    TimedOut,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            JobStatus::Running => "running",
+            JobStatus::Completed => "completed",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+            JobStatus::TimedOut => "timed_out",
+        }
+    }
+}
+
+/// One entry of a job's output stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobEvent {
+    /// Position in the job's stream, starting at 0
+    pub seq: u64,
+    #[serde(flatten)]
+    pub kind: JobEventKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobEventKind {
+    Stdout { line: String },
+    Stderr { line: String },
+    /// Always the last event of a job
+    Finished { status: JobStatus, exit_code: Option<i32> },
+}
+
+impl JobEventKind {
+    /// Event name used on the SSE stream
+    pub fn name(&self) -> &str {
+        match self {
+            JobEventKind::Stdout { .. } => "stdout",
+            JobEventKind::Stderr { .. } => "stderr",
+            JobEventKind::Finished { .. } => "finished",
+        }
+    }
+}
+
+/// Serializable summary of a job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobInfo {
+    pub id: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub working_dir: String,
+    pub status: JobStatus,
+    pub exit_code: Option<i32>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub user_confirmed: bool,
+    /// Command history entry written when the job finished
+    pub history_id: Option<i64>,
+    /// Output lines dropped from the backlog to stay within the output limit
+    pub dropped_lines: u64,
+}
+
+/// Mutable part of a job
+struct JobState {
+    info: JobInfo,
+    backlog: VecDeque<JobEvent>,
+    backlog_bytes: usize,
+    next_seq: u64,
+    stdout_dropped: bool,
+    stderr_dropped: bool,
+}
+
+/// A running or finished background job
+pub struct Job {
+    pub id: String,
+    state: Mutex<JobState>,
+    events: broadcast::Sender<JobEvent>,
+    stdin: tokio::sync::Mutex<Option<ChildStdin>>,
+    cancel: Notify,
+    max_backlog_bytes: usize,
+}
+
+impl Job {
+    /// Current summary
+    pub fn info(&self) -> JobInfo {
+        self.state.lock().unwrap().info.clone()
+    }
+
+    /// Subscribe to live events and get the backlog recorded so far
+    ///
+    /// Events in the backlog may also arrive on the receiver; callers skip
+    /// those by sequence number.
+    pub fn subscribe(&self) -> (broadcast::Receiver<JobEvent>, Vec<JobEvent>) {
+        // Subscribe under the lock so no event falls between backlog and receiver
+        let state = self.state.lock().unwrap();
+        (self.events.subscribe(), state.backlog.iter().cloned().collect())
+    }
+
+    /// Ask the job to stop; it is terminated by its runner task
+    pub fn cancel(&self) {
+        self.cancel.notify_one();
+    }
+
+    /// Write to the job's standard input
+    ///
+    /// # Arguments
+    /// * `data` - Bytes to write
+    /// * `close` - Close stdin afterwards so the program sees end-of-file
+    ///
+    /// # Returns
+    /// * `Ok(())` - Data written
+    /// * `Err(std::io::Error)` - Stdin is already closed, the program exited or did not read the data in time
+    pub async fn write_stdin(&self, data: &[u8], close: bool) -> std::io::Result<()> {
+        let write = async {
+            let mut stdin = self.stdin.lock().await;
+            let pipe = stdin
+                .as_mut()
+                .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "stdin is closed"))?;
+            pipe.write_all(data).await?;
+            pipe.flush().await?;
+            if close {
+                *stdin = None;
+            }
+            Ok(())
+        };
+        tokio::time::timeout(STDIN_WRITE_TIMEOUT, write).await.unwrap_or_else(|_| {
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "the program is not reading its input"))
+        })
+    }
+
+    /// Note that part of a stream was cut, so the history marks it truncated
+    fn mark_truncated(&self, is_stderr: bool) {
+        let mut state = self.state.lock().unwrap();
+        if is_stderr {
+            state.stderr_dropped = true;
+        } else {
+            state.stdout_dropped = true;
+        }
+    }
+
+    fn push(&self, kind: JobEventKind) {
+        let mut state = self.state.lock().unwrap();
+        let event = JobEvent { seq: state.next_seq, kind };
+        state.next_seq += 1;
+
+        let size = match &event.kind {
+            JobEventKind::Stdout { line } | JobEventKind::Stderr { line } => line.len(),
+            JobEventKind::Finished { .. } => 0,
+        };
+        state.backlog_bytes += size;
+        state.backlog.push_back(event.clone());
+        while state.backlog_bytes > self.max_backlog_bytes && state.backlog.len() > 1 {
+            match state.backlog.pop_front().map(|e| e.kind) {
+                Some(JobEventKind::Stdout { line }) => {
+                    state.backlog_bytes -= line.len();
+                    state.info.dropped_lines += 1;
+                    state.stdout_dropped = true;
+                }
+                Some(JobEventKind::Stderr { line }) => {
+                    state.backlog_bytes -= line.len();
+                    state.info.dropped_lines += 1;
+                    state.stderr_dropped = true;
+                }
+                _ => {}
+            }
+        }
+
+        // Nobody listening is fine
+        let _ = self.events.send(event);
+    }
+}
+
+/// Registry of background jobs
+pub struct JobManager {
+    db: Pool<SqliteConnectionManager>,
+    jobs: RwLock<HashMap<String, Arc<Job>>>,
+}
+
+impl JobManager {
+    pub fn new(db: Pool<SqliteConnectionManager>) -> Self {
+        JobManager { db, jobs: RwLock::new(HashMap::new()) }
+    }
+
+    /// Look up a job by ID
+    pub fn get(&self, id: &str) -> Option<Arc<Job>> {
+        self.jobs.read().unwrap().get(id).cloned()
+    }
+
+    /// All known jobs, newest first
+    pub fn list(&self) -> Vec<JobInfo> {
+        let mut jobs: Vec<JobInfo> = self.jobs.read().unwrap().values().map(|job| job.info()).collect();
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.started_at));
+        jobs
+    }
+
+    /// Start a command in the background
+    ///
+    /// The command must already have passed the execution policy.
+    ///
+    /// # Arguments
+    /// * `spec` - Program, arguments, working directory and environment
+    /// * `timeout` - Wall-clock limit after which the job is killed
+    /// * `limits` - Output cap for the backlog and rlimits
+    /// * `origin` - Who asked for the job, recorded in the history
+    ///
+    /// # Returns
+    /// * `Ok(Arc<Job>)` - The running job
+    /// * `Err(std::io::Error)` - The program could not be started
+    pub fn start(
+        &self,
+        spec: CommandSpec,
+        timeout: Duration,
+        limits: &ExecutionLimits,
+        origin: CommandOrigin,
+    ) -> std::io::Result<Arc<Job>> {
+        let mut cmd = limited_command(&spec, limits);
+        cmd.stdin(Stdio::piped());
+        let mut child = cmd.spawn()?;
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let job = Arc::new(Job {
+            id: id.clone(),
+            state: Mutex::new(JobState {
+                info: JobInfo {
+                    id: id.clone(),
+                    command: spec.program.clone(),
+                    args: spec.args.clone(),
+                    working_dir: spec.cwd.display().to_string(),
+                    status: JobStatus::Running,
+                    exit_code: None,
+                    started_at: Utc::now(),
+                    finished_at: None,
+                    user_confirmed: origin.user_confirmed,
+                    history_id: None,
+                    dropped_lines: 0,
+                },
+                backlog: VecDeque::new(),
+                backlog_bytes: 0,
+                next_seq: 0,
+                stdout_dropped: false,
+                stderr_dropped: false,
+            }),
+            events,
+            stdin: tokio::sync::Mutex::new(child.stdin.take()),
+            cancel: Notify::new(),
+            max_backlog_bytes: limits.max_output_bytes,
+        });
+
+        let stdout = child.stdout.take().map(|pipe| tokio::spawn(forward_lines(pipe, job.clone(), false)));
+        let stderr = child.stderr.take().map(|pipe| tokio::spawn(forward_lines(pipe, job.clone(), true)));
+
+        self.prune();
+        self.jobs.write().unwrap().insert(id.clone(), job.clone());
+        info!("Started job {}: {} {:?}", id, spec.program, spec.args);
+
+        // The runner owns the child, so the job outlives the request that started it
+        let runner_job = job.clone();
+        let db = self.db.clone();
+        let history_output_bytes = limits.history_output_bytes;
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let pid = child.id();
+            let outcome = tokio::select! {
+                status = child.wait() => status.map(|s| (s, None)),
+                _ = tokio::time::sleep(timeout) => terminate(&mut child, pid).await.map(|s| (s, Some(JobStatus::TimedOut))),
+                _ = runner_job.cancel.notified() => terminate(&mut child, pid).await.map(|s| (s, Some(JobStatus::Cancelled))),
+            };
+            let elapsed = start.elapsed();
+
+            // Give the readers a moment to forward the last lines
+            for reader in [stdout, stderr].into_iter().flatten() {
+                let abort = reader.abort_handle();
+                if tokio::time::timeout(Duration::from_millis(500), reader).await.is_err() {
+                    abort.abort();
+                }
+            }
+            *runner_job.stdin.lock().await = None;
+
+            let (status, exit_code) = match outcome {
+                Ok((exit, Some(forced))) => (forced, exit.code()),
+                Ok((exit, None)) if exit.success() => (JobStatus::Completed, exit.code()),
+                Ok((exit, None)) => (JobStatus::Failed, exit.code()),
+                Err(e) => {
+                    warn!("Failed to wait for job {}: {}", runner_job.id, e);
+                    (JobStatus::Failed, None)
+                }
+            };
+
+            let record = {
+                let mut state = runner_job.state.lock().unwrap();
+                state.info.status = status;
+                state.info.exit_code = exit_code;
+                state.info.finished_at = Some(Utc::now());
+
+                let (mut stdout, mut stderr) = (String::new(), String::new());
+                for event in &state.backlog {
+                    match &event.kind {
+                        JobEventKind::Stdout { line } => stdout.extend([line.as_str(), "\n"]),
+                        JobEventKind::Stderr { line } => stderr.extend([line.as_str(), "\n"]),
+                        JobEventKind::Finished { .. } => {}
+                    }
+                }
+                CommandRecord {
+                    success: status == JobStatus::Completed,
+                    exit_code: exit_code.unwrap_or(-1),
+                    execution_time_ms: elapsed.as_millis() as u64,
+                    stdout_truncated: state.stdout_dropped,
+                    stderr_truncated: state.stderr_dropped,
+                    timed_out: status == JobStatus::TimedOut,
+                    ..CommandRecord::new(&spec, origin)
+                }
+                .with_output(&stdout, &stderr, history_output_bytes)
+            };
+
+            let history_id = db
+                .get()
+                .ok()
+                .and_then(|conn| crate::db::queries::store_command_history(&conn, &record).ok());
+            runner_job.state.lock().unwrap().info.history_id = history_id;
+
+            runner_job.push(JobEventKind::Finished { status, exit_code });
+            info!("Job {} finished: {}", runner_job.id, status.as_str());
+        });
+
+        Ok(job)
+    }
+
+    /// Drop the oldest finished jobs beyond the retention limit
+    fn prune(&self) {
+        let mut jobs = self.jobs.write().unwrap();
+        let mut finished: Vec<(DateTime<Utc>, String)> = jobs
+            .values()
+            .map(|job| job.info())
+            .filter(|info| info.status != JobStatus::Running)
+            .map(|info| (info.started_at, info.id))
+            .collect();
+        if finished.len() < FINISHED_JOBS_KEPT {
+            return;
+        }
+        finished.sort();
+        for (_, id) in finished.iter().take(finished.len() + 1 - FINISHED_JOBS_KEPT) {
+            jobs.remove(id);
+        }
+    }
+}
+
+/// Forward a pipe line by line into the job's stream
+///
+/// At most `MAX_LINE_BYTES` of a line are buffered: a longer line is sent
+/// cut and the rest of it skipped, so output without newlines cannot grow
+/// the server's memory.
+async fn forward_lines<R: AsyncRead + Unpin>(pipe: R, job: Arc<Job>, is_stderr: bool) {
+    let send = |line: &mut Vec<u8>| {
+        let text = String::from_utf8_lossy(line).to_string();
+        line.clear();
+        job.push(if is_stderr { JobEventKind::Stderr { line: text } } else { JobEventKind::Stdout { line: text } });
+    };
+
+    let mut reader = BufReader::new(pipe);
+    let mut line = Vec::new();
+    // Skipping the rest of a line that was cut
+    let mut skipping = false;
+    loop {
+        let (consumed, newline, overflow) = match reader.fill_buf().await {
+            Ok([]) | Err(_) => break,
+            Ok(chunk) => {
+                let end = chunk.iter().position(|&b| b == b'\n');
+                let part = &chunk[..end.unwrap_or(chunk.len())];
+                let room = MAX_LINE_BYTES - line.len();
+                if !skipping {
+                    line.extend_from_slice(&part[..part.len().min(room)]);
+                }
+                (part.len() + end.is_some() as usize, end.is_some(), !skipping && part.len() > room)
+            }
+        };
+        reader.consume(consumed);
+
+        if overflow {
+            send(&mut line);
+            job.mark_truncated(is_stderr);
+            skipping = true;
+        }
+        if newline {
+            if !skipping {
+                send(&mut line);
+            }
+            skipping = false;
+        }
+    }
+    if !line.is_empty() {
+        send(&mut line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestDatabase;
+
+    fn shell(script: &str) -> CommandSpec {
+        CommandSpec {
+            program: "/bin/sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            cwd: std::env::temp_dir(),
+            env: Default::default(),
+        }
+    }
+
+    /// Output lines of a job and how it finished
+    async fn wait(job: &Job) -> (Vec<String>, JobStatus, Option<i32>) {
+        let (mut receiver, backlog) = job.subscribe();
+        let mut events = backlog;
+        if !matches!(events.last().map(|e| &e.kind), Some(JobEventKind::Finished { .. })) {
+            loop {
+                let event = tokio::time::timeout(Duration::from_secs(10), receiver.recv()).await.unwrap().unwrap();
+                let finished = matches!(event.kind, JobEventKind::Finished { .. });
+                events.push(event);
+                if finished {
+                    break;
+                }
+            }
+        }
+        let mut lines = Vec::new();
+        for event in events {
+            match event.kind {
+                JobEventKind::Stdout { line } => lines.push(line),
+                JobEventKind::Stderr { line } => lines.push(format!("err: {}", line)),
+                JobEventKind::Finished { status, exit_code } => return (lines, status, exit_code),
+            }
+        }
+        unreachable!()
+    }
+
+    #[tokio::test]
+    async fn finished_jobs_are_recorded() {
+        let db = TestDatabase::new();
+        let manager = JobManager::new(db.pool.clone());
+        let job = manager
+            .start(shell("echo one; echo two >&2; exit 3"), Duration::from_secs(10), &ExecutionLimits::default(), CommandOrigin::default())
+            .unwrap();
+
+        let (lines, status, exit_code) = wait(&job).await;
+        assert_eq!(status, JobStatus::Failed);
+        assert_eq!(exit_code, Some(3));
+        assert!(lines.contains(&"one".to_string()));
+        assert!(lines.contains(&"err: two".to_string()));
+
+        let info = job.info();
+        assert_eq!(info.status, JobStatus::Failed);
+        assert!(info.finished_at.is_some());
+        let history = crate::db::queries::get_command_history_entry(&db.conn(), info.history_id.unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(!history.success);
+        assert_eq!(history.exit_code, 3);
+    }
+
+    #[tokio::test]
+    async fn cancelled_jobs_stop() {
+        let db = TestDatabase::new();
+        let manager = JobManager::new(db.pool.clone());
+        let job = manager
+            .start(shell("sleep 30"), Duration::from_secs(60), &ExecutionLimits::default(), CommandOrigin::default())
+            .unwrap();
+
+        job.cancel();
+        let (_, status, _) = wait(&job).await;
+        assert_eq!(status, JobStatus::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn long_lines_are_cut() {
+        let db = TestDatabase::new();
+        let manager = JobManager::new(db.pool.clone());
+        let job = manager
+            .start(
+                shell("head -c 100000 /dev/zero | tr '\\0' a; echo; echo end"),
+                Duration::from_secs(10),
+                &ExecutionLimits::default(),
+                CommandOrigin::default(),
+            )
+            .unwrap();
+
+        let (lines, status, _) = wait(&job).await;
+        assert_eq!(status, JobStatus::Completed);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), MAX_LINE_BYTES);
+        assert_eq!(lines[1], "end");
+        let history = crate::db::queries::get_command_history_entry(&db.conn(), job.info().history_id.unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(history.stdout_truncated);
+    }
+
+    #[tokio::test]
+    async fn stdin_is_forwarded() {
+        let db = TestDatabase::new();
+        let manager = JobManager::new(db.pool.clone());
+        let job = manager
+            .start(shell("cat"), Duration::from_secs(10), &ExecutionLimits::default(), CommandOrigin::default())
+            .unwrap();
+
+        job.write_stdin(b"hello\n", true).await.unwrap();
+        let (lines, status, _) = wait(&job).await;
+        assert_eq!(status, JobStatus::Completed);
+        assert_eq!(lines, vec!["hello"]);
+        assert!(job.write_stdin(b"again\n", false).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stdin_writes_time_out_when_nothing_reads() {
+        let db = TestDatabase::new();
+        let manager = JobManager::new(db.pool.clone());
+        let job = manager
+            .start(shell("sleep 30"), Duration::from_secs(60), &ExecutionLimits::default(), CommandOrigin::default())
+            .unwrap();
+
+        // More than a pipe holds
+        let error = job.write_stdin(&vec![b'a'; 1 << 20], false).await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+        job.cancel();
+        let (_, status, _) = wait(&job).await;
+        assert_eq!(status, JobStatus::Cancelled);
+    }
+}
@@ -95,6 +95,7 @@ impl MemoryService {
             updated_at: Utc::now(),
             expires_at: None,
             is_active: true,
+            tags: self.extract_tags(&conn, value),
         };
 
         insert_enhanced_memory(&conn, &memory)?;
@@ -117,7 +118,7 @@ impl MemoryService {
         let conn = self.get_conn()?;
         let (title, description, priority, due_date) = self.parse_task_input(input);
         
-        let task = Task {
+        let mut task = Task {
             id: 0, // Will be auto-generated
             title,
             description,
@@ -128,10 +129,11 @@ impl MemoryService {
             updated_at: Utc::now(),
             completed_at: None,
             context: context.map(|s| s.to_string()),
-            tags: self.extract_tags(input),
+            tags: self.extract_tags(&conn, input),
+            recurrence: None,
         };
 
-        insert_task(&conn, &task)?;
+        task.id = insert_task(&conn, &task)?;
         info!("Created task: {} (priority: {}, due: {:?})", task.title, priority, due_date);
         Ok(task)
     }
@@ -162,6 +164,7 @@ impl MemoryService {
                 limit: Some(10),
                 offset: Some(0),
                 include_expired: Some(false),
+                ..Default::default()
             };
 
             if let Ok(response) = get_enhanced_memories(&conn, &memory_query) {
@@ -178,6 +181,7 @@ impl MemoryService {
                 limit: Some(5),
                 offset: Some(0),
                 include_expired: Some(false),
+                ..Default::default()
             };
 
             if let Ok(response) = get_enhanced_memories(&conn, &memory_query) {
@@ -214,6 +218,7 @@ impl MemoryService {
             limit: Some(50),
             offset: Some(0),
             include_completed: Some(false),
+            ..Default::default()
         };
 
         let response = get_tasks(&conn, &query)?;
@@ -470,29 +475,56 @@ impl MemoryService {
 
     /// Extract tags from text
     /// 
+    /// Picks up explicit `#hashtags`, any tag that already exists in the tags
+    /// table and appears as a word in the text, and a few built-in project tags.
+    /// 
     /// # Arguments
+    /// * `conn` - Database connection used to look up known tags
     /// * `text` - Text to extract tags from
     /// 
     /// # Returns
     /// * `Option<String>` - Comma-separated tags
-    fn extract_tags(&self, text: &str) -> Option<String> {
+    fn extract_tags(&self, conn: &Connection, text: &str) -> Option<String> {
         let text_lower = text.to_lowercase();
+        let words: Vec<&str> = text_lower
+            .split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_' || c == '#'))
+            .filter(|w| !w.is_empty())
+            .collect();
         let mut tags = Vec::new();
 
+        // Explicit hashtags
+        for word in &words {
+            if word.starts_with('#') {
+                if let Some(tag) = normalize_tag(word) {
+                    tags.push(tag);
+                }
+            }
+        }
+
+        // Tags the user has already created
+        if let Ok(known) = get_tags(conn) {
+            for tag in known.tags {
+                if words.iter().any(|w| w.trim_start_matches('#') == tag.name) {
+                    tags.push(tag.name);
+                }
+            }
+        }
+
         // Extract project-related tags
         if text_lower.contains("rust") || text_lower.contains("cargo") {
-            tags.push("rust");
+            tags.push("rust".to_string());
         }
         if text_lower.contains("system.rs") || text_lower.contains("system") {
-            tags.push("system");
+            tags.push("system".to_string());
         }
         if text_lower.contains("leara") {
-            tags.push("leara");
+            tags.push("leara".to_string());
         }
         if text_lower.contains("project") {
-            tags.push("project");
+            tags.push("project".to_string());
         }
 
+        let tags = parse_tag_list(&tags.join(","));
         if tags.is_empty() {
             None
         } else {
@@ -516,6 +548,7 @@ impl MemoryService {
             limit: Some(5),
             offset: Some(0),
             include_expired: Some(false),
+            ..Default::default()
         };
 
         if let Ok(response) = get_enhanced_memories(&conn, &memory_query) {
@@ -0,0 +1,468 @@
+/*
+ * Leara AI Assistant - Metrics History
+ *
+ * This module records the background system samples into SQLite so past
+ * resource usage can be looked up later. Raw samples are rolled up into
+ * one-minute and one-hour points as their periods complete, and each
+ * resolution is deleted once it is older than its retention.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/metrics.rs
+ * Purpose: Persisted, downsampled system metrics and range queries
+ */
+
+use chrono::{DateTime, Utc};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use crate::db::queries;
+use crate::models::system::{
+    HardwareSensors, MetricPoint, MetricResolution, MetricsResponse, ProcessInfo, ProcessUsage, ResourceUsage,
+};
+use crate::system::sampler::{ResourceSample, SystemSampler};
+use crate::system::sensors;
+
+/// Programs kept per point, by CPU and again by memory
+const TOP_PROCESSES: usize = 5;
+
+/// Most points a range query picks a resolution for
+const MAX_POINTS: i64 = 1000;
+
+const MINUTE: i64 = 60;
+const HOUR: i64 = 3600;
+const DAY: i64 = 24 * HOUR;
+
+/// How often samples are recorded and how long each resolution is kept
+#[derive(Debug, Clone)]
+pub struct MetricsConfig {
+    /// Seconds between recorded samples
+    pub interval_secs: i64,
+    /// Seconds raw samples are kept
+    pub raw_retention_secs: i64,
+    /// Seconds one-minute points are kept
+    pub minute_retention_secs: i64,
+    /// Seconds one-hour points are kept
+    pub hour_retention_secs: i64,
+}
+
+impl MetricsConfig {
+    /// Read `LEARA_METRICS_INTERVAL_SECS`, `LEARA_METRICS_RAW_HOURS`,
+    /// `LEARA_METRICS_MINUTE_DAYS` and `LEARA_METRICS_HOUR_DAYS`
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: i64| {
+            std::env::var(name).ok().and_then(|value| value.parse::<i64>().ok()).unwrap_or(default).max(1)
+        };
+        MetricsConfig {
+            interval_secs: var("LEARA_METRICS_INTERVAL_SECS", 10),
+            raw_retention_secs: var("LEARA_METRICS_RAW_HOURS", 24) * HOUR,
+            minute_retention_secs: var("LEARA_METRICS_MINUTE_DAYS", 7) * DAY,
+            hour_retention_secs: var("LEARA_METRICS_HOUR_DAYS", 90) * DAY,
+        }
+    }
+
+    /// Seconds covered by one point of a resolution
+    pub fn step_secs(&self, resolution: MetricResolution) -> i64 {
+        match resolution {
+            MetricResolution::Raw => self.interval_secs,
+            MetricResolution::Minute => MINUTE,
+            MetricResolution::Hour => HOUR,
+        }
+    }
+
+    fn retention_secs(&self, resolution: MetricResolution) -> i64 {
+        match resolution {
+            MetricResolution::Raw => self.raw_retention_secs,
+            MetricResolution::Minute => self.minute_retention_secs,
+            MetricResolution::Hour => self.hour_retention_secs,
+        }
+    }
+
+    /// Resolution to read a range from
+    ///
+    /// With a requested step this is the coarsest resolution still kept for
+    /// `since` that is at least as fine as the step, otherwise the finest kept
+    /// one that fits the range in `MAX_POINTS`.
+    pub fn resolution_for(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        step_secs: Option<i64>,
+        now: DateTime<Utc>,
+    ) -> MetricResolution {
+        let age = (now - since).num_seconds();
+        let kept: Vec<MetricResolution> = [MetricResolution::Raw, MetricResolution::Minute, MetricResolution::Hour]
+            .into_iter()
+            .filter(|resolution| *resolution == MetricResolution::Hour || age <= self.retention_secs(*resolution))
+            .collect();
+        let chosen = match step_secs {
+            Some(step) => kept.iter().rev().find(|resolution| self.step_secs(**resolution) <= step).or(kept.first()),
+            None => {
+                let span = (until - since).num_seconds().max(0);
+                kept.iter().find(|resolution| span / self.step_secs(**resolution) <= MAX_POINTS).or(kept.last())
+            }
+        };
+        chosen.copied().unwrap_or(MetricResolution::Hour)
+    }
+}
+
+/// Usage of every program in a sample, processes of the same name added up
+fn process_usage(processes: &[ProcessInfo]) -> Vec<ProcessUsage> {
+    let mut by_name: HashMap<&str, ProcessUsage> = HashMap::new();
+    for process in processes {
+        let usage = by_name.entry(process.name.as_str()).or_insert_with(|| ProcessUsage {
+            name: process.name.clone(),
+            cpu_usage: 0.0,
+            cpu_max: 0.0,
+            memory_usage: 0,
+        });
+        usage.cpu_usage += process.cpu_usage;
+        usage.cpu_max += process.cpu_usage;
+        usage.memory_usage += process.memory_usage;
+    }
+    by_name.into_values().collect()
+}
+
+/// The heaviest programs by CPU and by memory, by CPU first
+fn top_processes(mut usage: Vec<ProcessUsage>) -> Vec<ProcessUsage> {
+    usage.sort_by(|a, b| b.memory_usage.cmp(&a.memory_usage).then(a.name.cmp(&b.name)));
+    let by_memory: Vec<String> = usage.iter().take(TOP_PROCESSES).map(|usage| usage.name.clone()).collect();
+    usage.sort_by(|a, b| b.cpu_usage.total_cmp(&a.cpu_usage).then(a.name.cmp(&b.name)));
+    let mut kept = 0;
+    usage.retain(|usage| {
+        kept += 1;
+        kept <= TOP_PROCESSES || by_memory.contains(&usage.name)
+    });
+    usage
+}
+
+/// A raw point from one sample
+///
+/// # Arguments
+/// * `usage` - Resource usage of the sample
+/// * `sensors` - Sensor readings of the sample
+/// * `processes` - Processes of the sample
+/// * `timestamp` - When the sample was recorded
+pub fn sample_point(
+    usage: &ResourceUsage,
+    sensors: &HardwareSensors,
+    processes: &[ProcessInfo],
+    timestamp: DateTime<Utc>,
+) -> MetricPoint {
+    let temperature = sensors::hottest(sensors);
+    MetricPoint {
+        timestamp,
+        samples: 1,
+        cpu_usage: usage.cpu_usage,
+        cpu_max: usage.cpu_usage,
+        memory_used: usage.memory.used,
+        memory_max: usage.memory.used,
+        memory_total: usage.memory.total,
+        swap_used: usage.swap.used,
+        disk_used: usage.disk_usage,
+        load_average: usage.load_average.one,
+        network_rx_rate: usage.network_rx_rate,
+        network_tx_rate: usage.network_tx_rate,
+        temperature,
+        temperature_max: temperature,
+        battery_percent: sensors::battery_charge(sensors),
+        power_watts: sensors::power_draw(sensors),
+        top_processes: top_processes(process_usage(processes)),
+    }
+}
+
+/// Merge points, oldest first, into one point starting at `timestamp`
+///
+/// Averages are weighted by the samples behind each point. Programs missing
+/// from a point count as idle for its samples.
+pub fn merge(points: &[MetricPoint], timestamp: DateTime<Utc>) -> MetricPoint {
+    let samples: u32 = points.iter().map(|point| point.samples).sum();
+    let weight = samples.max(1) as f64;
+    let average = |value: fn(&MetricPoint) -> f64| {
+        points.iter().map(|point| value(point) * point.samples as f64).sum::<f64>() / weight
+    };
+    // Sensors may only have been read for some of the points
+    let sensor_average = |value: fn(&MetricPoint) -> Option<f32>| {
+        let (total, samples) = points
+            .iter()
+            .filter_map(|point| Some((value(point)? as f64 * point.samples as f64, point.samples as f64)))
+            .fold((0.0, 0.0), |(total, samples), (value, weight)| (total + value, samples + weight));
+        (samples > 0.0).then(|| (total / samples) as f32)
+    };
+
+    let mut processes: HashMap<&str, (f64, ProcessUsage)> = HashMap::new();
+    for point in points {
+        for usage in &point.top_processes {
+            let (cpu_total, merged) = processes.entry(usage.name.as_str()).or_insert_with(|| {
+                (0.0, ProcessUsage { name: usage.name.clone(), cpu_usage: 0.0, cpu_max: 0.0, memory_usage: 0 })
+            });
+            *cpu_total += usage.cpu_usage as f64 * point.samples as f64;
+            merged.cpu_max = merged.cpu_max.max(usage.cpu_max);
+            merged.memory_usage = merged.memory_usage.max(usage.memory_usage);
+        }
+    }
+    let processes = processes
+        .into_values()
+        .map(|(cpu_total, mut usage)| {
+            usage.cpu_usage = (cpu_total / weight) as f32;
+            usage
+        })
+        .collect();
+
+    let last = points.last();
+    MetricPoint {
+        timestamp,
+        samples,
+        cpu_usage: average(|point| point.cpu_usage as f64) as f32,
+        cpu_max: points.iter().map(|point| point.cpu_max).fold(0.0, f32::max),
+        memory_used: average(|point| point.memory_used as f64) as u64,
+        memory_max: points.iter().map(|point| point.memory_max).max().unwrap_or(0),
+        memory_total: last.map(|point| point.memory_total).unwrap_or(0),
+        swap_used: average(|point| point.swap_used as f64) as u64,
+        disk_used: last.map(|point| point.disk_used).unwrap_or(0),
+        load_average: average(|point| point.load_average),
+        network_rx_rate: average(|point| point.network_rx_rate),
+        network_tx_rate: average(|point| point.network_tx_rate),
+        temperature: sensor_average(|point| point.temperature),
+        temperature_max: points.iter().filter_map(|point| point.temperature_max).reduce(f32::max),
+        battery_percent: points.iter().rev().find_map(|point| point.battery_percent),
+        power_watts: sensor_average(|point| point.power_watts),
+        top_processes: top_processes(processes),
+    }
+}
+
+/// Merge points, oldest first, into periods of `step_secs` aligned to the Unix epoch
+pub fn downsample(points: &[MetricPoint], step_secs: i64) -> Vec<MetricPoint> {
+    let bucket = |point: &MetricPoint| {
+        let seconds = point.timestamp.timestamp();
+        seconds - seconds.rem_euclid(step_secs)
+    };
+    points
+        .chunk_by(|a, b| bucket(a) == bucket(b))
+        .map(|chunk| merge(chunk, DateTime::from_timestamp(bucket(&chunk[0]), 0).unwrap_or_default()))
+        .collect()
+}
+
+/// Roll the completed periods of `from` points up into `to` points
+fn roll_up(conn: &Connection, from: MetricResolution, to: MetricResolution, step_secs: i64, now: i64) -> rusqlite::Result<()> {
+    let start = queries::latest_metric_timestamp(conn, to)?.map(|latest| latest + step_secs).unwrap_or(0);
+    let cutoff = now - now.rem_euclid(step_secs);
+    if start >= cutoff {
+        return Ok(());
+    }
+    for point in downsample(&queries::get_metric_points(conn, from, start, cutoff)?, step_secs) {
+        queries::insert_metric_point(conn, to, &point)?;
+    }
+    Ok(())
+}
+
+/// Persisted resource usage history
+pub struct MetricsHistory {
+    config: MetricsConfig,
+    db: Pool<SqliteConnectionManager>,
+}
+
+impl MetricsHistory {
+    /// Record the sampler's latest sample at every interval in the background
+    ///
+    /// Must be called inside the Tokio runtime.
+    pub fn start(config: MetricsConfig, db: Pool<SqliteConnectionManager>, sampler: Arc<SystemSampler>) -> Arc<Self> {
+        let history = Arc::new(MetricsHistory { config, db });
+        info!(
+            "Recording system metrics every {}s, raw samples kept for {}h",
+            history.config.interval_secs,
+            history.config.raw_retention_secs / HOUR
+        );
+
+        let task_history = history.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(task_history.config.interval_secs as u64));
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let sample: Arc<ResourceSample> = sampler.latest();
+                let point = sample_point(&sample.usage, &sample.sensors, &sampler.processes(), Utc::now());
+                let history = task_history.clone();
+                let recorded = tokio::task::spawn_blocking(move || history.record(&point)).await;
+                if let Ok(Err(e)) = recorded {
+                    warn!("Failed to record system metrics: {}", e);
+                }
+            }
+        });
+        history
+    }
+
+    /// Store a raw point, roll up completed periods and drop expired points
+    fn record(&self, point: &MetricPoint) -> anyhow::Result<()> {
+        let conn = self.db.get()?;
+        let tx = conn.unchecked_transaction()?;
+        let now = point.timestamp.timestamp();
+        queries::insert_metric_point(&tx, MetricResolution::Raw, point)?;
+        roll_up(&tx, MetricResolution::Raw, MetricResolution::Minute, MINUTE, now)?;
+        roll_up(&tx, MetricResolution::Minute, MetricResolution::Hour, HOUR, now)?;
+        for resolution in [MetricResolution::Raw, MetricResolution::Minute, MetricResolution::Hour] {
+            queries::delete_metric_points_before(&tx, resolution, now - self.config.retention_secs(resolution))?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Resource usage between `since` and `until`
+    ///
+    /// # Arguments
+    /// * `since` / `until` - Time range
+    /// * `resolution` - Stored points to read; picked from the range if absent
+    /// * `step_secs` - Merge the points into periods this long, if coarser than the resolution
+    pub fn query(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        resolution: Option<MetricResolution>,
+        step_secs: Option<i64>,
+    ) -> anyhow::Result<MetricsResponse> {
+        let resolution = resolution.unwrap_or_else(|| self.config.resolution_for(since, until, step_secs, Utc::now()));
+        let conn = self.db.get()?;
+        let mut points = queries::get_metric_points(&conn, resolution, since.timestamp(), until.timestamp())?;
+        let mut step = self.config.step_secs(resolution);
+        if let Some(requested) = step_secs.filter(|requested| *requested > step) {
+            points = downsample(&points, requested);
+            step = requested;
+        }
+        let summary = points.first().map(|first| merge(&points, first.timestamp));
+        Ok(MetricsResponse { resolution, step_secs: step, since, until, points, summary })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(seconds, 0).unwrap()
+    }
+
+    fn usage(name: &str, cpu_usage: f32, memory_usage: u64) -> ProcessUsage {
+        ProcessUsage { name: name.to_string(), cpu_usage, cpu_max: cpu_usage, memory_usage }
+    }
+
+    fn point(seconds: i64, cpu_usage: f32, top_processes: Vec<ProcessUsage>) -> MetricPoint {
+        MetricPoint {
+            timestamp: at(seconds),
+            samples: 1,
+            cpu_usage,
+            cpu_max: cpu_usage,
+            memory_used: 1000 + seconds as u64,
+            memory_max: 1000 + seconds as u64,
+            memory_total: 8000,
+            swap_used: 0,
+            disk_used: seconds as u64,
+            load_average: 1.0,
+            network_rx_rate: 100.0,
+            network_tx_rate: 10.0,
+            temperature: None,
+            temperature_max: None,
+            battery_percent: None,
+            power_watts: None,
+            top_processes,
+        }
+    }
+
+    #[test]
+    fn merges_weighted_by_samples() {
+        let mut minute = point(0, 10.0, vec![usage("cargo", 80.0, 500)]);
+        minute.samples = 3;
+        let second = point(60, 50.0, vec![usage("firefox", 40.0, 900)]);
+        let merged = merge(&[minute, second], at(0));
+        assert_eq!(merged.samples, 4);
+        assert_eq!(merged.cpu_usage, 20.0);
+        assert_eq!(merged.cpu_max, 50.0);
+        assert_eq!(merged.memory_max, 1060);
+        assert_eq!(merged.disk_used, 60);
+        assert_eq!(merged.top_processes[0].name, "cargo");
+        assert_eq!(merged.top_processes[0].cpu_usage, 60.0);
+        assert_eq!(merged.top_processes[1].cpu_usage, 10.0);
+        assert_eq!(merged.top_processes[1].memory_usage, 900);
+        assert_eq!(merged.temperature, None);
+
+        let mut hot = point(0, 0.0, Vec::new());
+        hot.samples = 3;
+        (hot.temperature, hot.temperature_max, hot.battery_percent) = (Some(60.0), Some(70.0), Some(80.0));
+        let cool = point(60, 0.0, Vec::new());
+        let mut cooler = point(120, 0.0, Vec::new());
+        (cooler.temperature, cooler.temperature_max) = (Some(40.0), Some(40.0));
+        let merged = merge(&[hot, cool, cooler], at(0));
+        assert_eq!(merged.temperature, Some(55.0));
+        assert_eq!(merged.temperature_max, Some(70.0));
+        assert_eq!(merged.battery_percent, Some(80.0));
+        assert_eq!(merged.power_watts, None);
+    }
+
+    #[test]
+    fn downsamples_into_aligned_periods() {
+        let points: Vec<_> = [5, 30, 59, 60, 125].iter().map(|s| point(*s, *s as f32, Vec::new())).collect();
+        let minutes = downsample(&points, 60);
+        assert_eq!(minutes.iter().map(|p| p.timestamp.timestamp()).collect::<Vec<_>>(), vec![0, 60, 120]);
+        assert_eq!(minutes.iter().map(|p| p.samples).collect::<Vec<_>>(), vec![3, 1, 1]);
+        assert_eq!(minutes[0].cpu_max, 59.0);
+    }
+
+    #[test]
+    fn keeps_top_programs_by_cpu_and_memory() {
+        let processes: Vec<_> = (0..8)
+            .map(|i| ProcessInfo {
+                pid: i,
+                parent_pid: None,
+                name: if i < 2 { "chrome".to_string() } else { format!("p{}", i) },
+                command: Vec::new(),
+                user: None,
+                cpu_usage: i as f32,
+                memory_usage: if i == 2 { 10_000 } else { 10 },
+                virtual_memory: 0,
+                status: "Run".to_string(),
+                start_time: 0,
+                run_time: 0,
+            })
+            .collect();
+        let top = top_processes(process_usage(&processes));
+        let names: Vec<_> = top.iter().map(|usage| usage.name.as_str()).collect();
+        assert_eq!(names[..TOP_PROCESSES], ["p7", "p6", "p5", "p4", "p3"]);
+        assert!(names.contains(&"p2"));
+        assert_eq!(top.iter().find(|usage| usage.name == "chrome").unwrap().memory_usage, 20);
+    }
+
+    #[test]
+    fn picks_finest_kept_resolution() {
+        let config = MetricsConfig {
+            interval_secs: 10,
+            raw_retention_secs: DAY,
+            minute_retention_secs: 7 * DAY,
+            hour_retention_secs: 90 * DAY,
+        };
+        let now = at(100 * DAY);
+        let resolution = |since: i64, until: i64, step: Option<i64>| {
+            config.resolution_for(now - chrono::Duration::seconds(since), now - chrono::Duration::seconds(until), step, now)
+        };
+        assert_eq!(resolution(HOUR, 0, None), MetricResolution::Raw);
+        assert_eq!(resolution(2 * DAY, 2 * DAY - 10 * HOUR, None), MetricResolution::Minute);
+        assert_eq!(resolution(6 * HOUR, 0, None), MetricResolution::Minute);
+        assert_eq!(resolution(6 * HOUR, 0, Some(30)), MetricResolution::Raw);
+        assert_eq!(resolution(6 * HOUR, 0, Some(900)), MetricResolution::Minute);
+        assert_eq!(resolution(2 * DAY, 0, Some(5)), MetricResolution::Minute);
+        assert_eq!(resolution(3 * DAY, 0, None), MetricResolution::Hour);
+        assert_eq!(resolution(30 * DAY, 29 * DAY, None), MetricResolution::Hour);
+    }
+}
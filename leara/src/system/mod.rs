@@ -20,24 +20,25 @@
  * Purpose: System-level functionality and memory management
  */
 
+pub mod alerts;
+pub mod apps;
+pub mod environment;
+pub mod events;
+pub mod executor;
+pub mod files;
+pub mod indexer;
+pub mod jobs;
 pub mod memory_service;
+pub mod metrics;
+pub mod network;
+pub mod policy;
+pub mod processes;
+pub mod resources;
+pub mod sampler;
+pub mod sensors;
+pub mod systemd;
+pub mod terminal;
+pub mod tools;
+pub mod watch;
 
 pub use memory_service::MemoryService;
-
-use crate::models::system::SystemInfo;
-
-/// Get current system information
-pub fn get_system_info() -> SystemInfo {
-    let mut sys = sysinfo::System::new_all();
-    sys.refresh_all();
-
-    SystemInfo {
-        hostname: sysinfo::System::host_name().unwrap_or_else(|| "unknown".to_string()),
-        os_name: sysinfo::System::name().unwrap_or_else(|| "unknown".to_string()),
-        os_version: sysinfo::System::os_version().unwrap_or_else(|| "unknown".to_string()),
-        kernel_version: sysinfo::System::kernel_version().unwrap_or_else(|| "unknown".to_string()),
-        cpu_count: sys.physical_core_count().unwrap_or(0),
-        total_memory: sys.total_memory(),
-        uptime: sysinfo::System::uptime(),
-    }
-} 
\ No newline at end of file
@@ -0,0 +1,565 @@
+/*
+ * Leara AI Assistant - Network Diagnostics
+ *
+ * This module reads the machine's network state: interfaces and their
+ * addresses, the routing table, open sockets with the processes that own
+ * them and the DNS resolver configuration. Tables are parsed from /proc
+ * and /sys, addresses come from getifaddrs(3). It also measures latency to
+ * a host by timing TCP connections.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/network.rs
+ * Purpose: Interfaces, routes, sockets, DNS configuration and latency probes
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use crate::models::system::{
+    DnsConfig, InterfaceAddress, LatencyProbe, NetworkInterfaceDetails, NetworkRoute, SocketInfo,
+};
+
+/// Where procfs is mounted
+pub const PROC: &str = "/proc";
+
+/// Resolver configuration read by the C library
+pub const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// Interface flags from `<net/if.h>`
+const IFF_UP: u32 = 0x1;
+const IFF_LOOPBACK: u32 = 0x8;
+
+/// Route flags from `<linux/route.h>`
+const RTF_UP: u32 = 0x1;
+const RTF_GATEWAY: u32 = 0x2;
+
+/// Attempts and per-attempt timeout a latency probe is limited to
+pub const MAX_PROBE_ATTEMPTS: u32 = 10;
+pub const MAX_PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Contents of a sysfs attribute, trimmed
+fn attribute(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name))
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Addresses of every interface, from getifaddrs(3)
+pub fn interface_addresses() -> HashMap<String, Vec<InterfaceAddress>> {
+    let mut addresses: HashMap<String, Vec<InterfaceAddress>> = HashMap::new();
+    let mut list: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: getifaddrs(3) fills in a linked list that is only read until
+    // it is released with freeifaddrs(3) below
+    unsafe {
+        if libc::getifaddrs(&mut list) != 0 {
+            return addresses;
+        }
+        let mut entry = list;
+        while let Some(ifaddr) = entry.as_ref() {
+            entry = ifaddr.ifa_next;
+            if ifaddr.ifa_addr.is_null() || ifaddr.ifa_name.is_null() {
+                continue;
+            }
+            let name = std::ffi::CStr::from_ptr(ifaddr.ifa_name).to_string_lossy().to_string();
+            let (address, prefix_len) = match (*ifaddr.ifa_addr).sa_family as libc::c_int {
+                libc::AF_INET => {
+                    let addr = &*(ifaddr.ifa_addr as *const libc::sockaddr_in);
+                    let mask = match (ifaddr.ifa_netmask as *const libc::sockaddr_in).as_ref() {
+                        Some(mask) => mask.sin_addr.s_addr.count_ones(),
+                        None => 32,
+                    };
+                    (IpAddr::V4(Ipv4Addr::from(addr.sin_addr.s_addr.to_ne_bytes())), mask)
+                }
+                libc::AF_INET6 => {
+                    let addr = &*(ifaddr.ifa_addr as *const libc::sockaddr_in6);
+                    let mask = match (ifaddr.ifa_netmask as *const libc::sockaddr_in6).as_ref() {
+                        Some(mask) => mask.sin6_addr.s6_addr.iter().map(|byte| byte.count_ones()).sum(),
+                        None => 128,
+                    };
+                    (IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr)), mask)
+                }
+                _ => continue,
+            };
+            addresses.entry(name).or_default().push(InterfaceAddress {
+                family: if address.is_ipv4() { "ipv4" } else { "ipv6" }.to_string(),
+                address: address.to_string(),
+                prefix_len: prefix_len as u8,
+            });
+        }
+        libc::freeifaddrs(list);
+    }
+    addresses
+}
+
+/// Interfaces under `class/net` with their addresses
+///
+/// # Arguments
+/// * `class` - sysfs class directory, normally `/sys/class`
+/// * `addresses` - Addresses by interface name
+pub fn interfaces(class: &Path, mut addresses: HashMap<String, Vec<InterfaceAddress>>) -> Vec<NetworkInterfaceDetails> {
+    let mut found: Vec<NetworkInterfaceDetails> = fs::read_dir(class.join("net"))
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|dir| {
+            let name = dir.file_name()?.to_string_lossy().to_string();
+            let flags = attribute(&dir, "flags")
+                .and_then(|flags| u32::from_str_radix(flags.trim_start_matches("0x"), 16).ok())
+                .unwrap_or(0);
+            Some(NetworkInterfaceDetails {
+                addresses: addresses.remove(&name).unwrap_or_default(),
+                mac_address: attribute(&dir, "address").filter(|mac| mac != "00:00:00:00:00:00"),
+                mtu: attribute(&dir, "mtu").and_then(|mtu| mtu.parse().ok()),
+                state: attribute(&dir, "operstate").unwrap_or_else(|| "unknown".to_string()),
+                is_up: flags & IFF_UP != 0,
+                is_loopback: flags & IFF_LOOPBACK != 0,
+                name,
+                receive_rate: 0.0,
+                transmit_rate: 0.0,
+            })
+        })
+        .collect();
+    found.sort_by(|a, b| a.name.cmp(&b.name));
+    found
+}
+
+/// IPv4 address printed by the kernel as a native-endian hex word
+fn hex_ipv4(hex: &str) -> Option<Ipv4Addr> {
+    Some(Ipv4Addr::from(u32::from_str_radix(hex, 16).ok()?.to_ne_bytes()))
+}
+
+/// IPv6 address printed by the kernel as four native-endian hex words
+fn hex_ipv6_words(hex: &str) -> Option<Ipv6Addr> {
+    if hex.len() != 32 {
+        return None;
+    }
+    let mut bytes = [0u8; 16];
+    for (word, chunk) in bytes.chunks_mut(4).enumerate() {
+        let value = u32::from_str_radix(&hex[word * 8..word * 8 + 8], 16).ok()?;
+        chunk.copy_from_slice(&value.to_ne_bytes());
+    }
+    Some(Ipv6Addr::from(bytes))
+}
+
+/// IPv6 address printed by the kernel byte by byte
+fn hex_ipv6_bytes(hex: &str) -> Option<Ipv6Addr> {
+    if hex.len() != 32 {
+        return None;
+    }
+    let mut bytes = [0u8; 16];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).ok()?;
+    }
+    Some(Ipv6Addr::from(bytes))
+}
+
+/// The routing table from `net/route` and `net/ipv6_route` under `proc`
+///
+/// Routes that are down and the IPv6 routes of the loopback interface,
+/// which list every local address, are left out.
+pub fn routes(proc: &Path) -> Vec<NetworkRoute> {
+    let mut routes = Vec::new();
+    if let Ok(table) = fs::read_to_string(proc.join("net/route")) {
+        for line in table.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 {
+                continue;
+            }
+            let flags = u32::from_str_radix(fields[3], 16).unwrap_or(0);
+            let (Some(destination), Some(gateway), Some(mask)) = (hex_ipv4(fields[1]), hex_ipv4(fields[2]), hex_ipv4(fields[7])) else {
+                continue;
+            };
+            if flags & RTF_UP == 0 {
+                continue;
+            }
+            routes.push(NetworkRoute {
+                family: "ipv4".to_string(),
+                destination: format!("{}/{}", destination, u32::from(mask).count_ones()),
+                gateway: (flags & RTF_GATEWAY != 0).then(|| gateway.to_string()),
+                interface: fields[0].to_string(),
+                metric: fields[6].parse().unwrap_or(0),
+                is_default: u32::from(mask) == 0,
+            });
+        }
+    }
+    if let Ok(table) = fs::read_to_string(proc.join("net/ipv6_route")) {
+        for line in table.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 || fields[9] == "lo" {
+                continue;
+            }
+            let flags = u32::from_str_radix(fields[8], 16).unwrap_or(0);
+            let (Some(destination), Ok(prefix), Some(gateway)) =
+                (hex_ipv6_bytes(fields[0]), u8::from_str_radix(fields[1], 16), hex_ipv6_bytes(fields[4]))
+            else {
+                continue;
+            };
+            if flags & RTF_UP == 0 {
+                continue;
+            }
+            routes.push(NetworkRoute {
+                family: "ipv6".to_string(),
+                destination: format!("{}/{}", destination, prefix),
+                gateway: (!gateway.is_unspecified()).then(|| gateway.to_string()),
+                interface: fields[9].to_string(),
+                metric: u32::from_str_radix(fields[5], 16).unwrap_or(0),
+                is_default: prefix == 0,
+            });
+        }
+    }
+    routes
+}
+
+/// Name of a TCP state from `include/net/tcp_states.h`
+fn tcp_state(code: u8) -> &'static str {
+    match code {
+        0x01 => "established",
+        0x02 => "syn_sent",
+        0x03 => "syn_recv",
+        0x04 => "fin_wait1",
+        0x05 => "fin_wait2",
+        0x06 => "time_wait",
+        0x07 => "close",
+        0x08 => "close_wait",
+        0x09 => "last_ack",
+        0x0A => "listen",
+        0x0B => "closing",
+        _ => "unknown",
+    }
+}
+
+/// Address and port printed as `hex:hex`
+fn hex_endpoint(endpoint: &str, ipv6: bool) -> Option<(IpAddr, u16)> {
+    let (address, port) = endpoint.split_once(':')?;
+    let address = if ipv6 { IpAddr::V6(hex_ipv6_words(address)?) } else { IpAddr::V4(hex_ipv4(address)?) };
+    Some((address, u16::from_str_radix(port, 16).ok()?))
+}
+
+/// Sockets in one of `net/tcp`, `net/tcp6`, `net/udp` or `net/udp6`
+///
+/// UDP sockets have no connection state; bound ones are reported as
+/// `listen` and connected ones as `established`.
+fn socket_table(proc: &Path, protocol: &str) -> Vec<SocketInfo> {
+    let Ok(table) = fs::read_to_string(proc.join("net").join(protocol)) else {
+        return Vec::new();
+    };
+    let ipv6 = protocol.ends_with('6');
+    let udp = protocol.starts_with("udp");
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 {
+                return None;
+            }
+            let (local_address, local_port) = hex_endpoint(fields[1], ipv6)?;
+            let (remote_address, remote_port) = hex_endpoint(fields[2], ipv6)?;
+            let code = u8::from_str_radix(fields[3], 16).ok()?;
+            let state = match (udp, code) {
+                (true, 0x07) => "listen",
+                (true, 0x01) => "established",
+                (_, code) => tcp_state(code),
+            };
+            Some(SocketInfo {
+                protocol: protocol.to_string(),
+                local_address: local_address.to_string(),
+                local_port,
+                remote_address: (remote_port != 0).then(|| remote_address.to_string()),
+                remote_port: (remote_port != 0).then_some(remote_port),
+                state: state.to_string(),
+                uid: fields[7].parse().ok(),
+                inode: fields[9].parse().unwrap_or(0),
+                pid: None,
+                process: None,
+            })
+        })
+        .collect()
+}
+
+/// Processes owning socket inodes, from the descriptors under `proc/<pid>/fd`
+///
+/// Processes whose descriptors the server may not read are skipped.
+fn socket_owners(proc: &Path) -> HashMap<u64, (u32, String)> {
+    let mut owners = HashMap::new();
+    let Ok(entries) = fs::read_dir(proc) else {
+        return owners;
+    };
+    for entry in entries.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else {
+            continue;
+        };
+        let Ok(descriptors) = fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        let name = attribute(&entry.path(), "comm").unwrap_or_default();
+        for descriptor in descriptors.flatten() {
+            let Ok(target) = fs::read_link(descriptor.path()) else {
+                continue;
+            };
+            let target = target.to_string_lossy();
+            if let Some(inode) = target.strip_prefix("socket:[").and_then(|rest| rest.strip_suffix(']')) {
+                if let Ok(inode) = inode.parse() {
+                    owners.entry(inode).or_insert_with(|| (pid, name.clone()));
+                }
+            }
+        }
+    }
+    owners
+}
+
+/// TCP and UDP sockets over IPv4 and IPv6 with the processes that own them
+///
+/// # Arguments
+/// * `proc` - procfs mount point, normally `/proc`
+pub fn sockets(proc: &Path) -> Vec<SocketInfo> {
+    let mut sockets: Vec<SocketInfo> = ["tcp", "tcp6", "udp", "udp6"]
+        .iter()
+        .flat_map(|protocol| socket_table(proc, protocol))
+        .collect();
+    let owners = socket_owners(proc);
+    for socket in &mut sockets {
+        if let Some((pid, name)) = owners.get(&socket.inode) {
+            socket.pid = Some(*pid);
+            socket.process = Some(name.clone());
+        }
+    }
+    sockets
+}
+
+/// Parse resolv.conf(5)
+pub fn parse_resolv_conf(content: &str) -> DnsConfig {
+    let mut config = DnsConfig::default();
+    for line in content.lines() {
+        let line = line.split(['#', ';']).next().unwrap_or("").trim();
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("nameserver") => config.nameservers.extend(words.next().map(str::to_string)),
+            // The last of `domain` and `search` wins
+            Some("domain") => config.search = words.next().map(|domain| vec![domain.to_string()]).unwrap_or_default(),
+            Some("search") => config.search = words.map(str::to_string).collect(),
+            Some("options") => {
+                for option in words {
+                    let (name, value) = option.split_once(':').unwrap_or((option, ""));
+                    config.options.insert(name.to_string(), value.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    config
+}
+
+/// Time TCP connections to a host
+///
+/// Each attempt connects and closes the connection right away; no data is
+/// sent. Attempts follow each other without a pause.
+///
+/// # Arguments
+/// * `host` - Host name or address
+/// * `port` - TCP port
+/// * `attempts` - Connections to time, at most `MAX_PROBE_ATTEMPTS`
+/// * `timeout` - Limit for each connection, at most `MAX_PROBE_TIMEOUT`
+pub async fn probe_latency(host: &str, port: u16, attempts: u32, timeout: Duration) -> LatencyProbe {
+    let attempts = attempts.clamp(1, MAX_PROBE_ATTEMPTS);
+    let timeout = timeout.clamp(Duration::from_millis(1), MAX_PROBE_TIMEOUT);
+    let mut probe = LatencyProbe {
+        host: host.to_string(),
+        port,
+        address: None,
+        attempts,
+        successes: 0,
+        samples_ms: Vec::new(),
+        min_ms: None,
+        avg_ms: None,
+        max_ms: None,
+        error: None,
+    };
+
+    let address: SocketAddr = match tokio::time::timeout(timeout, tokio::net::lookup_host((host, port))).await {
+        Ok(Ok(mut addresses)) => match addresses.next() {
+            Some(address) => address,
+            None => {
+                probe.error = Some(format!("{} has no addresses", host));
+                return probe;
+            }
+        },
+        Ok(Err(e)) => {
+            probe.error = Some(format!("Failed to resolve {}: {}", host, e));
+            return probe;
+        }
+        Err(_) => {
+            probe.error = Some(format!("Resolving {} timed out", host));
+            return probe;
+        }
+    };
+    probe.address = Some(address.ip().to_string());
+
+    for _ in 0..attempts {
+        let start = Instant::now();
+        match tokio::time::timeout(timeout, TcpStream::connect(address)).await {
+            Ok(Ok(_)) => probe.samples_ms.push(Some(start.elapsed().as_secs_f64() * 1000.0)),
+            Ok(Err(e)) => {
+                probe.error = Some(e.to_string());
+                probe.samples_ms.push(None);
+            }
+            Err(_) => {
+                probe.error = Some(format!("Timed out after {}ms", timeout.as_millis()));
+                probe.samples_ms.push(None);
+            }
+        }
+    }
+
+    let times: Vec<f64> = probe.samples_ms.iter().flatten().copied().collect();
+    probe.successes = times.len() as u32;
+    if !times.is_empty() {
+        probe.min_ms = times.iter().copied().reduce(f64::min);
+        probe.max_ms = times.iter().copied().reduce(f64::max);
+        probe.avg_ms = Some(times.iter().sum::<f64>() / times.len() as f64);
+    }
+    probe
+}
+
+/// Count sockets by state, for summaries
+pub fn count_states(sockets: &[SocketInfo]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for socket in sockets {
+        *counts.entry(socket.state.clone()).or_default() += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempTree;
+
+    /// A fake /proc, removed when dropped
+    #[test]
+    fn parses_routes() {
+        let proc = TempTree::new();
+        proc.write(
+            "net/route",
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+             eth0\t00000000\t010200C0\t0003\t0\t0\t100\t00000000\t0\t0\t0\n\
+             eth0\t000200C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0\n",
+        );
+        proc.write(
+            "net/ipv6_route",
+            "fd000000000000000000000000000000 40 00000000000000000000000000000000 00 00000000000000000000000000000000 00000100 00000001 00000000 00000001     eth0\n\
+             00000000000000000000000000000000 00 00000000000000000000000000000000 00 fd000000000000000000000000000001 00000400 00000001 00000000 00000003     eth0\n\
+             00000000000000000000000000000001 80 00000000000000000000000000000000 00 00000000000000000000000000000000 00000000 00000001 00000000 80200001       lo\n",
+        );
+        let routes = routes(proc.path());
+        assert_eq!(routes.len(), 4);
+        assert_eq!(routes[0].destination, "0.0.0.0/0");
+        assert_eq!(routes[0].gateway.as_deref(), Some("192.0.2.1"));
+        assert!(routes[0].is_default);
+        assert_eq!(routes[0].metric, 100);
+        assert_eq!(routes[1].destination, "192.0.2.0/24");
+        assert_eq!(routes[1].gateway, None);
+        assert_eq!(routes[2].destination, "fd00::/64");
+        assert_eq!(routes[3].gateway.as_deref(), Some("fd00::1"));
+        assert_eq!(routes[3].metric, 1024);
+    }
+
+    #[test]
+    fn parses_sockets_with_owners() {
+        let proc = TempTree::new();
+        let header = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n";
+        proc.write(
+            "net/tcp",
+            format!(
+                "{}   0: 0100007F:0BB8 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 4242 1 0 100 0 0 10 0\n\
+                 \x20  1: 0100007F:0BB8 0100007F:D431 01 00000000:00000000 00:00000000 00000000  1000        0 4243 1 0 20 4 30 10 -1\n",
+                header
+            ),
+        );
+        proc.write(
+            "net/udp6",
+            format!(
+                "{}   0: 00000000000000000000000001000000:0035 00000000000000000000000000000000:0000 07 00000000:00000000 00:00000000 00000000     0        0 99 2 0 0\n",
+                header
+            ),
+        );
+        proc.write("1234/comm", "server\n");
+        fs::create_dir_all(proc.join("1234/fd")).unwrap();
+        std::os::unix::fs::symlink("socket:[4242]", proc.join("1234/fd/3")).unwrap();
+        std::os::unix::fs::symlink("/dev/null", proc.join("1234/fd/0")).unwrap();
+
+        let sockets = sockets(proc.path());
+        assert_eq!(sockets.len(), 3);
+        let listener = &sockets[0];
+        assert_eq!((listener.local_address.as_str(), listener.local_port), ("127.0.0.1", 3000));
+        assert_eq!(listener.state, "listen");
+        assert_eq!(listener.remote_address, None);
+        assert_eq!(listener.pid, Some(1234));
+        assert_eq!(listener.process.as_deref(), Some("server"));
+        assert_eq!(sockets[1].state, "established");
+        assert_eq!(sockets[1].remote_port, Some(54321));
+        assert_eq!(sockets[1].uid, Some(1000));
+        assert_eq!(sockets[1].pid, None);
+        assert_eq!((sockets[2].local_address.as_str(), sockets[2].local_port), ("::1", 53));
+        assert_eq!(sockets[2].state, "listen");
+        assert_eq!(count_states(&sockets)["listen"], 2);
+    }
+
+    #[test]
+    fn parses_resolv_conf() {
+        let config = parse_resolv_conf(
+            "# Generated\nnameserver 127.0.0.53\nnameserver 1.1.1.1 ; backup\ndomain example.org\n\
+             search lan example.com\noptions edns0 ndots:2 timeout:1\n",
+        );
+        assert_eq!(config.nameservers, vec!["127.0.0.53", "1.1.1.1"]);
+        assert_eq!(config.search, vec!["lan", "example.com"]);
+        assert_eq!(config.options["ndots"], "2");
+        assert_eq!(config.options["edns0"], "");
+    }
+
+    #[test]
+    fn finds_loopback_addresses() {
+        let addresses = interface_addresses();
+        let loopback = addresses.get("lo").expect("loopback interface");
+        assert!(loopback.iter().any(|address| address.address == "127.0.0.1" && address.prefix_len == 8));
+    }
+
+    #[tokio::test]
+    async fn probes_latency_over_loopback() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((connection, _)) = listener.accept().await {
+                drop(connection);
+            }
+        });
+
+        let probe = probe_latency("127.0.0.1", port, 3, Duration::from_secs(1)).await;
+        assert_eq!(probe.address.as_deref(), Some("127.0.0.1"));
+        assert_eq!(probe.successes, 3);
+        assert_eq!(probe.samples_ms.len(), 3);
+        assert!(probe.min_ms.unwrap() <= probe.avg_ms.unwrap() && probe.avg_ms.unwrap() <= probe.max_ms.unwrap());
+        assert_eq!(probe.error, None);
+
+        // Nothing listens on the port once the listener is gone
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let probe = probe_latency("127.0.0.1", closed, 2, Duration::from_secs(1)).await;
+        assert_eq!(probe.successes, 0);
+        assert_eq!(probe.avg_ms, None);
+        assert!(probe.error.is_some());
+    }
+}
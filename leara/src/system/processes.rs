@@ -0,0 +1,351 @@
+/*
+ * Leara AI Assistant - Processes
+ *
+ * This module turns the process table read by sysinfo into the process
+ * models served by the API. It filters and sorts process lists, arranges
+ * them into a tree by parent, reads the details of single processes and
+ * names the signals that may be sent to them.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/processes.rs
+ * Purpose: Process listing, process trees, process details and signals
+ */
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use sysinfo::{Pid, Process, ProcessRefreshKind, System, UpdateKind, Users};
+use crate::models::system::{ProcessDetails, ProcessInfo, ProcessSortField, ProcessTreeNode};
+use crate::system::environment::{EnvironmentConfig, REDACTED};
+
+/// Signals that may be sent to processes
+pub const SIGNALS: &[&str] = &["TERM", "KILL", "STOP", "CONT"];
+
+/// What the sampler refreshes for every process
+pub fn refresh_kind() -> ProcessRefreshKind {
+    ProcessRefreshKind::new()
+        .with_cpu()
+        .with_memory()
+        .with_user(UpdateKind::OnlyIfNotSet)
+        .with_cmd(UpdateKind::OnlyIfNotSet)
+}
+
+/// Owner of a process, by name if the user is known
+fn user_name(process: &Process, users: &Users) -> Option<String> {
+    let uid = process.user_id()?;
+    Some(match users.get_user_by_id(uid) {
+        Some(user) => user.name().to_string(),
+        None => uid.to_string(),
+    })
+}
+
+/// Summary of one process
+pub fn process_info(process: &Process, users: &Users) -> ProcessInfo {
+    ProcessInfo {
+        pid: process.pid().as_u32(),
+        parent_pid: process.parent().map(|pid| pid.as_u32()),
+        name: process.name().to_string(),
+        command: process.cmd().to_vec(),
+        user: user_name(process, users),
+        cpu_usage: process.cpu_usage(),
+        memory_usage: process.memory(),
+        virtual_memory: process.virtual_memory(),
+        status: process.status().to_string(),
+        start_time: process.start_time(),
+        run_time: process.run_time(),
+    }
+}
+
+/// Every process, without the threads sysinfo lists alongside them
+///
+/// # Arguments
+/// * `sys` - System with processes refreshed
+/// * `users` - Known users, to name the owners
+pub fn process_list(sys: &System, users: &Users) -> Vec<ProcessInfo> {
+    let mut processes: Vec<ProcessInfo> = sys
+        .processes()
+        .values()
+        .filter(|process| process.thread_kind().is_none())
+        .map(|process| process_info(process, users))
+        .collect();
+    processes.sort_by_key(|process| process.pid);
+    processes
+}
+
+/// Which processes a listing includes
+#[derive(Debug, Default)]
+pub struct ProcessFilter {
+    /// Case-insensitive match against the name and command line
+    pub name: Option<String>,
+    /// Exact owner name or user ID
+    pub user: Option<String>,
+    /// Lowest CPU usage in percent
+    pub min_cpu: Option<f32>,
+    /// Lowest resident memory in bytes
+    pub min_memory: Option<u64>,
+}
+
+impl ProcessFilter {
+    pub fn matches(&self, process: &ProcessInfo) -> bool {
+        if let Some(ref name) = self.name {
+            let needle = name.to_lowercase();
+            if !process.name.to_lowercase().contains(&needle)
+                && !process.command.join(" ").to_lowercase().contains(&needle)
+            {
+                return false;
+            }
+        }
+        if let Some(ref user) = self.user {
+            if process.user.as_deref() != Some(user.as_str()) {
+                return false;
+            }
+        }
+        if self.min_cpu.is_some_and(|min| process.cpu_usage < min) {
+            return false;
+        }
+        if self.min_memory.is_some_and(|min| process.memory_usage < min) {
+            return false;
+        }
+        true
+    }
+}
+
+/// Sort processes by a field, ties broken by PID
+pub fn sort_processes(processes: &mut [ProcessInfo], field: ProcessSortField, descending: bool) {
+    processes.sort_by(|a, b| {
+        let ordering = match field {
+            ProcessSortField::Cpu => a.cpu_usage.partial_cmp(&b.cpu_usage).unwrap_or(Ordering::Equal),
+            ProcessSortField::Memory => a.memory_usage.cmp(&b.memory_usage),
+            ProcessSortField::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            ProcessSortField::Pid => Ordering::Equal,
+            ProcessSortField::User => a.user.cmp(&b.user),
+            ProcessSortField::StartTime => a.start_time.cmp(&b.start_time),
+        };
+        let ordering = ordering.then(a.pid.cmp(&b.pid));
+        if descending { ordering.reverse() } else { ordering }
+    });
+}
+
+/// Arrange processes by parent
+///
+/// Processes whose parent is not in the list become roots. Children are
+/// ordered by PID.
+///
+/// # Arguments
+/// * `processes` - Processes to arrange
+/// * `root` - Only return the subtree of this process
+pub fn process_tree(processes: Vec<ProcessInfo>, root: Option<u32>) -> Vec<ProcessTreeNode> {
+    let pids: HashSet<u32> = processes.iter().map(|process| process.pid).collect();
+    let mut children: HashMap<u32, Vec<ProcessInfo>> = HashMap::new();
+    let mut roots = Vec::new();
+    for process in processes {
+        match process.parent_pid {
+            _ if Some(process.pid) == root => roots.push(process),
+            Some(parent) if parent != process.pid && pids.contains(&parent) => {
+                children.entry(parent).or_default().push(process)
+            }
+            _ if root.is_none() => roots.push(process),
+            _ => {}
+        }
+    }
+
+    fn build(process: ProcessInfo, children: &mut HashMap<u32, Vec<ProcessInfo>>) -> ProcessTreeNode {
+        let mut own = children.remove(&process.pid).unwrap_or_default();
+        own.sort_by_key(|child| child.pid);
+        let children = own.into_iter().map(|child| build(child, children)).collect();
+        ProcessTreeNode { process, children }
+    }
+
+    roots.sort_by_key(|process| process.pid);
+    roots.into_iter().map(|process| build(process, &mut children)).collect()
+}
+
+/// Everything that can be read about one process
+///
+/// Reads the process afresh, so CPU usage is not measured; callers take it
+/// from the latest sample. The environment and open files are only
+/// available for processes the server may inspect.
+///
+/// # Arguments
+/// * `pid` - Process ID
+/// * `environment` - Decides which variables hold secrets
+///
+/// # Returns
+/// * `Some(ProcessDetails)` - The process
+/// * `None` - No such process
+pub fn process_details(pid: u32, environment: &EnvironmentConfig) -> Option<ProcessDetails> {
+    let pid = Pid::from_u32(pid);
+    let mut sys = System::new();
+    if !sys.refresh_process_specifics(pid, ProcessRefreshKind::everything()) {
+        return None;
+    }
+    let process = sys.process(pid)?;
+    let users = Users::new_with_refreshed_list();
+
+    let environ = process.environ();
+    let variables = (!environ.is_empty()).then(|| {
+        environ
+            .iter()
+            .filter_map(|entry| entry.split_once('='))
+            .map(|(name, value)| {
+                let value = if environment.is_secret(name) { REDACTED } else { value };
+                (name.to_string(), value.to_string())
+            })
+            .collect::<BTreeMap<_, _>>()
+    });
+
+    Some(ProcessDetails {
+        process: process_info(process, &users),
+        executable: process.exe().map(|path| path.display().to_string()),
+        working_dir: process.cwd().map(|path| path.display().to_string()),
+        root_dir: process.root().map(|path| path.display().to_string()),
+        open_files: std::fs::read_dir(format!("/proc/{}/fd", pid.as_u32())).ok().map(|entries| entries.count()),
+        environment: variables,
+    })
+}
+
+/// Normalize a signal name such as `term`, `SIGTERM` or `15`
+///
+/// # Returns
+/// * `Ok(&str)` - The signal's name as `kill -s` accepts it
+/// * `Err(String)` - The signal is unknown or may not be sent
+pub fn parse_signal(signal: &str) -> Result<&'static str, String> {
+    let upper = signal.trim().to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    let name = match name {
+        "15" => "TERM",
+        "9" => "KILL",
+        "19" => "STOP",
+        "18" => "CONT",
+        other => other,
+    };
+    SIGNALS
+        .iter()
+        .find(|allowed| **allowed == name)
+        .copied()
+        .ok_or_else(|| format!("Unsupported signal {:?}; use one of {}", signal, SIGNALS.join(", ")))
+}
+
+/// When a process started, in clock ticks since boot (field 22 of `/proc/<pid>/stat`)
+///
+/// A PID together with its start time names one process: a PID reused by
+/// a later process has a later start time.
+///
+/// # Returns
+/// * `Some(u64)` - The start time
+/// * `None` - No process with this PID is running
+pub fn process_start_ticks(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // Fields after the command name start with field 3 (state)
+    stat.rsplit_once(')')?.1.split_whitespace().nth(19)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, parent_pid: Option<u32>, name: &str, cpu_usage: f32, memory_usage: u64) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            parent_pid,
+            name: name.to_string(),
+            command: vec![format!("/usr/bin/{}", name), "--flag".to_string()],
+            user: Some(if pid == 1 { "root" } else { "klea" }.to_string()),
+            cpu_usage,
+            memory_usage,
+            virtual_memory: memory_usage * 2,
+            status: "Run".to_string(),
+            start_time: 1_700_000_000 + pid as u64,
+            run_time: 100,
+        }
+    }
+
+    fn processes() -> Vec<ProcessInfo> {
+        vec![
+            process(1, None, "systemd", 0.1, 10),
+            process(200, Some(1), "sshd", 0.0, 20),
+            process(300, Some(200), "bash", 1.5, 5),
+            process(301, Some(300), "cargo", 90.0, 500),
+            process(400, Some(1), "Firefox", 12.0, 900),
+            process(500, Some(42), "orphan", 0.0, 1),
+        ]
+    }
+
+    #[test]
+    fn filters_by_name_user_and_usage() {
+        let filter = ProcessFilter { name: Some("FIRE".to_string()), ..Default::default() };
+        let names: Vec<_> = processes().into_iter().filter(|p| filter.matches(p)).map(|p| p.name).collect();
+        assert_eq!(names, vec!["Firefox"]);
+
+        let filter = ProcessFilter { name: Some("bin/ssh".to_string()), ..Default::default() };
+        assert_eq!(processes().iter().filter(|p| filter.matches(p)).count(), 1);
+
+        let filter = ProcessFilter { user: Some("root".to_string()), ..Default::default() };
+        assert_eq!(processes().iter().filter(|p| filter.matches(p)).count(), 1);
+
+        let filter = ProcessFilter { min_cpu: Some(10.0), min_memory: Some(600), ..Default::default() };
+        let pids: Vec<_> = processes().into_iter().filter(|p| filter.matches(p)).map(|p| p.pid).collect();
+        assert_eq!(pids, vec![400]);
+    }
+
+    #[test]
+    fn sorts_by_field_and_direction() {
+        let mut list = processes();
+        sort_processes(&mut list, ProcessSortField::Cpu, true);
+        assert_eq!(list.iter().map(|p| p.pid).take(3).collect::<Vec<_>>(), vec![301, 400, 300]);
+
+        sort_processes(&mut list, ProcessSortField::Name, false);
+        assert_eq!(list[0].name, "bash");
+        assert_eq!(list[2].name, "Firefox");
+
+        sort_processes(&mut list, ProcessSortField::Pid, true);
+        assert_eq!(list[0].pid, 500);
+    }
+
+    #[test]
+    fn builds_tree_by_parent() {
+        let tree = process_tree(processes(), None);
+        assert_eq!(tree.iter().map(|node| node.process.pid).collect::<Vec<_>>(), vec![1, 500]);
+        let init = &tree[0];
+        assert_eq!(init.children.iter().map(|node| node.process.pid).collect::<Vec<_>>(), vec![200, 400]);
+        assert_eq!(init.children[0].children[0].children[0].process.name, "cargo");
+
+        let subtree = process_tree(processes(), Some(300));
+        assert_eq!(subtree.len(), 1);
+        assert_eq!(subtree[0].children[0].process.pid, 301);
+        assert!(process_tree(processes(), Some(999)).is_empty());
+    }
+
+    #[test]
+    fn parses_allowed_signals() {
+        assert_eq!(parse_signal("term"), Ok("TERM"));
+        assert_eq!(parse_signal("SIGKILL"), Ok("KILL"));
+        assert_eq!(parse_signal("19"), Ok("STOP"));
+        assert_eq!(parse_signal(" cont "), Ok("CONT"));
+        assert!(parse_signal("HUP").is_err());
+        assert!(parse_signal("-9").is_err());
+    }
+
+    #[test]
+    fn start_times_tell_processes_apart() {
+        let own = process_start_ticks(std::process::id()).unwrap();
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let started = process_start_ticks(child.id()).unwrap();
+        assert!(started >= own);
+        assert_eq!(process_start_ticks(child.id()), Some(started));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(process_start_ticks(child.id()), None);
+    }
+}
@@ -0,0 +1,158 @@
+/*
+ * Leara AI Assistant - Resource Usage
+ *
+ * This module turns the readings of the sysinfo crate into the system
+ * models served by the API: host information, CPU, memory and swap usage,
+ * load average, mounted disks and network interface counters.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/resources.rs
+ * Purpose: Host information and resource usage from sysinfo
+ */
+
+use sysinfo::{Disks, Networks, System};
+use crate::models::system::{
+    CpuCoreUsage, DiskInfo, LoadAverage, MemoryInfo, NetworkInterfaceInfo, ResourceUsage, SystemInfo,
+};
+
+/// Share of `used` in `total`, in percent
+fn percentage(used: u64, total: u64) -> f32 {
+    if total == 0 {
+        0.0
+    } else {
+        (used as f64 / total as f64 * 100.0) as f32
+    }
+}
+
+/// Host name, operating system and hardware of the machine
+///
+/// # Arguments
+/// * `sys` - System with CPU and memory information refreshed
+pub fn system_info(sys: &System) -> SystemInfo {
+    SystemInfo {
+        hostname: System::host_name().unwrap_or_else(|| "unknown".to_string()),
+        os_name: System::name().unwrap_or_else(|| "unknown".to_string()),
+        os_version: System::os_version().unwrap_or_else(|| "unknown".to_string()),
+        kernel_version: System::kernel_version().unwrap_or_else(|| "unknown".to_string()),
+        cpu_count: sys.physical_core_count().unwrap_or(0),
+        logical_cpu_count: sys.cpus().len(),
+        cpu_brand: sys.cpus().first().map(|cpu| cpu.brand().trim().to_string()).unwrap_or_default(),
+        total_memory: sys.total_memory(),
+        uptime: System::uptime(),
+        boot_time: System::boot_time(),
+    }
+}
+
+/// Physical memory usage
+pub fn memory_info(sys: &System) -> MemoryInfo {
+    let used = sys.total_memory().saturating_sub(sys.available_memory());
+    MemoryInfo {
+        total: sys.total_memory(),
+        used,
+        available: sys.available_memory(),
+        usage_percentage: percentage(used, sys.total_memory()),
+    }
+}
+
+/// Swap usage
+pub fn swap_info(sys: &System) -> MemoryInfo {
+    MemoryInfo {
+        total: sys.total_swap(),
+        used: sys.used_swap(),
+        available: sys.free_swap(),
+        usage_percentage: percentage(sys.used_swap(), sys.total_swap()),
+    }
+}
+
+/// Usage of every mounted disk
+pub fn disk_info(disks: &Disks) -> Vec<DiskInfo> {
+    disks
+        .iter()
+        .map(|disk| {
+            let used = disk.total_space().saturating_sub(disk.available_space());
+            DiskInfo {
+                name: disk.name().to_string_lossy().to_string(),
+                mount_point: disk.mount_point().display().to_string(),
+                file_system: disk.file_system().to_string_lossy().to_string(),
+                kind: disk.kind().to_string(),
+                removable: disk.is_removable(),
+                total: disk.total_space(),
+                used,
+                available: disk.available_space(),
+                usage_percentage: percentage(used, disk.total_space()),
+            }
+        })
+        .collect()
+}
+
+/// Traffic counters of every network interface, sorted by name
+///
+/// Rates are left at zero; the sampler fills them in from consecutive refreshes.
+pub fn network_info(networks: &Networks) -> Vec<NetworkInterfaceInfo> {
+    let mut interfaces: Vec<NetworkInterfaceInfo> = networks
+        .iter()
+        .map(|(name, data)| NetworkInterfaceInfo {
+            name: name.clone(),
+            mac_address: data.mac_address().to_string(),
+            received: data.total_received(),
+            transmitted: data.total_transmitted(),
+            packets_received: data.total_packets_received(),
+            packets_transmitted: data.total_packets_transmitted(),
+            errors_received: data.total_errors_on_received(),
+            errors_transmitted: data.total_errors_on_transmitted(),
+            receive_rate: 0.0,
+            transmit_rate: 0.0,
+        })
+        .collect();
+    interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+    interfaces
+}
+
+/// CPU, memory, disk and network usage
+///
+/// CPU percentages are only meaningful if the CPUs were refreshed twice,
+/// at least `sysinfo::MINIMUM_CPU_UPDATE_INTERVAL` apart. Network rates are
+/// left at zero.
+///
+/// # Arguments
+/// * `sys` - System with CPU and memory information refreshed
+/// * `disks` - Mounted disks
+/// * `networks` - Network interfaces
+pub fn resource_usage(sys: &System, disks: &Disks, networks: &Networks) -> ResourceUsage {
+    let load = System::load_average();
+    let memory = memory_info(sys);
+    let interfaces = network_info(networks);
+    ResourceUsage {
+        cpu_usage: sys.global_cpu_info().cpu_usage(),
+        cpus: sys
+            .cpus()
+            .iter()
+            .map(|cpu| CpuCoreUsage {
+                name: cpu.name().to_string(),
+                usage: cpu.cpu_usage(),
+                frequency_mhz: cpu.frequency(),
+            })
+            .collect(),
+        load_average: LoadAverage { one: load.one, five: load.five, fifteen: load.fifteen },
+        memory_usage: memory.used,
+        memory,
+        swap: swap_info(sys),
+        disk_usage: disk_info(disks).iter().map(|disk| disk.used).sum(),
+        network_rx: interfaces.iter().map(|interface| interface.received).sum(),
+        network_tx: interfaces.iter().map(|interface| interface.transmitted).sum(),
+        network_rx_rate: 0.0,
+        network_tx_rate: 0.0,
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    }
+}
@@ -0,0 +1,230 @@
+/*
+ * Leara AI Assistant - System Sampler
+ *
+ * This module keeps one set of sysinfo readers alive and refreshes them in
+ * the background at a fixed interval. Requests read the latest sample from
+ * memory instead of scanning the system themselves, CPU percentages of the
+ * system and of every process are measured over the whole interval, and
+ * recent samples are kept in a ring buffer for short-term history.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/sampler.rs
+ * Purpose: Cached, periodically refreshed system resource samples
+ */
+
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use std::path::Path;
+use sysinfo::{Components, Disks, Networks, System, Users};
+use tracing::info;
+use crate::models::system::{DiskInfo, HardwareSensors, NetworkInterfaceInfo, ProcessInfo, ResourceUsage, SystemInfo};
+use crate::system::{processes, resources, sensors};
+
+/// Seconds between samples unless `LEARA_SAMPLE_INTERVAL_SECS` says otherwise
+const DEFAULT_INTERVAL_SECS: u64 = 2;
+
+/// Samples kept unless `LEARA_SAMPLE_HISTORY` says otherwise
+const DEFAULT_HISTORY_LEN: usize = 300;
+
+/// Samples between rescans of the mounted disks, network interfaces, users and sensors
+const RELIST_EVERY: u64 = 15;
+
+/// How often and how much the sampler records
+#[derive(Debug, Clone)]
+pub struct SamplerConfig {
+    pub interval: Duration,
+    /// Samples kept in the ring buffer
+    pub history_len: usize,
+}
+
+impl SamplerConfig {
+    /// Read `LEARA_SAMPLE_INTERVAL_SECS` and `LEARA_SAMPLE_HISTORY`
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().and_then(|value| value.parse::<u64>().ok());
+        SamplerConfig {
+            interval: Duration::from_secs(var("LEARA_SAMPLE_INTERVAL_SECS").unwrap_or(DEFAULT_INTERVAL_SECS).max(1)),
+            history_len: var("LEARA_SAMPLE_HISTORY").map(|len| len as usize).unwrap_or(DEFAULT_HISTORY_LEN).max(1),
+        }
+    }
+}
+
+/// Everything measured at one point in time
+#[derive(Debug)]
+pub struct ResourceSample {
+    pub usage: ResourceUsage,
+    pub disks: Vec<DiskInfo>,
+    pub networks: Vec<NetworkInterfaceInfo>,
+    pub sensors: HardwareSensors,
+}
+
+/// sysinfo readers, owned by the sampling task between samples
+struct Readers {
+    sys: System,
+    disks: Disks,
+    networks: Networks,
+    users: Users,
+    components: Components,
+    last_refresh: Instant,
+    samples_taken: u64,
+}
+
+impl Readers {
+    fn new() -> Self {
+        let mut sys = System::new();
+        sys.refresh_cpu();
+        sys.refresh_memory();
+        sys.refresh_processes_specifics(processes::refresh_kind());
+        Readers {
+            sys,
+            disks: Disks::new_with_refreshed_list(),
+            networks: Networks::new_with_refreshed_list(),
+            users: Users::new_with_refreshed_list(),
+            components: Components::new_with_refreshed_list(),
+            last_refresh: Instant::now(),
+            samples_taken: 0,
+        }
+    }
+
+    /// Refresh every reader and build a sample
+    fn sample(&mut self) -> ResourceSample {
+        self.sys.refresh_cpu();
+        self.sys.refresh_memory();
+        self.sys.refresh_processes_specifics(processes::refresh_kind());
+        if self.samples_taken.is_multiple_of(RELIST_EVERY) {
+            self.disks.refresh_list();
+            self.networks.refresh_list();
+            self.users.refresh_list();
+            self.components.refresh_list();
+        } else {
+            self.disks.refresh();
+            self.networks.refresh();
+            self.components.refresh();
+        }
+        let elapsed = self.last_refresh.elapsed().as_secs_f64();
+        self.last_refresh = Instant::now();
+        self.samples_taken += 1;
+
+        let mut usage = resources::resource_usage(&self.sys, &self.disks, &self.networks);
+        let mut networks = resources::network_info(&self.networks);
+        // Counters changed by the amounts since the previous refresh
+        for interface in &mut networks {
+            if let Some(data) = self.networks.get(&interface.name) {
+                interface.receive_rate = data.received() as f64 / elapsed;
+                interface.transmit_rate = data.transmitted() as f64 / elapsed;
+            }
+        }
+        usage.network_rx_rate = networks.iter().map(|interface| interface.receive_rate).sum();
+        usage.network_tx_rate = networks.iter().map(|interface| interface.transmit_rate).sum();
+        ResourceSample {
+            usage,
+            disks: resources::disk_info(&self.disks),
+            networks,
+            sensors: sensors::hardware_sensors(&self.components, Path::new(sensors::SYSFS_CLASS)),
+        }
+    }
+}
+
+/// Latest system samples, shared by all requests
+pub struct SystemSampler {
+    config: SamplerConfig,
+    info: RwLock<Option<Arc<SystemInfo>>>,
+    processes: RwLock<Arc<Vec<ProcessInfo>>>,
+    samples: RwLock<VecDeque<Arc<ResourceSample>>>,
+}
+
+impl SystemSampler {
+    /// Take the first sample and keep sampling in the background
+    ///
+    /// The first sample waits `sysinfo::MINIMUM_CPU_UPDATE_INTERVAL` so its
+    /// CPU percentages are already meaningful. Must be called inside the
+    /// Tokio runtime.
+    pub async fn start(config: SamplerConfig) -> Arc<Self> {
+        let sampler = Arc::new(SystemSampler {
+            config,
+            info: RwLock::new(None),
+            processes: RwLock::new(Arc::new(Vec::new())),
+            samples: RwLock::new(VecDeque::new()),
+        });
+
+        let mut readers = tokio::task::spawn_blocking(Readers::new).await.expect("sysinfo readers");
+        tokio::time::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL).await;
+        readers = sampler.record(readers).await;
+        info!(
+            "Sampling system resources every {}s, keeping {} samples",
+            sampler.config.interval.as_secs(),
+            sampler.config.history_len
+        );
+
+        let task_sampler = sampler.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(task_sampler.config.interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                readers = task_sampler.record(readers).await;
+            }
+        });
+        sampler
+    }
+
+    /// Take a sample off the async runtime and store it
+    async fn record(&self, readers: Readers) -> Readers {
+        let (readers, info, sample, process_list) = tokio::task::spawn_blocking(move || {
+            let mut readers = readers;
+            let sample = readers.sample();
+            let info = resources::system_info(&readers.sys);
+            let process_list = processes::process_list(&readers.sys, &readers.users);
+            (readers, info, sample, process_list)
+        })
+        .await
+        .expect("system sample");
+
+        *self.info.write().unwrap() = Some(Arc::new(info));
+        *self.processes.write().unwrap() = Arc::new(process_list);
+        let mut samples = self.samples.write().unwrap();
+        if samples.len() == self.config.history_len {
+            samples.pop_front();
+        }
+        samples.push_back(Arc::new(sample));
+        readers
+    }
+
+    /// Host, operating system and hardware as of the latest sample
+    pub fn info(&self) -> Arc<SystemInfo> {
+        self.info.read().unwrap().clone().expect("sampler started")
+    }
+
+    /// Processes as of the latest sample, sorted by PID
+    pub fn processes(&self) -> Arc<Vec<ProcessInfo>> {
+        self.processes.read().unwrap().clone()
+    }
+
+    /// The latest sample
+    pub fn latest(&self) -> Arc<ResourceSample> {
+        self.samples.read().unwrap().back().cloned().expect("sampler started")
+    }
+
+    /// Up to `limit` of the most recent samples, oldest first
+    pub fn history(&self, limit: usize) -> Vec<Arc<ResourceSample>> {
+        let samples = self.samples.read().unwrap();
+        samples.iter().skip(samples.len().saturating_sub(limit)).cloned().collect()
+    }
+
+    /// Time between samples
+    pub fn interval(&self) -> Duration {
+        self.config.interval
+    }
+}
@@ -0,0 +1,361 @@
+/*
+ * Leara AI Assistant - Hardware Sensors
+ *
+ * This module reads temperatures, fan speeds, power draw and batteries.
+ * Temperatures come from sysinfo's components and the kernel's thermal
+ * zones; fans and power meters from hwmon; batteries and AC adapters from
+ * the power supply class in sysfs. Machines without sensors, such as
+ * containers and most virtual machines, simply report none.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/sensors.rs
+ * Purpose: Temperature, fan, power and battery readings
+ */
+
+use std::fs;
+use std::path::Path;
+use sysinfo::Components;
+use crate::models::system::{BatteryInfo, FanReading, HardwareSensors, PowerReading, TemperatureReading};
+
+/// Where the kernel exposes device classes
+pub const SYSFS_CLASS: &str = "/sys/class";
+
+/// Contents of a sysfs attribute, trimmed
+fn attribute(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name))
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// A numeric sysfs attribute
+fn number(dir: &Path, name: &str) -> Option<f64> {
+    attribute(dir, name)?.parse().ok()
+}
+
+/// Entries of a sysfs class directory, sorted by name
+fn class_entries(class: &Path) -> Vec<std::path::PathBuf> {
+    let mut entries: Vec<_> = fs::read_dir(class)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    entries.sort();
+    entries
+}
+
+/// Temperatures of the components sysinfo found
+///
+/// Sensors that could not be read report NaN and are left out.
+pub fn component_temperatures(components: &Components) -> Vec<TemperatureReading> {
+    components
+        .iter()
+        .filter(|component| component.temperature().is_finite())
+        .map(|component| TemperatureReading {
+            label: component.label().to_string(),
+            temperature: component.temperature(),
+            max: Some(component.max()).filter(|max| max.is_finite() && *max > 0.0),
+            critical: component.critical().filter(|critical| critical.is_finite()),
+        })
+        .collect()
+}
+
+/// Temperatures of the thermal zones under `class/thermal`
+///
+/// Readings are in millidegrees; the critical trip point, if any, becomes
+/// the critical temperature.
+pub fn thermal_zones(class: &Path) -> Vec<TemperatureReading> {
+    class_entries(&class.join("thermal"))
+        .iter()
+        .filter(|zone| zone.file_name().is_some_and(|name| name.to_string_lossy().starts_with("thermal_zone")))
+        .filter_map(|zone| {
+            let temperature = number(zone, "temp")? / 1000.0;
+            let critical = (0..16)
+                .take_while(|trip| zone.join(format!("trip_point_{}_type", trip)).exists())
+                .find(|trip| attribute(zone, &format!("trip_point_{}_type", trip)).as_deref() == Some("critical"))
+                .and_then(|trip| number(zone, &format!("trip_point_{}_temp", trip)))
+                .map(|temp| (temp / 1000.0) as f32);
+            Some(TemperatureReading {
+                label: format!("{} {}", attribute(zone, "type").unwrap_or_else(|| "thermal".to_string()), zone.file_name()?.to_string_lossy()),
+                temperature: temperature as f32,
+                max: None,
+                critical,
+            })
+        })
+        .collect()
+}
+
+/// Label of an hwmon channel such as `fan1`: its own label, or the chip name and channel
+fn channel_label(chip: &Path, channel: &str) -> String {
+    attribute(chip, &format!("{}_label", channel)).unwrap_or_else(|| {
+        format!("{} {}", attribute(chip, "name").unwrap_or_else(|| "hwmon".to_string()), channel)
+    })
+}
+
+/// Inputs of one kind in an hwmon chip, e.g. `fan1_input`, `fan2_input`
+fn channels(chip: &Path, prefix: &str, suffix: &str) -> Vec<String> {
+    let mut found: Vec<String> = fs::read_dir(chip)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+                .filter_map(|name| {
+                    let channel = name.strip_suffix(suffix)?;
+                    let index = channel.strip_prefix(prefix)?;
+                    (!index.is_empty() && index.chars().all(|c| c.is_ascii_digit())).then(|| channel.to_string())
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    found.sort();
+    found
+}
+
+/// Fan speeds and power meters of the hwmon chips under `class/hwmon`
+pub fn hwmon_readings(class: &Path) -> (Vec<FanReading>, Vec<PowerReading>) {
+    let mut fans = Vec::new();
+    let mut power = Vec::new();
+    for chip in class_entries(&class.join("hwmon")) {
+        for channel in channels(&chip, "fan", "_input") {
+            if let Some(rpm) = number(&chip, &format!("{}_input", channel)) {
+                fans.push(FanReading { label: channel_label(&chip, &channel), rpm: rpm as u32 });
+            }
+        }
+        // Power meters report microwatts, averaged or instantaneous
+        for channel in channels(&chip, "power", "_average").into_iter().chain(channels(&chip, "power", "_input")) {
+            if power.iter().any(|reading: &PowerReading| reading.label == channel_label(&chip, &channel)) {
+                continue;
+            }
+            let microwatts = number(&chip, &format!("{}_average", channel)).or_else(|| number(&chip, &format!("{}_input", channel)));
+            if let Some(microwatts) = microwatts {
+                power.push(PowerReading { label: channel_label(&chip, &channel), watts: (microwatts / 1e6) as f32 });
+            }
+        }
+    }
+    (fans, power)
+}
+
+/// One battery under `class/power_supply`
+///
+/// Batteries report energy in µWh and power in µW, or charge in µAh and
+/// current in µA, which are converted with the voltage.
+fn battery(dir: &Path) -> Option<BatteryInfo> {
+    let name = dir.file_name()?.to_string_lossy().to_string();
+    let status = attribute(dir, "status").unwrap_or_else(|| "Unknown".to_string());
+    let voltage = number(dir, "voltage_min_design").or_else(|| number(dir, "voltage_now")).map(|uv| uv / 1e6);
+    let energy = |kind: &str| {
+        number(dir, &format!("energy_{}", kind))
+            .map(|uwh| uwh / 1e6)
+            .or_else(|| Some(number(dir, &format!("charge_{}", kind))? / 1e6 * voltage?))
+    };
+    let energy_now = energy("now");
+    let energy_full = energy("full");
+    let energy_full_design = energy("full_design");
+    let power = number(dir, "power_now")
+        .map(|uw| uw / 1e6)
+        .or_else(|| Some(number(dir, "current_now")? / 1e6 * number(dir, "voltage_now")? / 1e6))
+        .map(f64::abs);
+
+    let charge_percent = number(dir, "capacity").or_else(|| Some(energy_now? / energy_full? * 100.0));
+    let health_percent = match (energy_full, energy_full_design) {
+        (Some(full), Some(design)) if design > 0.0 => Some(full / design * 100.0),
+        _ => None,
+    };
+    let drawing = power.filter(|watts| *watts > 0.0);
+    let time_to_empty = number(dir, "time_to_empty_now").or_else(|| {
+        (status == "Discharging").then_some(())?;
+        Some(energy_now? / drawing? * 3600.0)
+    });
+    let time_to_full = number(dir, "time_to_full_now").or_else(|| {
+        (status == "Charging").then_some(())?;
+        Some((energy_full? - energy_now?).max(0.0) / drawing? * 3600.0)
+    });
+
+    Some(BatteryInfo {
+        name,
+        status,
+        charge_percent: charge_percent.map(|percent| percent as f32),
+        health_percent: health_percent.map(|percent| percent as f32),
+        energy_wh: energy_now.map(|wh| wh as f32),
+        energy_full_wh: energy_full.map(|wh| wh as f32),
+        energy_full_design_wh: energy_full_design.map(|wh| wh as f32),
+        power_watts: power.map(|watts| watts as f32),
+        time_to_empty_secs: time_to_empty.map(|secs| secs as u64),
+        time_to_full_secs: time_to_full.map(|secs| secs as u64),
+        cycle_count: number(dir, "cycle_count").map(|count| count as u32).filter(|count| *count > 0),
+        technology: attribute(dir, "technology"),
+        model: attribute(dir, "model_name"),
+    })
+}
+
+/// Batteries and whether an AC adapter is online, under `class/power_supply`
+///
+/// # Returns
+/// * `(Vec<BatteryInfo>, Option<bool>)` - Batteries, and `None` for the
+///   adapter if the machine reports none
+pub fn power_supplies(class: &Path) -> (Vec<BatteryInfo>, Option<bool>) {
+    let mut batteries = Vec::new();
+    let mut on_ac_power = None;
+    for supply in class_entries(&class.join("power_supply")) {
+        match attribute(&supply, "type").as_deref() {
+            Some("Battery") => batteries.extend(battery(&supply)),
+            Some("Mains") | Some("USB") => {
+                let online = attribute(&supply, "online").as_deref() == Some("1");
+                on_ac_power = Some(on_ac_power.unwrap_or(false) || online);
+            }
+            _ => {}
+        }
+    }
+    (batteries, on_ac_power)
+}
+
+/// Every sensor reading
+///
+/// # Arguments
+/// * `components` - sysinfo components, refreshed
+/// * `class` - sysfs class directory, normally `SYSFS_CLASS`
+pub fn hardware_sensors(components: &Components, class: &Path) -> HardwareSensors {
+    let mut temperatures = component_temperatures(components);
+    temperatures.extend(thermal_zones(class));
+    let (fans, power) = hwmon_readings(class);
+    let (batteries, on_ac_power) = power_supplies(class);
+    HardwareSensors { temperatures, fans, power, batteries, on_ac_power }
+}
+
+/// Highest temperature reported by any sensor
+pub fn hottest(sensors: &HardwareSensors) -> Option<f32> {
+    sensors.temperatures.iter().map(|reading| reading.temperature).reduce(f32::max)
+}
+
+/// Charge of all batteries together, in percent
+///
+/// Batteries are weighted by their capacity when it is known.
+pub fn battery_charge(sensors: &HardwareSensors) -> Option<f32> {
+    let charges: Vec<(f32, f32)> = sensors
+        .batteries
+        .iter()
+        .filter_map(|battery| Some((battery.charge_percent?, battery.energy_full_wh.unwrap_or(1.0))))
+        .collect();
+    let capacity: f32 = charges.iter().map(|(_, weight)| weight).sum();
+    (capacity > 0.0).then(|| charges.iter().map(|(charge, weight)| charge * weight).sum::<f32>() / capacity)
+}
+
+/// Power drawn from the batteries, or measured by power meters when on AC
+pub fn power_draw(sensors: &HardwareSensors) -> Option<f32> {
+    let discharging: Vec<f32> = sensors
+        .batteries
+        .iter()
+        .filter(|battery| battery.status == "Discharging")
+        .filter_map(|battery| battery.power_watts)
+        .collect();
+    if !discharging.is_empty() {
+        return Some(discharging.iter().sum());
+    }
+    (!sensors.power.is_empty()).then(|| sensors.power.iter().map(|reading| reading.watts).sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempTree;
+
+    /// A fake sysfs class directory, removed when dropped
+    #[test]
+    fn reads_batteries_and_adapters() {
+        let sysfs = TempTree::new();
+        sysfs.write("power_supply/AC/type", "Mains");
+        sysfs.write("power_supply/AC/online", "0");
+        sysfs.write("power_supply/BAT0/type", "Battery");
+        sysfs.write("power_supply/BAT0/status", "Discharging");
+        sysfs.write("power_supply/BAT0/energy_now", "30000000");
+        sysfs.write("power_supply/BAT0/energy_full", "45000000");
+        sysfs.write("power_supply/BAT0/energy_full_design", "50000000");
+        sysfs.write("power_supply/BAT0/power_now", "10000000");
+        sysfs.write("power_supply/BAT0/capacity", "66");
+        sysfs.write("power_supply/BAT0/cycle_count", "0");
+        // Charge-based battery without a capacity file
+        sysfs.write("power_supply/BAT1/type", "Battery");
+        sysfs.write("power_supply/BAT1/status", "Charging");
+        sysfs.write("power_supply/BAT1/charge_now", "2000000");
+        sysfs.write("power_supply/BAT1/charge_full", "4000000");
+        sysfs.write("power_supply/BAT1/voltage_min_design", "10000000");
+        sysfs.write("power_supply/BAT1/current_now", "1000000");
+        sysfs.write("power_supply/BAT1/voltage_now", "10000000");
+
+        let (batteries, on_ac_power) = power_supplies(sysfs.path());
+        assert_eq!(on_ac_power, Some(false));
+        assert_eq!(batteries.len(), 2);
+
+        let bat0 = &batteries[0];
+        assert_eq!(bat0.charge_percent, Some(66.0));
+        assert_eq!(bat0.health_percent, Some(90.0));
+        assert_eq!(bat0.power_watts, Some(10.0));
+        assert_eq!(bat0.time_to_empty_secs, Some(3 * 3600));
+        assert_eq!(bat0.time_to_full_secs, None);
+        assert_eq!(bat0.cycle_count, None);
+
+        let bat1 = &batteries[1];
+        assert_eq!(bat1.energy_wh, Some(20.0));
+        assert_eq!(bat1.charge_percent, Some(50.0));
+        assert_eq!(bat1.health_percent, None);
+        assert_eq!(bat1.time_to_full_secs, Some(2 * 3600));
+
+        let sensors = HardwareSensors { batteries, on_ac_power, ..Default::default() };
+        assert_eq!(battery_charge(&sensors), Some((66.0 * 45.0 + 50.0 * 40.0) / 85.0));
+        assert_eq!(power_draw(&sensors), Some(10.0));
+    }
+
+    #[test]
+    fn reads_hwmon_and_thermal_zones() {
+        let sysfs = TempTree::new();
+        sysfs.write("hwmon/hwmon0/name", "thinkpad");
+        sysfs.write("hwmon/hwmon0/fan1_input", "2400");
+        sysfs.write("hwmon/hwmon0/fan2_input", "0");
+        sysfs.write("hwmon/hwmon0/fan2_label", "GPU fan");
+        sysfs.write("hwmon/hwmon1/name", "amdgpu");
+        sysfs.write("hwmon/hwmon1/power1_average", "15500000");
+        sysfs.write("thermal/thermal_zone0/type", "acpitz");
+        sysfs.write("thermal/thermal_zone0/temp", "47500");
+        sysfs.write("thermal/thermal_zone0/trip_point_0_type", "passive");
+        sysfs.write("thermal/thermal_zone0/trip_point_0_temp", "90000");
+        sysfs.write("thermal/thermal_zone0/trip_point_1_type", "critical");
+        sysfs.write("thermal/thermal_zone0/trip_point_1_temp", "105000");
+        sysfs.write("thermal/cooling_device0/type", "Processor");
+
+        let (fans, power) = hwmon_readings(sysfs.path());
+        assert_eq!(fans.iter().map(|fan| (fan.label.as_str(), fan.rpm)).collect::<Vec<_>>(), vec![("thinkpad fan1", 2400), ("GPU fan", 0)]);
+        assert_eq!(power.len(), 1);
+        assert_eq!(power[0].watts, 15.5);
+
+        let zones = thermal_zones(sysfs.path());
+        assert_eq!(zones.len(), 1);
+        assert_eq!(zones[0].label, "acpitz thermal_zone0");
+        assert_eq!(zones[0].temperature, 47.5);
+        assert_eq!(zones[0].critical, Some(105.0));
+
+        let sensors = HardwareSensors { temperatures: zones, power, ..Default::default() };
+        assert_eq!(hottest(&sensors), Some(47.5));
+        assert_eq!(power_draw(&sensors), Some(15.5));
+    }
+
+    #[test]
+    fn reports_nothing_without_sysfs() {
+        let sysfs = TempTree::new();
+        let (batteries, on_ac_power) = power_supplies(sysfs.path());
+        assert!(batteries.is_empty());
+        assert_eq!(on_ac_power, None);
+        let sensors = HardwareSensors::default();
+        assert_eq!(hottest(&sensors), None);
+        assert_eq!(battery_charge(&sensors), None);
+        assert_eq!(power_draw(&sensors), None);
+    }
+}
@@ -0,0 +1,363 @@
+/*
+ * Leara AI Assistant - systemd
+ *
+ * This module builds the systemctl and journalctl command lines behind the
+ * service endpoints and parses what they print: the unit list, the
+ * properties of one unit and journal entries in journalctl's JSON format.
+ * The commands themselves run through the command policy like any other.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/systemd.rs
+ * Purpose: systemd unit and journal commands and their output
+ */
+
+use std::collections::HashMap;
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::Value;
+use crate::models::system::{JournalEntry, SystemdUnit, UnitStatus};
+
+/// Actions that may be taken on units
+pub const UNIT_ACTIONS: &[&str] = &["start", "stop", "restart"];
+
+/// Journal priorities by level
+pub const PRIORITIES: &[&str] = &["emerg", "alert", "crit", "err", "warning", "notice", "info", "debug"];
+
+/// Properties read for a unit's status
+const STATUS_PROPERTIES: &[&str] = &[
+    "Id", "Description", "LoadState", "ActiveState", "SubState", "UnitFileState",
+    "FragmentPath", "MainPID", "ActiveEnterTimestamp", "StateChangeTimestamp",
+    "MemoryCurrent", "CPUUsageNSec", "TasksCurrent", "NRestarts", "Result",
+];
+
+/// Check a unit name before it is passed to systemctl or journalctl
+///
+/// # Returns
+/// * `Ok(())` - The name only uses characters systemd allows in unit names
+/// * `Err(String)` - The name is empty, too long or could be read as an option
+pub fn validate_unit_name(unit: &str) -> Result<(), String> {
+    if unit.is_empty() || unit.len() > 256 {
+        return Err("Unit names must be between 1 and 256 characters".to_string());
+    }
+    if unit.starts_with('-') {
+        return Err(format!("Invalid unit name: {}", unit));
+    }
+    if !unit.chars().all(|c| c.is_ascii_alphanumeric() || ":-_.@\\".contains(c)) {
+        return Err(format!("Invalid unit name: {}", unit));
+    }
+    Ok(())
+}
+
+/// Normalize a unit action such as `Restart`
+///
+/// # Returns
+/// * `Ok(&str)` - The action as systemctl takes it
+/// * `Err(String)` - The action is unknown or may not be taken
+pub fn parse_unit_action(action: &str) -> Result<&'static str, String> {
+    let action = action.trim().to_ascii_lowercase();
+    UNIT_ACTIONS
+        .iter()
+        .find(|allowed| **allowed == action)
+        .copied()
+        .ok_or_else(|| format!("Unsupported action {:?}; use one of {}", action, UNIT_ACTIONS.join(", ")))
+}
+
+/// Journal priority level from a name such as `warning` or a number `0`-`7`
+pub fn parse_priority(priority: &str) -> Result<u8, String> {
+    let priority = priority.trim().to_ascii_lowercase();
+    let aliased = match priority.as_str() {
+        "error" => "err",
+        "warn" => "warning",
+        "emergency" => "emerg",
+        "critical" => "crit",
+        other => other,
+    };
+    if let Some(level) = PRIORITIES.iter().position(|name| *name == aliased) {
+        return Ok(level as u8);
+    }
+    match priority.parse::<u8>() {
+        Ok(level) if (level as usize) < PRIORITIES.len() => Ok(level),
+        _ => Err(format!("Unknown priority {:?}; use 0-7 or one of {}", priority, PRIORITIES.join(", "))),
+    }
+}
+
+/// Arguments for `systemctl` listing units
+///
+/// # Arguments
+/// * `unit_type` - Unit type such as `service` or `timer`
+pub fn list_units_args(unit_type: &str) -> Vec<String> {
+    ["list-units", "--all", "--plain", "--no-legend", "--no-pager"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .chain(std::iter::once(format!("--type={}", unit_type)))
+        .collect()
+}
+
+/// Arguments for `systemctl` reading the status properties of a unit
+pub fn show_unit_args(unit: &str) -> Vec<String> {
+    vec![
+        "show".to_string(),
+        "--no-pager".to_string(),
+        "--timestamp=unix".to_string(),
+        format!("--property={}", STATUS_PROPERTIES.join(",")),
+        "--".to_string(),
+        unit.to_string(),
+    ]
+}
+
+/// What to read from the journal
+#[derive(Debug, Default)]
+pub struct JournalFilter {
+    pub unit: Option<String>,
+    /// Highest priority level to include, 0 (emerg) to 7 (debug)
+    pub priority: Option<u8>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Pattern matched against the message by journalctl
+    pub grep: Option<String>,
+    /// Most recent entries to return
+    pub limit: usize,
+}
+
+impl JournalFilter {
+    /// Arguments for `journalctl`, newest entries last
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--output=json".to_string(),
+            "--no-pager".to_string(),
+            format!("--lines={}", self.limit),
+        ];
+        if let Some(ref unit) = self.unit {
+            args.push(format!("--unit={}", unit));
+        }
+        if let Some(priority) = self.priority {
+            args.push(format!("--priority={}", priority));
+        }
+        if let Some(since) = self.since {
+            args.push(format!("--since=@{}", since.timestamp()));
+        }
+        if let Some(until) = self.until {
+            args.push(format!("--until=@{}", until.timestamp()));
+        }
+        if let Some(ref grep) = self.grep {
+            args.push(format!("--grep={}", grep));
+        }
+        args
+    }
+}
+
+/// Parse `systemctl list-units --plain --no-legend`
+///
+/// Each line holds the unit, its load, active and sub state, and the rest
+/// is the description.
+pub fn parse_unit_list(output: &str) -> Vec<SystemdUnit> {
+    output
+        .lines()
+        .filter_map(|line| {
+            // Some versions still mark failed units with a bullet
+            let mut rest = line.trim_start_matches(|c: char| c == '●' || c == '*' || c.is_whitespace());
+            let mut columns = Vec::with_capacity(4);
+            while columns.len() < 4 {
+                let (column, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                if column.is_empty() {
+                    return None;
+                }
+                columns.push(column);
+                rest = tail.trim_start();
+            }
+            Some(SystemdUnit {
+                name: columns[0].to_string(),
+                load_state: columns[1].to_string(),
+                active_state: columns[2].to_string(),
+                sub_state: columns[3].to_string(),
+                description: rest.trim_end().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parse `systemctl show` output of `show_unit_args`
+///
+/// # Returns
+/// * `Some(UnitStatus)` - The unit
+/// * `None` - systemd does not know the unit
+pub fn parse_unit_status(output: &str) -> Option<UnitStatus> {
+    let properties: HashMap<&str, &str> = output
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .collect();
+    let text = |name: &str| properties.get(name).filter(|value| !value.is_empty()).map(|value| value.to_string());
+    // Unset counters are reported as "[not set]" or as the largest u64
+    let number = |name: &str| {
+        properties
+            .get(name)
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|value| *value != u64::MAX)
+    };
+    let timestamp = |name: &str| {
+        properties
+            .get(name)
+            .and_then(|value| value.strip_prefix('@'))
+            .and_then(|secs| secs.parse::<i64>().ok())
+            .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+    };
+
+    let load_state = text("LoadState")?;
+    if load_state == "not-found" {
+        return None;
+    }
+    Some(UnitStatus {
+        name: text("Id")?,
+        description: text("Description"),
+        load_state,
+        active_state: text("ActiveState").unwrap_or_default(),
+        sub_state: text("SubState").unwrap_or_default(),
+        unit_file_state: text("UnitFileState"),
+        fragment_path: text("FragmentPath"),
+        main_pid: number("MainPID").filter(|pid| *pid != 0).map(|pid| pid as u32),
+        active_since: timestamp("ActiveEnterTimestamp"),
+        state_changed_at: timestamp("StateChangeTimestamp"),
+        memory_current: number("MemoryCurrent"),
+        cpu_usage_nsec: number("CPUUsageNSec"),
+        tasks_current: number("TasksCurrent"),
+        restarts: number("NRestarts"),
+        result: text("Result"),
+        recent_logs: Vec::new(),
+    })
+}
+
+/// Journal field as text
+///
+/// journalctl prints fields that are not valid UTF-8 as arrays of bytes.
+fn field_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(bytes) => {
+            let bytes: Vec<u8> = bytes.iter().filter_map(|byte| byte.as_u64()).map(|byte| byte as u8).collect();
+            Some(String::from_utf8_lossy(&bytes).to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Parse `journalctl --output=json`, one entry per line
+///
+/// Lines that are not journal entries, such as a line cut off by the output
+/// limit, are skipped.
+pub fn parse_journal(output: &str) -> Vec<JournalEntry> {
+    output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Map<String, Value>>(line).ok())
+        .filter_map(|fields| {
+            let text = |name: &str| fields.get(name).and_then(field_text);
+            let micros = text("__REALTIME_TIMESTAMP")?.parse::<i64>().ok()?;
+            let priority = text("PRIORITY").and_then(|level| level.parse::<u8>().ok()).unwrap_or(6);
+            Some(JournalEntry {
+                timestamp: Utc.timestamp_micros(micros).single()?,
+                priority,
+                priority_name: PRIORITIES.get(priority as usize).unwrap_or(&"info").to_string(),
+                unit: text("_SYSTEMD_UNIT").or_else(|| text("UNIT")),
+                identifier: text("SYSLOG_IDENTIFIER").or_else(|| text("_COMM")),
+                pid: text("_PID").and_then(|pid| pid.parse().ok()),
+                message: text("MESSAGE").unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_unit_names_and_actions() {
+        assert!(validate_unit_name("nginx.service").is_ok());
+        assert!(validate_unit_name("getty@tty1.service").is_ok());
+        assert!(validate_unit_name("dev-disk-by\\x2duuid.device").is_ok());
+        assert!(validate_unit_name("--all").is_err());
+        assert!(validate_unit_name("a b.service").is_err());
+        assert!(validate_unit_name("").is_err());
+
+        assert_eq!(parse_unit_action("Restart"), Ok("restart"));
+        assert!(parse_unit_action("mask").is_err());
+        assert_eq!(parse_priority("warning"), Ok(4));
+        assert_eq!(parse_priority("ERROR"), Ok(3));
+        assert_eq!(parse_priority("7"), Ok(7));
+        assert!(parse_priority("8").is_err());
+    }
+
+    #[test]
+    fn parses_unit_list() {
+        let output = "cron.service            loaded    active   running Regular background program processing daemon\n\
+                      ● nginx.service         loaded    failed   failed  A high performance web server\n\
+                      foo.service             not-found inactive dead    foo.service\n\
+                      \n";
+        let units = parse_unit_list(output);
+        assert_eq!(units.len(), 3);
+        assert_eq!(units[0].name, "cron.service");
+        assert_eq!(units[0].sub_state, "running");
+        assert_eq!(units[0].description, "Regular background program processing daemon");
+        assert_eq!(units[1].name, "nginx.service");
+        assert_eq!(units[1].active_state, "failed");
+        assert_eq!(units[2].load_state, "not-found");
+    }
+
+    #[test]
+    fn parses_unit_status() {
+        let output = "Id=nginx.service\nDescription=A high performance web server\nLoadState=loaded\n\
+                      ActiveState=active\nSubState=running\nUnitFileState=enabled\nMainPID=812\n\
+                      ActiveEnterTimestamp=@1719650000\nStateChangeTimestamp=\nMemoryCurrent=[not set]\n\
+                      CPUUsageNSec=18446744073709551615\nTasksCurrent=3\nNRestarts=1\nResult=success\n";
+        let status = parse_unit_status(output).unwrap();
+        assert_eq!(status.name, "nginx.service");
+        assert_eq!(status.main_pid, Some(812));
+        assert_eq!(status.active_since.unwrap().timestamp(), 1_719_650_000);
+        assert_eq!(status.state_changed_at, None);
+        assert_eq!(status.memory_current, None);
+        assert_eq!(status.cpu_usage_nsec, None);
+        assert_eq!(status.tasks_current, Some(3));
+
+        assert!(parse_unit_status("Id=nope.service\nLoadState=not-found\n").is_none());
+    }
+
+    #[test]
+    fn parses_journal_entries_and_builds_args() {
+        let output = concat!(
+            r#"{"__REALTIME_TIMESTAMP":"1719650000123456","PRIORITY":"3","_SYSTEMD_UNIT":"nginx.service","SYSLOG_IDENTIFIER":"nginx","_PID":"812","MESSAGE":"bind() failed"}"#,
+            "\n",
+            r#"{"__REALTIME_TIMESTAMP":"1719650001000000","MESSAGE":[104,105,255]}"#,
+            "\n",
+            r#"{"__REALTIME_TIMESTAMP":"17196"#,
+        );
+        let entries = parse_journal(output);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].priority_name, "err");
+        assert_eq!(entries[0].pid, Some(812));
+        assert_eq!(entries[0].timestamp.timestamp_subsec_micros(), 123_456);
+        assert_eq!(entries[1].message, "hi\u{fffd}");
+        assert_eq!(entries[1].priority, 6);
+
+        let filter = JournalFilter {
+            unit: Some("nginx.service".to_string()),
+            priority: Some(3),
+            since: Utc.timestamp_opt(1_719_650_000, 0).single(),
+            grep: Some("failed".to_string()),
+            limit: 50,
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.args(),
+            vec!["--output=json", "--no-pager", "--lines=50", "--unit=nginx.service", "--priority=3", "--since=@1719650000", "--grep=failed"]
+        );
+    }
+}
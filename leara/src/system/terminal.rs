@@ -0,0 +1,278 @@
+/*
+ * Leara AI Assistant - Terminal Sessions
+ *
+ * This module runs interactive programs, usually the user's shell, on a
+ * pseudo-terminal. The API relays the terminal's input, output and size
+ * over a WebSocket; the output is also recorded as a transcript so the
+ * assistant can look back at what happened in a session.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/terminal.rs
+ * Purpose: Pseudo-terminal sessions and transcripts
+ */
+
+use regex::Regex;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::process::{ExitStatus, Stdio};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::unix::AsyncFd;
+use tokio::process::Child;
+use crate::system::executor::{apply_rlimits, base_command, signal_group, terminate, CommandSpec, ExecutionLimits};
+
+/// Time a hung-up session gets to exit before it is terminated
+const HANGUP_GRACE: Duration = Duration::from_secs(1);
+
+/// Terminal type announced to programs running in a session
+pub const TERM: &str = "xterm-256color";
+
+/// Size of a terminal in character cells
+#[derive(Debug, Clone, Copy)]
+pub struct TerminalSize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl Default for TerminalSize {
+    fn default() -> Self {
+        TerminalSize { cols: 80, rows: 24 }
+    }
+}
+
+impl TerminalSize {
+    fn winsize(&self) -> libc::winsize {
+        libc::winsize {
+            ws_row: self.rows.max(1),
+            ws_col: self.cols.max(1),
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        }
+    }
+}
+
+/// Controlling side of a pseudo-terminal
+pub struct Pty {
+    master: AsyncFd<OwnedFd>,
+}
+
+impl Pty {
+    /// Read output written by the program
+    ///
+    /// # Returns
+    /// * `Ok(0)` - The terminal was closed by every process using it
+    /// * `Ok(n)` - Number of bytes read into `buf`
+    pub async fn read(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let mut guard = self.master.readable().await?;
+            // SAFETY: the descriptor is owned by `self.master` and `buf` is valid for its length
+            let result = guard.try_io(|fd| {
+                cvt(unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) })
+            });
+            match result {
+                // Linux reports EIO once the last slave descriptor is closed
+                Ok(Err(e)) if e.raw_os_error() == Some(libc::EIO) => return Ok(0),
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Send input to the program as if it was typed
+    pub async fn write(&self, mut data: &[u8]) -> std::io::Result<()> {
+        while !data.is_empty() {
+            let mut guard = self.master.writable().await?;
+            // SAFETY: the descriptor is owned by `self.master` and `data` is valid for its length
+            let result = guard.try_io(|fd| {
+                cvt(unsafe { libc::write(fd.as_raw_fd(), data.as_ptr().cast(), data.len()) })
+            });
+            if let Ok(written) = result {
+                data = &data[written?..];
+            }
+        }
+        Ok(())
+    }
+
+    /// Change the terminal size; the program receives SIGWINCH
+    pub fn resize(&self, size: TerminalSize) -> std::io::Result<()> {
+        let winsize = size.winsize();
+        // SAFETY: TIOCSWINSZ only reads the winsize struct passed by pointer
+        if unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &winsize) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+fn cvt(result: libc::ssize_t) -> std::io::Result<usize> {
+    if result < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(result as usize)
+    }
+}
+
+/// A program running on its own pseudo-terminal
+pub struct TerminalProcess {
+    pub pty: Pty,
+    pub child: Child,
+    pid: Option<u32>,
+}
+
+impl TerminalProcess {
+    /// Start a program on a new pseudo-terminal
+    ///
+    /// The program becomes the leader of a new session with the terminal as
+    /// its controlling terminal, so job control and Ctrl-C work as usual.
+    /// The policy's rlimits apply; its timeouts do not, as a session lasts
+    /// as long as the user keeps it open.
+    ///
+    /// # Arguments
+    /// * `spec` - Program to execute, which must already have passed the policy
+    /// * `size` - Initial terminal size
+    /// * `limits` - Limits from the execution policy
+    ///
+    /// # Returns
+    /// * `Ok(TerminalProcess)` - The running program
+    /// * `Err(std::io::Error)` - No terminal could be allocated or the program could not be started
+    pub fn spawn(
+        spec: &CommandSpec,
+        size: TerminalSize,
+        limits: &ExecutionLimits,
+    ) -> std::io::Result<Self> {
+        let (master, slave) = open_pty(size)?;
+
+        let mut cmd = base_command(spec);
+        cmd.stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave))
+            .kill_on_drop(true);
+        // SAFETY: the closure runs between fork and exec and only calls
+        // setsid(2) and ioctl(2), which are async-signal-safe
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                if libc::ioctl(0, libc::TIOCSCTTY, 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        apply_rlimits(&mut cmd, limits);
+
+        // The slave descriptors are closed with `cmd`, so reads see EOF once the program exits
+        let child = cmd.spawn()?;
+        drop(cmd);
+        let pid = child.id();
+        // SAFETY: the OwnedFd is moved into the AsyncFd and stays open until it is dropped
+        let master = unsafe { AsyncFd::register(master) }.map_err(std::io::Error::from)?;
+        Ok(TerminalProcess { pty: Pty { master }, child, pid })
+    }
+
+    /// End the session the way closing a terminal window does
+    ///
+    /// Every process in the session receives SIGHUP, including background
+    /// jobs the shell moved to other process groups; whatever survives it
+    /// is killed.
+    pub async fn hangup(&mut self) -> std::io::Result<ExitStatus> {
+        let Some(pid) = self.pid else {
+            return terminate(&mut self.child, None).await;
+        };
+        signal_session(pid, libc::SIGHUP);
+        signal_session(pid, libc::SIGCONT);
+        let status = match tokio::time::timeout(HANGUP_GRACE, self.child.wait()).await {
+            Ok(status) => status?,
+            Err(_) => terminate(&mut self.child, Some(pid)).await?,
+        };
+        signal_session(pid, libc::SIGKILL);
+        Ok(status)
+    }
+}
+
+/// Send a signal to every process of a session
+fn signal_session(sid: u32, signal: libc::c_int) {
+    signal_group(sid, signal);
+    for pid in session_members(sid) {
+        // SAFETY: kill(2) only sends a signal
+        unsafe { libc::kill(pid as libc::pid_t, signal) };
+    }
+}
+
+/// Processes whose session ID is `sid`, found through /proc
+fn session_members(sid: u32) -> Vec<u32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+            let stat = std::fs::read_to_string(entry.path().join("stat")).ok()?;
+            // Fields after the command name: state, ppid, pgrp, session
+            let session: u32 = stat.rsplit_once(')')?.1.split_whitespace().nth(3)?.parse().ok()?;
+            (session == sid).then_some(pid)
+        })
+        .collect()
+}
+
+/// Allocate a pseudo-terminal pair with a non-blocking master
+fn open_pty(size: TerminalSize) -> std::io::Result<(OwnedFd, OwnedFd)> {
+    let mut master: libc::c_int = -1;
+    let mut slave: libc::c_int = -1;
+    let winsize = size.winsize();
+    // SAFETY: openpty(3) writes two descriptors on success and reads the optional winsize
+    let result = unsafe { libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null(), &winsize) };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: both descriptors were just opened and are owned by nobody else
+    let (master, slave) = unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+
+    // The master must not leak into the program, and is polled by tokio
+    // SAFETY: fcntl(2) on a descriptor we own
+    unsafe {
+        let fd = master.as_raw_fd();
+        if libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) != 0
+            || libc::fcntl(fd, libc::F_SETFL, libc::fcntl(fd, libc::F_GETFL) | libc::O_NONBLOCK) != 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok((master, slave))
+}
+
+/// Turn raw terminal output into plain text
+///
+/// Escape sequences (colors, cursor movement, window titles) are removed,
+/// backspaces erase the previous character and line endings are normalized.
+pub fn plain_text(raw: &str) -> String {
+    static ESCAPES: OnceLock<Regex> = OnceLock::new();
+    let escapes = ESCAPES.get_or_init(|| {
+        Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(\x07|\x1b\\)|\x1b[PX^_][^\x1b]*\x1b\\|\x1b[ -/]*[0-~]")
+            .expect("valid escape sequence pattern")
+    });
+
+    let mut text = String::with_capacity(raw.len());
+    for c in escapes.replace_all(raw, "").chars() {
+        match c {
+            '\u{8}' => {
+                text.pop();
+            }
+            '\r' | '\u{7}' => {}
+            _ => text.push(c),
+        }
+    }
+    text
+}
@@ -0,0 +1,738 @@
+/*
+ * Leara AI Assistant - Assistant Tools
+ *
+ * This module defines the tools the chat model can call: running commands,
+ * reading and finding files, searching memory, creating tasks and looking at the
+ * system. Every tool goes through the same checks as the matching API
+ * endpoint, so commands are subject to the command policy and its
+ * confirmation flow and file reads to the file roots.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/tools.rs
+ * Purpose: Tool registry and execution for the chat model
+ */
+
+use axum::extract::{Json, Query, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Instant;
+use tracing::info;
+use crate::api::system::{
+    find_indexed_files, journal_filter, list_units, read_journal, search_file_index, submit_command, unit_status,
+    AppSearchQuery, CommandError, CommandSubmission, ExecuteCommandRequest, FileReadQuery, IndexSearchQuery, IndexedFileQuery,
+    JournalQuery, MetricsQuery, PendingCommand, ServiceActionRequest, ServiceListQuery,
+};
+use crate::models::{AppState, TaskRequest, ToolCallStatus, ToolCallTrace};
+use crate::system::executor::truncate_output;
+use crate::utils::ollama::{OllamaFunction, OllamaTool};
+
+/// Bytes of command output or file content returned to the model
+const MAX_RESULT_BYTES: usize = 16 * 1024;
+
+/// Entries returned by listing and search tools unless the model asks for fewer
+const DEFAULT_LIST_LIMIT: usize = 20;
+
+/// Points of the metrics history returned to the model
+const METRIC_POINTS: i64 = 12;
+
+/// Services listed to the model; there can be hundreds
+const MAX_SERVICES: usize = 100;
+
+/// Bytes of each journal message returned to the model
+const MAX_MESSAGE_BYTES: usize = 1024;
+
+/// A tool the model can call
+struct ToolSpec {
+    name: &'static str,
+    description: &'static str,
+    /// JSON schema of the arguments
+    parameters: fn() -> Value,
+}
+
+const TOOLS: &[ToolSpec] = &[
+    ToolSpec {
+        name: "run_command",
+        description: "Run a program on the user's computer and return its exit code and output. \
+                      Commands are checked against the command policy; some are blocked and some \
+                      wait for the user to confirm them.",
+        parameters: || json!({
+            "type": "object",
+            "properties": {
+                "command": { "type": "string", "description": "Program to run, or a script when shell is true" },
+                "args": { "type": "array", "items": { "type": "string" }, "description": "Arguments, passed without expansion" },
+                "working_dir": { "type": "string", "description": "Working directory" },
+                "shell": { "type": "boolean", "description": "Run command as a /bin/sh script; args become \"$1\", \"$2\", ..." },
+                "timeout_secs": { "type": "integer", "description": "Wall-clock timeout in seconds" }
+            },
+            "required": ["command"]
+        }),
+    },
+    ToolSpec {
+        name: "read_file",
+        description: "Read a text file in the directories the user allows. Relative paths are resolved against the user's home directory.",
+        parameters: || json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Path of the file" },
+                "offset": { "type": "integer", "description": "Byte to start at; use next_offset of the previous read to continue" },
+                "max_bytes": { "type": "integer", "description": "Bytes to read" }
+            },
+            "required": ["path"]
+        }),
+    },
+    ToolSpec {
+        name: "search_files",
+        description: "Find files in the directories the user indexed, by the text they contain or by name, \
+                      e.g. the config file that mentions a setting.",
+        parameters: || json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "Words the file must contain; \"quoted phrases\" and prefix* work" },
+                "name": { "type": "string", "description": "Glob the file name must match, e.g. \"*.toml\"" },
+                "limit": { "type": "integer", "description": "Maximum number of files" }
+            }
+        }),
+    },
+    ToolSpec {
+        name: "search_memory",
+        description: "Search what Leara remembers about the user by key, value or category.",
+        parameters: || json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "Text to search for" },
+                "limit": { "type": "integer", "description": "Maximum number of memories" }
+            },
+            "required": ["query"]
+        }),
+    },
+    ToolSpec {
+        name: "create_task",
+        description: "Create a task or reminder for the user.",
+        parameters: || json!({
+            "type": "object",
+            "properties": {
+                "title": { "type": "string" },
+                "description": { "type": "string" },
+                "priority": { "type": "integer", "description": "1 (highest) to 5 (lowest), default 3" },
+                "due_date": { "type": "string", "description": "RFC 3339 date and time" },
+                "tags": { "type": "string", "description": "Comma-separated tag names" }
+            },
+            "required": ["title"]
+        }),
+    },
+    ToolSpec {
+        name: "get_system_info",
+        description: "Get the hostname, operating system, kernel, CPU count, memory and uptime.",
+        parameters: || json!({ "type": "object", "properties": {} }),
+    },
+    ToolSpec {
+        name: "get_metrics_history",
+        description: "Look up past CPU, memory, swap, disk and network usage of the user's computer, \
+                      including which programs used the most CPU and memory.",
+        parameters: || json!({
+            "type": "object",
+            "properties": {
+                "from_minutes_ago": { "type": "integer", "description": "Start of the period in minutes before now, default 60" },
+                "to_minutes_ago": { "type": "integer", "description": "End of the period in minutes before now, default 0" }
+            }
+        }),
+    },
+    ToolSpec {
+        name: "list_apps",
+        description: "Find applications installed on the user's computer, most relevant and most used first.",
+        parameters: || json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "What to look for, e.g. \"browser\" or \"image editor\"; matched fuzzily against names, commands, descriptions, keywords and categories" },
+                "limit": { "type": "integer", "description": "Maximum number of applications" }
+            }
+        }),
+    },
+    ToolSpec {
+        name: "list_services",
+        description: "List systemd services with their load, active and sub state, e.g. to find failed services.",
+        parameters: || json!({
+            "type": "object",
+            "properties": {
+                "state": { "type": "string", "description": "Only services in this active state: \"active\", \"inactive\" or \"failed\"" },
+                "name": { "type": "string", "description": "Text the service name or description must contain" }
+            }
+        }),
+    },
+    ToolSpec {
+        name: "get_service_status",
+        description: "Get the state, main PID, memory, restarts and latest log lines of a systemd unit.",
+        parameters: || json!({
+            "type": "object",
+            "properties": {
+                "unit": { "type": "string", "description": "Unit name, e.g. \"nginx.service\"" }
+            },
+            "required": ["unit"]
+        }),
+    },
+    ToolSpec {
+        name: "query_journal",
+        description: "Read the systemd journal, newest entries last, to find out why a service failed.",
+        parameters: || json!({
+            "type": "object",
+            "properties": {
+                "unit": { "type": "string", "description": "Only entries of this unit" },
+                "priority": { "type": "string", "description": "Highest priority to include: emerg, alert, crit, err, warning, notice, info or debug" },
+                "since_minutes_ago": { "type": "integer", "description": "Only entries from the last this many minutes" },
+                "grep": { "type": "string", "description": "Regular expression the message must match" },
+                "limit": { "type": "integer", "description": "Maximum number of entries, default 20" }
+            }
+        }),
+    },
+];
+
+/// Tools in the form Ollama's chat API expects
+pub fn definitions() -> Vec<OllamaTool> {
+    TOOLS
+        .iter()
+        .map(|tool| OllamaTool {
+            kind: "function".to_string(),
+            function: OllamaFunction {
+                name: tool.name.to_string(),
+                description: tool.description.to_string(),
+                parameters: (tool.parameters)(),
+            },
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct RunCommandArgs {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    working_dir: Option<String>,
+    shell: Option<bool>,
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReadFileArgs {
+    path: String,
+    offset: Option<u64>,
+    max_bytes: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchFilesArgs {
+    query: Option<String>,
+    name: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchMemoryArgs {
+    query: String,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateTaskArgs {
+    title: String,
+    description: Option<String>,
+    priority: Option<i32>,
+    due_date: Option<DateTime<Utc>>,
+    tags: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetricsHistoryArgs {
+    from_minutes_ago: Option<i64>,
+    to_minutes_ago: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListServicesArgs {
+    state: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServiceStatusArgs {
+    unit: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryJournalArgs {
+    unit: Option<String>,
+    priority: Option<String>,
+    since_minutes_ago: Option<i64>,
+    grep: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListAppsArgs {
+    query: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Result of a tool call before it is traced
+struct ToolOutput {
+    status: ToolCallStatus,
+    result: Value,
+}
+
+impl ToolOutput {
+    fn ok(result: Value) -> Self {
+        ToolOutput { status: ToolCallStatus::Ok, result }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        ToolOutput { status: ToolCallStatus::Error, result: json!({ "error": message.into() }) }
+    }
+}
+
+/// Run a tool the model asked for
+///
+/// Unknown tools and invalid arguments are reported back to the model as
+/// errors so it can correct itself.
+///
+/// # Arguments
+/// * `state` - Application state
+/// * `name` - Tool name
+/// * `arguments` - Arguments as sent by the model
+/// * `conversation_id` - Conversation the call belongs to, recorded with commands
+///
+/// # Returns
+/// * `ToolCallTrace` - What was called and what it returned
+pub async fn call(state: &AppState, name: &str, arguments: Value, conversation_id: &str) -> ToolCallTrace {
+    // Some models send the arguments as a JSON string instead of an object
+    let arguments = match arguments {
+        Value::String(text) => serde_json::from_str(&text).unwrap_or(Value::String(text)),
+        Value::Null => json!({}),
+        other => other,
+    };
+
+    info!("Assistant called tool {} with {}", name, arguments);
+    let start = Instant::now();
+    let output = match name {
+        "run_command" => match parse(&arguments) {
+            Ok(args) => run_command(state, args, conversation_id).await,
+            Err(output) => output,
+        },
+        "read_file" => match parse(&arguments) {
+            Ok(args) => read_file(state, args).await,
+            Err(output) => output,
+        },
+        "search_files" => match parse(&arguments) {
+            Ok(args) => search_files(state, args),
+            Err(output) => output,
+        },
+        "search_memory" => match parse(&arguments) {
+            Ok(args) => search_memory(state, args),
+            Err(output) => output,
+        },
+        "create_task" => match parse(&arguments) {
+            Ok(args) => create_task(state, args).await,
+            Err(output) => output,
+        },
+        "get_system_info" => {
+            let (_, Json(info)) = crate::api::system::get_system_info(State(state.clone())).await;
+            ToolOutput::ok(json!(info))
+        }
+        "get_metrics_history" => match parse(&arguments) {
+            Ok(args) => get_metrics_history(state, args).await,
+            Err(output) => output,
+        },
+        "list_apps" => match parse(&arguments) {
+            Ok(args) => list_apps(state, args).await,
+            Err(output) => output,
+        },
+        "list_services" => match parse(&arguments) {
+            Ok(args) => list_services(state, args, conversation_id).await,
+            Err(output) => output,
+        },
+        "get_service_status" => match parse(&arguments) {
+            Ok(args) => get_service_status(state, args, conversation_id).await,
+            Err(output) => output,
+        },
+        "query_journal" => match parse(&arguments) {
+            Ok(args) => query_journal(state, args, conversation_id).await,
+            Err(output) => output,
+        },
+        _ => ToolOutput::error(format!("Unknown tool: {}", name)),
+    };
+
+    ToolCallTrace {
+        tool: name.to_string(),
+        arguments,
+        status: output.status,
+        result: output.result,
+        duration_ms: start.elapsed().as_millis() as u64,
+    }
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(arguments: &Value) -> Result<T, ToolOutput> {
+    serde_json::from_value(arguments.clone()).map_err(|e| ToolOutput::error(format!("Invalid arguments: {}", e)))
+}
+
+/// Submit a command like `/execute` and describe the outcome for the model
+async fn submit(state: &AppState, request: ExecuteCommandRequest) -> ToolOutput {
+    match submit_command(state, &request).await {
+        Ok(CommandSubmission::Completed(response)) => {
+            let (stdout, _) = truncate_output(&response.stdout, MAX_RESULT_BYTES);
+            let (stderr, _) = truncate_output(&response.stderr, MAX_RESULT_BYTES);
+            ToolOutput::ok(json!({
+                "exit_code": response.exit_code,
+                "success": response.success,
+                "timed_out": response.timed_out,
+                "stdout": stdout,
+                "stderr": stderr,
+                "history_id": response.history_id,
+            }))
+        }
+        Ok(CommandSubmission::Pending(pending)) => pending_output(&pending),
+        Err(error) => error_output(error),
+    }
+}
+
+/// Tell the model a command waits for the user
+fn pending_output(pending: &PendingCommand) -> ToolOutput {
+    ToolOutput {
+        status: ToolCallStatus::Pending,
+        result: json!({
+            "pending_command_id": pending.id,
+            "explanation": pending.explanation,
+            "note": "The command has not run. It waits for the user to approve it; tell the user what it does and why.",
+        }),
+    }
+}
+
+/// Report an API error, telling policy denials apart
+fn error_output((status, Json(error)): (StatusCode, Json<CommandError>)) -> ToolOutput {
+    if status == StatusCode::FORBIDDEN {
+        ToolOutput { status: ToolCallStatus::Denied, result: json!({ "error": error.error }) }
+    } else {
+        ToolOutput::error(error.error)
+    }
+}
+
+/// Describe the outcome of a command whose output was parsed
+fn parsed_output<T>(submission: Result<CommandSubmission<T>, (StatusCode, Json<CommandError>)>, result: impl FnOnce(T) -> Value) -> ToolOutput {
+    match submission {
+        Ok(CommandSubmission::Completed(parsed)) => ToolOutput::ok(result(parsed)),
+        Ok(CommandSubmission::Pending(pending)) => pending_output(&pending),
+        Err(error) => error_output(error),
+    }
+}
+
+async fn run_command(state: &AppState, args: RunCommandArgs, conversation_id: &str) -> ToolOutput {
+    submit(state, ExecuteCommandRequest {
+        command: args.command,
+        args: Some(args.args),
+        working_dir: args.working_dir,
+        timeout_secs: args.timeout_secs,
+        shell: args.shell,
+        conversation_id: Some(conversation_id.to_string()),
+        ..Default::default()
+    })
+    .await
+}
+
+/// Read a page of a text file, like `GET /api/system/files/read`
+///
+/// The path has to be inside the file roots and not protected, so keys and
+/// credentials in `[files].deny` are never read.
+async fn read_file(state: &AppState, args: ReadFileArgs) -> ToolOutput {
+    let query = FileReadQuery {
+        path: args.path,
+        offset: args.offset,
+        length: Some(args.max_bytes.unwrap_or(MAX_RESULT_BYTES).clamp(1, MAX_RESULT_BYTES) as u64),
+    };
+    match crate::api::system::read_file(State(state.clone()), Query(query)).await {
+        Ok(Json(page)) => ToolOutput::ok(json!({
+            "path": page.path,
+            "size": page.size,
+            "offset": page.offset,
+            "next_offset": page.next_offset,
+            "content": page.content,
+        })),
+        Err(error) => error_output(error),
+    }
+}
+
+/// Search indexed files by content, or by name when no words are given
+///
+/// With both, content matches are narrowed to names matching the glob.
+fn search_files(state: &AppState, args: SearchFilesArgs) -> ToolOutput {
+    let limit = args.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    let Some(text) = args.query.filter(|text| !text.trim().is_empty()) else {
+        let Some(glob) = args.name else {
+            return ToolOutput::error("Give query, name or both");
+        };
+        let query = IndexedFileQuery { glob: Some(glob), limit: Some(limit), ..Default::default() };
+        return match find_indexed_files(state, &query) {
+            Ok(found) => {
+                let files: Vec<Value> = found
+                    .files
+                    .iter()
+                    .map(|file| json!({ "path": file.path, "size": file.size, "modified": file.modified }))
+                    .collect();
+                ToolOutput::ok(json!({ "files": files, "total": found.total }))
+            }
+            Err(error) => error_output(error),
+        };
+    };
+
+    let matcher = match args.name.as_deref().map(crate::system::indexer::name_matcher).transpose() {
+        Ok(matcher) => matcher,
+        Err(error) => return ToolOutput::error(error),
+    };
+    // Fetch more when filtering by name so the limit still fills up
+    let fetch = if matcher.is_some() { limit * 10 } else { limit };
+    let query = IndexSearchQuery { q: text, limit: Some(fetch as i64), ..Default::default() };
+    match search_file_index(state, &query) {
+        Ok(found) => {
+            let files: Vec<Value> = found
+                .results
+                .iter()
+                .filter(|found| matcher.as_ref().is_none_or(|matcher| matcher.is_match(&found.file.path)))
+                .take(limit)
+                .map(|found| json!({
+                    "path": found.file.path,
+                    "size": found.file.size,
+                    "modified": found.file.modified,
+                    "snippet": found.snippet,
+                }))
+                .collect();
+            ToolOutput::ok(json!({ "files": files }))
+        }
+        Err(error) => error_output(error),
+    }
+}
+
+fn search_memory(state: &AppState, args: SearchMemoryArgs) -> ToolOutput {
+    let db = state.db.get().unwrap();
+    match crate::db::queries::search_memories(&db, &args.query) {
+        Ok(response) => {
+            let memories: Vec<Value> = response
+                .memories
+                .iter()
+                .take(args.limit.unwrap_or(DEFAULT_LIST_LIMIT))
+                .map(|memory| json!({
+                    "key": memory.key,
+                    "value": memory.value,
+                    "category": memory.category,
+                    "tags": memory.tags,
+                    "updated_at": memory.updated_at,
+                }))
+                .collect();
+            ToolOutput::ok(json!({ "memories": memories, "total": response.total }))
+        }
+        Err(e) => ToolOutput::error(e.to_string()),
+    }
+}
+
+async fn create_task(state: &AppState, args: CreateTaskArgs) -> ToolOutput {
+    let request = TaskRequest {
+        title: args.title,
+        description: args.description,
+        priority: args.priority,
+        due_date: args.due_date,
+        context: Some("Created by the assistant".to_string()),
+        tags: args.tags,
+        recurrence: None,
+    };
+    match crate::api::memory::create_task(State(state.clone()), Json(request)).await {
+        Ok(Json(task)) => ToolOutput::ok(json!(task)),
+        Err((_, Json(error))) => ToolOutput::error(error.error),
+    }
+}
+
+async fn get_metrics_history(state: &AppState, args: MetricsHistoryArgs) -> ToolOutput {
+    let now = Utc::now();
+    let since = now - chrono::Duration::minutes(args.from_minutes_ago.unwrap_or(60));
+    let until = now - chrono::Duration::minutes(args.to_minutes_ago.unwrap_or(0));
+    // A dozen points are enough to see when usage changed
+    let request = MetricsQuery {
+        since: Some(since),
+        until: Some(until),
+        step_secs: Some(((until - since).num_seconds() / METRIC_POINTS).max(1)),
+        ..Default::default()
+    };
+    match crate::api::system::get_metrics(State(state.clone()), Query(request)).await {
+        Ok(Json(metrics)) => ToolOutput::ok(json!({
+            "summary": metrics.summary,
+            "points": metrics.points,
+            "step_secs": metrics.step_secs,
+        })),
+        Err((_, Json(error))) => ToolOutput::error(error.error),
+    }
+}
+
+async fn list_apps(state: &AppState, args: ListAppsArgs) -> ToolOutput {
+    let request = AppSearchQuery {
+        q: args.query,
+        limit: Some(args.limit.unwrap_or(DEFAULT_LIST_LIMIT)),
+        ..Default::default()
+    };
+    let found = match crate::api::system::search_apps(State(state.clone()), Query(request)).await {
+        Ok(Json(found)) => found,
+        Err((_, Json(error))) => return ToolOutput::error(error.error),
+    };
+    let apps: Vec<Value> = found
+        .results
+        .into_iter()
+        .map(|found| {
+            let app = found.app;
+            json!({
+                "id": app.id,
+                "name": app.name,
+                "command": app.command,
+                "description": app.description,
+                "category": app.category,
+                "launches": found.usage.count,
+            })
+        })
+        .collect();
+    ToolOutput::ok(json!({ "applications": apps, "total": found.total }))
+}
+
+fn service_options(conversation_id: &str) -> ServiceActionRequest {
+    ServiceActionRequest { conversation_id: Some(conversation_id.to_string()), ..Default::default() }
+}
+
+async fn list_services(state: &AppState, args: ListServicesArgs, conversation_id: &str) -> ToolOutput {
+    let query = ServiceListQuery { state: args.state, name: args.name, ..Default::default() };
+    let listed = list_units(state, &query, &service_options(conversation_id)).await;
+    parsed_output(listed, |listed| {
+        let services: Vec<Value> = listed
+            .units
+            .iter()
+            .take(MAX_SERVICES)
+            .map(|unit| json!({
+                "name": unit.name,
+                "active_state": unit.active_state,
+                "sub_state": unit.sub_state,
+                "description": unit.description,
+            }))
+            .collect();
+        json!({ "services": services, "total": listed.total })
+    })
+}
+
+async fn get_service_status(state: &AppState, args: ServiceStatusArgs, conversation_id: &str) -> ToolOutput {
+    let status = unit_status(state, &args.unit, &service_options(conversation_id)).await;
+    parsed_output(status, |status| json!(status))
+}
+
+async fn query_journal(state: &AppState, args: QueryJournalArgs, conversation_id: &str) -> ToolOutput {
+    let query = JournalQuery {
+        unit: args.unit,
+        priority: args.priority,
+        since: args.since_minutes_ago.map(|minutes| Utc::now() - chrono::Duration::minutes(minutes)),
+        grep: args.grep,
+        limit: Some(args.limit.unwrap_or(DEFAULT_LIST_LIMIT)),
+        ..Default::default()
+    };
+    let filter = match journal_filter(query) {
+        Ok(filter) => filter,
+        Err(error) => return error_output(error),
+    };
+    let entries = read_journal(state, &filter, &service_options(conversation_id)).await;
+    parsed_output(entries, |entries| {
+        let entries: Vec<Value> = entries
+            .iter()
+            .map(|entry| {
+                let (message, _) = truncate_output(&entry.message, MAX_MESSAGE_BYTES);
+                json!({
+                    "timestamp": entry.timestamp,
+                    "priority": entry.priority_name,
+                    "unit": entry.unit,
+                    "identifier": entry.identifier,
+                    "message": message,
+                })
+            })
+            .collect();
+        json!({ "entries": entries })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app_state, TempTree, TestDatabase};
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn arguments_may_be_encoded_as_a_string() {
+        let db = TestDatabase::new();
+        let state = app_state(&db, PathBuf::from("/nonexistent/policy.toml")).await;
+
+        let trace = call(&state, "search_memory", Value::String(r#"{"query": "editor"}"#.to_string()), "c1").await;
+        assert_eq!(trace.status, ToolCallStatus::Ok);
+        assert_eq!(trace.arguments, json!({ "query": "editor" }));
+        assert_eq!(trace.result["total"], 0);
+
+        let trace = call(&state, "search_memory", json!({ "limit": 3 }), "c1").await;
+        assert_eq!(trace.status, ToolCallStatus::Error);
+        assert!(trace.result["error"].as_str().unwrap().starts_with("Invalid arguments"));
+    }
+
+    #[tokio::test]
+    async fn unknown_tools_are_errors() {
+        let db = TestDatabase::new();
+        let state = app_state(&db, PathBuf::from("/nonexistent/policy.toml")).await;
+
+        let trace = call(&state, "format_disk", Value::Null, "c1").await;
+        assert_eq!(trace.status, ToolCallStatus::Error);
+        assert_eq!(trace.result["error"], "Unknown tool: format_disk");
+    }
+
+    #[tokio::test]
+    async fn denied_commands_are_reported_as_denied() {
+        let db = TestDatabase::new();
+        let state = app_state(&db, PathBuf::from("/nonexistent/policy.toml")).await;
+
+        let trace = call(&state, "run_command", json!({ "command": "sudo", "args": ["id"] }), "c1").await;
+        assert_eq!(trace.status, ToolCallStatus::Denied);
+        assert_eq!(trace.tool, "run_command");
+    }
+
+    #[tokio::test]
+    async fn files_are_read_only_inside_the_roots() {
+        let tree = TempTree::new();
+        tree.write("home/notes.txt", "remember the milk");
+        tree.write("home/.ssh/id_ed25519", "PRIVATE KEY");
+        tree.write("elsewhere/secret.txt", "secret");
+        let policy = tree.write(
+            "policy.toml",
+            format!(
+                "default_action = \"confirm\"\n[files]\nroots = [\"{}\"]\ndeny = [\"{}/.ssh/**\"]\n",
+                tree.join("home").display(),
+                tree.join("home").display()
+            ),
+        );
+        let db = TestDatabase::new();
+        let state = app_state(&db, policy).await;
+        let read = |path: PathBuf| json!({ "path": path.display().to_string() });
+
+        let trace = call(&state, "read_file", read(tree.join("home/notes.txt")), "c1").await;
+        assert_eq!(trace.status, ToolCallStatus::Ok);
+        assert_eq!(trace.result["content"], "remember the milk");
+
+        for path in ["home/.ssh/id_ed25519", "elsewhere/secret.txt", "home/../elsewhere/secret.txt"] {
+            let trace = call(&state, "read_file", read(tree.join(path)), "c1").await;
+            assert_eq!(trace.status, ToolCallStatus::Denied, "{}", path);
+        }
+    }
+}
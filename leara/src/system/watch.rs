@@ -0,0 +1,169 @@
+/*
+ * Leara AI Assistant - Directory Watching
+ *
+ * This module wraps Linux inotify so caches built from the filesystem can
+ * be refreshed when the directories they were built from change, instead
+ * of being rebuilt on every request.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/system/watch.rs
+ * Purpose: inotify-based directory watcher
+ */
+
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use tokio::io::unix::AsyncFd;
+
+/// Changes reported for watched directories
+const WATCH_MASK: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_ATTRIB
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF;
+
+/// A change inside a watched directory
+#[derive(Debug, Clone)]
+pub struct WatchEvent {
+    /// The entry that changed, or the watched directory itself
+    pub path: PathBuf,
+    /// inotify event mask
+    pub mask: u32,
+}
+
+impl WatchEvent {
+    /// Whether the entry is a directory
+    pub fn is_dir(&self) -> bool {
+        self.mask & libc::IN_ISDIR != 0
+    }
+
+    /// Whether the kernel's queue overflowed and changes were lost
+    ///
+    /// An overflow event names no directory; its path is empty.
+    pub fn is_overflow(&self) -> bool {
+        self.mask & libc::IN_Q_OVERFLOW != 0
+    }
+
+    /// Whether the entry is gone (deleted or moved away)
+    pub fn is_removal(&self) -> bool {
+        self.mask & (libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_DELETE_SELF | libc::IN_MOVE_SELF) != 0
+    }
+}
+
+/// Watches directories (not recursively) for changes
+pub struct DirectoryWatcher {
+    fd: AsyncFd<OwnedFd>,
+    watches: HashMap<libc::c_int, PathBuf>,
+}
+
+impl DirectoryWatcher {
+    /// Create a watcher with no directories
+    ///
+    /// # Returns
+    /// * `Err(std::io::Error)` - inotify is unavailable or its instance limit is reached
+    pub fn new() -> std::io::Result<Self> {
+        // SAFETY: inotify_init1(2) takes no pointers and returns a new descriptor
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: the descriptor was just created and is owned by nobody else
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        // SAFETY: the OwnedFd is moved into the AsyncFd and stays open until it is dropped
+        let fd = unsafe { AsyncFd::register(fd) }.map_err(std::io::Error::from)?;
+        Ok(DirectoryWatcher { fd, watches: HashMap::new() })
+    }
+
+    /// Start watching a directory
+    ///
+    /// # Returns
+    /// * `Err(std::io::Error)` - The directory does not exist or the watch limit is reached
+    pub fn watch(&mut self, dir: &Path) -> std::io::Result<()> {
+        let path = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: the path is a valid NUL-terminated string for the duration of the call
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), WATCH_MASK | libc::IN_ONLYDIR) };
+        if wd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        self.watches.insert(wd, dir.to_path_buf());
+        Ok(())
+    }
+
+    /// Directories currently watched
+    pub fn watched(&self) -> impl Iterator<Item = &Path> {
+        self.watches.values().map(PathBuf::as_path)
+    }
+
+    /// Wait for the next changes
+    ///
+    /// # Returns
+    /// * `Ok(Vec<WatchEvent>)` - One or more changes, in the order they happened; an
+    ///   overflow event means some were lost and the directories should be rescanned
+    /// * `Err(std::io::Error)` - Reading the inotify queue failed
+    pub async fn next_events(&mut self) -> std::io::Result<Vec<WatchEvent>> {
+        // Aligned for inotify_event, large enough for at least one event with a maximal name
+        let mut buffer = vec![0u32; 4096];
+        let len = loop {
+            let mut guard = self.fd.readable().await?;
+            // SAFETY: the buffer is valid for its length in bytes
+            let result = guard.try_io(|fd| {
+                let n = unsafe {
+                    libc::read(fd.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len() * std::mem::size_of::<u32>())
+                };
+                if n < 0 {
+                    Err(std::io::Error::last_os_error())
+                } else {
+                    Ok(n as usize)
+                }
+            });
+            match result {
+                Ok(result) => break result?,
+                Err(_would_block) => continue,
+            }
+        };
+
+        // SAFETY: u32 has no padding and the buffer holds at least `len` initialized bytes
+        let bytes = unsafe { std::slice::from_raw_parts(buffer.as_ptr().cast::<u8>(), len) };
+        let header = std::mem::size_of::<libc::inotify_event>();
+        let mut events = Vec::new();
+        let mut offset = 0;
+        while offset + header <= len {
+            // SAFETY: the kernel writes whole events; read_unaligned copes with any offset
+            let event: libc::inotify_event = unsafe { std::ptr::read_unaligned(bytes[offset..].as_ptr().cast()) };
+            let name = &bytes[offset + header..offset + header + event.len as usize];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            offset += header + event.len as usize;
+
+            if event.mask & libc::IN_IGNORED != 0 {
+                self.watches.remove(&event.wd);
+                continue;
+            }
+            // Not tied to a watch (wd is -1)
+            if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                events.push(WatchEvent { path: PathBuf::new(), mask: event.mask });
+                continue;
+            }
+            let dir = self.watches.get(&event.wd).cloned().unwrap_or_default();
+            let path = if name.is_empty() { dir } else { dir.join(OsStr::from_bytes(name)) };
+            events.push(WatchEvent { path, mask: event.mask });
+        }
+        Ok(events)
+    }
+}
@@ -0,0 +1,112 @@
+/*
+ * Leara AI Assistant - Test Helpers
+ *
+ * This module holds fixtures shared by the unit tests: temporary
+ * directory trees standing in for /proc, /sys or a user's files,
+ * migrated databases and the application state handlers run with.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/testing.rs
+ * Purpose: Shared fixtures for unit tests
+ */
+
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::TempDir;
+use crate::models::AppState;
+use crate::system::alerts::AlertManager;
+use crate::system::apps::AppCatalog;
+use crate::system::events::EventBus;
+use crate::system::indexer::{FileIndexer, IndexConfig};
+use crate::system::jobs::JobManager;
+use crate::system::metrics::{MetricsConfig, MetricsHistory};
+use crate::system::policy::PolicyStore;
+use crate::system::sampler::{SamplerConfig, SystemSampler};
+use crate::system::MemoryService;
+
+/// Temporary directory tree, removed when dropped
+pub struct TempTree {
+    _dir: TempDir,
+    root: PathBuf,
+}
+
+impl TempTree {
+    pub fn new() -> Self {
+        let dir = tempfile::Builder::new().prefix("leara-test-").tempdir().unwrap();
+        // Canonical, so paths compare equal to resolved ones
+        let root = fs::canonicalize(dir.path()).unwrap();
+        TempTree { _dir: dir, root }
+    }
+
+    /// Root of the tree
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Write a file below the root, creating its directories
+    pub fn write(&self, path: &str, content: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+/// Migrated database in a temporary directory, pooled like the server's
+pub struct TestDatabase {
+    _dir: TempTree,
+    pub pool: Pool<SqliteConnectionManager>,
+}
+
+impl TestDatabase {
+    pub fn new() -> Self {
+        let dir = TempTree::new();
+        let pool = crate::db::open_pool(&dir.join("leara.db").display().to_string()).unwrap();
+        crate::db::migrations::run_migrations(&pool.get().unwrap()).unwrap();
+        TestDatabase { _dir: dir, pool }
+    }
+
+    pub fn conn(&self) -> PooledConnection<SqliteConnectionManager> {
+        self.pool.get().unwrap()
+    }
+}
+
+/// Application state over a test database with the policy file at `policy`
+///
+/// A missing policy file means the built-in policy.
+pub async fn app_state(db: &TestDatabase, policy: PathBuf) -> AppState {
+    let db = db.pool.clone();
+    let policy = Arc::new(PolicyStore::new(policy));
+    let sampler = SystemSampler::start(SamplerConfig::from_env()).await;
+    let events = Arc::new(EventBus::new());
+    AppState {
+        memory_service: Arc::new(MemoryService::new(db.clone())),
+        jobs: Arc::new(JobManager::new(db.clone())),
+        apps: AppCatalog::new(),
+        metrics: MetricsHistory::start(MetricsConfig::from_env(), db.clone(), sampler.clone()),
+        alerts: AlertManager::start(db.clone(), sampler.clone(), events.clone()),
+        indexer: FileIndexer::start(IndexConfig::from_env(), db.clone(), policy.clone()),
+        db,
+        policy,
+        sampler,
+        events,
+    }
+}
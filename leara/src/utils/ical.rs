@@ -0,0 +1,564 @@
+/*
+ * Leara AI Assistant - iCalendar Conversion
+ *
+ * This module converts tasks to and from the iCalendar (RFC 5545) format so
+ * they can be subscribed to from calendar applications or imported from them.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/utils/ical.rs
+ * Purpose: iCalendar export and import of tasks
+ */
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use crate::models::memory::{Task, TaskStatus};
+
+/// Calendar component used to represent exported tasks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalendarComponent {
+    /// VTODO items, shown as to-dos by most calendar apps
+    Todo,
+    /// VEVENT items starting at the task's due date
+    Event,
+}
+
+impl CalendarComponent {
+    pub fn as_str(&self) -> &str {
+        match self {
+            CalendarComponent::Todo => "VTODO",
+            CalendarComponent::Event => "VEVENT",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "vtodo" | "todo" => Some(CalendarComponent::Todo),
+            "vevent" | "event" => Some(CalendarComponent::Event),
+            _ => None,
+        }
+    }
+}
+
+/// Parts a recurrence rule may have (RFC 5545 section 3.3.10)
+const RRULE_PARTS: &[&str] = &[
+    "FREQ", "UNTIL", "COUNT", "INTERVAL", "BYSECOND", "BYMINUTE", "BYHOUR", "BYDAY",
+    "BYMONTHDAY", "BYYEARDAY", "BYWEEKNO", "BYMONTH", "BYSETPOS", "WKST",
+];
+
+const RRULE_FREQUENCIES: &[&str] = &["SECONDLY", "MINUTELY", "HOURLY", "DAILY", "WEEKLY", "MONTHLY", "YEARLY"];
+
+/// Check that a recurrence rule is an RRULE value such as `FREQ=WEEKLY;BYDAY=MO`
+///
+/// Rules are exported as they are stored, so anything else (in particular
+/// line breaks) could add properties to the exported calendar.
+///
+/// # Returns
+/// * `Ok(())` - The rule is well-formed
+/// * `Err(anyhow::Error)` - What is wrong with it
+pub fn validate_rrule(rule: &str) -> Result<()> {
+    let mut frequency = None;
+    for part in rule.split(';') {
+        let Some((name, value)) = part.split_once('=') else {
+            return Err(anyhow::anyhow!("Invalid recurrence rule part {:?}: expected NAME=VALUE", part));
+        };
+        let name = name.to_uppercase();
+        if !RRULE_PARTS.contains(&name.as_str()) {
+            return Err(anyhow::anyhow!("Unknown recurrence rule part {}", name));
+        }
+        if value.is_empty() || !value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, ',' | '+' | '-')) {
+            return Err(anyhow::anyhow!("Invalid value for {} in recurrence rule: {:?}", name, value));
+        }
+        if name == "FREQ" {
+            frequency = Some(value.to_uppercase());
+        }
+    }
+    match frequency {
+        Some(freq) if RRULE_FREQUENCIES.contains(&freq.as_str()) => Ok(()),
+        Some(freq) => Err(anyhow::anyhow!("Unknown recurrence frequency {}", freq)),
+        None => Err(anyhow::anyhow!("Recurrence rule needs a FREQ")),
+    }
+}
+
+/// Map a Leara priority (1 lowest - 5 highest) to iCalendar PRIORITY (1 highest - 9 lowest)
+pub fn leara_priority_to_ical(priority: i32) -> u8 {
+    match priority {
+        p if p >= 5 => 1,
+        4 => 3,
+        3 => 5,
+        2 => 7,
+        _ => 9,
+    }
+}
+
+/// Map an iCalendar PRIORITY (0 = undefined) back to a Leara priority
+pub fn ical_priority_to_leara(priority: u8) -> i32 {
+    match priority {
+        1 | 2 => 5,
+        3 | 4 => 4,
+        6 | 7 => 2,
+        8 | 9 => 1,
+        _ => 3,
+    }
+}
+
+/// Render tasks as an iCalendar document
+///
+/// Only tasks with a due date are exported. Lines are CRLF-terminated and
+/// folded at 75 octets as required by RFC 5545.
+///
+/// # Arguments
+/// * `tasks` - Tasks to export
+/// * `component` - Whether tasks become VTODO or VEVENT items
+///
+/// # Returns
+/// * `String` - The complete VCALENDAR document
+pub fn tasks_to_ics(tasks: &[Task], component: CalendarComponent) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:-//Leara//Leara AI Assistant {}//EN", env!("CARGO_PKG_VERSION")),
+        "CALSCALE:GREGORIAN".to_string(),
+        "X-WR-CALNAME:Leara Tasks".to_string(),
+    ];
+
+    for task in tasks {
+        let Some(due) = task.due_date else { continue };
+        let status = TaskStatus::from_str(&task.status);
+
+        lines.push(format!("BEGIN:{}", component.as_str()));
+        lines.push(format!("UID:task-{}@leara", task.id));
+        lines.push(format!("DTSTAMP:{}", format_datetime(&task.updated_at)));
+        lines.push(format!("CREATED:{}", format_datetime(&task.created_at)));
+        lines.push(format!("LAST-MODIFIED:{}", format_datetime(&task.updated_at)));
+        lines.push(format!("SUMMARY:{}", escape_text(&task.title)));
+        if let Some(ref description) = task.description {
+            lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        match component {
+            CalendarComponent::Todo => lines.push(format!("DUE:{}", format_datetime(&due))),
+            CalendarComponent::Event => lines.push(format!("DTSTART:{}", format_datetime(&due))),
+        }
+        lines.push(format!("PRIORITY:{}", leara_priority_to_ical(task.priority)));
+        lines.push(format!("STATUS:{}", ical_status(&status, component)));
+        if component == CalendarComponent::Todo {
+            if let Some(completed_at) = task.completed_at {
+                lines.push(format!("COMPLETED:{}", format_datetime(&completed_at)));
+            }
+        }
+        if let Some(ref tags) = task.tags {
+            let categories: Vec<String> = tags.split(',').map(escape_text).collect();
+            lines.push(format!("CATEGORIES:{}", categories.join(",")));
+        }
+        // Rules stored before they were validated are left out
+        if let Some(rule) = task.recurrence.as_ref().filter(|rule| validate_rrule(rule).is_ok()) {
+            lines.push(format!("RRULE:{}", rule));
+        }
+        lines.push(format!("END:{}", component.as_str()));
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold_line(line)).collect()
+}
+
+/// Parse an iCalendar document into new tasks
+///
+/// Every VTODO and VEVENT becomes a task, with `id` 0 ready for insertion
+/// or, for a `task-<id>@leara` UID from an export, the ID of the exported
+/// task. Times with a TZID parameter are read in that time zone, floating
+/// times (neither `Z` nor TZID) and unknown zones in the local time zone.
+///
+/// # Arguments
+/// * `input` - The iCalendar document
+///
+/// # Returns
+/// * `Ok(Vec<Task>)` - Parsed tasks
+/// * `Err(anyhow::Error)` - The input is not an iCalendar document or has an invalid RRULE
+pub fn parse_ics(input: &str) -> Result<Vec<Task>> {
+    let unfolded = input
+        .replace("\r\n ", "")
+        .replace("\r\n\t", "")
+        .replace("\n ", "")
+        .replace("\n\t", "");
+
+    let mut lines = unfolded.lines().map(|l| l.trim_end_matches('\r')).filter(|l| !l.is_empty());
+    match lines.next() {
+        Some(first) if first.eq_ignore_ascii_case("BEGIN:VCALENDAR") => {}
+        _ => return Err(anyhow::anyhow!("Not an iCalendar document: missing BEGIN:VCALENDAR")),
+    }
+
+    let mut tasks = Vec::new();
+    let mut current: Option<(CalendarComponent, Vec<Property>)> = None;
+    let mut nested_depth = 0;
+
+    for line in lines {
+        let Some(property) = Property::parse(line) else { continue };
+
+        if let Some((component, mut properties)) = current.take() {
+            match property.name.as_str() {
+                "BEGIN" => nested_depth += 1,
+                "END" if nested_depth > 0 => nested_depth -= 1,
+                "END" if property.value.eq_ignore_ascii_case(component.as_str()) => {
+                    let task = task_from_properties(component, &properties);
+                    if let Some(ref rule) = task.recurrence {
+                        validate_rrule(rule).map_err(|e| anyhow::anyhow!("Task {:?}: {}", task.title, e))?;
+                    }
+                    tasks.push(task);
+                    continue;
+                }
+                _ if nested_depth == 0 => properties.push(property),
+                _ => {}
+            }
+            current = Some((component, properties));
+        } else if property.name == "BEGIN" {
+            if let Some(component) = CalendarComponent::from_str(&property.value) {
+                current = Some((component, Vec::new()));
+                nested_depth = 0;
+            }
+        }
+    }
+
+    Ok(tasks)
+}
+
+/// A single content line: `NAME;PARAM=VALUE:value`
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn parse(line: &str) -> Option<Self> {
+        // The value starts at the first colon outside a quoted parameter value
+        let mut in_quotes = false;
+        let split = line.char_indices().find(|&(_, c)| {
+            if c == '"' {
+                in_quotes = !in_quotes;
+            }
+            c == ':' && !in_quotes
+        })?.0;
+
+        let (head, value) = (&line[..split], &line[split + 1..]);
+        let mut parts = head.split(';');
+        let name = parts.next()?.trim().to_uppercase();
+        let params = parts
+            .filter_map(|p| p.split_once('='))
+            .map(|(k, v)| (k.trim().to_uppercase(), v.trim_matches('"').to_string()))
+            .collect();
+
+        Some(Self { name, params, value: value.to_string() })
+    }
+
+    fn is_date_only(&self) -> bool {
+        self.params.iter().any(|(k, v)| k == "VALUE" && v.eq_ignore_ascii_case("DATE"))
+    }
+
+    fn tzid(&self) -> Option<&str> {
+        self.params.iter().find(|(k, _)| k == "TZID").map(|(_, v)| v.as_str())
+    }
+}
+
+/// Build a task from the properties of one VTODO or VEVENT
+fn task_from_properties(component: CalendarComponent, properties: &[Property]) -> Task {
+    let get = |name: &str| properties.iter().find(|p| p.name == name);
+    let text = |name: &str| get(name).map(|p| unescape_text(&p.value)).filter(|v| !v.is_empty());
+    let datetime = |name: &str| get(name).and_then(|p| parse_datetime(&p.value, p.is_date_only(), p.tzid()));
+
+    let due_date = match component {
+        CalendarComponent::Todo => datetime("DUE").or_else(|| datetime("DTSTART")),
+        CalendarComponent::Event => datetime("DTSTART"),
+    };
+    let priority = get("PRIORITY")
+        .and_then(|p| p.value.trim().parse::<u8>().ok())
+        .map(ical_priority_to_leara)
+        .unwrap_or(3);
+
+    let mut status = get("STATUS")
+        .map(|p| task_status_from_ical(&p.value))
+        .unwrap_or(TaskStatus::Pending);
+    let completed_at = datetime("COMPLETED");
+    let fully_done = get("PERCENT-COMPLETE").map(|p| p.value.trim() == "100").unwrap_or(false);
+    if status == TaskStatus::Pending && (completed_at.is_some() || fully_done) {
+        status = TaskStatus::Completed;
+    }
+    let completed_at = match status {
+        TaskStatus::Completed => Some(completed_at.unwrap_or_else(Utc::now)),
+        _ => None,
+    };
+
+    let tags: Vec<String> = properties
+        .iter()
+        .filter(|p| p.name == "CATEGORIES")
+        .flat_map(|p| split_escaped_list(&p.value))
+        .collect();
+
+    let id = get("UID")
+        .and_then(|p| p.value.trim().strip_prefix("task-")?.strip_suffix("@leara")?.parse().ok())
+        .unwrap_or(0);
+
+    let now = Utc::now();
+    Task {
+        id,
+        title: text("SUMMARY").unwrap_or_else(|| "(untitled)".to_string()),
+        description: text("DESCRIPTION"),
+        status: status.as_str().to_string(),
+        priority,
+        due_date,
+        created_at: datetime("CREATED").or_else(|| datetime("DTSTAMP")).unwrap_or(now),
+        updated_at: datetime("LAST-MODIFIED").unwrap_or(now),
+        completed_at,
+        context: None,
+        tags: if tags.is_empty() { None } else { Some(tags.join(",")) },
+        recurrence: get("RRULE").map(|p| p.value.trim().to_string()).filter(|r| !r.is_empty()),
+    }
+}
+
+/// iCalendar STATUS value for a task status
+fn ical_status(status: &TaskStatus, component: CalendarComponent) -> &'static str {
+    match (component, status) {
+        (CalendarComponent::Todo, TaskStatus::InProgress) => "IN-PROCESS",
+        (CalendarComponent::Todo, TaskStatus::Completed) => "COMPLETED",
+        (CalendarComponent::Todo, TaskStatus::Cancelled) => "CANCELLED",
+        (CalendarComponent::Todo, _) => "NEEDS-ACTION",
+        (CalendarComponent::Event, TaskStatus::Cancelled) => "CANCELLED",
+        (CalendarComponent::Event, TaskStatus::Completed) => "CONFIRMED",
+        (CalendarComponent::Event, _) => "TENTATIVE",
+    }
+}
+
+/// Task status for an iCalendar STATUS value
+fn task_status_from_ical(value: &str) -> TaskStatus {
+    match value.trim().to_uppercase().as_str() {
+        "IN-PROCESS" => TaskStatus::InProgress,
+        "COMPLETED" => TaskStatus::Completed,
+        "CANCELLED" => TaskStatus::Cancelled,
+        _ => TaskStatus::Pending,
+    }
+}
+
+/// Format a timestamp as a UTC DATE-TIME value
+fn format_datetime(dt: &DateTime<Utc>) -> String {
+    dt.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Parse a DATE or DATE-TIME value
+///
+/// # Arguments
+/// * `value` - The value, `Z`-suffixed for UTC times
+/// * `date_only` - Whether the property has `VALUE=DATE`
+/// * `tzid` - The property's TZID parameter; floating times and zones
+///   unknown to the tz database are taken as local time
+fn parse_datetime(value: &str, date_only: bool, tzid: Option<&str>) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if date_only || value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|dt| dt.and_utc());
+    }
+    if let Some(utc) = value.strip_suffix('Z') {
+        return NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok().map(|dt| dt.and_utc());
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    match tzid.and_then(|id| id.parse::<Tz>().ok()) {
+        Some(zone) => in_zone(&zone, naive),
+        None => in_zone(&Local, naive),
+    }
+}
+
+/// A wall-clock time in a time zone, as UTC
+///
+/// Ambiguous times take the earlier instant; times skipped by a DST change
+/// are moved forward by an hour, as clocks do.
+fn in_zone<Z: TimeZone>(zone: &Z, naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+    zone.from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| zone.from_local_datetime(&(naive + Duration::hours(1))).earliest())
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Escape a TEXT value
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Reverse `escape_text`
+fn unescape_text(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => result.push('\n'),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Split a comma-separated list of TEXT values, honouring escaped commas
+fn split_escaped_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in value.chars() {
+        match c {
+            _ if escaped => {
+                current.push('\\');
+                current.push(c);
+                escaped = false;
+            }
+            '\\' => escaped = true,
+            ',' => items.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    items.push(current);
+    items
+        .iter()
+        .map(|item| unescape_text(item).trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Fold a content line at 75 octets and terminate it with CRLF
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 8);
+    let mut width = 0;
+    for c in line.chars() {
+        let len = c.len_utf8();
+        if width + len > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += len;
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn task(id: i64, status: TaskStatus, priority: i32) -> Task {
+        let created = Utc.with_ymd_and_hms(2024, 6, 1, 9, 0, 0).unwrap();
+        Task {
+            id,
+            title: "Call Bob, Alice; and Eve".to_string(),
+            description: Some("first line\nsecond, with \\ backslash".to_string()),
+            status: status.as_str().to_string(),
+            priority,
+            due_date: Some(Utc.with_ymd_and_hms(2024, 7, 1, 17, 30, 0).unwrap()),
+            created_at: created,
+            updated_at: created,
+            completed_at: (status == TaskStatus::Completed).then_some(created),
+            context: None,
+            tags: Some("work,home".to_string()),
+            recurrence: Some("FREQ=WEEKLY;BYDAY=MO,WE".to_string()),
+        }
+    }
+
+    #[test]
+    fn tasks_survive_a_round_trip() {
+        let tasks = vec![
+            task(7, TaskStatus::Pending, 5),
+            task(8, TaskStatus::InProgress, 3),
+            task(9, TaskStatus::Completed, 1),
+            task(10, TaskStatus::Cancelled, 4),
+        ];
+        let ics = tasks_to_ics(&tasks, CalendarComponent::Todo);
+        assert!(ics.ends_with("\r\n") && ics.split("\r\n").all(|line| line.len() <= 75 && !line.contains('\n')));
+
+        let parsed = parse_ics(&ics).unwrap();
+        assert_eq!(parsed.len(), tasks.len());
+        for (original, parsed) in tasks.iter().zip(&parsed) {
+            assert_eq!(parsed.id, original.id);
+            assert_eq!(parsed.title, original.title);
+            assert_eq!(parsed.description, original.description);
+            assert_eq!(parsed.status, original.status);
+            assert_eq!(parsed.priority, original.priority);
+            assert_eq!(parsed.due_date, original.due_date);
+            assert_eq!(parsed.created_at, original.created_at);
+            assert_eq!(parsed.completed_at, original.completed_at);
+            assert_eq!(parsed.tags, original.tags);
+            assert_eq!(parsed.recurrence, original.recurrence);
+        }
+    }
+
+    #[test]
+    fn events_start_at_the_due_date() {
+        let ics = tasks_to_ics(&[task(3, TaskStatus::Pending, 3)], CalendarComponent::Event);
+        assert!(ics.contains("BEGIN:VEVENT\r\n"));
+        assert!(ics.contains("DTSTART:20240701T173000Z\r\n"));
+        let parsed = parse_ics(&ics).unwrap();
+        assert_eq!(parsed[0].due_date, task(3, TaskStatus::Pending, 3).due_date);
+    }
+
+    #[test]
+    fn foreign_tasks_are_new() {
+        let ics = "BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nUID:1234@example.com\r\nSUMMARY:Other\r\nDUE;VALUE=DATE:20240701\r\n\
+                   PERCENT-COMPLETE:100\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+        let parsed = parse_ics(ics).unwrap();
+        assert_eq!(parsed[0].id, 0);
+        assert_eq!(parsed[0].status, "completed");
+        assert_eq!(parsed[0].due_date, Some(Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn zoned_and_floating_times_are_not_read_as_utc() {
+        let ics = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nSUMMARY:Meeting\r\nDTSTART;TZID=Europe/Berlin:20240701T190000\r\n\
+                   END:VEVENT\r\nBEGIN:VEVENT\r\nSUMMARY:Winter\r\nDTSTART;TZID=\"Europe/Berlin\":20240115T190000\r\n\
+                   END:VEVENT\r\nBEGIN:VEVENT\r\nSUMMARY:Floating\r\nDTSTART:20240701T190000\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let parsed = parse_ics(ics).unwrap();
+        assert_eq!(parsed[0].due_date, Some(Utc.with_ymd_and_hms(2024, 7, 1, 17, 0, 0).unwrap()));
+        assert_eq!(parsed[1].due_date, Some(Utc.with_ymd_and_hms(2024, 1, 15, 18, 0, 0).unwrap()));
+        let local = Local.with_ymd_and_hms(2024, 7, 1, 19, 0, 0).earliest().unwrap().with_timezone(&Utc);
+        assert_eq!(parsed[2].due_date, Some(local));
+
+        // Exported in UTC, the same instant comes back
+        let ics = tasks_to_ics(&parsed[..1], CalendarComponent::Event);
+        assert!(ics.contains("DTSTART:20240701T170000Z\r\n"));
+        assert_eq!(parse_ics(&ics).unwrap()[0].due_date, parsed[0].due_date);
+    }
+
+    #[test]
+    fn recurrence_rules_are_validated() {
+        assert!(validate_rrule("FREQ=DAILY").is_ok());
+        assert!(validate_rrule("freq=monthly;BYMONTHDAY=-1;UNTIL=20241231T000000Z").is_ok());
+        assert!(validate_rrule("BYDAY=MO").is_err());
+        assert!(validate_rrule("FREQ=FORTNIGHTLY").is_err());
+        assert!(validate_rrule("FREQ=DAILY;X-NAME=1").is_err());
+        assert!(validate_rrule("FREQ=DAILY\r\nEND:VTODO\r\nBEGIN:VEVENT").is_err());
+
+        let mut injected = task(1, TaskStatus::Pending, 3);
+        injected.recurrence = Some("FREQ=DAILY\r\nATTACH:http://example.com".to_string());
+        let ics = tasks_to_ics(&[injected], CalendarComponent::Todo);
+        assert!(!ics.contains("RRULE") && !ics.contains("ATTACH"));
+
+        let ics = "BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nSUMMARY:x\r\nRRULE:FREQ=SOMETIMES\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+        assert!(parse_ics(ics).is_err());
+    }
+}
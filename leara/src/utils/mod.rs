@@ -21,6 +21,8 @@
  */
 
 pub mod ollama;
+pub mod ical;
+pub mod todotxt;
 
 /// Get current timestamp in ISO format
 pub fn get_timestamp() -> String {
@@ -83,6 +83,82 @@ pub struct OllamaResponse {
     pub eval_duration: Option<u64>,
 }
 
+/// A message in an Ollama chat conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaChatMessage {
+    /// system, user, assistant or tool
+    pub role: String,
+    pub content: String,
+    /// Tools the assistant wants to call before answering
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<OllamaToolCall>,
+    /// Tool whose result this message carries (role `tool`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+}
+
+impl OllamaChatMessage {
+    /// Create a plain message without tool calls
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_name: None,
+        }
+    }
+}
+
+/// A tool call requested by the model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaToolCall {
+    pub function: OllamaFunctionCall,
+}
+
+/// Name and arguments of a requested tool call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaFunctionCall {
+    pub name: String,
+    /// Arguments as a JSON object; some models send it as a JSON string
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+/// A tool offered to the model, described by a JSON schema
+#[derive(Debug, Clone, Serialize)]
+pub struct OllamaTool {
+    /// Always `function`
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: OllamaFunction,
+}
+
+/// Name, description and parameter schema of a tool
+#[derive(Debug, Clone, Serialize)]
+pub struct OllamaFunction {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+/// Ollama API request structure for chat completions with tools
+#[derive(Debug, Serialize)]
+pub struct OllamaChatRequest {
+    pub model: String,
+    pub messages: Vec<OllamaChatMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<OllamaTool>,
+    /// Always false; the whole reply is needed to see its tool calls
+    pub stream: bool,
+}
+
+/// Ollama chat API response structure
+#[derive(Debug, Deserialize)]
+pub struct OllamaChatResponse {
+    pub message: OllamaChatMessage,
+    pub done: bool,
+}
+
 /// Client for communicating with Ollama API
 pub struct OllamaClient {
     /// HTTP client for making requests
@@ -178,6 +254,51 @@ impl OllamaClient {
         Ok(full_response)
     }
 
+    /// Send a conversation to the chat API, offering tools to the model
+    /// 
+    /// # Arguments
+    /// * `model` - The model name to use; it must support tool calling for tools to be used
+    /// * `messages` - The conversation so far, including earlier tool results
+    /// * `tools` - Tools the model may call
+    /// 
+    /// # Returns
+    /// * `Ok(OllamaChatMessage)` - The assistant's reply, possibly asking for tool calls
+    /// * `Err(anyhow::Error)` - Error if the request fails
+    pub async fn chat(
+        &self,
+        model: &str,
+        messages: &[OllamaChatMessage],
+        tools: &[OllamaTool],
+    ) -> Result<OllamaChatMessage> {
+        let request = OllamaChatRequest {
+            model: model.to_string(),
+            messages: messages.to_vec(),
+            tools: tools.to_vec(),
+            stream: false,
+        };
+
+        info!("Sending chat request to Ollama model: {}", model);
+
+        let url = format!("{}/api/chat", self.base_url);
+        let response = self.client
+            .post(&url)
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            error!("Ollama API error: {}", error_text);
+            return Err(anyhow::anyhow!("Ollama API error: {}", error_text));
+        }
+
+        let chat_response: OllamaChatResponse = response.json().await?;
+        if !chat_response.done {
+            return Err(anyhow::anyhow!("Ollama returned an incomplete chat response"));
+        }
+        Ok(chat_response.message)
+    }
+
     /// Check if a model is available locally
     /// 
     /// # Arguments
@@ -0,0 +1,504 @@
+/*
+ * Leara AI Assistant - Plain-Text Task Lists
+ *
+ * This module converts tasks to and from todo.txt and GitHub-style Markdown
+ * checklists so existing plain-text lists can be migrated into Leara.
+ *
+ * Copyright (c) 2024 Leara AI Assistant Contributors
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Author: KleaSCM
+ * Created: 2024-06-28
+ * Last Modified: 2024-06-28
+ * Version: 0.1.0
+ *
+ * File: src/utils/todotxt.rs
+ * Purpose: todo.txt and Markdown checklist export and import of tasks
+ */
+
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::models::memory::{Task, TaskStatus};
+
+/// Plain-text task list formats
+///
+/// Both formats share the todo.txt inline syntax for metadata:
+/// `(A)` priority, `+project` tags, `@context` and `due:YYYY-MM-DD`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskListFormat {
+    /// One task per line, see <https://github.com/todotxt/todo.txt>
+    TodoTxt,
+    /// `- [ ]` / `- [x]` checklist items; `#` headings tag the items below them
+    Markdown,
+}
+
+impl TaskListFormat {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "todotxt" | "todo.txt" | "todo" => Some(TaskListFormat::TodoTxt),
+            "markdown" | "md" => Some(TaskListFormat::Markdown),
+            _ => None,
+        }
+    }
+
+    /// MIME type of rendered documents
+    pub fn content_type(&self) -> &str {
+        match self {
+            TaskListFormat::TodoTxt => "text/plain; charset=utf-8",
+            TaskListFormat::Markdown => "text/markdown; charset=utf-8",
+        }
+    }
+
+    /// Render tasks in this format
+    pub fn render(&self, tasks: &[Task]) -> String {
+        match self {
+            TaskListFormat::TodoTxt => tasks_to_todotxt(tasks),
+            TaskListFormat::Markdown => tasks_to_markdown(tasks),
+        }
+    }
+
+    /// Parse a document in this format into new tasks
+    pub fn parse(&self, input: &str) -> Vec<Task> {
+        match self {
+            TaskListFormat::TodoTxt => parse_todotxt(input),
+            TaskListFormat::Markdown => parse_markdown(input),
+        }
+    }
+}
+
+/// Map a Leara priority (1 lowest - 5 highest) to a todo.txt priority letter
+pub fn leara_priority_to_letter(priority: i32) -> char {
+    match priority {
+        p if p >= 5 => 'A',
+        4 => 'B',
+        3 => 'C',
+        2 => 'D',
+        _ => 'E',
+    }
+}
+
+/// Map a todo.txt priority letter back to a Leara priority
+pub fn letter_to_leara_priority(letter: char) -> i32 {
+    match letter.to_ascii_uppercase() {
+        'A' => 5,
+        'B' => 4,
+        'C' => 3,
+        'D' => 2,
+        _ => 1,
+    }
+}
+
+/// Render tasks as a todo.txt document
+///
+/// Completed and cancelled tasks are written as done (`x`) lines; cancelled
+/// ones carry a `status:cancelled` extension so they round-trip.
+pub fn tasks_to_todotxt(tasks: &[Task]) -> String {
+    let mut output = String::new();
+    for task in tasks {
+        let status = TaskStatus::from_str(&task.status);
+        let mut parts = Vec::new();
+
+        let done = matches!(status, TaskStatus::Completed | TaskStatus::Cancelled);
+        if done {
+            parts.push("x".to_string());
+            parts.push(format_date(&task.completed_at.unwrap_or(task.updated_at)));
+        } else {
+            parts.push(format!("({})", leara_priority_to_letter(task.priority)));
+        }
+        parts.push(format_date(&task.created_at));
+        parts.push(inline_body(task, &status));
+        if done {
+            parts.push(format!("pri:{}", leara_priority_to_letter(task.priority)));
+        }
+
+        output.push_str(&parts.join(" "));
+        output.push('\n');
+    }
+    output
+}
+
+/// Render tasks as a Markdown checklist
+pub fn tasks_to_markdown(tasks: &[Task]) -> String {
+    let mut output = String::from("# Leara Tasks\n\n");
+    for task in tasks {
+        let status = TaskStatus::from_str(&task.status);
+        let done = matches!(status, TaskStatus::Completed | TaskStatus::Cancelled);
+        output.push_str(&format!(
+            "- [{}] ({}) {}\n",
+            if done { 'x' } else { ' ' },
+            leara_priority_to_letter(task.priority),
+            inline_body(task, &status)
+        ));
+        if let Some(ref description) = task.description {
+            for line in description.lines() {
+                output.push_str(&format!("  {}\n", line));
+            }
+        }
+    }
+    output
+}
+
+/// Parse a todo.txt document into new tasks
+pub fn parse_todotxt(input: &str) -> Vec<Task> {
+    input
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| parse_todotxt_line(line, &[]))
+        .collect()
+}
+
+/// Parse a Markdown checklist into new tasks
+///
+/// Checklist items (`- [ ]`, `* [x]`, `1. [ ]`) become tasks. Headings add
+/// their text as a tag to the items beneath them, and indented non-checklist
+/// lines directly under an item become its description.
+pub fn parse_markdown(input: &str) -> Vec<Task> {
+    let mut tasks: Vec<Task> = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut last_item_indent: Option<usize> = None;
+
+    for raw in input.lines() {
+        let indent = raw.len() - raw.trim_start().len();
+        let line = raw.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(level) = heading_level(line) {
+            let title = line[level..].trim();
+            headings.retain(|(l, _)| *l < level);
+            // The document title above every item is not a useful tag
+            if level > 1 {
+                headings.push((level, title.to_string()));
+            }
+            last_item_indent = None;
+            continue;
+        }
+
+        if let Some((done, body)) = checklist_item(line) {
+            let heading_tags: Vec<String> = headings.iter().map(|(_, h)| h.clone()).collect();
+            let body = if done { format!("x {}", body) } else { body.to_string() };
+            if let Some(task) = parse_todotxt_line(&body, &heading_tags) {
+                tasks.push(task);
+                last_item_indent = Some(indent);
+            }
+            continue;
+        }
+
+        // Indented prose under an item is its description
+        match (last_item_indent, tasks.last_mut()) {
+            (Some(item_indent), Some(task)) if indent > item_indent => {
+                let description = task.description.get_or_insert_with(String::new);
+                if !description.is_empty() {
+                    description.push('\n');
+                }
+                description.push_str(line);
+            }
+            _ => last_item_indent = None,
+        }
+    }
+
+    tasks
+}
+
+/// Title followed by the todo.txt inline metadata for a task
+fn inline_body(task: &Task, status: &TaskStatus) -> String {
+    let mut parts = vec![task.title.replace('\n', " ")];
+    if let Some(ref tags) = task.tags {
+        parts.extend(tags.split(',').filter(|t| !t.is_empty()).map(|t| format!("+{}", t)));
+    }
+    if let Some(ref context) = task.context {
+        parts.extend(
+            context
+                .split(',')
+                .map(|c| c.split_whitespace().collect::<Vec<_>>().join("-"))
+                .filter(|c| !c.is_empty())
+                .map(|c| format!("@{}", c)),
+        );
+    }
+    if let Some(due) = task.due_date {
+        parts.push(format!("due:{}", format_date(&due)));
+    }
+    match status {
+        TaskStatus::InProgress => parts.push("status:in_progress".to_string()),
+        TaskStatus::Cancelled => parts.push("status:cancelled".to_string()),
+        _ => {}
+    }
+    parts.join(" ")
+}
+
+/// Parse one todo.txt line into a task
+fn parse_todotxt_line(line: &str, extra_tags: &[String]) -> Option<Task> {
+    let mut tokens: Vec<&str> = line.split_whitespace().collect();
+    let mut done = false;
+    let mut completed_at = None;
+    let mut created_at = None;
+    let mut priority = 3;
+
+    if tokens.first() == Some(&"x") {
+        done = true;
+        tokens.remove(0);
+        if let Some(date) = tokens.first().and_then(|t| parse_date(t)) {
+            completed_at = Some(date);
+            tokens.remove(0);
+        }
+    }
+    if let Some(letter) = tokens.first().and_then(|t| priority_letter(t)) {
+        priority = letter_to_leara_priority(letter);
+        tokens.remove(0);
+    }
+    if let Some(date) = tokens.first().and_then(|t| parse_date(t)) {
+        created_at = Some(date);
+        tokens.remove(0);
+    }
+
+    let mut title_words = Vec::new();
+    let mut tags: Vec<String> = extra_tags.to_vec();
+    let mut contexts = Vec::new();
+    let mut due_date = None;
+    let mut status = None;
+
+    for token in tokens {
+        if let Some(project) = token.strip_prefix('+').filter(|p| !p.is_empty()) {
+            tags.push(project.to_string());
+        } else if let Some(context) = token.strip_prefix('@').filter(|c| !c.is_empty()) {
+            contexts.push(context.to_string());
+        } else if let Some(due) = token.strip_prefix("due:").and_then(parse_date) {
+            due_date = Some(due);
+        } else if let Some(value) = token.strip_prefix("status:") {
+            status = Some(TaskStatus::from_str(value));
+        } else if let Some(letter) = token.strip_prefix("pri:").and_then(|p| p.chars().next()) {
+            // Done lines keep their priority as a `pri:` tag by convention
+            priority = letter_to_leara_priority(letter);
+        } else {
+            title_words.push(token);
+        }
+    }
+
+    if title_words.is_empty() {
+        return None;
+    }
+
+    let status = match status {
+        Some(TaskStatus::Cancelled) => TaskStatus::Cancelled,
+        _ if done => TaskStatus::Completed,
+        Some(status) => status,
+        None => TaskStatus::Pending,
+    };
+    let now = Utc::now();
+
+    Some(Task {
+        id: 0,
+        title: title_words.join(" "),
+        description: None,
+        completed_at: match status {
+            TaskStatus::Completed | TaskStatus::Cancelled => Some(completed_at.unwrap_or(now)),
+            _ => None,
+        },
+        status: status.as_str().to_string(),
+        priority,
+        due_date,
+        created_at: created_at.unwrap_or(now),
+        updated_at: now,
+        context: if contexts.is_empty() { None } else { Some(contexts.join(",")) },
+        tags: if tags.is_empty() { None } else { Some(tags.join(",")) },
+        recurrence: None,
+    })
+}
+
+/// `(A)` style priority token
+fn priority_letter(token: &str) -> Option<char> {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next(), chars.next(), chars.next()) {
+        (Some('('), Some(letter), Some(')'), None) if letter.is_ascii_uppercase() => Some(letter),
+        _ => None,
+    }
+}
+
+/// Markdown heading level (`## Title` -> 2)
+fn heading_level(line: &str) -> Option<usize> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&level) && line[level..].starts_with(' ') {
+        Some(level)
+    } else {
+        None
+    }
+}
+
+/// Split a checklist item into (done, body)
+fn checklist_item(line: &str) -> Option<(bool, &str)> {
+    let rest = line
+        .strip_prefix("- ")
+        .or_else(|| line.strip_prefix("* "))
+        .or_else(|| line.strip_prefix("+ "))
+        .or_else(|| {
+            let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+            if digits > 0 { line[digits..].strip_prefix(". ") } else { None }
+        })?;
+
+    let done = if rest.starts_with("[ ]") {
+        false
+    } else if rest.starts_with("[x]") || rest.starts_with("[X]") {
+        true
+    } else {
+        return None;
+    };
+    Some((done, rest[3..].trim()))
+}
+
+fn format_date(dt: &DateTime<Utc>) -> String {
+    dt.format("%Y-%m-%d").to_string()
+}
+
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn date(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, day, 0, 0, 0).unwrap()
+    }
+
+    fn task(title: &str, status: TaskStatus, priority: i32) -> Task {
+        Task {
+            id: 0,
+            title: title.to_string(),
+            description: None,
+            status: status.as_str().to_string(),
+            priority,
+            due_date: None,
+            created_at: date(1),
+            updated_at: date(2),
+            completed_at: matches!(status, TaskStatus::Completed | TaskStatus::Cancelled).then(|| date(3)),
+            context: None,
+            tags: None,
+            recurrence: None,
+        }
+    }
+
+    fn tasks() -> Vec<Task> {
+        let mut report = task("Write report", TaskStatus::Pending, 5);
+        report.due_date = Some(date(10));
+        report.tags = Some("work,q2".to_string());
+        report.context = Some("office,laptop".to_string());
+        let mut review = task("Review PR", TaskStatus::InProgress, 4);
+        review.tags = Some("work".to_string());
+        vec![
+            report,
+            review,
+            task("Buy milk", TaskStatus::Completed, 3),
+            task("Old idea", TaskStatus::Cancelled, 1),
+        ]
+    }
+
+    /// Title, status, priority, due, created, completed, tags and context
+    type Summary = (String, String, i32, Option<DateTime<Utc>>, DateTime<Utc>, Option<DateTime<Utc>>, Option<String>, Option<String>);
+
+    /// Fields both formats carry
+    fn summary(task: &Task) -> Summary {
+        (
+            task.title.clone(),
+            task.status.clone(),
+            task.priority,
+            task.due_date,
+            task.created_at,
+            task.completed_at,
+            task.tags.clone(),
+            task.context.clone(),
+        )
+    }
+
+    #[test]
+    fn todotxt_lines_are_rendered() {
+        let rendered = tasks_to_todotxt(&tasks());
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "(A) 2024-06-01 Write report +work +q2 @office @laptop due:2024-06-10",
+                "(B) 2024-06-01 Review PR +work status:in_progress",
+                "x 2024-06-03 2024-06-01 Buy milk pri:C",
+                "x 2024-06-03 2024-06-01 Old idea status:cancelled pri:E",
+            ]
+        );
+    }
+
+    #[test]
+    fn todotxt_round_trips() {
+        let tasks = tasks();
+        let parsed = parse_todotxt(&tasks_to_todotxt(&tasks));
+        assert_eq!(parsed.iter().map(summary).collect::<Vec<_>>(), tasks.iter().map(summary).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn todotxt_lines_are_parsed() {
+        let parsed = parse_todotxt("(B) Call mom @phone +family due:2024-06-05\n\nx 2024-06-04 (A) 2024-06-01 Pay rent\n+only-tags\n");
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].title, "Call mom");
+        assert_eq!(parsed[0].priority, 4);
+        assert_eq!(parsed[0].context.as_deref(), Some("phone"));
+        assert_eq!(parsed[0].tags.as_deref(), Some("family"));
+        assert_eq!(parsed[0].due_date, Some(date(5)));
+        assert_eq!(parsed[1].status, "completed");
+        assert_eq!(parsed[1].priority, 5);
+        assert_eq!(parsed[1].completed_at, Some(date(4)));
+        assert_eq!(parsed[1].created_at, date(1));
+    }
+
+    #[test]
+    fn markdown_round_trips() {
+        let mut tasks = tasks();
+        tasks[0].description = Some("first draft\nthen polish".to_string());
+        let parsed = parse_markdown(&tasks_to_markdown(&tasks));
+        // Markdown items carry no dates of their own
+        let undated = |task: &Task| {
+            let (title, status, priority, due, _, _, tags, context) = summary(task);
+            (title, status, priority, due, tags, context, task.description.clone())
+        };
+        assert_eq!(parsed.iter().map(undated).collect::<Vec<_>>(), tasks.iter().map(undated).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn markdown_headings_and_nested_items_are_parsed() {
+        let input = "# Plan\n\
+                     \n\
+                     ## Home\n\
+                     - [ ] Clean kitchen\n\
+                     \x20 - [x] Empty dishwasher\n\
+                     \x20   run it first\n\
+                     \x20 * [ ] Wipe counters @kitchen\n\
+                     ### Garden\n\
+                     1. [ ] Mow lawn due:2024-06-08\n\
+                     ## Work\n\
+                     - [X] (A) Ship release +launch\n\
+                     - not a task\n";
+        let parsed = parse_markdown(input);
+        let items: Vec<(&str, &str, Option<&str>)> =
+            parsed.iter().map(|t| (t.title.as_str(), t.status.as_str(), t.tags.as_deref())).collect();
+        assert_eq!(
+            items,
+            vec![
+                ("Clean kitchen", "pending", Some("Home")),
+                ("Empty dishwasher", "completed", Some("Home")),
+                ("Wipe counters", "pending", Some("Home")),
+                ("Mow lawn", "pending", Some("Home,Garden")),
+                ("Ship release", "completed", Some("Work,launch")),
+            ]
+        );
+        assert_eq!(parsed[1].description.as_deref(), Some("run it first"));
+        assert_eq!(parsed[2].context.as_deref(), Some("kitchen"));
+        assert_eq!(parsed[3].due_date, Some(date(8)));
+        assert_eq!(parsed[4].priority, 5);
+    }
+}